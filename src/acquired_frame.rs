@@ -0,0 +1,442 @@
+use crate::duplication_context::DuplicationContext;
+use crate::error::Error;
+use crate::model::{MoveRect, Rect, Result};
+use crate::preview::{preview_size, subsample};
+use crate::utils::{FormatExt, FrameInfoExt};
+use std::mem;
+use windows::core::ComInterface;
+use windows::Win32::Foundation::RECT;
+use windows::Win32::Graphics::Direct3D11::{ID3D11Texture2D, D3D11_TEXTURE2D_DESC};
+use windows::Win32::Graphics::Dxgi::{
+  IDXGIResource, IDXGISurface1, DXGI_MAPPED_RECT, DXGI_MAP_READ, DXGI_OUTDUPL_FRAME_INFO,
+  DXGI_OUTDUPL_MOVE_RECT, DXGI_OUTDUPL_POINTER_SHAPE_INFO,
+};
+
+/// A frame acquired from the duplication, held until dropped or [released](AcquiredFrame::release).
+///
+/// While the frame is held no new frame can be acquired, so release it as soon as possible.
+pub struct AcquiredFrame<'a> {
+  ctx: &'a DuplicationContext,
+  resource: IDXGIResource,
+  info: DXGI_OUTDUPL_FRAME_INFO,
+  released: bool,
+}
+
+impl<'a> AcquiredFrame<'a> {
+  pub fn info(&self) -> &DXGI_OUTDUPL_FRAME_INFO {
+    &self.info
+  }
+
+  /// The desktop image resource, only valid until the frame is released.
+  pub fn resource(&self) -> &IDXGIResource {
+    &self.resource
+  }
+
+  /// The desktop image as a GPU texture, only valid until the frame is released.
+  pub fn texture(&self) -> Result<ID3D11Texture2D> {
+    self
+      .resource
+      .cast()
+      .map_err(|e| self.ctx.windows_error("IDXGIResource.cast", e))
+  }
+
+  /// The areas updated since the last frame, in frame coordinates.
+  pub fn dirty_rects(&self) -> Result<Vec<Rect>> {
+    let mut rects = vec![RECT::default(); self.metadata_capacity::<RECT>()];
+    let mut size = 0;
+    unsafe {
+      self.ctx.output_duplication().GetFrameDirtyRects(
+        mem::size_of_val(rects.as_slice()) as u32,
+        rects.as_mut_ptr(),
+        &mut size,
+      )
+    }
+    .map_err(|e| self.ctx.windows_error("GetFrameDirtyRects", e))?;
+    rects.truncate(size as usize / mem::size_of::<RECT>());
+    Ok(rects.into_iter().map(Rect::from).collect())
+  }
+
+  /// The blocks moved since the last frame, e.g. by scrolling or dragging windows.
+  pub fn move_rects(&self) -> Result<Vec<MoveRect>> {
+    let mut rects =
+      vec![DXGI_OUTDUPL_MOVE_RECT::default(); self.metadata_capacity::<DXGI_OUTDUPL_MOVE_RECT>()];
+    let mut size = 0;
+    unsafe {
+      self.ctx.output_duplication().GetFrameMoveRects(
+        mem::size_of_val(rects.as_slice()) as u32,
+        rects.as_mut_ptr(),
+        &mut size,
+      )
+    }
+    .map_err(|e| self.ctx.windows_error("GetFrameMoveRects", e))?;
+    rects.truncate(size as usize / mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>());
+    Ok(rects.into_iter().map(MoveRect::from).collect())
+  }
+
+  /// Copy the desktop image to a readable texture and its pixels to `dest`,
+  /// which must hold all pixels of the `texture_desc`.
+  /// Can be called multiple times, e.g. with different readable textures.
+  ///
+  /// With [`TextureOptions::map_system_memory`](crate::model::TextureOptions::map_system_memory)
+  /// the desktop image is copied from system memory instead when possible.
+  pub fn copy_to_slice(
+    &self,
+    dest: &mut [u8],
+    readable_texture: &ID3D11Texture2D,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+  ) -> Result<()> {
+    let len = self.ctx.check_dest(dest, texture_desc)?;
+    if self.ctx.maps_desktop_surface() {
+      return self.ctx.copy_desktop_surface(dest, len, texture_desc);
+    }
+    self.ctx.copy_resource(readable_texture, &self.texture()?)?;
+    self.ctx.copy_surface(
+      &readable_texture.cast().unwrap(),
+      dest.as_mut_ptr(),
+      len,
+      texture_desc,
+    )
+  }
+
+  /// Update `dest`, which holds the previous frame, to this frame:
+  /// apply the move rects within `dest` and only copy the dirty rects from the desktop image.
+  /// This is much cheaper than a full copy while scrolling or dragging windows.
+  ///
+  /// Fall back to a full copy if the metadata is not available.
+  /// The dirty rects are read from system memory like [`AcquiredFrame::copy_to_slice`].
+  pub fn update_slice(
+    &self,
+    dest: &mut [u8],
+    readable_texture: &ID3D11Texture2D,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+  ) -> Result<()> {
+    if !self.info.desktop_updated() {
+      return Ok(());
+    }
+    let (Ok(move_rects), Ok(dirty_rects)) = (self.move_rects(), self.dirty_rects()) else {
+      return self.copy_to_slice(dest, readable_texture, texture_desc);
+    };
+    let len = self.ctx.check_dest(dest, texture_desc)?;
+    let bytes_per_pixel = texture_desc.Format.bytes_per_pixel();
+
+    let bounds = Rect::new(0, 0, texture_desc.Width as i32, texture_desc.Height as i32);
+    for rect in &move_rects {
+      apply_move_rect(
+        &mut dest[..len],
+        texture_desc.Width,
+        bytes_per_pixel,
+        &bounds,
+        rect,
+      );
+    }
+
+    let mapped_surface = self.map(readable_texture)?;
+    let pitch = mapped_surface.Pitch as usize;
+    let line_bytes = texture_desc.Width as usize * bytes_per_pixel;
+    for rect in dirty_rects
+      .iter()
+      .filter_map(|rect| rect.intersect(&bounds))
+    {
+      let offset = rect.left as usize * bytes_per_pixel;
+      let bytes = rect.width() as usize * bytes_per_pixel;
+      for y in rect.top as usize..rect.bottom as usize {
+        // the mapped surface holds `Height` rows of `pitch` bytes
+        let src = unsafe {
+          std::slice::from_raw_parts(mapped_surface.pBits.add(y * pitch + offset), bytes)
+        };
+        let start = y * line_bytes + offset;
+        let row = &mut dest[start..start + bytes];
+        row.copy_from_slice(src);
+        self.ctx.apply_color_adjustment(row, texture_desc.Format);
+      }
+    }
+    self.unmap(readable_texture)
+  }
+
+  /// Map the desktop image for reading, in system memory if the context maps the desktop surface,
+  /// otherwise copied to `readable_texture`. Unmap it with [`AcquiredFrame::unmap`].
+  fn map(&self, readable_texture: &ID3D11Texture2D) -> Result<DXGI_MAPPED_RECT> {
+    if self.ctx.maps_desktop_surface() {
+      return unsafe { self.ctx.output_duplication().MapDesktopSurface() }
+        .map_err(|e| self.ctx.windows_error("MapDesktopSurface", e));
+    }
+    self.ctx.copy_resource(readable_texture, &self.texture()?)?;
+    let surface: IDXGISurface1 = readable_texture.cast().unwrap();
+    let mut mapped_surface = DXGI_MAPPED_RECT::default();
+    unsafe { surface.Map(&mut mapped_surface, DXGI_MAP_READ) }
+      .map_err(|e| self.ctx.windows_error("Map", e))?;
+    Ok(mapped_surface)
+  }
+
+  fn unmap(&self, readable_texture: &ID3D11Texture2D) -> Result<()> {
+    if self.ctx.maps_desktop_surface() {
+      return unsafe { self.ctx.output_duplication().UnMapDesktopSurface() }
+        .map_err(|e| self.ctx.windows_error("UnMapDesktopSurface", e));
+    }
+    let surface: IDXGISurface1 = readable_texture.cast().unwrap();
+    unsafe { surface.Unmap() }.map_err(|e| self.ctx.windows_error("Unmap", e))
+  }
+
+  /// Copy every `step`-th pixel of every `step`-th row of the desktop image to `dest`
+  /// in a single pass over the mapped texture, see [`preview_size`] for the size of the preview.
+  /// Return the preview size.
+  pub fn copy_preview(
+    &self,
+    dest: &mut [u8],
+    readable_texture: &ID3D11Texture2D,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+    step: u32,
+  ) -> Result<(u32, u32)> {
+    let (width, height) = preview_size(texture_desc.Width, texture_desc.Height, step);
+    let bytes_per_pixel = texture_desc.Format.bytes_per_pixel();
+    if dest.len() < width as usize * height as usize * bytes_per_pixel {
+      return Err(Error::new("Invalid buffer length").with_context(self.ctx.error_context()));
+    }
+
+    self.ctx.copy_resource(readable_texture, &self.texture()?)?;
+    let surface: IDXGISurface1 = readable_texture.cast().unwrap();
+    let mut mapped_surface = DXGI_MAPPED_RECT::default();
+    unsafe { surface.Map(&mut mapped_surface, DXGI_MAP_READ) }
+      .map_err(|e| self.ctx.windows_error("Map", e))?;
+    let pitch = mapped_surface.Pitch as usize;
+    let line_bytes = texture_desc.Width as usize * bytes_per_pixel;
+    // the last row may not be padded to the pitch
+    let len = (texture_desc.Height as usize).saturating_sub(1) * pitch + line_bytes;
+    let src = unsafe { std::slice::from_raw_parts(mapped_surface.pBits, len) };
+    subsample(
+      src,
+      pitch,
+      texture_desc.Width,
+      texture_desc.Height,
+      bytes_per_pixel,
+      step,
+      dest,
+    );
+    self.ctx.apply_color_adjustment(
+      &mut dest[..width as usize * height as usize * bytes_per_pixel],
+      texture_desc.Format,
+    );
+    unsafe { surface.Unmap() }.map_err(|e| self.ctx.windows_error("Unmap", e))?;
+    Ok((width, height))
+  }
+
+  /// If the pointer shape is updated, resize `pointer_shape_buffer` if needed and write the shape to it.
+  pub fn pointer_shape(
+    &self,
+    pointer_shape_buffer: &mut Vec<u8>,
+  ) -> Result<Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>> {
+    self.ctx.pointer_shape(&self.info, pointer_shape_buffer)
+  }
+
+  /// Release the frame, reporting the failure which dropping ignores.
+  pub fn release(mut self) -> Result<()> {
+    self.released = true;
+    self.ctx.release_frame()
+  }
+
+  /// The metadata buffer holds both dirty rects and move rects,
+  /// so its size bounds the count of either.
+  fn metadata_capacity<T>(&self) -> usize {
+    self.info.metadata_size() / mem::size_of::<T>()
+  }
+}
+
+impl Drop for AcquiredFrame<'_> {
+  fn drop(&mut self) {
+    if !self.released {
+      self.ctx.release_frame().ok();
+    }
+  }
+}
+
+/// Move a block of pixels within `buffer` of `width` pixels per row,
+/// clipping the source and destination to `bounds`.
+fn apply_move_rect(
+  buffer: &mut [u8],
+  width: u32,
+  bytes_per_pixel: usize,
+  bounds: &Rect,
+  rect: &MoveRect,
+) {
+  let dx = rect.destination.left - rect.source.x;
+  let dy = rect.destination.top - rect.source.y;
+  let source = Rect::new(
+    rect.source.x,
+    rect.source.y,
+    rect.source.x + rect.destination.width() as i32,
+    rect.source.y + rect.destination.height() as i32,
+  );
+  // clip the source so both the source and the destination are in bounds
+  let shifted_bounds = Rect::new(
+    bounds.left - dx,
+    bounds.top - dy,
+    bounds.right - dx,
+    bounds.bottom - dy,
+  );
+  let Some(source) = source
+    .intersect(bounds)
+    .and_then(|source| source.intersect(&shifted_bounds))
+  else {
+    return;
+  };
+
+  let line_bytes = width as usize * bytes_per_pixel;
+  let bytes = source.width() as usize * bytes_per_pixel;
+  let offset = |x: i32, y: i32| y as usize * line_bytes + x as usize * bytes_per_pixel;
+  let copy_row = |buffer: &mut [u8], y: i32| {
+    let start = offset(source.left, y);
+    buffer.copy_within(start..start + bytes, offset(source.left + dx, y + dy));
+  };
+  // copy rows in the direction which doesn't overwrite unread source rows
+  if dy > 0 {
+    (source.top..source.bottom)
+      .rev()
+      .for_each(|y| copy_row(buffer, y));
+  } else {
+    (source.top..source.bottom).for_each(|y| copy_row(buffer, y));
+  }
+}
+
+impl DuplicationContext {
+  /// Acquire the next frame without copying it, to read its metadata
+  /// or run several GPU operations on it before it is released.
+  pub fn acquire(&self) -> Result<AcquiredFrame<'_>> {
+    let (resource, info) = self.acquire_resource(self.timeout_ms())?;
+    Ok(AcquiredFrame {
+      ctx: self,
+      resource,
+      info,
+      released: false,
+    })
+  }
+
+  /// Like [`DuplicationContext::capture_to_slice_with_pointer_shape`],
+  /// but `dest` must hold the previous frame, which is updated by [`AcquiredFrame::update_slice`].
+  /// The pointer shape is only captured if `pointer_shape_buffer` is `Some`.
+  pub fn update_slice(
+    &self,
+    dest: &mut [u8],
+    readable_texture: &ID3D11Texture2D,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+    pointer_shape_buffer: Option<&mut Vec<u8>>,
+  ) -> Result<(
+    DXGI_OUTDUPL_FRAME_INFO,
+    Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
+  )> {
+    let frame = self.acquire()?;
+    frame.update_slice(dest, readable_texture, texture_desc)?;
+    let pointer_shape_info = match pointer_shape_buffer {
+      Some(buffer) => frame.pointer_shape(buffer)?,
+      None => None,
+    };
+    let info = frame.info;
+    frame.release()?;
+    Ok((info, pointer_shape_info))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::apply_move_rect;
+  use crate::{
+    manager::Manager,
+    model::{MoveRect, Point, Rect},
+    preview::preview_size,
+    utils::{FrameInfoExt, OutDuplDescExt},
+  };
+  use std::{thread, time::Duration};
+
+  #[test]
+  fn acquired_frame() {
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    let (texture, desc, texture_desc) = ctx.create_readable_texture().unwrap();
+    let mut buffer = vec![0u8; desc.calc_buffer_size()];
+
+    // sleep for a while before capture to wait system to update the screen
+    thread::sleep(Duration::from_millis(100));
+
+    let frame = ctx.acquire().unwrap();
+    assert!(frame.info().desktop_updated());
+    // the first frame updates the whole desktop
+    assert!(!frame.dirty_rects().unwrap().is_empty());
+    frame.move_rects().unwrap();
+    frame
+      .copy_to_slice(&mut buffer, &texture, &texture_desc)
+      .unwrap();
+    assert!(buffer.iter().any(|&b| b != 0));
+
+    // a quarter of the resolution, matching the full copy
+    let (width, height) = preview_size(texture_desc.Width, texture_desc.Height, 4);
+    let mut preview = vec![0u8; width as usize * height as usize * 4];
+    frame
+      .copy_preview(&mut preview, &texture, &texture_desc, 4)
+      .unwrap();
+    assert_eq!(
+      preview[width as usize * 4..][..4],
+      buffer[texture_desc.Width as usize * 16..][..4]
+    );
+    assert!(frame
+      .copy_preview(&mut [], &texture, &texture_desc, 4)
+      .is_err());
+
+    // the frame must be released before the next one is acquired
+    assert!(ctx.acquire().is_err());
+    frame.release().unwrap();
+    ctx.acquire().unwrap();
+  }
+
+  #[test]
+  fn move_rects() {
+    // a 4x4 image where each pixel is filled with its index
+    let pixels = || -> Vec<u8> { (0..16u8).flat_map(|i| [i; 4]).collect() };
+    let bounds = Rect::new(0, 0, 4, 4);
+    let index = |buffer: &[u8], x: usize, y: usize| buffer[(y * 4 + x) * 4];
+
+    // scroll up by one row, overlapping the source
+    let mut buffer = pixels();
+    apply_move_rect(
+      &mut buffer,
+      4,
+      4,
+      &bounds,
+      &MoveRect {
+        source: Point::new(0, 1),
+        destination: Rect::new(0, 0, 4, 3),
+      },
+    );
+    assert_eq!(index(&buffer, 0, 0), 4);
+    assert_eq!(index(&buffer, 3, 2), 15);
+    assert_eq!(index(&buffer, 3, 3), 15);
+
+    // scroll down by one row
+    let mut buffer = pixels();
+    apply_move_rect(
+      &mut buffer,
+      4,
+      4,
+      &bounds,
+      &MoveRect {
+        source: Point::new(0, 0),
+        destination: Rect::new(0, 1, 4, 4),
+      },
+    );
+    assert_eq!(index(&buffer, 0, 1), 0);
+    assert_eq!(index(&buffer, 3, 3), 11);
+
+    // the block is clipped to the image
+    let mut buffer = pixels();
+    apply_move_rect(
+      &mut buffer,
+      4,
+      4,
+      &bounds,
+      &MoveRect {
+        source: Point::new(2, 2),
+        destination: Rect::new(3, 3, 6, 6),
+      },
+    );
+    assert_eq!(index(&buffer, 3, 3), 10);
+    assert_eq!(buffer[..15 * 4], pixels()[..15 * 4]);
+  }
+}
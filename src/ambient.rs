@@ -0,0 +1,161 @@
+//! Average colors along the screen edges, e.g. to drive LED strips behind a monitor.
+
+use crate::color::average_color;
+use crate::frame::Frame;
+use crate::model::Rect;
+
+/// How the screen edges are split into zones.
+///
+/// Zones are ordered clockwise from the top-left corner:
+/// the top edge from left to right, the right edge from top to bottom,
+/// the bottom edge from right to left and the left edge from bottom to top.
+/// Zones of adjacent edges overlap at the corners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EdgeLayout {
+  pub top: u32,
+  pub right: u32,
+  pub bottom: u32,
+  pub left: u32,
+  /// How far zones reach into the screen, in pixels.
+  pub depth: u32,
+  /// Sample every `step`-th pixel and row, 1 to average every pixel.
+  pub step: u32,
+}
+
+impl EdgeLayout {
+  /// `horizontal` zones on the top and bottom edges and `vertical` zones on the left and right edges.
+  pub fn new(horizontal: u32, vertical: u32, depth: u32) -> Self {
+    Self {
+      top: horizontal,
+      right: vertical,
+      bottom: horizontal,
+      left: vertical,
+      depth,
+      step: 1,
+    }
+  }
+
+  /// The number of zones.
+  pub fn len(&self) -> usize {
+    (self.top + self.right + self.bottom + self.left) as usize
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// The zones of a `width`x`height` frame, in the order of the colors.
+  pub fn zones(&self, width: u32, height: u32) -> Vec<Rect> {
+    let (width, height) = (width as i32, height as i32);
+    let depth = self.depth.max(1) as i32;
+    // split `length` into `count` spans which differ by at most one pixel
+    let span = |i: u32, count: u32, length: i32| {
+      let count = count as i64;
+      let start = i as i64 * length as i64 / count;
+      let end = (i as i64 + 1) * length as i64 / count;
+      (start as i32, end as i32)
+    };
+
+    let mut zones = Vec::with_capacity(self.len());
+    for i in 0..self.top {
+      let (left, right) = span(i, self.top, width);
+      zones.push(Rect::new(left, 0, right, depth));
+    }
+    for i in 0..self.right {
+      let (top, bottom) = span(i, self.right, height);
+      zones.push(Rect::new(width - depth, top, width, bottom));
+    }
+    for i in (0..self.bottom).rev() {
+      let (left, right) = span(i, self.bottom, width);
+      zones.push(Rect::new(left, height - depth, right, height));
+    }
+    for i in (0..self.left).rev() {
+      let (top, bottom) = span(i, self.left, height);
+      zones.push(Rect::new(0, top, depth, bottom));
+    }
+    zones
+  }
+
+  /// Average the zones of a BGRA32 `buffer` of `width` pixels per row into `colors` as `[r, g, b]`,
+  /// ready to send to LED strips. Zones outside the buffer are black.
+  pub fn colors_into(&self, buffer: &[u8], width: u32, colors: &mut Vec<[u8; 3]>) {
+    let height = (buffer.len() / 4 / width.max(1) as usize) as u32;
+    colors.clear();
+    colors.extend(self.zones(width, height).iter().map(|zone| {
+      average_color(buffer, width, zone, self.step).map_or([0; 3], |[b, g, r, _]| [r, g, b])
+    }));
+  }
+}
+
+impl Frame {
+  /// Average the zones along the edges of the frame as `[r, g, b]`, see [`EdgeLayout`].
+  /// The frame must be 8-bit BGRA.
+  pub fn edge_colors(&self, layout: &EdgeLayout) -> Vec<[u8; 3]> {
+    let mut colors = Vec::with_capacity(layout.len());
+    layout.colors_into(&self.buffer, self.width, &mut colors);
+    colors
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::EdgeLayout;
+  use crate::model::Rect;
+  use crate::test_utils::generate;
+
+  #[test]
+  fn zones() {
+    let layout = EdgeLayout::new(2, 1, 10);
+    assert_eq!(layout.len(), 6);
+    assert_eq!(
+      layout.zones(100, 50),
+      [
+        Rect::new(0, 0, 50, 10),
+        Rect::new(50, 0, 100, 10),
+        Rect::new(90, 0, 100, 50),
+        Rect::new(50, 40, 100, 50),
+        Rect::new(0, 40, 50, 50),
+        Rect::new(0, 0, 10, 50),
+      ]
+    );
+    // uneven spans
+    let layout = EdgeLayout {
+      top: 3,
+      right: 0,
+      bottom: 0,
+      left: 0,
+      depth: 1,
+      step: 1,
+    };
+    let widths: Vec<u32> = layout
+      .zones(10, 10)
+      .iter()
+      .map(|zone| zone.width())
+      .collect();
+    assert_eq!(widths, [3, 3, 4]);
+  }
+
+  #[test]
+  fn edge_colors() {
+    // red top half, blue bottom half
+    let frame = generate(
+      64,
+      32,
+      |_, y| if y < 16 { [0, 0, 0xFF] } else { [0xFF, 0, 0] },
+    );
+    let mut layout = EdgeLayout::new(2, 2, 4);
+    let red = [0xFF, 0, 0];
+    let blue = [0, 0, 0xFF];
+    assert_eq!(
+      frame.edge_colors(&layout),
+      [red, red, red, blue, blue, blue, blue, red]
+    );
+
+    // sampling gives the same result on uniform zones
+    layout.step = 3;
+    assert_eq!(
+      frame.edge_colors(&layout),
+      [red, red, red, blue, blue, blue, blue, red]
+    );
+  }
+}
@@ -0,0 +1,265 @@
+//! Capture system audio with WASAPI loopback, e.g. to record it along with the screen.
+//! Enable the `audio` feature to use this module.
+
+use crate::com::ComGuard;
+use crate::error::Error;
+use crate::model::Result;
+use std::ptr;
+use std::time::Duration;
+use windows::core::GUID;
+use windows::Win32::Media::Audio::{
+  eConsole, eRender, IAudioCaptureClient, IAudioClient, IMMDeviceEnumerator, MMDeviceEnumerator,
+  AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY, AUDCLNT_BUFFERFLAGS_SILENT, AUDCLNT_SHAREMODE_SHARED,
+  AUDCLNT_STREAMFLAGS_LOOPBACK, WAVEFORMATEX, WAVEFORMATEXTENSIBLE,
+};
+use windows::Win32::System::Com::{CoCreateInstance, CoTaskMemFree, CLSCTX_ALL};
+
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+const KSDATAFORMAT_SUBTYPE_IEEE_FLOAT: GUID =
+  GUID::from_u128(0x00000003_0000_0010_8000_00aa00389b71);
+
+/// The size of the WASAPI buffer, in 100-nanosecond units.
+const BUFFER_DURATION_HNS: i64 = 10_000_000;
+
+/// The PCM format of captured audio, which is the mix format of the audio device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioFormat {
+  pub sample_rate: u32,
+  pub channels: u16,
+  pub bits_per_sample: u16,
+  /// Bytes per frame, i.e. one sample of every channel.
+  pub block_align: u16,
+  /// Samples are `f32` instead of integers.
+  pub float: bool,
+}
+
+impl AudioFormat {
+  /// The duration of `frames` audio frames.
+  pub fn duration(&self, frames: u32) -> Duration {
+    Duration::from_nanos(frames as u64 * 1_000_000_000 / self.sample_rate.max(1) as u64)
+  }
+}
+
+/// The sample type of `WAVE_FORMAT_EXTENSIBLE` formats is only known from their `SubFormat`,
+/// convert them from [`WAVEFORMATEXTENSIBLE`] instead.
+impl From<&WAVEFORMATEX> for AudioFormat {
+  fn from(format: &WAVEFORMATEX) -> Self {
+    Self {
+      sample_rate: format.nSamplesPerSec,
+      channels: format.nChannels,
+      bits_per_sample: format.wBitsPerSample,
+      block_align: format.nBlockAlign,
+      float: format.wFormatTag == WAVE_FORMAT_IEEE_FLOAT,
+    }
+  }
+}
+
+impl From<&WAVEFORMATEXTENSIBLE> for AudioFormat {
+  fn from(format: &WAVEFORMATEXTENSIBLE) -> Self {
+    let sub_format = format.SubFormat;
+    Self {
+      float: sub_format == KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
+      ..Self::from(&{ format.Format })
+    }
+  }
+}
+
+/// A packet of captured audio.
+#[derive(Debug, Clone, Default)]
+pub struct AudioBuffer {
+  /// Interleaved samples in the [`AudioFormat`] of the capture.
+  pub data: Vec<u8>,
+  pub frames: u32,
+  /// When the first frame was recorded, as a QPC time converted to 100-nanosecond units.
+  pub time: u64,
+  /// Position of the first frame in the stream, in frames.
+  pub position: u64,
+  /// Some frames are lost before this packet, e.g. the capture was not read fast enough.
+  pub discontinuity: bool,
+}
+
+/// Capture what the default render device plays.
+///
+/// The device only delivers packets while something is playing,
+/// so expect gaps between packets and use [`AudioBuffer::time`] to place them.
+pub struct LoopbackCapture {
+  client: IAudioClient,
+  capture: IAudioCaptureClient,
+  format: AudioFormat,
+  discontinuities: u64,
+  /// Dropped after the clients.
+  _com: ComGuard,
+}
+
+impl LoopbackCapture {
+  /// Open the default render device and start capturing.
+  /// COM is initialized for the current thread until the capture is dropped if it is not yet.
+  pub fn new() -> Result<Self> {
+    let com = ComGuard::new();
+
+    let enumerator: IMMDeviceEnumerator =
+      unsafe { CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL) }
+        .map_err(|e| Error::windows("CoCreateInstance.MMDeviceEnumerator", e))?;
+    let device = unsafe { enumerator.GetDefaultAudioEndpoint(eRender, eConsole) }
+      .map_err(|e| Error::windows("GetDefaultAudioEndpoint", e))?;
+    let client: IAudioClient = unsafe { device.Activate(CLSCTX_ALL, None) }
+      .map_err(|e| Error::windows("IMMDevice.Activate", e))?;
+
+    let mix_format =
+      unsafe { client.GetMixFormat() }.map_err(|e| Error::windows("GetMixFormat", e))?;
+    let format = unsafe {
+      if (*mix_format).wFormatTag == WAVE_FORMAT_EXTENSIBLE {
+        AudioFormat::from(&*(mix_format as *const WAVEFORMATEXTENSIBLE))
+      } else {
+        AudioFormat::from(&*mix_format)
+      }
+    };
+    let initialized = unsafe {
+      client.Initialize(
+        AUDCLNT_SHAREMODE_SHARED,
+        AUDCLNT_STREAMFLAGS_LOOPBACK,
+        BUFFER_DURATION_HNS,
+        0,
+        mix_format,
+        None,
+      )
+    };
+    unsafe { CoTaskMemFree(Some(mix_format as *const _)) };
+    initialized.map_err(|e| Error::windows("IAudioClient.Initialize", e))?;
+
+    let capture: IAudioCaptureClient =
+      unsafe { client.GetService() }.map_err(|e| Error::windows("GetService", e))?;
+    unsafe { client.Start() }.map_err(|e| Error::windows("IAudioClient.Start", e))?;
+
+    Ok(Self {
+      client,
+      capture,
+      format,
+      discontinuities: 0,
+      _com: com,
+    })
+  }
+
+  pub fn format(&self) -> AudioFormat {
+    self.format
+  }
+
+  /// How many packets were marked as discontinuous since the capture started.
+  pub fn discontinuities(&self) -> u64 {
+    self.discontinuities
+  }
+
+  /// Read the next packet into `buffer`, reusing its allocation.
+  /// Return `false` if no packet is available yet.
+  pub fn read_into(&mut self, buffer: &mut AudioBuffer) -> Result<bool> {
+    let size = unsafe { self.capture.GetNextPacketSize() }
+      .map_err(|e| Error::windows("GetNextPacketSize", e))?;
+    if size == 0 {
+      return Ok(false);
+    }
+
+    let mut data = ptr::null_mut();
+    let mut frames = 0;
+    let mut flags = 0;
+    let mut position = 0;
+    let mut time = 0;
+    unsafe {
+      self.capture.GetBuffer(
+        &mut data,
+        &mut frames,
+        &mut flags,
+        Some(&mut position),
+        Some(&mut time),
+      )
+    }
+    .map_err(|e| Error::windows("IAudioCaptureClient.GetBuffer", e))?;
+
+    let len = frames as usize * self.format.block_align as usize;
+    buffer.data.clear();
+    if flags & AUDCLNT_BUFFERFLAGS_SILENT.0 as u32 != 0 {
+      buffer.data.resize(len, 0);
+    } else {
+      buffer
+        .data
+        .extend_from_slice(unsafe { std::slice::from_raw_parts(data, len) });
+    }
+    buffer.frames = frames;
+    buffer.time = time;
+    buffer.position = position;
+    buffer.discontinuity = flags & AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY.0 as u32 != 0;
+    if buffer.discontinuity {
+      self.discontinuities += 1;
+    }
+
+    unsafe { self.capture.ReleaseBuffer(frames) }
+      .map_err(|e| Error::windows("IAudioCaptureClient.ReleaseBuffer", e))?;
+    Ok(true)
+  }
+
+  /// Read the next packet. Return `None` if no packet is available yet.
+  pub fn read(&mut self) -> Result<Option<AudioBuffer>> {
+    let mut buffer = AudioBuffer::default();
+    Ok(self.read_into(&mut buffer)?.then_some(buffer))
+  }
+}
+
+impl Drop for LoopbackCapture {
+  fn drop(&mut self) {
+    unsafe { self.client.Stop() }.ok();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    AudioFormat, LoopbackCapture, KSDATAFORMAT_SUBTYPE_IEEE_FLOAT, WAVE_FORMAT_EXTENSIBLE,
+  };
+  use std::time::Duration;
+  use windows::core::GUID;
+  use windows::Win32::Media::Audio::{WAVEFORMATEX, WAVEFORMATEXTENSIBLE, WAVEFORMATEXTENSIBLE_0};
+
+  #[test]
+  fn format() {
+    let extensible = |sub_format| WAVEFORMATEXTENSIBLE {
+      Format: WAVEFORMATEX {
+        wFormatTag: WAVE_FORMAT_EXTENSIBLE,
+        nChannels: 2,
+        nSamplesPerSec: 48000,
+        nAvgBytesPerSec: 48000 * 8,
+        nBlockAlign: 8,
+        wBitsPerSample: 32,
+        cbSize: 22,
+      },
+      Samples: WAVEFORMATEXTENSIBLE_0 {
+        wValidBitsPerSample: 32,
+      },
+      dwChannelMask: 3,
+      SubFormat: sub_format,
+    };
+    let format = AudioFormat::from(&extensible(KSDATAFORMAT_SUBTYPE_IEEE_FLOAT));
+    assert!(format.float);
+    assert_eq!(format.block_align, 8);
+    assert_eq!(format.duration(480), Duration::from_millis(10));
+
+    // 32-bit integer PCM
+    let pcm = GUID::from_u128(0x00000001_0000_0010_8000_00aa00389b71);
+    assert!(!AudioFormat::from(&extensible(pcm)).float);
+  }
+
+  #[test]
+  fn loopback() {
+    let mut capture = LoopbackCapture::new().unwrap();
+    let format = capture.format();
+    assert!(format.sample_rate > 0);
+    std::thread::sleep(Duration::from_millis(50));
+    // packets only arrive while something is playing
+    while let Some(buffer) = capture.read().unwrap() {
+      assert_eq!(
+        buffer.data.len(),
+        buffer.frames as usize * format.block_align as usize
+      );
+    }
+  }
+}
@@ -0,0 +1,153 @@
+//! Blend the last frames into one to smooth flickering content, e.g. for time-lapses and thumbnails.
+
+use crate::frame::Frame;
+use std::collections::VecDeque;
+
+/// The most frames which can be blended, so the weighted sums fit in `u32`.
+pub const MAX_BLEND_FRAMES: usize = 256;
+
+/// Blend each pushed frame with the frames before it by relative weights.
+///
+/// Frames must be 8-bit BGRA of the same size. A frame of another size or format
+/// drops the kept frames. Blending runs on the CPU in loops the compiler vectorizes.
+#[derive(Debug, Clone)]
+pub struct FrameBlender {
+  /// Weights from the newest frame.
+  weights: Vec<u16>,
+  /// Kept frames, the newest at the back.
+  frames: VecDeque<Frame>,
+  sums: Vec<u32>,
+}
+
+impl FrameBlender {
+  /// Average the last `frames` frames equally, at least 1 and at most [`MAX_BLEND_FRAMES`].
+  pub fn average(frames: usize) -> Self {
+    Self::with_weights(&vec![1; frames.clamp(1, MAX_BLEND_FRAMES)])
+  }
+
+  /// Blend the last frames by `weights` from the newest frame,
+  /// e.g. `[4, 2, 1]` favors recent frames. Weights after [`MAX_BLEND_FRAMES`] are ignored.
+  /// If all weights are zero the newest frame is used.
+  pub fn with_weights(weights: &[u16]) -> Self {
+    let mut weights = weights[..weights.len().min(MAX_BLEND_FRAMES)].to_vec();
+    if weights.iter().all(|&weight| weight == 0) {
+      weights = vec![1];
+    }
+    Self {
+      weights,
+      frames: VecDeque::new(),
+      sums: Vec::new(),
+    }
+  }
+
+  /// How many frames are kept.
+  pub fn len(&self) -> usize {
+    self.frames.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.frames.is_empty()
+  }
+
+  /// Drop the kept frames, e.g. after a scene change.
+  pub fn reset(&mut self) {
+    self.frames.clear();
+  }
+
+  /// Keep `frame` and return the blend of the kept frames, with the size, format and info of `frame`.
+  /// Until enough frames are kept, the weights of the missing frames are left out.
+  pub fn push(&mut self, frame: Frame) -> Frame {
+    if let Some(last) = self.frames.back() {
+      if (last.width, last.height, last.format) != (frame.width, frame.height, frame.format)
+        || last.buffer.len() != frame.buffer.len()
+      {
+        self.frames.clear();
+      }
+    }
+    let mut output = Frame {
+      buffer: Vec::new(),
+      width: frame.width,
+      height: frame.height,
+      info: frame.info,
+      format: frame.format,
+      provenance: frame.provenance,
+    };
+    self.frames.push_back(frame);
+    // the buffer of the evicted frame is reused for the output
+    if self.frames.len() > self.weights.len() {
+      output.buffer = self.frames.pop_front().unwrap().buffer;
+    }
+
+    let len = output.buffer.len().max(self.frames[0].buffer.len());
+    self.sums.clear();
+    self.sums.resize(len, 0);
+    let mut total = 0u32;
+    for (frame, &weight) in self.frames.iter().rev().zip(&self.weights) {
+      let weight = weight as u32;
+      total += weight;
+      for (sum, &byte) in self.sums.iter_mut().zip(&frame.buffer) {
+        *sum += byte as u32 * weight;
+      }
+    }
+    if total == 0 {
+      // only zero weights for the kept frames, use the newest frame
+      total = 1;
+      for (sum, &byte) in self
+        .sums
+        .iter_mut()
+        .zip(&self.frames.back().unwrap().buffer)
+      {
+        *sum = byte as u32;
+      }
+    }
+
+    output.buffer.clear();
+    output.buffer.extend(
+      self
+        .sums
+        .iter()
+        .map(|&sum| ((sum + total / 2) / total) as u8),
+    );
+    output
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::FrameBlender;
+  use crate::test_utils::generate;
+
+  #[test]
+  fn average() {
+    let mut blender = FrameBlender::average(2);
+    let first = blender.push(generate(2, 1, |_, _| [0, 100, 200]));
+    assert_eq!(first.buffer, [0, 100, 200, 0xFF, 0, 100, 200, 0xFF]);
+    let second = blender.push(generate(2, 1, |x, _| [100, 0, x as u8]));
+    assert_eq!(
+      second.as_pixels(),
+      [[50, 50, 100, 0xFF], [50, 50, 101, 0xFF]]
+    );
+    // the first frame is evicted
+    let third = blender.push(generate(2, 1, |_, _| [0, 0, 0]));
+    assert_eq!(third.as_pixels()[0], [50, 0, 0, 0xFF]);
+    assert_eq!(blender.len(), 2);
+
+    // a new size drops the kept frames
+    let resized = blender.push(generate(1, 1, |_, _| [10, 20, 30]));
+    assert_eq!(resized.buffer, [10, 20, 30, 0xFF]);
+    assert_eq!(blender.len(), 1);
+  }
+
+  #[test]
+  fn weights() {
+    let mut blender = FrameBlender::with_weights(&[3, 1]);
+    blender.push(generate(1, 1, |_, _| [0, 0, 0]));
+    let blended = blender.push(generate(1, 1, |_, _| [200, 40, 0]));
+    assert_eq!(blended.as_pixels()[0], [150, 30, 0, 0xFF]);
+
+    // the newest frame is used if its weight is zero and no other frame is kept
+    let mut blender = FrameBlender::with_weights(&[0, 1]);
+    let blended = blender.push(generate(1, 1, |_, _| [1, 2, 3]));
+    assert_eq!(blended.as_pixels()[0], [1, 2, 3, 0xFF]);
+  }
+}
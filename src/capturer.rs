@@ -1,4 +1,32 @@
+//! Capturers copy frames of a [`DuplicationContext`](crate::duplication_context::DuplicationContext)
+//! into a buffer and implement [`Capturer`](model::Capturer).
+//!
+//! They only use the public API of the core, so a capturer in another crate looks like them:
+//! acquire a frame with [`DuplicationContext::acquire`](crate::duplication_context::DuplicationContext::acquire),
+//! copy or process it with [`AcquiredFrame`](crate::acquired_frame::AcquiredFrame)
+//! or the copy methods of the context, release it as soon as possible,
+//! and implement [`Capturer`](model::Capturer) to work with code generic over capturers.
+
+#[cfg(feature = "threaded")]
+pub mod bus;
 pub mod custom;
+#[cfg(feature = "threaded")]
+pub mod history;
+#[cfg(feature = "threaded")]
+pub mod latest;
 pub mod model;
+pub mod observer;
+#[cfg(feature = "threaded")]
+pub mod queue;
+#[cfg(feature = "shared-memory")]
 pub mod shared;
+#[cfg(feature = "shared-memory")]
+pub mod shared_layout;
 pub mod simple;
+pub mod sliced;
+pub mod stats;
+#[cfg(feature = "threaded")]
+pub mod supervised;
+#[cfg(feature = "threaded")]
+pub mod synced;
+pub mod texture_history;
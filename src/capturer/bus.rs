@@ -0,0 +1,280 @@
+use crate::error::Error;
+use crate::model::Backpressure;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::System::Threading::{CreateEventW, ResetEvent, SetEvent};
+
+/// Items which the bus may drop or coalesce, e.g. frames but not status events.
+pub(crate) trait Droppable {
+  fn droppable(&self) -> bool;
+}
+
+struct State<T> {
+  items: VecDeque<T>,
+  /// The sender is dropped, no more items will arrive.
+  sender_closed: bool,
+  /// The receiver is dropped, items are discarded and blocked senders are released.
+  receiver_closed: bool,
+  dropped: u64,
+  /// Created by [`BusReceiver::event`].
+  event: Option<Event>,
+}
+
+impl<T> State<T> {
+  /// Signal the event while `recv` would return immediately.
+  fn update_event(&self) {
+    if let Some(event) = &self.event {
+      unsafe {
+        if self.items.is_empty() && !self.sender_closed {
+          ResetEvent(event.0);
+        } else {
+          SetEvent(event.0);
+        }
+      }
+    }
+  }
+}
+
+/// A manual-reset event, closed when dropped.
+struct Event(HANDLE);
+
+impl Drop for Event {
+  fn drop(&mut self) {
+    unsafe { CloseHandle(self.0) };
+  }
+}
+
+struct Bus<T> {
+  backpressure: Backpressure,
+  state: Mutex<State<T>>,
+  /// Notified when an item is pushed or the sender is closed.
+  readable: Condvar,
+  /// Notified when an item is popped or the receiver is closed.
+  writable: Condvar,
+}
+
+/// Create a single-producer single-consumer queue which applies `backpressure`
+/// to droppable items when the receiver falls behind.
+pub(crate) fn bus<T: Droppable>(backpressure: Backpressure) -> (BusSender<T>, BusReceiver<T>) {
+  let bus = Arc::new(Bus {
+    backpressure,
+    state: Mutex::new(State {
+      items: VecDeque::new(),
+      sender_closed: false,
+      receiver_closed: false,
+      dropped: 0,
+      event: None,
+    }),
+    readable: Condvar::new(),
+    writable: Condvar::new(),
+  });
+  (BusSender(bus.clone()), BusReceiver(bus))
+}
+
+pub(crate) struct BusSender<T>(Arc<Bus<T>>);
+
+impl<T: Droppable> BusSender<T> {
+  /// Push an item. Return `false` if the receiver is closed.
+  pub fn send(&self, item: T) -> bool {
+    let bus = &self.0;
+    let mut state = bus.state.lock().unwrap();
+    if state.receiver_closed {
+      return false;
+    }
+
+    if item.droppable() {
+      let queued = |state: &State<T>| state.items.iter().filter(|i| i.droppable()).count();
+      match bus.backpressure {
+        Backpressure::Block(capacity) => {
+          while !state.receiver_closed && queued(&state) >= capacity.max(1) {
+            state = bus.writable.wait(state).unwrap();
+          }
+          if state.receiver_closed {
+            return false;
+          }
+        }
+        Backpressure::DropOldest(capacity) => {
+          while queued(&state) >= capacity.max(1) {
+            let index = state.items.iter().position(|i| i.droppable()).unwrap();
+            state.items.remove(index);
+            state.dropped += 1;
+          }
+        }
+        Backpressure::DropNewest(capacity) => {
+          if queued(&state) >= capacity.max(1) {
+            state.dropped += 1;
+            return true;
+          }
+        }
+        Backpressure::Latest => {
+          let before = state.items.len();
+          state.items.retain(|i| !i.droppable());
+          state.dropped += (before - state.items.len()) as u64;
+        }
+      }
+    }
+
+    state.items.push_back(item);
+    state.update_event();
+    bus.readable.notify_one();
+    true
+  }
+}
+
+impl<T> Drop for BusSender<T> {
+  fn drop(&mut self) {
+    let mut state = self.0.state.lock().unwrap();
+    state.sender_closed = true;
+    state.update_event();
+    self.0.readable.notify_all();
+  }
+}
+
+pub(crate) struct BusReceiver<T>(Arc<Bus<T>>);
+
+impl<T> BusReceiver<T> {
+  /// Pop an item, waiting at most `timeout` if it is `Some`.
+  /// Return `Ok(None)` on timeout and `Err(())` if the sender is closed and the queue is empty.
+  pub fn recv(&self, timeout: Option<Duration>) -> Result<Option<T>, ()> {
+    let bus = &self.0;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut state = bus.state.lock().unwrap();
+    loop {
+      if let Some(item) = state.items.pop_front() {
+        state.update_event();
+        bus.writable.notify_one();
+        return Ok(Some(item));
+      }
+      if state.sender_closed {
+        return Err(());
+      }
+      state = match deadline {
+        None => bus.readable.wait(state).unwrap(),
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return Ok(None);
+          }
+          bus.readable.wait_timeout(state, deadline - now).unwrap().0
+        }
+      };
+    }
+  }
+
+  /// How many items are dropped or coalesced by the backpressure policy.
+  pub fn dropped(&self) -> u64 {
+    self.0.state.lock().unwrap().dropped
+  }
+
+  /// Get an event which is signaled while [`BusReceiver::recv`] would return immediately,
+  /// i.e. items are queued or the sender is closed. The event is created on the first call
+  /// and closed with the bus.
+  pub fn event(&self) -> crate::model::Result<HANDLE> {
+    let mut state = self.0.state.lock().unwrap();
+    if state.event.is_none() {
+      let handle = unsafe { CreateEventW(None, true, false, None) }
+        .map_err(|e| Error::windows("CreateEventW", e))?;
+      state.event = Some(Event(handle));
+      state.update_event();
+    }
+    Ok(state.event.as_ref().unwrap().0)
+  }
+
+  /// Discard queued items and release a blocked sender.
+  pub fn close(&self) {
+    let mut state = self.0.state.lock().unwrap();
+    state.receiver_closed = true;
+    state.items.clear();
+    state.update_event();
+    self.0.writable.notify_all();
+  }
+}
+
+impl<T> Drop for BusReceiver<T> {
+  fn drop(&mut self) {
+    self.close();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{bus, Droppable};
+  use crate::model::Backpressure;
+  use std::{thread, time::Duration};
+  use windows::Win32::Foundation::{WAIT_OBJECT_0, WAIT_TIMEOUT};
+  use windows::Win32::System::Threading::WaitForSingleObject;
+
+  /// Positive numbers are droppable frames, others are status events.
+  impl Droppable for i32 {
+    fn droppable(&self) -> bool {
+      *self > 0
+    }
+  }
+
+  fn drain(backpressure: Backpressure) -> (Vec<i32>, u64) {
+    let (sender, receiver) = bus(backpressure);
+    for item in [1, 2, -1, 3, 4] {
+      assert!(sender.send(item));
+    }
+    drop(sender);
+    let mut items = Vec::new();
+    while let Ok(Some(item)) = receiver.recv(None) {
+      items.push(item);
+    }
+    (items, receiver.dropped())
+  }
+
+  #[test]
+  fn drop_policies() {
+    assert_eq!(drain(Backpressure::DropOldest(2)), (vec![-1, 3, 4], 2));
+    assert_eq!(drain(Backpressure::DropNewest(2)), (vec![1, 2, -1], 2));
+    assert_eq!(drain(Backpressure::Latest), (vec![-1, 4], 3));
+  }
+
+  #[test]
+  fn block() {
+    let (sender, receiver) = bus(Backpressure::Block(1));
+    let producer = thread::spawn(move || (1..=3).all(|i| sender.send(i)));
+    thread::sleep(Duration::from_millis(100));
+    for i in 1..=3 {
+      assert_eq!(receiver.recv(None), Ok(Some(i)));
+    }
+    assert!(producer.join().unwrap());
+    assert_eq!(receiver.recv(None), Err(()));
+    assert_eq!(receiver.dropped(), 0);
+
+    // closing the receiver releases a blocked sender
+    let (sender, receiver) = bus(Backpressure::Block(1));
+    let producer = thread::spawn(move || (1..=3).all(|i| sender.send(i)));
+    thread::sleep(Duration::from_millis(100));
+    receiver.close();
+    assert!(!producer.join().unwrap());
+    assert_eq!(receiver.recv(Some(Duration::from_millis(10))), Err(()));
+  }
+
+  #[test]
+  fn event() {
+    let (sender, receiver) = bus(Backpressure::Latest);
+    let event = receiver.event().unwrap();
+    assert_eq!(receiver.event().unwrap(), event);
+    assert_eq!(unsafe { WaitForSingleObject(event, 0) }, WAIT_TIMEOUT);
+
+    let producer = thread::spawn(move || {
+      thread::sleep(Duration::from_millis(100));
+      sender.send(1);
+      sender
+    });
+    // signaled until the queue is drained
+    assert_eq!(unsafe { WaitForSingleObject(event, 5000) }, WAIT_OBJECT_0);
+    assert_eq!(unsafe { WaitForSingleObject(event, 0) }, WAIT_OBJECT_0);
+    let sender = producer.join().unwrap();
+    assert_eq!(receiver.recv(Some(Duration::ZERO)), Ok(Some(1)));
+    assert_eq!(unsafe { WaitForSingleObject(event, 0) }, WAIT_TIMEOUT);
+
+    // and after the sender is closed
+    drop(sender);
+    assert_eq!(unsafe { WaitForSingleObject(event, 0) }, WAIT_OBJECT_0);
+  }
+}
@@ -1,8 +1,10 @@
-use super::model::Capturer;
+use super::model::{self, Capturer, CapturerBuffer, MemoryUsage, PointerShapeStats};
+use super::observer::{CaptureObserver, Observers};
 use crate::duplication_context::DuplicationContext;
 use crate::error::Error;
-use crate::model::Result;
+use crate::model::{CaptureArgs, Result};
 use crate::utils::OutDuplDescExt;
+use std::sync::Arc;
 use windows::Win32::Graphics::Direct3D11::D3D11_TEXTURE2D_DESC;
 use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_POINTER_SHAPE_INFO;
 use windows::Win32::Graphics::{
@@ -18,6 +20,12 @@ pub struct CustomCapturer<'a> {
   texture_desc: D3D11_TEXTURE2D_DESC,
   pointer_shape_buffer: Vec<u8>,
   pointer_shape_buffer_size: usize,
+  pointer_shape_stats: PointerShapeStats,
+  observers: Observers,
+  auto_grow: bool,
+  apply_move_rects: bool,
+  /// Copy the whole next frame even if move rects are applied, since the buffer missed a frame.
+  full_copy: bool,
 }
 
 impl<'a> CustomCapturer<'a> {
@@ -34,6 +42,11 @@ impl<'a> CustomCapturer<'a> {
       texture_desc,
       pointer_shape_buffer: Vec::new(),
       pointer_shape_buffer_size: 0,
+      pointer_shape_stats: PointerShapeStats::default(),
+      observers: Observers::default(),
+      auto_grow: false,
+      apply_move_rects: false,
+      full_copy: true,
     }
   }
 
@@ -41,6 +54,22 @@ impl<'a> CustomCapturer<'a> {
     let (texture, _desc, texture_desc) = ctx.create_readable_texture()?;
     Ok(Self::with_texture(ctx, buffer, texture, texture_desc))
   }
+
+  /// Grow the buffer and the texture if auto grow is enabled.
+  fn grow_buffer(&mut self) -> Result<()> {
+    if !self.auto_grow {
+      return Ok(());
+    }
+    let buffer = &mut self.buffer;
+    self.full_copy |= model::auto_grow(
+      self.ctx,
+      &mut self.texture,
+      &mut self.texture_desc,
+      &self.observers,
+      |_, len| buffer.try_fit(len),
+    )?;
+    Ok(())
+  }
 }
 
 impl Capturer for CustomCapturer<'_> {
@@ -53,11 +82,11 @@ impl Capturer for CustomCapturer<'_> {
   }
 
   fn buffer(&self) -> &[u8] {
-    &self.buffer
+    self.buffer
   }
 
   fn buffer_mut(&mut self) -> &mut [u8] {
-    &mut self.buffer
+    self.buffer
   }
 
   fn check_buffer(&self) -> Result<()> {
@@ -72,37 +101,120 @@ impl Capturer for CustomCapturer<'_> {
     &self.pointer_shape_buffer[..self.pointer_shape_buffer_size]
   }
 
+  fn reserve_pointer_shape_buffer(&mut self, size: usize) {
+    if self.pointer_shape_buffer.len() < size {
+      self.pointer_shape_buffer.resize(size, 0);
+    }
+  }
+
+  fn pointer_shape_stats(&self) -> PointerShapeStats {
+    self.pointer_shape_stats
+  }
+
+  fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      // the borrowed slice can't hold more than its length
+      buffers: self.buffer.len(),
+      pointer_shape_buffer: self.pointer_shape_buffer.capacity(),
+      staging_textures: MemoryUsage::texture_bytes(&self.texture_desc),
+      pools: 0,
+    }
+  }
+
+  fn add_observer(&mut self, observer: Arc<dyn CaptureObserver>) {
+    self.observers.add(observer);
+  }
+
+  fn set_auto_grow(&mut self, auto_grow: bool) {
+    self.auto_grow = auto_grow;
+  }
+
+  fn set_apply_move_rects(&mut self, apply_move_rects: bool) {
+    self.apply_move_rects = apply_move_rects;
+    self.full_copy = true;
+  }
+
   fn capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
-    self.ctx.capture(
-      self.buffer.as_mut_ptr(),
-      self.buffer.len(),
-      &self.texture,
-      &self.texture_desc,
-    )
+    self.grow_buffer()?;
+    let result = if self.apply_move_rects && !self.full_copy {
+      self
+        .ctx
+        .update_slice(
+          self.buffer.as_bytes_mut(),
+          &self.texture,
+          &self.texture_desc,
+          None,
+        )
+        .map(|(info, _)| info)
+    } else {
+      self.ctx.capture_to_slice(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+      )
+    };
+    // a failed capture may have acquired a frame which isn't in the buffer
+    self.full_copy = result.is_err();
+    self.observers.notify(self.ctx.id(), result.as_ref());
+    result
   }
 
   fn safe_capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
-    self.check_buffer()?;
+    if !self.auto_grow {
+      self.check_buffer()?;
+    }
     self.capture()
   }
 
+  fn capture_with(&mut self, args: &CaptureArgs) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    self.grow_buffer()?;
+    let result = self.ctx.capture_to_slice_with(
+      self.buffer.as_bytes_mut(),
+      &self.texture,
+      &self.texture_desc,
+      args,
+    );
+    // the frame is only copied to the region, and the pointer may be drawn
+    self.full_copy = true;
+    self.observers.notify(self.ctx.id(), result.as_ref());
+    result
+  }
+
   fn capture_with_pointer_shape(
     &mut self,
   ) -> Result<(
     DXGI_OUTDUPL_FRAME_INFO,
     Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )> {
-    let (frame_info, pointer_shape_info) = self.ctx.capture_with_pointer_shape(
-      self.buffer.as_mut_ptr(),
-      self.buffer.len(),
-      &self.texture,
-      &self.texture_desc,
-      &mut self.pointer_shape_buffer,
-    )?;
+    self.grow_buffer()?;
+    let buffer_len = self.pointer_shape_buffer.len();
+    let result = if self.apply_move_rects && !self.full_copy {
+      self.ctx.update_slice(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+        Some(&mut self.pointer_shape_buffer),
+      )
+    } else {
+      self.ctx.capture_to_slice_with_pointer_shape(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+        &mut self.pointer_shape_buffer,
+      )
+    };
+    self.full_copy = result.is_err();
+    self
+      .observers
+      .notify(self.ctx.id(), result.as_ref().map(|(info, _)| info));
+    let (frame_info, pointer_shape_info) = result?;
 
     if pointer_shape_info.is_some() {
       // record the pointer shape buffer size
       self.pointer_shape_buffer_size = frame_info.PointerShapeBufferSize as usize;
+      self
+        .pointer_shape_stats
+        .record(buffer_len, self.pointer_shape_buffer_size);
     }
 
     Ok((frame_info, pointer_shape_info))
@@ -114,20 +226,25 @@ impl Capturer for CustomCapturer<'_> {
     DXGI_OUTDUPL_FRAME_INFO,
     Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )> {
-    self.check_buffer()?;
+    if !self.auto_grow {
+      self.check_buffer()?;
+    }
     self.capture_with_pointer_shape()
   }
 }
 
 impl DuplicationContext {
-  pub fn custom_capturer<'a>(&'a self, buffer: &'a mut [u8]) -> Result<CustomCapturer> {
+  pub fn custom_capturer<'a>(&'a self, buffer: &'a mut [u8]) -> Result<CustomCapturer<'a>> {
     CustomCapturer::<'a>::new(self, buffer)
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use std::{thread, time::Duration};
+  use std::{
+    thread,
+    time::{Duration, Instant},
+  };
 
   use crate::{
     capturer::model::Capturer,
@@ -153,14 +270,7 @@ mod tests {
 
     let buffer = capturer.buffer();
     // ensure buffer not all zero
-    let mut all_zero = true;
-    for i in 0..buffer.len() {
-      if buffer[i] != 0 {
-        all_zero = false;
-        break;
-      }
-    }
-    assert!(!all_zero);
+    assert!(buffer.iter().any(|&b| b != 0));
 
     // sleep for a while before capture to wait system to update the mouse
     thread::sleep(Duration::from_millis(1000));
@@ -171,13 +281,21 @@ mod tests {
     assert!(pointer_shape_info.is_some());
     let pointer_shape_data = capturer.pointer_shape_buffer();
     // make sure pointer shape buffer is not all zero
-    let mut all_zero = true;
-    for i in 0..pointer_shape_data.len() {
-      if pointer_shape_data[i] != 0 {
-        all_zero = false;
-        break;
-      }
-    }
-    assert!(!all_zero);
+    assert!(pointer_shape_data.iter().any(|&b| b != 0));
+  }
+
+  #[test]
+  fn warm_up() {
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    let mut buffer = vec![0u8; ctx.dxgi_outdupl_desc().calc_buffer_size()];
+    let mut capturer = ctx.custom_capturer(&mut buffer).unwrap();
+
+    // no sleep before the first capture
+    let info = capturer
+      .warm_up(Instant::now() + Duration::from_secs(1))
+      .unwrap();
+    assert!(info.desktop_updated());
+    assert!(capturer.buffer().iter().any(|&b| b != 0));
   }
 }
@@ -0,0 +1,214 @@
+use super::model::MemoryUsage;
+use crate::frame::Frame;
+use crate::model::Provenance;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use windows::Win32::Graphics::Dxgi::{Common::DXGI_FORMAT, DXGI_OUTDUPL_FRAME_INFO};
+
+/// How many buffers of evicted frames are kept for reuse.
+const POOL_SIZE: usize = 4;
+
+/// Keep the most recent frames with their capture time, e.g. to clip the last 30 seconds.
+///
+/// Frames are evicted when there are more than `max_frames` of them,
+/// or when they are older than `max_age` compared to the newest frame.
+/// Buffers of evicted frames are reused by [`HistoryBuffer::record`].
+#[derive(Debug, Clone)]
+pub struct HistoryBuffer {
+  frames: VecDeque<(Instant, Frame)>,
+  pool: Vec<Vec<u8>>,
+  max_frames: Option<usize>,
+  max_age: Option<Duration>,
+}
+
+impl HistoryBuffer {
+  /// Keep at most `max_frames` frames, at least 1.
+  pub fn with_frames(max_frames: usize) -> Self {
+    Self::new(Some(max_frames.max(1)), None)
+  }
+
+  /// Keep the frames captured in the last `max_age`.
+  pub fn with_duration(max_age: Duration) -> Self {
+    Self::new(None, Some(max_age))
+  }
+
+  fn new(max_frames: Option<usize>, max_age: Option<Duration>) -> Self {
+    Self {
+      frames: VecDeque::new(),
+      pool: Vec::new(),
+      max_frames,
+      max_age,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.frames.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.frames.is_empty()
+  }
+
+  /// Report the memory of the kept frames and the buffers kept for reuse.
+  pub fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      buffers: self
+        .frames
+        .iter()
+        .map(|(_, frame)| frame.buffer.capacity())
+        .sum(),
+      pools: self.pool.iter().map(Vec::capacity).sum(),
+      ..Default::default()
+    }
+  }
+
+  /// The time between the oldest and the newest frame.
+  pub fn duration(&self) -> Duration {
+    match (self.frames.front(), self.frames.back()) {
+      (Some((first, _)), Some((last, _))) => last.duration_since(*first),
+      _ => Duration::ZERO,
+    }
+  }
+
+  /// Add a frame captured at `time`. Frames must be added in time order.
+  pub fn push(&mut self, time: Instant, frame: Frame) {
+    self.frames.push_back((time, frame));
+    self.evict(time);
+  }
+
+  /// Copy a captured frame, e.g. from [`Capturer::buffer`](super::model::Capturer::buffer),
+  /// into a reused buffer and add it like [`HistoryBuffer::push`].
+  /// `provenance` is usually from the [`ProvenanceStamp`](crate::frame::ProvenanceStamp) of the capturer.
+  #[allow(clippy::too_many_arguments)]
+  pub fn record(
+    &mut self,
+    time: Instant,
+    width: u32,
+    height: u32,
+    info: DXGI_OUTDUPL_FRAME_INFO,
+    format: DXGI_FORMAT,
+    provenance: Option<Provenance>,
+    pixels: &[u8],
+  ) {
+    let mut buffer = self.pool.pop().unwrap_or_default();
+    buffer.clear();
+    buffer.extend_from_slice(pixels);
+    self.push(
+      time,
+      Frame {
+        buffer,
+        width,
+        height,
+        info,
+        format,
+        provenance,
+      },
+    );
+  }
+
+  /// Return the newest frame captured at or before `time` with its capture time.
+  pub fn frame_at(&self, time: Instant) -> Option<(Instant, &Frame)> {
+    let index = self.frames.partition_point(|(t, _)| *t <= time);
+    index
+      .checked_sub(1)
+      .map(|i| (self.frames[i].0, &self.frames[i].1))
+  }
+
+  /// Clone the frames captured between `start` and `end`, inclusive, from oldest to newest.
+  pub fn snapshot_range(&self, start: Instant, end: Instant) -> Vec<(Instant, Frame)> {
+    let first = self.frames.partition_point(|(t, _)| *t < start);
+    let last = self.frames.partition_point(|(t, _)| *t <= end);
+    self.frames.range(first..last.max(first)).cloned().collect()
+  }
+
+  /// Remove all frames, keeping some buffers for reuse.
+  pub fn clear(&mut self) {
+    while let Some((_, frame)) = self.frames.pop_front() {
+      self.recycle(frame);
+    }
+  }
+
+  fn evict(&mut self, now: Instant) {
+    while let Some((time, _)) = self.frames.front() {
+      let too_many = self.max_frames.is_some_and(|max| self.frames.len() > max);
+      let too_old = self
+        .max_age
+        .is_some_and(|max| now.saturating_duration_since(*time) > max);
+      if !too_many && !too_old {
+        break;
+      }
+      let (_, frame) = self.frames.pop_front().unwrap();
+      self.recycle(frame);
+    }
+  }
+
+  fn recycle(&mut self, frame: Frame) {
+    if self.pool.len() < POOL_SIZE {
+      self.pool.push(frame.buffer);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::HistoryBuffer;
+  use crate::test_utils::filled;
+  use std::time::{Duration, Instant};
+  use windows::Win32::Graphics::Dxgi::{
+    Common::DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_OUTDUPL_FRAME_INFO,
+  };
+
+  #[test]
+  fn max_frames() {
+    let start = Instant::now();
+    let mut history = HistoryBuffer::with_frames(2);
+    assert!(history.is_empty());
+    for i in 0..3 {
+      history.push(start + Duration::from_secs(i as u64), filled(1, 1, i));
+    }
+    assert_eq!(history.len(), 2);
+    assert_eq!(history.duration(), Duration::from_secs(1));
+
+    // the evicted buffer is reused
+    let evicted = history.pool[0].as_ptr();
+    history.record(
+      start + Duration::from_secs(3),
+      1,
+      1,
+      DXGI_OUTDUPL_FRAME_INFO::default(),
+      DXGI_FORMAT_B8G8R8A8_UNORM,
+      None,
+      &[3; 4],
+    );
+    let (_, newest) = history.frame_at(start + Duration::from_secs(3)).unwrap();
+    assert_eq!(newest.buffer, [3; 4]);
+    assert_eq!(newest.buffer.as_ptr(), evicted);
+    // the frame evicted by the record is pooled
+    let usage = history.memory_usage();
+    assert_eq!((usage.buffers, usage.pools), (8, 4));
+  }
+
+  #[test]
+  fn max_age() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let mut history = HistoryBuffer::with_duration(Duration::from_millis(100));
+    for i in 0..5 {
+      history.push(at(i * 40), filled(1, 1, i as u8));
+    }
+    // frames at 0 and 40ms are older than 100ms compared to 160ms
+    assert_eq!(history.len(), 3);
+    assert!(history.frame_at(at(79)).is_none());
+    assert_eq!(history.frame_at(at(100)).unwrap().0, at(80));
+    assert_eq!(history.frame_at(at(1000)).unwrap().1.buffer, [4; 4]);
+
+    let range = history.snapshot_range(at(80), at(120));
+    assert_eq!(range.len(), 2);
+    assert_eq!(range[1].1.buffer, [3; 4]);
+    assert!(history.snapshot_range(at(130), at(150)).is_empty());
+    assert!(history.snapshot_range(at(150), at(130)).is_empty());
+
+    history.clear();
+    assert!(history.is_empty());
+  }
+}
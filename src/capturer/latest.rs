@@ -0,0 +1,212 @@
+use super::model::MemoryUsage;
+use crate::frame::Frame;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const SLOT_COUNT: usize = 3;
+const NO_SLOT: usize = usize::MAX;
+
+struct Slot {
+  frame: UnsafeCell<Option<Frame>>,
+  /// The sequence number of the frame in this slot.
+  seq: AtomicU64,
+  /// How many readers are reading this slot.
+  readers: AtomicUsize,
+  /// The buffer capacity of the frame in this slot, readable while the slot is written.
+  bytes: AtomicUsize,
+}
+
+struct Shared {
+  slots: [Slot; SLOT_COUNT],
+  /// The index of the slot with the most recent complete frame, or `NO_SLOT`.
+  latest: AtomicUsize,
+}
+
+// The publisher only writes a slot which is not the latest and has no readers,
+// readers only read the latest slot after registering themselves, see `LatestFrame::read`.
+unsafe impl Sync for Shared {}
+unsafe impl Send for Shared {}
+
+/// A triple-buffered slot holding the most recent frame.
+///
+/// One [`FramePublisher`] publishes frames and any number of readers grab the most recent one.
+/// Neither side blocks or takes a lock, older frames are simply overwritten,
+/// which suits previews and overlays where queueing frames only adds latency.
+#[derive(Clone)]
+pub struct LatestFrame {
+  shared: Arc<Shared>,
+}
+
+/// The writing side of a [`LatestFrame`], created together with it.
+pub struct FramePublisher {
+  shared: Arc<Shared>,
+  seq: u64,
+}
+
+impl LatestFrame {
+  pub fn new() -> (FramePublisher, LatestFrame) {
+    let shared = Arc::new(Shared {
+      slots: std::array::from_fn(|_| Slot {
+        frame: UnsafeCell::new(None),
+        seq: AtomicU64::new(0),
+        readers: AtomicUsize::new(0),
+        bytes: AtomicUsize::new(0),
+      }),
+      latest: AtomicUsize::new(NO_SLOT),
+    });
+    (
+      FramePublisher {
+        shared: shared.clone(),
+        seq: 0,
+      },
+      LatestFrame { shared },
+    )
+  }
+
+  /// Return the sequence number of the most recent frame, starting from 1.
+  /// Return `0` if nothing is published yet.
+  pub fn seq(&self) -> u64 {
+    match self.shared.latest.load(Ordering::SeqCst) {
+      NO_SLOT => 0,
+      index => self.shared.slots[index].seq.load(Ordering::SeqCst),
+    }
+  }
+
+  /// Call `f` with the most recent frame and its sequence number.
+  /// Return `None` if nothing is published yet.
+  ///
+  /// The slot is held while `f` runs, keep it short so the publisher always finds a free slot.
+  pub fn read<R>(&self, f: impl FnOnce(u64, &Frame) -> R) -> Option<R> {
+    loop {
+      let index = self.shared.latest.load(Ordering::SeqCst);
+      if index == NO_SLOT {
+        return None;
+      }
+      let slot = &self.shared.slots[index];
+      slot.readers.fetch_add(1, Ordering::SeqCst);
+      // the publisher may have reused the slot before we registered, try again
+      if self.shared.latest.load(Ordering::SeqCst) != index {
+        slot.readers.fetch_sub(1, Ordering::SeqCst);
+        continue;
+      }
+      let result = unsafe { &*slot.frame.get() }
+        .as_ref()
+        .map(|frame| f(slot.seq.load(Ordering::SeqCst), frame));
+      slot.readers.fetch_sub(1, Ordering::SeqCst);
+      return result;
+    }
+  }
+
+  /// Report the memory of the frames in all slots, including the buffers kept for reuse.
+  pub fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      buffers: self
+        .shared
+        .slots
+        .iter()
+        .map(|slot| slot.bytes.load(Ordering::SeqCst))
+        .sum(),
+      ..Default::default()
+    }
+  }
+
+  /// Clone the most recent frame. Return `None` if nothing is published yet.
+  pub fn get(&self) -> Option<Frame> {
+    self.read(|_, frame| frame.clone())
+  }
+}
+
+impl FramePublisher {
+  /// Write a frame in place and publish it.
+  /// `f` receives the frame of a free slot, which is `None` before the slot is first used,
+  /// so buffers can be reused without allocation.
+  ///
+  /// Return `false` and skip the frame if every other slot is being read.
+  pub fn publish_with(&mut self, f: impl FnOnce(&mut Option<Frame>)) -> bool {
+    let latest = self.shared.latest.load(Ordering::SeqCst);
+    let Some(index) = (0..SLOT_COUNT).find(|&index| {
+      index != latest && self.shared.slots[index].readers.load(Ordering::SeqCst) == 0
+    }) else {
+      return false;
+    };
+
+    // no reader can enter this slot until it is published as the latest
+    let slot = &self.shared.slots[index];
+    let frame = unsafe { &mut *slot.frame.get() };
+    f(frame);
+    slot.bytes.store(
+      frame.as_ref().map_or(0, |frame| frame.buffer.capacity()),
+      Ordering::SeqCst,
+    );
+    self.seq += 1;
+    slot.seq.store(self.seq, Ordering::SeqCst);
+    self.shared.latest.store(index, Ordering::SeqCst);
+    true
+  }
+
+  /// Publish a frame. See [`FramePublisher::publish_with`].
+  pub fn publish(&mut self, frame: Frame) -> bool {
+    self.publish_with(|slot| *slot = Some(frame))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::LatestFrame;
+  use crate::test_utils::filled;
+  use std::thread;
+
+  #[test]
+  fn latest_frame() {
+    let (mut publisher, latest) = LatestFrame::new();
+    assert_eq!(latest.seq(), 0);
+    assert!(latest.get().is_none());
+
+    assert!(publisher.publish(filled(1, 1, 1)));
+    assert!(publisher.publish(filled(1, 1, 2)));
+    assert_eq!(latest.seq(), 2);
+    assert_eq!(latest.get().unwrap().buffer, [2; 4]);
+
+    // reuse the buffer of a free slot
+    assert!(publisher.publish_with(|slot| slot.as_mut().unwrap().buffer.fill(3)));
+    assert_eq!(
+      latest.read(|seq, frame| (seq, frame.buffer[0])),
+      Some((3, 3))
+    );
+    assert_eq!(latest.memory_usage().buffers, 8);
+  }
+
+  #[test]
+  fn concurrent_readers() {
+    let (mut publisher, latest) = LatestFrame::new();
+    let readers: Vec<_> = (0..4)
+      .map(|_| {
+        let latest = latest.clone();
+        thread::spawn(move || {
+          let mut last_seq = 0;
+          while last_seq < 1000 {
+            if let Some((seq, complete)) = latest.read(|seq, frame| {
+              // every byte is written in one publish, a torn frame would mix values
+              (seq, frame.buffer.iter().all(|&b| b == frame.buffer[0]))
+            }) {
+              assert!(complete);
+              assert!(seq >= last_seq);
+              last_seq = seq;
+            }
+          }
+        })
+      })
+      .collect();
+
+    let mut published = 0;
+    while published < 1000 {
+      if publisher.publish(filled(16, 16, (published % 256) as u8)) {
+        published += 1;
+      }
+    }
+    for reader in readers {
+      reader.join().unwrap();
+    }
+  }
+}
@@ -1,6 +1,17 @@
-use crate::model::Result;
+use super::observer::{CaptureObserver, Observers};
+use crate::color;
+use crate::duplication_context::DuplicationContext;
+use crate::error::Error;
+use crate::model::{CaptureArgs, Rect, Result};
+use crate::utils::{FormatExt, FrameInfoExt, OutDuplDescExt};
+use std::iter::Sum;
+use std::ops::Add;
+use std::sync::Arc;
+use std::time::Instant;
+use windows::Win32::Graphics::Direct3D11::{ID3D11Texture2D, D3D11_TEXTURE2D_DESC};
 use windows::Win32::Graphics::Dxgi::{
-  DXGI_OUTDUPL_DESC, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_POINTER_SHAPE_INFO, DXGI_OUTPUT_DESC,
+  DXGI_ERROR_WAIT_TIMEOUT, DXGI_OUTDUPL_DESC, DXGI_OUTDUPL_FRAME_INFO,
+  DXGI_OUTDUPL_POINTER_SHAPE_INFO, DXGI_OUTPUT_DESC,
 };
 
 /// Capturer is stateful, it holds a buffer of the last captured frame.
@@ -22,9 +33,81 @@ pub trait Capturer {
   /// Check buffer size.
   fn check_buffer(&self) -> Result<()>;
 
+  /// Get the width and height of captured frames, swapped if the screen is rotated by 90 or 270 degrees.
+  fn frame_size(&self) -> Result<(u32, u32)> {
+    let mode = self.dxgi_outdupl_desc().ModeDesc;
+    let rotation = self.dxgi_output_desc()?.Rotation.0;
+    if rotation == 2 || rotation == 4 {
+      Ok((mode.Height, mode.Width))
+    } else {
+      Ok((mode.Width, mode.Height))
+    }
+  }
+
+  /// Get the `[b, g, r, a]` pixel at (`x`, `y`) of the last captured frame,
+  /// or `None` if it is outside the frame.
+  /// Return an error if the captured format is not 8-bit BGRA.
+  fn pixel_at(&self, x: u32, y: u32) -> Result<Option<[u8; 4]>> {
+    color::check_bgra8(self.dxgi_outdupl_desc().ModeDesc.Format, "read pixels")?;
+    let (width, height) = self.frame_size()?;
+    if y >= height {
+      return Ok(None);
+    }
+    Ok(color::pixel_at(self.buffer(), width, x, y))
+  }
+
+  /// Average the pixels of `rect` of the last captured frame as `[b, g, r, a]`,
+  /// or `None` if `rect` is outside the frame.
+  /// Return an error if the captured format is not 8-bit BGRA.
+  fn average_color(&self, rect: &Rect) -> Result<Option<[u8; 4]>> {
+    color::check_bgra8(self.dxgi_outdupl_desc().ModeDesc.Format, "average pixels")?;
+    let (width, height) = self.frame_size()?;
+    // the buffer may be larger than the frame, e.g. after auto grow
+    let len = (width as usize * height as usize * 4).min(self.buffer().len());
+    Ok(color::average_color(&self.buffer()[..len], width, rect, 1))
+  }
+
   /// Get the buffer of the captured pointer shape.
   fn pointer_shape_buffer(&self) -> &[u8];
 
+  /// Allocate the pointer shape buffer for shapes of at least `size` bytes,
+  /// e.g. [`MAX_POINTER_SHAPE_SIZE`], so capturing doesn't allocate when the pointer shape changes.
+  /// Does nothing by default.
+  fn reserve_pointer_shape_buffer(&mut self, _size: usize) {}
+
+  /// Report how the pointer shape buffer was reused or grown by captures.
+  /// Nothing is recorded by default.
+  fn pointer_shape_stats(&self) -> PointerShapeStats {
+    PointerShapeStats::default()
+  }
+
+  /// Report the memory held by this capturer, e.g. to cap the memory of many capturers.
+  /// By default only the buffers are reported, by their length.
+  fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      buffers: self.buffer().len(),
+      pointer_shape_buffer: self.pointer_shape_buffer().len(),
+      ..Default::default()
+    }
+  }
+
+  /// Register an observer notified on every capture of this capturer.
+  /// Observers are ignored by default.
+  fn add_observer(&mut self, _observer: Arc<dyn CaptureObserver>) {}
+
+  /// Grow the buffer and the readable texture before each capture if they are too small,
+  /// e.g. after a resolution increase, instead of failing the capture. Disabled by default.
+  /// Growing fails if the buffer doesn't support [`CapturerBuffer::try_resize`].
+  /// Capturers which can't grow ignore this by default.
+  fn set_auto_grow(&mut self, _auto_grow: bool) {}
+
+  /// Update the buffer with the move rects and dirty rects of each frame
+  /// instead of copying the whole frame, see
+  /// [`AcquiredFrame::update_slice`](crate::acquired_frame::AcquiredFrame::update_slice).
+  /// Disabled by default. The buffer must not be modified between captures.
+  /// Capturers which can't apply move rects ignore this by default.
+  fn set_apply_move_rects(&mut self, _apply_move_rects: bool) {}
+
   /// Capture the screen and return the frame info.
   /// The pixel data is stored in the `buffer`.
   fn capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO>;
@@ -33,6 +116,42 @@ pub trait Capturer {
   /// The pixel data is stored in the `buffer`.
   fn safe_capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO>;
 
+  /// Capture once with per-call overrides, without changing the configuration of the capturer,
+  /// e.g. a short timeout and a region for thumbnails and the pointer for full grabs.
+  /// The buffer size is checked and move rects are not applied,
+  /// the next capture applying move rects copies the whole frame instead.
+  ///
+  /// By default only the default arguments are supported, which capture like [`Capturer::safe_capture`].
+  fn capture_with(&mut self, args: &CaptureArgs) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    if *args != CaptureArgs::default() {
+      return Err(Error::new(
+        "Capture arguments are not supported by this capturer",
+      ));
+    }
+    self.safe_capture()
+  }
+
+  /// Capture until a frame with a desktop image arrives, so the `buffer` holds a valid first frame
+  /// instead of sleeping before the first capture. Frames which only update the pointer and timeouts are skipped.
+  ///
+  /// Fail if no desktop image arrives before `deadline`,
+  /// which may be exceeded by the timeout of the duplication context.
+  fn warm_up(&mut self, deadline: Instant) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    loop {
+      match self.safe_capture() {
+        Ok(info) if info.desktop_updated() => return Ok(info),
+        Ok(_) => {}
+        Err(e) if e.windows.as_ref().map(|e| e.code()) == Some(DXGI_ERROR_WAIT_TIMEOUT) => {}
+        Err(e) => return Err(e),
+      }
+      if Instant::now() >= deadline {
+        return Err(Error::new(
+          "No desktop image arrived before the warm-up deadline",
+        ));
+      }
+    }
+  }
+
   /// Capture the screen and return the frame info.
   /// The pixel data is stored in the `buffer`.
   /// If mouse is updated, the `Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>` is Some.
@@ -55,3 +174,263 @@ pub trait Capturer {
     Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )>;
 }
+
+/// The largest pointer shape in bytes, a 256x256 color pointer.
+pub const MAX_POINTER_SHAPE_SIZE: usize = 256 * 256 * 4;
+
+/// How the pointer shape buffer of a capturer was used, see [`Capturer::pointer_shape_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PointerShapeStats {
+  /// Captured pointer shapes.
+  pub shapes: u64,
+  /// Shapes which fit in the allocated buffer.
+  pub reuses: u64,
+  /// Shapes which grew the buffer, each is an allocation while capturing.
+  pub growths: u64,
+  /// The largest captured shape, in bytes.
+  pub max_size: usize,
+}
+
+impl PointerShapeStats {
+  /// Record a shape of `size` bytes captured into a buffer of `buffer_len` bytes.
+  pub(crate) fn record(&mut self, buffer_len: usize, size: usize) {
+    self.shapes += 1;
+    if size > buffer_len {
+      self.growths += 1;
+    } else {
+      self.reuses += 1;
+    }
+    self.max_size = self.max_size.max(size);
+  }
+}
+
+/// Memory held by a capturer or frame store, in bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+  /// CPU buffers of frames, e.g. the capture buffer, including buffers borrowed by the capturer.
+  pub buffers: usize,
+  /// The allocated pointer shape buffer.
+  pub pointer_shape_buffer: usize,
+  /// GPU staging textures, estimated from their size and format without driver padding.
+  pub staging_textures: usize,
+  /// Buffers kept for reuse.
+  pub pools: usize,
+}
+
+impl MemoryUsage {
+  pub fn total(&self) -> usize {
+    self.buffers + self.pointer_shape_buffer + self.staging_textures + self.pools
+  }
+
+  /// The estimated size of a staging texture.
+  pub(crate) fn texture_bytes(desc: &D3D11_TEXTURE2D_DESC) -> usize {
+    desc.Width as usize * desc.Height as usize * desc.Format.bytes_per_pixel()
+  }
+}
+
+impl Add for MemoryUsage {
+  type Output = MemoryUsage;
+
+  fn add(self, other: MemoryUsage) -> MemoryUsage {
+    MemoryUsage {
+      buffers: self.buffers + other.buffers,
+      pointer_shape_buffer: self.pointer_shape_buffer + other.pointer_shape_buffer,
+      staging_textures: self.staging_textures + other.staging_textures,
+      pools: self.pools + other.pools,
+    }
+  }
+}
+
+impl Sum for MemoryUsage {
+  fn sum<I: Iterator<Item = MemoryUsage>>(iter: I) -> MemoryUsage {
+    iter.fold(MemoryUsage::default(), Add::add)
+  }
+}
+
+/// A byte buffer owned by a capturer to store captured frames.
+pub trait CapturerBuffer {
+  fn as_bytes(&self) -> &[u8];
+  fn as_bytes_mut(&mut self) -> &mut [u8];
+
+  /// Resize the buffer to `new_len` bytes, e.g. after a mode change.
+  /// Return an error by default, for fixed buffers which can't grow.
+  fn try_resize(&mut self, new_len: usize) -> Result<()> {
+    Err(Error::new(format!(
+      "Buffer can't be resized from {} to {} bytes",
+      self.as_bytes().len(),
+      new_len
+    )))
+  }
+
+  /// Grow the buffer with [`CapturerBuffer::try_resize`] if it is shorter than `len` bytes.
+  fn try_fit(&mut self, len: usize) -> Result<()> {
+    if self.as_bytes().len() < len {
+      self.try_resize(len)
+    } else {
+      Ok(())
+    }
+  }
+}
+
+impl CapturerBuffer for Vec<u8> {
+  fn as_bytes(&self) -> &[u8] {
+    self
+  }
+
+  fn as_bytes_mut(&mut self) -> &mut [u8] {
+    self
+  }
+
+  fn try_resize(&mut self, new_len: usize) -> Result<()> {
+    self.resize(new_len, 0);
+    Ok(())
+  }
+}
+
+impl CapturerBuffer for &mut [u8] {
+  fn as_bytes(&self) -> &[u8] {
+    self
+  }
+
+  fn as_bytes_mut(&mut self) -> &mut [u8] {
+    self
+  }
+}
+
+/// Grow the readable texture and the buffer of a capturer before a capture if auto grow is enabled,
+/// see [`Capturer::set_auto_grow`]. `fit` grows the buffer for frames of the texture
+/// to at least the given length. Failures are reported to `observers`.
+/// Return whether the texture was recreated.
+pub(crate) fn auto_grow(
+  ctx: &DuplicationContext,
+  texture: &mut ID3D11Texture2D,
+  texture_desc: &mut D3D11_TEXTURE2D_DESC,
+  observers: &Observers,
+  fit: impl FnOnce(&D3D11_TEXTURE2D_DESC, usize) -> Result<()>,
+) -> Result<bool> {
+  ctx
+    .refresh_readable_texture(texture, texture_desc)
+    .and_then(|recreated| {
+      fit(texture_desc, ctx.dxgi_outdupl_desc().calc_buffer_size())?;
+      Ok(recreated)
+    })
+    .inspect_err(|e| observers.error(e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Capturer, CapturerBuffer, MemoryUsage, PointerShapeStats};
+  use crate::model::{CaptureArgs, Result};
+  use windows::Win32::Graphics::Dxgi::{
+    DXGI_OUTDUPL_DESC, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_POINTER_SHAPE_INFO, DXGI_OUTPUT_DESC,
+  };
+
+  /// A capturer implementing only the required methods.
+  struct Minimal(Vec<u8>);
+
+  impl Capturer for Minimal {
+    fn dxgi_output_desc(&self) -> Result<DXGI_OUTPUT_DESC> {
+      Ok(DXGI_OUTPUT_DESC::default())
+    }
+    fn dxgi_outdupl_desc(&self) -> DXGI_OUTDUPL_DESC {
+      DXGI_OUTDUPL_DESC::default()
+    }
+    fn buffer(&self) -> &[u8] {
+      &self.0
+    }
+    fn buffer_mut(&mut self) -> &mut [u8] {
+      &mut self.0
+    }
+    fn check_buffer(&self) -> Result<()> {
+      Ok(())
+    }
+    fn pointer_shape_buffer(&self) -> &[u8] {
+      &[]
+    }
+    fn capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+      Ok(DXGI_OUTDUPL_FRAME_INFO::default())
+    }
+    fn safe_capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+      self.capture()
+    }
+    fn capture_with_pointer_shape(
+      &mut self,
+    ) -> Result<(
+      DXGI_OUTDUPL_FRAME_INFO,
+      Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
+    )> {
+      Ok((self.capture()?, None))
+    }
+    fn safe_capture_with_pointer_shape(
+      &mut self,
+    ) -> Result<(
+      DXGI_OUTDUPL_FRAME_INFO,
+      Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
+    )> {
+      self.capture_with_pointer_shape()
+    }
+  }
+
+  #[test]
+  fn defaults() {
+    let mut capturer = Minimal(vec![0; 16]);
+    capturer.set_auto_grow(true);
+    capturer.set_apply_move_rects(true);
+    capturer.reserve_pointer_shape_buffer(64);
+    assert_eq!(capturer.memory_usage().total(), 16);
+    assert_eq!(capturer.pointer_shape_stats(), PointerShapeStats::default());
+
+    assert!(capturer.capture_with(&CaptureArgs::default()).is_ok());
+    let args = CaptureArgs {
+      include_cursor: true,
+      ..Default::default()
+    };
+    assert!(capturer.capture_with(&args).is_err());
+  }
+
+  #[test]
+  fn resize_buffers() {
+    let mut vec = vec![1u8; 4];
+    vec.try_fit(8).unwrap();
+    assert_eq!(vec, [1, 1, 1, 1, 0, 0, 0, 0]);
+    // never shrink to fit
+    vec.try_fit(2).unwrap();
+    assert_eq!(vec.len(), 8);
+
+    let mut array = [0u8; 4];
+    let mut slice = &mut array[..];
+    assert!(slice.try_fit(4).is_ok());
+    assert!(slice.try_fit(8).is_err());
+  }
+
+  #[test]
+  fn memory_usage() {
+    let usage = MemoryUsage {
+      buffers: 8,
+      pointer_shape_buffer: 4,
+      staging_textures: 8,
+      pools: 0,
+    };
+    assert_eq!(usage.total(), 20);
+    let total: MemoryUsage = [usage, usage].into_iter().sum();
+    assert_eq!(total.buffers, 16);
+    assert_eq!(total.total(), 40);
+  }
+
+  #[test]
+  fn pointer_shape_stats() {
+    let mut stats = PointerShapeStats::default();
+    stats.record(0, 128);
+    stats.record(128, 64);
+    stats.record(128, 128);
+    assert_eq!(
+      stats,
+      PointerShapeStats {
+        shapes: 3,
+        reuses: 2,
+        growths: 1,
+        max_size: 128,
+      }
+    );
+  }
+}
@@ -0,0 +1,104 @@
+use crate::error::Error;
+use crate::model::MonitorId;
+use std::sync::Arc;
+use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_FRAME_INFO;
+
+/// Receive notifications from capturers, e.g. to log errors or collect metrics in one place.
+/// All methods do nothing by default.
+pub trait CaptureObserver: Send + Sync {
+  /// A frame is captured.
+  fn on_frame(&self, _id: MonitorId, _info: &DXGI_OUTDUPL_FRAME_INFO) {}
+
+  /// Capturing failed. The error context tells which monitor failed, if known.
+  fn on_error(&self, _err: &Error) {}
+
+  /// The captured monitor is (re)opened with a new frame size,
+  /// e.g. after a resolution or rotation change.
+  fn on_mode_change(&self, _id: MonitorId, _width: u32, _height: u32) {}
+}
+
+/// A list of observers, used by capturers to store and notify them.
+#[derive(Clone, Default)]
+pub struct Observers(Vec<Arc<dyn CaptureObserver>>);
+
+impl Observers {
+  pub fn add(&mut self, observer: Arc<dyn CaptureObserver>) {
+    self.0.push(observer);
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// Notify `on_frame` or `on_error` according to a capture result.
+  pub fn notify(
+    &self,
+    id: MonitorId,
+    result: std::result::Result<&DXGI_OUTDUPL_FRAME_INFO, &Error>,
+  ) {
+    match result {
+      Ok(info) => self.frame(id, info),
+      Err(err) => self.error(err),
+    }
+  }
+
+  pub fn frame(&self, id: MonitorId, info: &DXGI_OUTDUPL_FRAME_INFO) {
+    self.0.iter().for_each(|o| o.on_frame(id, info));
+  }
+
+  pub fn error(&self, err: &Error) {
+    self.0.iter().for_each(|o| o.on_error(err));
+  }
+
+  pub fn mode_change(&self, id: MonitorId, width: u32, height: u32) {
+    self
+      .0
+      .iter()
+      .for_each(|o| o.on_mode_change(id, width, height));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{CaptureObserver, Observers};
+  use crate::{error::Error, model::MonitorId};
+  use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+  };
+  use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_FRAME_INFO;
+
+  #[derive(Default)]
+  struct Counter {
+    frames: AtomicU32,
+    errors: AtomicU32,
+  }
+
+  impl CaptureObserver for Counter {
+    fn on_frame(&self, _id: MonitorId, _info: &DXGI_OUTDUPL_FRAME_INFO) {
+      self.frames.fetch_add(1, Ordering::Relaxed);
+    }
+    fn on_error(&self, _err: &Error) {
+      self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+  }
+
+  #[test]
+  fn observers() {
+    let counter = Arc::new(Counter::default());
+    let mut observers = Observers::default();
+    assert!(observers.is_empty());
+    observers.add(counter.clone());
+
+    let id = MonitorId {
+      adapter: 0,
+      output: 0,
+    };
+    observers.notify(id, Ok(&DXGI_OUTDUPL_FRAME_INFO::default()));
+    observers.notify(id, Err(&Error::new("AcquireNextFrame")));
+    // no-op by default
+    observers.mode_change(id, 1920, 1080);
+    assert_eq!(counter.frames.load(Ordering::Relaxed), 1);
+    assert_eq!(counter.errors.load(Ordering::Relaxed), 1);
+  }
+}
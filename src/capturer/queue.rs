@@ -0,0 +1,241 @@
+use super::model::MemoryUsage;
+use crate::frame::Frame;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+struct Ring {
+  slots: Vec<Option<Frame>>,
+  /// The index of the oldest frame.
+  head: usize,
+  len: usize,
+  overwritten: u64,
+  /// Frames older than this are dropped when popped.
+  max_age: Option<Duration>,
+  expired: u64,
+}
+
+/// A bounded ring buffer of frames shared between threads.
+///
+/// When the queue is full, pushing overwrites the oldest frame instead of blocking the producer,
+/// so recorders tolerate short consumer stalls with a fixed memory footprint.
+#[derive(Clone)]
+pub struct FrameQueue {
+  ring: Arc<(Mutex<Ring>, Condvar)>,
+}
+
+impl FrameQueue {
+  /// Create a queue holding at most `capacity` frames. `capacity` is at least 1.
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      ring: Arc::new((
+        Mutex::new(Ring {
+          slots: (0..capacity.max(1)).map(|_| None).collect(),
+          head: 0,
+          len: 0,
+          overwritten: 0,
+          max_age: None,
+          expired: 0,
+        }),
+        Condvar::new(),
+      )),
+    }
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.ring.0.lock().unwrap().slots.len()
+  }
+
+  pub fn len(&self) -> usize {
+    self.ring.0.lock().unwrap().len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// How many frames are overwritten before being popped.
+  pub fn overwritten(&self) -> u64 {
+    self.ring.0.lock().unwrap().overwritten
+  }
+
+  pub fn max_age(&self) -> Option<Duration> {
+    self.ring.0.lock().unwrap().max_age
+  }
+
+  /// Drop frames older than `max_age` when popping instead of returning them,
+  /// see [`Frame::age`]. Frames without provenance never expire. `None` by default.
+  pub fn set_max_age(&self, max_age: Option<Duration>) {
+    self.ring.0.lock().unwrap().max_age = max_age;
+  }
+
+  /// How many frames are dropped because they are older than [`FrameQueue::max_age`].
+  pub fn expired(&self) -> u64 {
+    self.ring.0.lock().unwrap().expired
+  }
+
+  /// Report the memory of the queued frames.
+  pub fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      buffers: self
+        .ring
+        .0
+        .lock()
+        .unwrap()
+        .slots
+        .iter()
+        .flatten()
+        .map(|frame| frame.buffer.capacity())
+        .sum(),
+      ..Default::default()
+    }
+  }
+
+  /// Push a frame, overwriting the oldest one if the queue is full.
+  /// Return the overwritten frame so its buffer can be reused.
+  pub fn push(&self, frame: Frame) -> Option<Frame> {
+    let (ring, readable) = &*self.ring;
+    let mut ring = ring.lock().unwrap();
+    let capacity = ring.slots.len();
+    let tail = (ring.head + ring.len) % capacity;
+    let overwritten = ring.slots[tail].replace(frame);
+    if ring.len == capacity {
+      ring.head = (ring.head + 1) % capacity;
+      ring.overwritten += 1;
+    } else {
+      ring.len += 1;
+    }
+    readable.notify_one();
+    overwritten
+  }
+
+  /// Pop the oldest frame without blocking.
+  pub fn try_pop(&self) -> Option<Frame> {
+    Self::take(&mut self.ring.0.lock().unwrap())
+  }
+
+  /// Wait for a frame and pop the oldest one.
+  pub fn pop(&self) -> Frame {
+    let (ring, readable) = &*self.ring;
+    let mut ring = ring.lock().unwrap();
+    loop {
+      if let Some(frame) = Self::take(&mut ring) {
+        return frame;
+      }
+      ring = readable.wait(ring).unwrap();
+    }
+  }
+
+  /// Wait at most `timeout` for a frame and pop the oldest one.
+  pub fn pop_timeout(&self, timeout: Duration) -> Option<Frame> {
+    let deadline = Instant::now() + timeout;
+    let (ring, readable) = &*self.ring;
+    let mut ring = ring.lock().unwrap();
+    loop {
+      if let Some(frame) = Self::take(&mut ring) {
+        return Some(frame);
+      }
+      let now = Instant::now();
+      if now >= deadline {
+        return None;
+      }
+      ring = readable.wait_timeout(ring, deadline - now).unwrap().0;
+    }
+  }
+
+  /// Pop the oldest frame which isn't expired.
+  fn take(ring: &mut Ring) -> Option<Frame> {
+    while ring.len > 0 {
+      let frame = ring.slots[ring.head].take();
+      ring.head = (ring.head + 1) % ring.slots.len();
+      ring.len -= 1;
+      match frame {
+        Some(frame) if ring.max_age.is_some_and(|max_age| frame.is_stale(max_age)) => {
+          ring.expired += 1
+        }
+        frame => return frame,
+      }
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::FrameQueue;
+  use crate::model::{MonitorId, Provenance};
+  use crate::test_utils::filled;
+  use std::thread;
+  use std::time::{Duration, SystemTime};
+
+  #[test]
+  fn overwrite_oldest() {
+    let queue = FrameQueue::new(2);
+    assert!(queue.is_empty());
+    assert!(queue.push(filled(1, 1, 1)).is_none());
+    assert!(queue.push(filled(1, 1, 2)).is_none());
+    assert_eq!(queue.push(filled(1, 1, 3)).unwrap().buffer, [1; 4]);
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue.overwritten(), 1);
+
+    assert_eq!(queue.try_pop().unwrap().buffer, [2; 4]);
+    assert_eq!(queue.try_pop().unwrap().buffer, [3; 4]);
+    assert!(queue.try_pop().is_none());
+    assert!(queue.pop_timeout(Duration::from_millis(10)).is_none());
+  }
+
+  #[test]
+  fn memory_usage() {
+    let queue = FrameQueue::new(2);
+    assert_eq!(queue.memory_usage().total(), 0);
+    queue.push(filled(1, 1, 1));
+    queue.push(filled(1, 1, 2));
+    queue.push(filled(1, 1, 3));
+    assert_eq!(queue.memory_usage().buffers, 8);
+    queue.try_pop();
+    assert_eq!(queue.memory_usage().buffers, 4);
+  }
+
+  #[test]
+  fn expire() {
+    let captured_ago = |age: Duration| {
+      Some(Provenance {
+        monitor: MonitorId::default(),
+        adapter_luid: 0,
+        source: 0,
+        sequence: 0,
+        captured_at: SystemTime::now() - age,
+      })
+    };
+    let queue = FrameQueue::new(4);
+    queue.set_max_age(Some(Duration::from_secs(1)));
+    assert_eq!(queue.max_age(), Some(Duration::from_secs(1)));
+    queue.push(filled(1, 1, 1).with_provenance(captured_ago(Duration::from_secs(5))));
+    queue.push(filled(1, 1, 2).with_provenance(captured_ago(Duration::from_secs(3))));
+    queue.push(filled(1, 1, 3));
+    queue.push(filled(1, 1, 4).with_provenance(captured_ago(Duration::ZERO)));
+
+    // frames without provenance never expire
+    assert_eq!(queue.try_pop().unwrap().buffer, [3; 4]);
+    assert_eq!(queue.expired(), 2);
+    assert_eq!(queue.try_pop().unwrap().buffer, [4; 4]);
+
+    queue.push(filled(1, 1, 5).with_provenance(captured_ago(Duration::from_secs(5))));
+    assert!(queue.pop_timeout(Duration::from_millis(10)).is_none());
+    assert_eq!(queue.expired(), 3);
+    assert!(queue.is_empty());
+  }
+
+  #[test]
+  fn pop_across_threads() {
+    let queue = FrameQueue::new(4);
+    let producer = {
+      let queue = queue.clone();
+      thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        queue.push(filled(1, 1, 1));
+      })
+    };
+    assert_eq!(queue.pop().buffer, [1; 4]);
+    producer.join().unwrap();
+  }
+}
@@ -1,10 +1,14 @@
-use super::model::Capturer;
+use super::model::{self, Capturer, CapturerBuffer, MemoryUsage, PointerShapeStats};
+use super::observer::{CaptureObserver, Observers};
+use super::shared_layout::{SectionWriter, SharedCursor, SharedHeader, CURSOR_CAPACITY};
 use crate::duplication_context::DuplicationContext;
 use crate::error::Error;
-use crate::model::Result;
+use crate::model::{CaptureArgs, DuplicationInfo, FrameInfo, OutputInfo, PointerShapeInfo, Result};
+use crate::pointer::ShapeId;
 use crate::utils::OutDuplDescExt;
 use std::ffi::CString;
 use std::slice;
+use std::sync::Arc;
 use windows::core::PCSTR;
 use windows::Win32::Foundation::{CloseHandle, HANDLE};
 use windows::Win32::Graphics::Direct3D11::D3D11_TEXTURE2D_DESC;
@@ -22,139 +26,343 @@ use windows::Win32::{
   System::Memory::PAGE_READWRITE,
 };
 
+/// A named shared memory mapping, unmapped when dropped or [closed](SharedMemory::close).
+///
+/// The mapping is only reachable through borrows of this owner,
+/// so no slice outlives the mapped view.
+pub struct SharedMemory {
+  name: CString,
+  /// Whether the mapping is created by us, otherwise it is opened.
+  created: bool,
+  view: MEMORYMAPPEDVIEW_HANDLE,
+  file: HANDLE,
+  size: usize,
+  /// The mapped size, may be larger than `size` after shrinking.
+  capacity: usize,
+}
+
+impl SharedMemory {
+  /// Create a named shared memory of `size` bytes.
+  pub fn create(name: &str, size: usize) -> Result<Self> {
+    let name = CString::new(name).map_err(|_| Error::new("Invalid shared memory name"))?;
+    let file = unsafe {
+      CreateFileMappingA(
+        INVALID_HANDLE_VALUE,
+        None,
+        PAGE_READWRITE,
+        (size as u64 >> 32) as u32,
+        size as u32,
+        PCSTR(name.as_ptr() as *const _),
+      )
+    }
+    .map_err(|e| Error::windows("CreateFileMappingA", e))?;
+    Self::map(name, true, file, size)
+  }
+
+  /// Open an existing named shared memory and map `size` bytes of it.
+  pub fn open(name: &str, size: usize) -> Result<Self> {
+    let name = CString::new(name).map_err(|_| Error::new("Invalid shared memory name"))?;
+    let file = unsafe {
+      OpenFileMappingA(
+        FILE_MAP_ALL_ACCESS.0,
+        false,
+        PCSTR(name.as_ptr() as *const _),
+      )
+    }
+    .map_err(|e| Error::windows("OpenFileMappingA", e))?;
+    Self::map(name, false, file, size)
+  }
+
+  /// Map the view of `file`, closing `file` on failure.
+  fn map(name: CString, created: bool, file: HANDLE, size: usize) -> Result<Self> {
+    match unsafe { MapViewOfFile(file, FILE_MAP_ALL_ACCESS, 0, 0, size) } {
+      Ok(view) => Ok(Self {
+        name,
+        created,
+        view,
+        file,
+        size,
+        capacity: size,
+      }),
+      Err(e) => {
+        unsafe { CloseHandle(file) };
+        Err(Error::windows("MapViewOfFile", e))
+      }
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.size
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  /// Unmap the view and close the handle, reporting failures which dropping ignores.
+  pub fn close(mut self) -> Result<()> {
+    self.release()
+  }
+
+  /// Unmap the view and close the handle once, later calls do nothing.
+  fn release(&mut self) -> Result<()> {
+    if self.view.is_invalid() {
+      return Ok(());
+    }
+    let unmapped = unsafe { UnmapViewOfFile(self.view) }
+      .ok()
+      .map_err(|e| Error::windows("UnmapViewOfFile", e));
+    let closed = unsafe { CloseHandle(self.file) }
+      .ok()
+      .map_err(|e| Error::windows("CloseHandle", e));
+    self.view = MEMORYMAPPEDVIEW_HANDLE::default();
+    self.size = 0;
+    self.capacity = 0;
+    unmapped.and(closed)
+  }
+}
+
+impl CapturerBuffer for SharedMemory {
+  fn as_bytes(&self) -> &[u8] {
+    if self.view.is_invalid() {
+      return &[];
+    }
+    // the view is mapped with `size` bytes until `self` is dropped or closed
+    unsafe { slice::from_raw_parts(self.view.0 as *const u8, self.size) }
+  }
+
+  fn as_bytes_mut(&mut self) -> &mut [u8] {
+    if self.view.is_invalid() {
+      return &mut [];
+    }
+    unsafe { slice::from_raw_parts_mut(self.view.0 as *mut u8, self.size) }
+  }
+
+  /// Shrink within the mapped view, or remap a mapping of `new_len` bytes with the same name.
+  /// An opened mapping is remapped before the old view is released,
+  /// and growing it fails until its creator grows it.
+  /// A created mapping can only grow after it is released, since the name refers to it until then,
+  /// so it fails if other processes still have it open and the memory is empty after a failed remap.
+  fn try_resize(&mut self, new_len: usize) -> Result<()> {
+    if new_len <= self.capacity {
+      self.size = new_len;
+      return Ok(());
+    }
+    let name = self
+      .name
+      .to_str()
+      .map_err(|_| Error::new("Invalid shared memory name"))?
+      .to_string();
+    if self.created {
+      self.release()?;
+      *self = Self::create(&name, new_len)?;
+    } else {
+      // the old view is released when it is replaced
+      *self = Self::open(&name, new_len)?;
+    }
+    Ok(())
+  }
+}
+
+impl Drop for SharedMemory {
+  fn drop(&mut self) {
+    self.release().ok();
+  }
+}
+
 /// Capture screen to a chunk of shared memory.
+///
+/// By default the memory only holds the pixels.
+/// A [sectioned](SharedCapturer::sectioned) memory also holds the pointer and the frame metadata,
+/// see [`SharedHeader`] for the layout and [`SharedReader`](super::shared_layout::SharedReader) to read it.
 pub struct SharedCapturer<'a> {
-  buffer: *mut u8,
-  buffer_size: usize,
-  file: HANDLE,
+  buffer: SharedMemory,
   ctx: &'a DuplicationContext,
   texture: ID3D11Texture2D,
   texture_desc: D3D11_TEXTURE2D_DESC,
   pointer_shape_buffer: Vec<u8>,
   pointer_shape_buffer_size: usize,
+  pointer_shape_stats: PointerShapeStats,
+  observers: Observers,
+  auto_grow: bool,
+  apply_move_rects: bool,
+  /// Copy the whole next frame even if move rects are applied, since the buffer missed a frame.
+  full_copy: bool,
+  /// The layout of a sectioned memory.
+  layout: Option<SharedHeader>,
+  /// The last pointer state, kept across frames which don't update it.
+  cursor: SharedCursor,
+  /// The shape of the image in the cursor section, to skip writing unchanged shapes.
+  cursor_shape: Option<ShapeId>,
 }
 
 impl<'a> SharedCapturer<'a> {
   pub fn new(ctx: &'a DuplicationContext, name: &str) -> Result<Self> {
-    let (buffer, buffer_size, file, texture, texture_desc) = Self::allocate(ctx, name)?;
-    Ok(Self {
-      buffer,
-      buffer_size,
-      file,
-      texture,
-      texture_desc,
-      ctx,
-      pointer_shape_buffer: Vec::new(),
-      pointer_shape_buffer_size: 0,
-    })
+    Self::with_memory(ctx, false, |size| SharedMemory::create(name, size))
   }
 
   pub fn open(ctx: &'a DuplicationContext, name: &str) -> Result<Self> {
-    let (buffer, buffer_size, file, texture, texture_desc) = Self::open_file(ctx, name)?;
+    Self::with_memory(ctx, false, |size| SharedMemory::open(name, size))
+  }
+
+  /// Create a named shared memory with sections for the pixels, the decoded pointer image and position,
+  /// and the frame metadata with dirty rects, so other processes get everything a local capturer gets.
+  /// The pointer and metadata are written by both [`Capturer::capture`]
+  /// and [`Capturer::capture_with_pointer_shape`].
+  pub fn sectioned(ctx: &'a DuplicationContext, name: &str) -> Result<Self> {
+    Self::with_memory(ctx, true, |size| SharedMemory::create(name, size))
+  }
+
+  fn with_memory(
+    ctx: &'a DuplicationContext,
+    sectioned: bool,
+    memory: impl FnOnce(usize) -> Result<SharedMemory>,
+  ) -> Result<Self> {
+    let (texture, desc, texture_desc) = ctx.create_readable_texture()?;
+    let layout = sectioned.then(|| layout(ctx, &texture_desc, desc.calc_buffer_size()));
+    let size = match &layout {
+      Some(layout) => layout.size as usize,
+      None => desc.calc_buffer_size(),
+    };
+    let mut buffer = memory(size)?;
+    if let Some(layout) = layout {
+      // publish the layout before the first capture
+      drop(SectionWriter::begin(buffer.as_bytes_mut(), layout));
+    }
     Ok(Self {
       buffer,
-      buffer_size,
-      file,
       texture,
       texture_desc,
       ctx,
       pointer_shape_buffer: Vec::new(),
       pointer_shape_buffer_size: 0,
+      pointer_shape_stats: PointerShapeStats::default(),
+      observers: Observers::default(),
+      auto_grow: false,
+      apply_move_rects: false,
+      full_copy: true,
+      layout,
+      cursor: SharedCursor::default(),
+      cursor_shape: None,
     })
   }
 
-  fn allocate(
-    ctx: &'a DuplicationContext,
-    name: &str,
-  ) -> Result<(
-    *mut u8,
-    usize,
-    HANDLE,
-    ID3D11Texture2D,
-    D3D11_TEXTURE2D_DESC,
-  )> {
-    let (texture, desc, texture_desc) = ctx.create_readable_texture()?;
-    let buffer_size = desc.calc_buffer_size();
-    let name = CString::new(name).unwrap(); // make the name null terminated
+  /// The shared memory holding the captured frames.
+  pub fn memory(&self) -> &SharedMemory {
+    &self.buffer
+  }
 
-    unsafe {
-      let file = CreateFileMappingA(
-        INVALID_HANDLE_VALUE,
-        None,
-        PAGE_READWRITE,
-        0,
-        buffer_size as u32,
-        PCSTR(name.as_ptr() as *const _),
-      )
-      .map_err(|e| Error::windows("CreateFileMappingA", e))?;
-
-      let buffer = match MapViewOfFile(
-        file,                // handle to map object
-        FILE_MAP_ALL_ACCESS, // read/write permission
-        0,
-        0,
-        buffer_size,
-      )
-      .map_err(|e| Error::windows("MapViewOfFile", e))
-      {
-        Ok(buffer) => buffer,
-        Err(e) => {
-          CloseHandle(file);
-          return Err(e);
-        }
-      }
-      .0 as *mut u8;
-      Ok((buffer, buffer_size, file, texture, texture_desc))
+  /// Release the shared memory, reporting failures which dropping ignores.
+  pub fn close(self) -> Result<()> {
+    self.buffer.close()
+  }
+
+  /// Grow the buffer and the texture if auto grow is enabled, relayouting a sectioned memory.
+  fn grow_buffer(&mut self) -> Result<()> {
+    if !self.auto_grow {
+      return Ok(());
     }
+    let (ctx, buffer, header) = (self.ctx, &mut self.buffer, &mut self.layout);
+    self.full_copy |= model::auto_grow(
+      ctx,
+      &mut self.texture,
+      &mut self.texture_desc,
+      &self.observers,
+      |texture_desc, mut len| {
+        if let Some(header) = header {
+          if len as u64 > header.pixels_size
+            || (header.width, header.height) != (texture_desc.Width, texture_desc.Height)
+          {
+            *header = layout(ctx, texture_desc, len);
+          }
+          len = header.size as usize;
+        }
+        buffer.try_fit(len)
+      },
+    )?;
+    Ok(())
   }
 
-  fn open_file(
-    ctx: &'a DuplicationContext,
-    name: &str,
+  /// Capture a frame to the pixel section, and the pointer and frame info to their sections.
+  fn capture_sections(
+    &mut self,
   ) -> Result<(
-    *mut u8,
-    usize,
-    HANDLE,
-    ID3D11Texture2D,
-    D3D11_TEXTURE2D_DESC,
+    DXGI_OUTDUPL_FRAME_INFO,
+    Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )> {
-    let (texture, desc, texture_desc) = ctx.create_readable_texture()?;
-    let buffer_size = desc.calc_buffer_size();
-    let name = CString::new(name).unwrap(); // make the name null terminated
+    let layout = self.layout.unwrap();
+    if self.buffer.len() < layout.size as usize {
+      return Err(Error::new("Invalid buffer length").with_context(self.ctx.error_context()));
+    }
+    let frame = self.ctx.acquire()?;
+    let info = *frame.info();
+    let dirty_rects = frame.dirty_rects().ok();
+    let buffer_len = self.pointer_shape_buffer.len();
+    let pointer_shape_info = frame.pointer_shape(&mut self.pointer_shape_buffer)?;
+    if pointer_shape_info.is_some() {
+      self.pointer_shape_buffer_size = info.PointerShapeBufferSize as usize;
+      self
+        .pointer_shape_stats
+        .record(buffer_len, self.pointer_shape_buffer_size);
+    }
 
-    unsafe {
-      let file = OpenFileMappingA(
-        FILE_MAP_ALL_ACCESS.0,
-        false,
-        PCSTR(name.as_ptr() as *const _),
-      )
-      .map_err(|e| Error::windows("CreateFileMappingA", e))?;
-
-      let buffer = match MapViewOfFile(
-        file,                // handle to map object
-        FILE_MAP_ALL_ACCESS, // read/write permission
-        0,
-        0,
-        buffer_size,
-      )
-      .map_err(|e| Error::windows("MapViewOfFile", e))
-      {
-        Ok(buffer) => buffer,
-        Err(e) => {
-          CloseHandle(file);
-          return Err(e);
-        }
+    let mut writer = SectionWriter::begin(self.buffer.as_bytes_mut(), layout);
+    if self.apply_move_rects && !self.full_copy {
+      frame.update_slice(writer.pixels_mut(), &self.texture, &self.texture_desc)?;
+    } else {
+      frame.copy_to_slice(writer.pixels_mut(), &self.texture, &self.texture_desc)?;
+    }
+
+    if info.LastMouseUpdateTime != 0 {
+      self.cursor.visible = info.PointerPosition.Visible.as_bool() as u32;
+      self.cursor.x = info.PointerPosition.Position.x;
+      self.cursor.y = info.PointerPosition.Position.y;
+    }
+    // the context decoded the shape, which is only written if it changed
+    let mut image = None;
+    if let Some(shape_info) = pointer_shape_info {
+      let state = self.ctx.cursor_state();
+      if state.shape_id != self.cursor_shape {
+        self.cursor_shape = state.shape_id;
+        image = state
+          .decoded_image
+          .filter(|image| image.buffer.len() <= CURSOR_CAPACITY);
+        self.cursor.shape = PointerShapeInfo::from(&shape_info);
+        self.cursor.width = image.as_ref().map_or(0, |image| image.width);
+        self.cursor.height = image.as_ref().map_or(0, |image| image.height);
+        self.cursor.image_size = image.as_ref().map_or(0, |image| image.buffer.len() as u32);
+        self.cursor.shape_sequence = self.cursor.shape_sequence.wrapping_add(1);
       }
-      .0 as *mut u8;
-      Ok((buffer, buffer_size, file, texture, texture_desc))
     }
+    writer.set_cursor(&self.cursor, image.as_ref().map(|image| &image.buffer[..]));
+
+    writer.set_metadata(&FrameInfo::from(&info), dirty_rects.as_deref());
+    drop(writer);
+
+    frame.release()?;
+    Ok((info, pointer_shape_info))
   }
+}
 
-  fn free(&self) {
-    unsafe {
-      UnmapViewOfFile(MEMORYMAPPEDVIEW_HANDLE(self.buffer as isize));
-      CloseHandle(self.file);
-    }
+/// The layout of a sectioned memory for `pixels_size` bytes of pixels of the output of `ctx`.
+fn layout(
+  ctx: &DuplicationContext,
+  texture_desc: &D3D11_TEXTURE2D_DESC,
+  pixels_size: usize,
+) -> SharedHeader {
+  SharedHeader {
+    // the output may be gone, e.g. while the desktop is switched
+    output: ctx
+      .dxgi_output_desc()
+      .map(|desc| OutputInfo::from(&desc))
+      .unwrap_or_default(),
+    duplication: DuplicationInfo::from(&ctx.dxgi_outdupl_desc()),
+    ..SharedHeader::new(
+      texture_desc.Width,
+      texture_desc.Height,
+      texture_desc.Format,
+      pixels_size,
+    )
   }
 }
 
@@ -167,16 +375,23 @@ impl<'a> Capturer for SharedCapturer<'a> {
     self.ctx.dxgi_outdupl_desc()
   }
 
+  /// The pixel section of a sectioned memory.
   fn buffer(&self) -> &[u8] {
-    unsafe { slice::from_raw_parts(self.buffer, self.buffer_size) }
+    match &self.layout {
+      Some(layout) => &self.buffer.as_bytes()[layout.pixels()],
+      None => self.buffer.as_bytes(),
+    }
   }
 
   fn buffer_mut(&mut self) -> &mut [u8] {
-    unsafe { slice::from_raw_parts_mut(self.buffer, self.buffer_size) }
+    match &self.layout {
+      Some(layout) => &mut self.buffer.as_bytes_mut()[layout.pixels()],
+      None => self.buffer.as_bytes_mut(),
+    }
   }
 
   fn check_buffer(&self) -> Result<()> {
-    if self.buffer_size < self.dxgi_outdupl_desc().calc_buffer_size() {
+    if self.buffer().len() < self.dxgi_outdupl_desc().calc_buffer_size() {
       Err(Error::new("Invalid buffer length"))
     } else {
       Ok(())
@@ -187,37 +402,128 @@ impl<'a> Capturer for SharedCapturer<'a> {
     &self.pointer_shape_buffer[..self.pointer_shape_buffer_size]
   }
 
+  fn reserve_pointer_shape_buffer(&mut self, size: usize) {
+    if self.pointer_shape_buffer.len() < size {
+      self.pointer_shape_buffer.resize(size, 0);
+    }
+  }
+
+  fn pointer_shape_stats(&self) -> PointerShapeStats {
+    self.pointer_shape_stats
+  }
+
+  fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      buffers: self.buffer.capacity,
+      pointer_shape_buffer: self.pointer_shape_buffer.capacity(),
+      staging_textures: MemoryUsage::texture_bytes(&self.texture_desc),
+      pools: 0,
+    }
+  }
+
+  fn add_observer(&mut self, observer: Arc<dyn CaptureObserver>) {
+    self.observers.add(observer);
+  }
+
+  fn set_auto_grow(&mut self, auto_grow: bool) {
+    self.auto_grow = auto_grow;
+  }
+
+  fn set_apply_move_rects(&mut self, apply_move_rects: bool) {
+    self.apply_move_rects = apply_move_rects;
+    self.full_copy = true;
+  }
+
   fn capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
-    self.ctx.capture(
-      self.buffer,
-      self.buffer_size,
-      &self.texture,
-      &self.texture_desc,
-    )
+    self.grow_buffer()?;
+    let result = if self.layout.is_some() {
+      self.capture_sections().map(|(info, _)| info)
+    } else if self.apply_move_rects && !self.full_copy {
+      self
+        .ctx
+        .update_slice(
+          self.buffer.as_bytes_mut(),
+          &self.texture,
+          &self.texture_desc,
+          None,
+        )
+        .map(|(info, _)| info)
+    } else {
+      self.ctx.capture_to_slice(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+      )
+    };
+    // a failed capture may have acquired a frame which isn't in the buffer
+    self.full_copy = result.is_err();
+    self.observers.notify(self.ctx.id(), result.as_ref());
+    result
   }
 
   fn safe_capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
-    self.check_buffer()?;
+    if !self.auto_grow {
+      self.check_buffer()?;
+    }
     self.capture()
   }
 
+  fn capture_with(&mut self, args: &CaptureArgs) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    if self.layout.is_some() {
+      return Err(Error::new(
+        "Capture arguments are not supported with a section layout",
+      ));
+    }
+    self.grow_buffer()?;
+    let result = self.ctx.capture_to_slice_with(
+      self.buffer.as_bytes_mut(),
+      &self.texture,
+      &self.texture_desc,
+      args,
+    );
+    // the frame is only copied to the region, and the pointer may be drawn
+    self.full_copy = true;
+    self.observers.notify(self.ctx.id(), result.as_ref());
+    result
+  }
+
   fn capture_with_pointer_shape(
     &mut self,
   ) -> Result<(
     DXGI_OUTDUPL_FRAME_INFO,
     Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )> {
-    let (frame_info, pointer_shape_info) = self.ctx.capture_with_pointer_shape(
-      self.buffer,
-      self.buffer_size,
-      &self.texture,
-      &self.texture_desc,
-      &mut self.pointer_shape_buffer,
-    )?;
-
-    if pointer_shape_info.is_some() {
+    self.grow_buffer()?;
+    let buffer_len = self.pointer_shape_buffer.len();
+    let result = if self.layout.is_some() {
+      self.capture_sections()
+    } else if self.apply_move_rects && !self.full_copy {
+      self.ctx.update_slice(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+        Some(&mut self.pointer_shape_buffer),
+      )
+    } else {
+      self.ctx.capture_to_slice_with_pointer_shape(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+        &mut self.pointer_shape_buffer,
+      )
+    };
+    self.full_copy = result.is_err();
+    self
+      .observers
+      .notify(self.ctx.id(), result.as_ref().map(|(info, _)| info));
+    let (frame_info, pointer_shape_info) = result?;
+
+    if pointer_shape_info.is_some() && self.layout.is_none() {
       // record the pointer shape buffer size
       self.pointer_shape_buffer_size = frame_info.PointerShapeBufferSize as usize;
+      self
+        .pointer_shape_stats
+        .record(buffer_len, self.pointer_shape_buffer_size);
     }
 
     Ok((frame_info, pointer_shape_info))
@@ -229,24 +535,24 @@ impl<'a> Capturer for SharedCapturer<'a> {
     DXGI_OUTDUPL_FRAME_INFO,
     Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )> {
-    self.check_buffer()?;
+    if !self.auto_grow {
+      self.check_buffer()?;
+    }
     self.capture_with_pointer_shape()
   }
 }
 
 impl DuplicationContext {
-  pub fn shared_capturer(&self, name: &str) -> Result<SharedCapturer> {
+  pub fn shared_capturer(&self, name: &str) -> Result<SharedCapturer<'_>> {
     SharedCapturer::new(self, name)
   }
 
-  pub fn shared_capturer_open(&self, name: &str) -> Result<SharedCapturer> {
+  pub fn shared_capturer_open(&self, name: &str) -> Result<SharedCapturer<'_>> {
     SharedCapturer::open(self, name)
   }
-}
 
-impl<'a> Drop for SharedCapturer<'a> {
-  fn drop(&mut self) {
-    self.free()
+  pub fn sectioned_shared_capturer(&self, name: &str) -> Result<SharedCapturer<'_>> {
+    SharedCapturer::sectioned(self, name)
   }
 }
 
@@ -254,7 +560,39 @@ impl<'a> Drop for SharedCapturer<'a> {
 mod tests {
   use std::{thread, time::Duration};
 
-  use crate::{capturer::model::Capturer, manager::Manager, utils::FrameInfoExt};
+  use super::SharedMemory;
+  use crate::{
+    capturer::{
+      model::{Capturer, CapturerBuffer},
+      shared_layout::SharedReader,
+    },
+    manager::Manager,
+    utils::FrameInfoExt,
+  };
+
+  #[test]
+  fn shared_memory() {
+    let mut created = SharedMemory::create("RustyDuplicationMemoryTest", 16).unwrap();
+    created.as_bytes_mut().fill(7);
+
+    // another view of the same mapping sees the data
+    let opened = SharedMemory::open("RustyDuplicationMemoryTest", 16).unwrap();
+    assert_eq!(opened.as_bytes(), [7; 16]);
+    opened.close().unwrap();
+
+    // shrink within the view, then grow by remapping
+    created.try_resize(8).unwrap();
+    assert_eq!(created.len(), 8);
+    created.try_resize(32).unwrap();
+    assert_eq!(created.len(), 32);
+
+    // an opened mapping keeps its view if it can't grow
+    let mut opened = SharedMemory::open("RustyDuplicationMemoryTest", 16).unwrap();
+    assert!(opened.try_resize(1 << 20).is_err());
+    assert_eq!(opened.as_bytes().len(), 16);
+    opened.close().unwrap();
+    created.close().unwrap();
+  }
 
   #[test]
   fn shared_capturer() {
@@ -273,14 +611,7 @@ mod tests {
 
     let buffer = capturer.buffer();
     // ensure buffer not all zero
-    let mut all_zero = true;
-    for i in 0..buffer.len() {
-      if buffer[i] != 0 {
-        all_zero = false;
-        break;
-      }
-    }
-    assert!(!all_zero);
+    assert!(buffer.iter().any(|&b| b != 0));
 
     // sleep for a while before capture to wait system to update the mouse
     thread::sleep(Duration::from_millis(1000));
@@ -291,13 +622,43 @@ mod tests {
     assert!(pointer_shape_info.is_some());
     let pointer_shape_data = capturer.pointer_shape_buffer();
     // make sure pointer shape buffer is not all zero
-    let mut all_zero = true;
-    for i in 0..pointer_shape_data.len() {
-      if pointer_shape_data[i] != 0 {
-        all_zero = false;
-        break;
-      }
+    assert!(pointer_shape_data.iter().any(|&b| b != 0));
+
+    capturer.close().unwrap();
+  }
+
+  #[test]
+  fn sectioned_shared_capturer() {
+    let manager = Manager::default().unwrap();
+    let mut capturer = manager.contexts[0]
+      .sectioned_shared_capturer("RustyDuplicationSectionedTest")
+      .unwrap();
+    let reader = SharedReader::open("RustyDuplicationSectionedTest").unwrap();
+    assert_eq!(
+      reader.header().pixels_size as usize,
+      capturer.buffer().len()
+    );
+
+    // sleep for a while before capture to wait system to update the screen and the mouse
+    thread::sleep(Duration::from_millis(1000));
+
+    let (info, pointer_shape_info) = capturer.safe_capture_with_pointer_shape().unwrap();
+    assert!(info.desktop_updated());
+    let (pixels, cursor, metadata) = reader
+      .read(|sections| {
+        (
+          sections.pixels().to_vec(),
+          sections.cursor().0,
+          sections.metadata(),
+        )
+      })
+      .unwrap();
+    assert_eq!(pixels, capturer.buffer());
+    assert_eq!(metadata.info.last_present_time, info.LastPresentTime);
+    if pointer_shape_info.is_some() {
+      assert_eq!(cursor.shape_sequence, 1);
     }
-    assert!(!all_zero);
+
+    capturer.close().unwrap();
   }
 }
@@ -0,0 +1,623 @@
+use super::model::CapturerBuffer;
+use super::shared::SharedMemory;
+use crate::error::Error;
+use crate::model::{
+  DuplicationInfo, FrameInfo, MonitorId, OutputInfo, PointerShapeInfo, Rect, Result,
+};
+use crate::utils::FormatExt;
+use std::mem::size_of;
+use std::ops::{BitOr, Range};
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT;
+
+/// The first bytes of a sectioned shared memory.
+pub const SHARED_MAGIC: [u8; 4] = *b"RDSM";
+/// Incremented on incompatible changes of the sectioned layout.
+/// Compatible changes only append fields to the header, see [`SharedHeader::header_size`].
+pub const SHARED_LAYOUT_VERSION: u32 = 1;
+/// The largest decoded pointer image the cursor section holds, in bytes.
+pub const CURSOR_CAPACITY: usize = 256 * 256 * 4;
+/// The most dirty rects the metadata section holds.
+pub const MAX_DIRTY_RECTS: usize = 512;
+/// Sections start at multiples of this.
+const SECTION_ALIGN: usize = 64;
+/// How many times [`SharedReader::read`] retries while the capturer writes.
+const READ_RETRIES: u32 = 100;
+/// How many adapters and outputs per adapter [`SharedReader::enumerate`] probes.
+const PROBED_ADAPTERS: u32 = 8;
+const PROBED_OUTPUTS: u32 = 16;
+
+/// The mapping name of a monitor by convention, `{prefix}-{adapter}-{output}`,
+/// e.g. for one producer process per monitor. Consumers find them with [`SharedReader::enumerate`].
+/// Use a `Global\` prefix to share mappings across sessions.
+pub fn shared_memory_name(prefix: &str, id: MonitorId) -> String {
+  format!("{}-{}-{}", prefix, id.adapter, id.output)
+}
+
+/// A sectioned shared memory found by [`SharedReader::enumerate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedMapping {
+  /// Open it with [`SharedReader::open`].
+  pub name: String,
+  pub monitor: MonitorId,
+  /// The header when the mapping was found, e.g. to pick a monitor by its output or frame size.
+  pub header: SharedHeader,
+}
+
+/// The header of a sectioned shared memory, at offset 0.
+///
+/// All structs of the layout are `repr(C)` with native (little-endian) integers,
+/// so other processes and languages can declare them the same way.
+/// Consumers should check the header with [`SharedHeader::check`] before reading sections.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SharedHeader {
+  /// [`SHARED_MAGIC`].
+  pub magic: [u8; 4],
+  /// [`SHARED_LAYOUT_VERSION`].
+  pub version: u32,
+  /// Odd while the capturer writes a frame, see [`SharedReader::read`].
+  pub sequence: u64,
+  /// The size of the whole layout in bytes.
+  pub size: u64,
+  pub pixels_offset: u64,
+  pub pixels_size: u64,
+  /// A [`SharedCursor`] followed by the pointer image.
+  pub cursor_offset: u64,
+  pub cursor_size: u64,
+  /// A [`SharedMetadata`] followed by the dirty rects.
+  pub metadata_offset: u64,
+  pub metadata_size: u64,
+  pub width: u32,
+  pub height: u32,
+  /// The `DXGI_FORMAT` of the pixels.
+  pub format: u32,
+  /// Bytes per row of pixels, rows are not padded.
+  pub pitch: u32,
+  /// The size of the header written by the producer, fields past the size known to a consumer
+  /// are added by newer producers and can be ignored.
+  pub header_size: u32,
+  /// [`SharedCapabilities`] bits of the producer.
+  pub capabilities: u32,
+  /// How many frames the pixel section holds one after another.
+  pub ring_size: u32,
+  pub reserved: u32,
+  /// The captured output.
+  pub output: OutputInfo,
+  pub duplication: DuplicationInfo,
+}
+
+/// Optional parts of a sectioned shared memory, as bits of [`SharedHeader::capabilities`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SharedCapabilities(pub u32);
+
+impl SharedCapabilities {
+  /// The cursor section holds the pointer position and image.
+  pub const CURSOR: Self = Self(1);
+  /// The metadata section holds the frame info.
+  pub const METADATA: Self = Self(1 << 1);
+  /// The metadata section holds the dirty rects.
+  pub const DIRTY_RECTS: Self = Self(1 << 2);
+  /// The capabilities of this crate's producer.
+  pub const ALL: Self = Self(Self::CURSOR.0 | Self::METADATA.0 | Self::DIRTY_RECTS.0);
+
+  pub fn contains(self, other: Self) -> bool {
+    self.0 & other.0 == other.0
+  }
+}
+
+impl BitOr for SharedCapabilities {
+  type Output = Self;
+
+  fn bitor(self, other: Self) -> Self {
+    Self(self.0 | other.0)
+  }
+}
+
+/// What a consumer needs from a sectioned shared memory, see [`SharedHeader::check`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SharedRequirements {
+  pub capabilities: SharedCapabilities,
+  /// Accepted pixel formats, any format if empty.
+  pub formats: Vec<DXGI_FORMAT>,
+  pub min_ring_size: u32,
+}
+
+/// The pointer position and shape, at the start of the cursor section.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SharedCursor {
+  /// Non-zero if the pointer is visible.
+  pub visible: u32,
+  /// The top-left corner of the pointer image in frame coordinates.
+  pub x: i32,
+  pub y: i32,
+  /// The size of the BGRA32 pointer image following this struct,
+  /// decoded by [`decode_pointer_shape`](crate::pointer::decode_pointer_shape).
+  pub width: u32,
+  pub height: u32,
+  /// Bytes of the image, 0 if no shape is captured yet or it exceeds [`CURSOR_CAPACITY`].
+  pub image_size: u32,
+  /// Incremented when the image changes.
+  pub shape_sequence: u32,
+  pub reserved: u32,
+  /// The info of the last captured shape, including its hot spot.
+  pub shape: PointerShapeInfo,
+}
+
+/// The frame info, at the start of the metadata section.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SharedMetadata {
+  pub info: FrameInfo,
+  /// The count of dirty rects following this struct, each as `[left, top, right, bottom]` `i32`s.
+  pub dirty_rect_count: u32,
+  /// Non-zero if the dirty rects are unknown or more than [`MAX_DIRTY_RECTS`],
+  /// the whole frame should be treated as dirty.
+  pub dirty_rects_overflow: u32,
+}
+
+impl SharedHeader {
+  /// Lay out the sections for `pixels_size` bytes of `width`x`height` pixels.
+  pub(crate) fn new(width: u32, height: u32, format: DXGI_FORMAT, pixels_size: usize) -> Self {
+    let align = |offset: usize| offset.next_multiple_of(SECTION_ALIGN);
+    let pixels_offset = align(size_of::<SharedHeader>());
+    let cursor_offset = align(pixels_offset + pixels_size);
+    let cursor_size = size_of::<SharedCursor>() + CURSOR_CAPACITY;
+    let metadata_offset = align(cursor_offset + cursor_size);
+    let metadata_size = size_of::<SharedMetadata>() + MAX_DIRTY_RECTS * size_of::<[i32; 4]>();
+    Self {
+      magic: SHARED_MAGIC,
+      version: SHARED_LAYOUT_VERSION,
+      sequence: 0,
+      size: (metadata_offset + metadata_size) as u64,
+      pixels_offset: pixels_offset as u64,
+      pixels_size: pixels_size as u64,
+      cursor_offset: cursor_offset as u64,
+      cursor_size: cursor_size as u64,
+      metadata_offset: metadata_offset as u64,
+      metadata_size: metadata_size as u64,
+      width,
+      height,
+      format: format.0,
+      pitch: width * format.bytes_per_pixel() as u32,
+      header_size: size_of::<SharedHeader>() as u32,
+      capabilities: SharedCapabilities::ALL.0,
+      ring_size: 1,
+      reserved: 0,
+      output: OutputInfo::default(),
+      duplication: DuplicationInfo::default(),
+    }
+  }
+
+  pub fn is_valid(&self) -> bool {
+    self.check_version().is_ok()
+  }
+
+  fn check_version(&self) -> Result<()> {
+    if self.magic != SHARED_MAGIC {
+      return Err(Error::new("Not a sectioned shared memory"));
+    }
+    if self.version != SHARED_LAYOUT_VERSION {
+      return Err(Error::new(format!(
+        "Unsupported shared memory layout version {}, expected {}",
+        self.version, SHARED_LAYOUT_VERSION
+      )));
+    }
+    if (self.header_size as usize) < size_of::<SharedHeader>() {
+      return Err(Error::new(format!(
+        "Shared memory header of {} bytes is shorter than {} bytes",
+        self.header_size,
+        size_of::<SharedHeader>()
+      )));
+    }
+    Ok(())
+  }
+
+  pub fn capabilities(&self) -> SharedCapabilities {
+    SharedCapabilities(self.capabilities)
+  }
+
+  /// Check the layout version and whether the producer meets `requirements`,
+  /// returning an error naming the mismatch.
+  pub fn check(&self, requirements: &SharedRequirements) -> Result<()> {
+    self.check_version()?;
+    if !self.capabilities().contains(requirements.capabilities) {
+      return Err(Error::new(format!(
+        "Shared memory lacks capabilities {:#x}",
+        requirements.capabilities.0 & !self.capabilities
+      )));
+    }
+    if !requirements.formats.is_empty() && !requirements.formats.contains(&DXGI_FORMAT(self.format))
+    {
+      return Err(Error::new(format!(
+        "Shared memory pixel format {} is not accepted",
+        self.format
+      )));
+    }
+    if self.ring_size < requirements.min_ring_size {
+      return Err(Error::new(format!(
+        "Shared memory holds {} frames, at least {} are required",
+        self.ring_size, requirements.min_ring_size
+      )));
+    }
+    Ok(())
+  }
+
+  pub fn pixels(&self) -> Range<usize> {
+    section(self.pixels_offset, self.pixels_size)
+  }
+
+  pub fn cursor(&self) -> Range<usize> {
+    section(self.cursor_offset, self.cursor_size)
+  }
+
+  pub fn metadata(&self) -> Range<usize> {
+    section(self.metadata_offset, self.metadata_size)
+  }
+}
+
+fn section(offset: u64, size: u64) -> Range<usize> {
+  offset as usize..(offset + size) as usize
+}
+
+/// Read a `repr(C)` struct of integers at `offset`.
+fn read_struct<T: Copy>(bytes: &[u8], offset: usize) -> T {
+  let bytes = &bytes[offset..offset + size_of::<T>()];
+  unsafe { ptr::read_unaligned(bytes.as_ptr() as *const T) }
+}
+
+fn write_struct<T: Copy>(bytes: &mut [u8], offset: usize, value: &T) {
+  let bytes = &mut bytes[offset..offset + size_of::<T>()];
+  unsafe { ptr::write_unaligned(bytes.as_mut_ptr() as *mut T, *value) }
+}
+
+/// The sequence number of the header, shared with other processes.
+fn sequence(bytes: &[u8]) -> &AtomicU64 {
+  let sequence = &bytes[8..16];
+  // the mapping is page-aligned, so the sequence is 8-byte aligned
+  unsafe { &*(sequence.as_ptr() as *const AtomicU64) }
+}
+
+/// Write a frame to a sectioned layout. The sequence is odd until this is dropped.
+pub(crate) struct SectionWriter<'a> {
+  bytes: &'a mut [u8],
+  header: SharedHeader,
+}
+
+impl<'a> SectionWriter<'a> {
+  /// `bytes` must hold `header.size` bytes.
+  pub(crate) fn begin(bytes: &'a mut [u8], header: SharedHeader) -> Self {
+    let current = sequence(bytes).load(Ordering::Relaxed);
+    // an odd sequence is left by a writer which crashed while writing
+    sequence(bytes).store(current + 1 + (current & 1), Ordering::Relaxed);
+    fence(Ordering::Release);
+    let header = SharedHeader {
+      sequence: sequence(bytes).load(Ordering::Relaxed),
+      ..header
+    };
+    write_struct(bytes, 0, &header);
+    Self { bytes, header }
+  }
+
+  pub(crate) fn pixels_mut(&mut self) -> &mut [u8] {
+    &mut self.bytes[self.header.pixels()]
+  }
+
+  /// Write the pointer state, and the image if it is `Some`.
+  pub(crate) fn set_cursor(&mut self, cursor: &SharedCursor, image: Option<&[u8]>) {
+    let section = self.header.cursor();
+    if let Some(image) = image {
+      let start = section.start + size_of::<SharedCursor>();
+      self.bytes[start..start + image.len()].copy_from_slice(image);
+    }
+    write_struct(self.bytes, section.start, cursor);
+  }
+
+  /// Write the frame info and the dirty rects, `None` if they are unknown.
+  pub(crate) fn set_metadata(&mut self, info: &FrameInfo, dirty_rects: Option<&[Rect]>) {
+    let section = self.header.metadata();
+    let rects = dirty_rects.unwrap_or_default();
+    let count = rects.len().min(MAX_DIRTY_RECTS);
+    let start = section.start + size_of::<SharedMetadata>();
+    for (i, rect) in rects[..count].iter().enumerate() {
+      let rect = [rect.left, rect.top, rect.right, rect.bottom];
+      write_struct(self.bytes, start + i * size_of::<[i32; 4]>(), &rect);
+    }
+    let metadata = SharedMetadata {
+      info: *info,
+      dirty_rect_count: count as u32,
+      dirty_rects_overflow: (dirty_rects.is_none() || rects.len() > MAX_DIRTY_RECTS) as u32,
+    };
+    write_struct(self.bytes, section.start, &metadata);
+  }
+}
+
+impl Drop for SectionWriter<'_> {
+  fn drop(&mut self) {
+    sequence(self.bytes).store(self.header.sequence + 1, Ordering::Release);
+  }
+}
+
+/// A consistent view of the sections, see [`SharedReader::read`].
+pub struct SharedSections<'a> {
+  bytes: &'a [u8],
+  header: SharedHeader,
+}
+
+impl SharedSections<'_> {
+  pub fn header(&self) -> &SharedHeader {
+    &self.header
+  }
+
+  pub fn pixels(&self) -> &[u8] {
+    &self.bytes[self.header.pixels()]
+  }
+
+  /// The pointer state and its BGRA32 image.
+  pub fn cursor(&self) -> (SharedCursor, &[u8]) {
+    let section = self.header.cursor();
+    let cursor: SharedCursor = read_struct(self.bytes, section.start);
+    let start = section.start + size_of::<SharedCursor>();
+    let size = (cursor.image_size as usize).min(CURSOR_CAPACITY);
+    (cursor, &self.bytes[start..start + size])
+  }
+
+  pub fn metadata(&self) -> SharedMetadata {
+    read_struct(self.bytes, self.header.metadata().start)
+  }
+
+  pub fn dirty_rects(&self) -> Vec<Rect> {
+    let start = self.header.metadata().start + size_of::<SharedMetadata>();
+    let count = (self.metadata().dirty_rect_count as usize).min(MAX_DIRTY_RECTS);
+    (0..count)
+      .map(|i| {
+        let [left, top, right, bottom]: [i32; 4] =
+          read_struct(self.bytes, start + i * size_of::<[i32; 4]>());
+        Rect::new(left, top, right, bottom)
+      })
+      .collect()
+  }
+}
+
+/// Read the sectioned shared memory of a
+/// [`SharedCapturer::sectioned`](super::shared::SharedCapturer::sectioned) from another process.
+pub struct SharedReader {
+  memory: SharedMemory,
+}
+
+impl SharedReader {
+  pub fn open(name: &str) -> Result<Self> {
+    Self::open_with(name, &SharedRequirements::default())
+  }
+
+  /// Open the memory if its producer meets `requirements`, see [`SharedHeader::check`].
+  pub fn open_with(name: &str, requirements: &SharedRequirements) -> Result<Self> {
+    let memory = SharedMemory::open(name, size_of::<SharedHeader>())?;
+    let header: SharedHeader = read_struct(memory.as_bytes(), 0);
+    memory.close()?;
+    header.check(requirements)?;
+    Ok(Self {
+      memory: SharedMemory::open(name, header.size as usize)?,
+    })
+  }
+
+  /// List the sectioned memories named by [`shared_memory_name`] with `prefix`, ordered by monitor.
+  ///
+  /// Windows can't list named mappings, so the names of the first 8 adapters
+  /// with 16 outputs each are probed. Mappings which aren't sectioned
+  /// or have an unsupported layout version are skipped.
+  pub fn enumerate(prefix: &str) -> Vec<SharedMapping> {
+    let mut mappings = Vec::new();
+    for adapter in 0..PROBED_ADAPTERS {
+      for output in 0..PROBED_OUTPUTS {
+        let monitor = MonitorId { adapter, output };
+        let name = shared_memory_name(prefix, monitor);
+        let Ok(memory) = SharedMemory::open(&name, size_of::<SharedHeader>()) else {
+          continue;
+        };
+        let header: SharedHeader = read_struct(memory.as_bytes(), 0);
+        if header.is_valid() {
+          mappings.push(SharedMapping {
+            name,
+            monitor,
+            header,
+          });
+        }
+      }
+    }
+    mappings
+  }
+
+  /// The header, which may change while reading, prefer [`SharedReader::read`].
+  pub fn header(&self) -> SharedHeader {
+    read_struct(self.memory.as_bytes(), 0)
+  }
+
+  /// Run `f` on the sections until it runs while the capturer doesn't write, and return its result.
+  /// Return `None` if the capturer keeps writing,
+  /// or if the capturer grew the memory, which must be opened again.
+  pub fn read<T>(&self, mut f: impl FnMut(&SharedSections) -> T) -> Option<T> {
+    read_consistent(self.memory.as_bytes(), &mut f)
+  }
+}
+
+fn read_consistent<T>(bytes: &[u8], f: &mut impl FnMut(&SharedSections) -> T) -> Option<T> {
+  for _ in 0..READ_RETRIES {
+    let before = sequence(bytes).load(Ordering::Acquire);
+    if before % 2 == 1 {
+      std::thread::yield_now();
+      continue;
+    }
+    let header: SharedHeader = read_struct(bytes, 0);
+    if !header.is_valid() || header.size as usize != bytes.len() {
+      return None;
+    }
+    let result = f(&SharedSections { bytes, header });
+    fence(Ordering::Acquire);
+    if sequence(bytes).load(Ordering::Relaxed) == before {
+      return Some(result);
+    }
+  }
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    read_consistent, shared_memory_name, write_struct, SectionWriter, SharedCapabilities,
+    SharedCursor, SharedHeader, SharedReader, SharedRequirements, MAX_DIRTY_RECTS,
+    SHARED_LAYOUT_VERSION,
+  };
+  use crate::capturer::model::CapturerBuffer;
+  use crate::capturer::shared::SharedMemory;
+  use crate::model::{FrameInfo, MonitorId, Rect};
+  use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R16G16B16A16_FLOAT,
+  };
+
+  #[test]
+  fn layout() {
+    let header = SharedHeader::new(4, 2, DXGI_FORMAT_B8G8R8A8_UNORM, 32);
+    assert!(header.is_valid());
+    assert_eq!(header.pitch, 16);
+    assert_eq!(header.pixels(), 256..288);
+    // sections don't overlap and start at multiples of 64
+    assert_eq!(header.cursor().start, 320);
+    assert!(header.metadata().start >= header.cursor().end);
+    assert_eq!(header.metadata().start % 64, 0);
+    assert_eq!(header.size as usize, header.metadata().end);
+  }
+
+  #[test]
+  fn negotiation() {
+    let header = SharedHeader::new(4, 2, DXGI_FORMAT_B8G8R8A8_UNORM, 32);
+    assert!(header.check(&SharedRequirements::default()).is_ok());
+    let requirements = SharedRequirements {
+      capabilities: SharedCapabilities::CURSOR | SharedCapabilities::DIRTY_RECTS,
+      formats: vec![DXGI_FORMAT_B8G8R8A8_UNORM],
+      min_ring_size: 1,
+    };
+    assert!(header.check(&requirements).is_ok());
+
+    let without_cursor = SharedHeader {
+      capabilities: SharedCapabilities::METADATA.0,
+      ..header
+    };
+    assert!(without_cursor.check(&requirements).is_err());
+    let hdr = SharedHeader {
+      format: DXGI_FORMAT_R16G16B16A16_FLOAT.0,
+      ..header
+    };
+    assert!(hdr.check(&requirements).is_err());
+    let ring = SharedRequirements {
+      min_ring_size: 2,
+      ..Default::default()
+    };
+    assert!(header.check(&ring).is_err());
+
+    // newer producers may append fields, but not change the version
+    let newer = SharedHeader {
+      header_size: header.header_size + 8,
+      ..header
+    };
+    assert!(newer.is_valid());
+    let incompatible = SharedHeader {
+      version: SHARED_LAYOUT_VERSION + 1,
+      ..header
+    };
+    assert!(!incompatible.is_valid());
+  }
+
+  #[test]
+  fn sections() {
+    let header = SharedHeader::new(2, 1, DXGI_FORMAT_B8G8R8A8_UNORM, 8);
+    // u64 words keep the sequence aligned like a mapped view
+    let mut words = vec![0u64; (header.size as usize).div_ceil(8)];
+    let bytes = unsafe {
+      std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, header.size as usize)
+    };
+
+    let cursor = SharedCursor {
+      visible: 1,
+      x: 5,
+      width: 1,
+      height: 1,
+      image_size: 4,
+      ..Default::default()
+    };
+    {
+      let mut writer = SectionWriter::begin(bytes, header);
+      writer.pixels_mut().fill(7);
+      writer.set_cursor(&cursor, Some(&[1, 2, 3, 4]));
+      writer.set_metadata(
+        &FrameInfo {
+          last_present_time: 42,
+          ..Default::default()
+        },
+        Some(&[Rect::new(0, 0, 2, 1)]),
+      );
+      // readers wait while the frame is written
+      assert!(read_consistent(writer.bytes, &mut |_| ()).is_none());
+    }
+
+    let (pixels, (read_cursor, image), metadata, rects) = read_consistent(bytes, &mut |sections| {
+      (
+        sections.pixels().to_vec(),
+        (sections.cursor().0, sections.cursor().1.to_vec()),
+        sections.metadata(),
+        sections.dirty_rects(),
+      )
+    })
+    .unwrap();
+    assert_eq!(pixels, [7; 8]);
+    assert_eq!(read_cursor, cursor);
+    assert_eq!(image, [1, 2, 3, 4]);
+    assert_eq!(metadata.info.last_present_time, 42);
+    assert_eq!(metadata.dirty_rects_overflow, 0);
+    assert_eq!(rects, [Rect::new(0, 0, 2, 1)]);
+
+    // too many dirty rects are truncated and flagged
+    let rects = vec![Rect::new(0, 0, 1, 1); MAX_DIRTY_RECTS + 1];
+    SectionWriter::begin(bytes, header).set_metadata(&FrameInfo::default(), Some(&rects));
+    let metadata = read_consistent(bytes, &mut |sections| sections.metadata()).unwrap();
+    assert_eq!(metadata.dirty_rect_count as usize, MAX_DIRTY_RECTS);
+    assert_eq!(metadata.dirty_rects_overflow, 1);
+  }
+  #[test]
+  fn enumerate() {
+    let prefix = "rusty-duplication-enumerate";
+    let monitor = |adapter, output| MonitorId { adapter, output };
+    assert_eq!(
+      shared_memory_name(prefix, monitor(1, 2)),
+      format!("{}-1-2", prefix)
+    );
+
+    let header = SharedHeader::new(2, 1, DXGI_FORMAT_B8G8R8A8_UNORM, 8);
+    let mut memories = Vec::new();
+    for id in [monitor(1, 0), monitor(0, 3)] {
+      let mut memory =
+        SharedMemory::create(&shared_memory_name(prefix, id), header.size as usize).unwrap();
+      write_struct(memory.as_bytes_mut(), 0, &header);
+      memories.push(memory);
+    }
+    // not sectioned
+    memories.push(SharedMemory::create(&shared_memory_name(prefix, monitor(0, 0)), 4096).unwrap());
+
+    let mappings = SharedReader::enumerate(prefix);
+    assert_eq!(
+      mappings
+        .iter()
+        .map(|mapping| mapping.monitor)
+        .collect::<Vec<_>>(),
+      [monitor(0, 3), monitor(1, 0)]
+    );
+    assert_eq!(mappings[0].name, shared_memory_name(prefix, monitor(0, 3)));
+    assert_eq!(mappings[0].header, header);
+    assert!(SharedReader::open(&mappings[1].name).is_ok());
+
+    drop(memories);
+    assert!(SharedReader::enumerate(prefix).is_empty());
+  }
+}
@@ -1,8 +1,10 @@
-use super::model::Capturer;
+use super::model::{self, Capturer, CapturerBuffer, MemoryUsage, PointerShapeStats};
+use super::observer::{CaptureObserver, Observers};
 use crate::duplication_context::DuplicationContext;
 use crate::error::Error;
-use crate::model::Result;
+use crate::model::{CaptureArgs, Result};
 use crate::utils::OutDuplDescExt;
+use std::sync::Arc;
 use windows::Win32::Graphics::Direct3D11::D3D11_TEXTURE2D_DESC;
 use windows::Win32::Graphics::Dxgi::{
   DXGI_OUTDUPL_DESC, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_POINTER_SHAPE_INFO,
@@ -17,6 +19,12 @@ pub struct SimpleCapturer<'a> {
   texture_desc: D3D11_TEXTURE2D_DESC,
   pointer_shape_buffer: Vec<u8>,
   pointer_shape_buffer_size: usize,
+  pointer_shape_stats: PointerShapeStats,
+  observers: Observers,
+  auto_grow: bool,
+  apply_move_rects: bool,
+  /// Copy the whole next frame even if move rects are applied, since the buffer missed a frame.
+  full_copy: bool,
 }
 
 impl<'a> SimpleCapturer<'a> {
@@ -29,6 +37,11 @@ impl<'a> SimpleCapturer<'a> {
       texture_desc,
       pointer_shape_buffer: Vec::new(),
       pointer_shape_buffer_size: 0,
+      pointer_shape_stats: PointerShapeStats::default(),
+      observers: Observers::default(),
+      auto_grow: false,
+      apply_move_rects: false,
+      full_copy: true,
     })
   }
 
@@ -39,6 +52,22 @@ impl<'a> SimpleCapturer<'a> {
     let buffer = vec![0u8; desc.calc_buffer_size()];
     Ok((buffer, texture, texture_desc))
   }
+
+  /// Grow the buffer and the texture if auto grow is enabled.
+  fn grow_buffer(&mut self) -> Result<()> {
+    if !self.auto_grow {
+      return Ok(());
+    }
+    let buffer = &mut self.buffer;
+    self.full_copy |= model::auto_grow(
+      self.ctx,
+      &mut self.texture,
+      &mut self.texture_desc,
+      &self.observers,
+      |_, len| buffer.try_fit(len),
+    )?;
+    Ok(())
+  }
 }
 
 impl Capturer for SimpleCapturer<'_> {
@@ -70,37 +99,119 @@ impl Capturer for SimpleCapturer<'_> {
     &self.pointer_shape_buffer[..self.pointer_shape_buffer_size]
   }
 
+  fn reserve_pointer_shape_buffer(&mut self, size: usize) {
+    if self.pointer_shape_buffer.len() < size {
+      self.pointer_shape_buffer.resize(size, 0);
+    }
+  }
+
+  fn pointer_shape_stats(&self) -> PointerShapeStats {
+    self.pointer_shape_stats
+  }
+
+  fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      buffers: self.buffer.capacity(),
+      pointer_shape_buffer: self.pointer_shape_buffer.capacity(),
+      staging_textures: MemoryUsage::texture_bytes(&self.texture_desc),
+      pools: 0,
+    }
+  }
+
+  fn add_observer(&mut self, observer: Arc<dyn CaptureObserver>) {
+    self.observers.add(observer);
+  }
+
+  fn set_auto_grow(&mut self, auto_grow: bool) {
+    self.auto_grow = auto_grow;
+  }
+
+  fn set_apply_move_rects(&mut self, apply_move_rects: bool) {
+    self.apply_move_rects = apply_move_rects;
+    self.full_copy = true;
+  }
+
   fn capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
-    self.ctx.capture(
-      self.buffer.as_mut_ptr(),
-      self.buffer.len(),
-      &self.texture,
-      &self.texture_desc,
-    )
+    self.grow_buffer()?;
+    let result = if self.apply_move_rects && !self.full_copy {
+      self
+        .ctx
+        .update_slice(
+          self.buffer.as_bytes_mut(),
+          &self.texture,
+          &self.texture_desc,
+          None,
+        )
+        .map(|(info, _)| info)
+    } else {
+      self.ctx.capture_to_slice(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+      )
+    };
+    // a failed capture may have acquired a frame which isn't in the buffer
+    self.full_copy = result.is_err();
+    self.observers.notify(self.ctx.id(), result.as_ref());
+    result
   }
 
   fn safe_capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
-    self.check_buffer()?;
+    if !self.auto_grow {
+      self.check_buffer()?;
+    }
     self.capture()
   }
 
+  fn capture_with(&mut self, args: &CaptureArgs) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    self.grow_buffer()?;
+    let result = self.ctx.capture_to_slice_with(
+      self.buffer.as_bytes_mut(),
+      &self.texture,
+      &self.texture_desc,
+      args,
+    );
+    // the frame is only copied to the region, and the pointer may be drawn
+    self.full_copy = true;
+    self.observers.notify(self.ctx.id(), result.as_ref());
+    result
+  }
+
   fn capture_with_pointer_shape(
     &mut self,
   ) -> Result<(
     DXGI_OUTDUPL_FRAME_INFO,
     Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )> {
-    let (frame_info, pointer_shape_info) = self.ctx.capture_with_pointer_shape(
-      self.buffer.as_mut_ptr(),
-      self.buffer.len(),
-      &self.texture,
-      &self.texture_desc,
-      &mut self.pointer_shape_buffer,
-    )?;
+    self.grow_buffer()?;
+    let buffer_len = self.pointer_shape_buffer.len();
+    let result = if self.apply_move_rects && !self.full_copy {
+      self.ctx.update_slice(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+        Some(&mut self.pointer_shape_buffer),
+      )
+    } else {
+      self.ctx.capture_to_slice_with_pointer_shape(
+        self.buffer.as_bytes_mut(),
+        &self.texture,
+        &self.texture_desc,
+        &mut self.pointer_shape_buffer,
+      )
+    };
+    self.full_copy = result.is_err();
+    self
+      .observers
+      .notify(self.ctx.id(), result.as_ref().map(|(info, _)| info));
+    let (frame_info, pointer_shape_info) = result?;
 
     if pointer_shape_info.is_some() {
       // record the pointer shape buffer size
       self.pointer_shape_buffer_size = frame_info.PointerShapeBufferSize as usize;
+      self
+        .pointer_shape_stats
+        .record(buffer_len, self.pointer_shape_buffer_size);
     }
 
     Ok((frame_info, pointer_shape_info))
@@ -112,22 +223,32 @@ impl Capturer for SimpleCapturer<'_> {
     DXGI_OUTDUPL_FRAME_INFO,
     Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )> {
-    self.check_buffer()?;
+    if !self.auto_grow {
+      self.check_buffer()?;
+    }
     self.capture_with_pointer_shape()
   }
 }
 
 impl DuplicationContext {
-  pub fn simple_capturer(&self) -> Result<SimpleCapturer> {
+  pub fn simple_capturer(&self) -> Result<SimpleCapturer<'_>> {
     SimpleCapturer::new(self)
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use std::{thread, time::Duration};
+  use std::{
+    thread,
+    time::{Duration, Instant},
+  };
 
-  use crate::{capturer::model::Capturer, manager::Manager, utils::FrameInfoExt};
+  use crate::{
+    capturer::model::{Capturer, MAX_POINTER_SHAPE_SIZE},
+    manager::Manager,
+    model::{CaptureArgs, Rect},
+    utils::FrameInfoExt,
+  };
 
   #[test]
   fn simple_capturer() {
@@ -144,14 +265,18 @@ mod tests {
 
     let buffer = capturer.buffer();
     // ensure buffer not all zero
-    let mut all_zero = true;
-    for i in 0..buffer.len() {
-      if buffer[i] != 0 {
-        all_zero = false;
-        break;
-      }
-    }
-    assert!(!all_zero);
+    assert!(buffer.iter().any(|&b| b != 0));
+
+    // sample the captured frame
+    let (width, height) = capturer.frame_size().unwrap();
+    assert_eq!(capturer.pixel_at(0, 0).unwrap().unwrap(), buffer[..4]);
+    assert!(capturer.pixel_at(width, 0).unwrap().is_none());
+    let all = Rect::new(0, 0, width as i32, height as i32);
+    assert!(capturer.average_color(&all).unwrap().is_some());
+
+    let usage = capturer.memory_usage();
+    assert!(usage.buffers >= capturer.buffer().len());
+    assert!(usage.staging_textures >= usage.buffers);
 
     // sleep for a while before capture to wait system to update the mouse
     thread::sleep(Duration::from_millis(1000));
@@ -162,13 +287,126 @@ mod tests {
     assert!(pointer_shape_info.is_some());
     let pointer_shape_data = capturer.pointer_shape_buffer();
     // make sure pointer shape buffer is not all zero
-    let mut all_zero = true;
-    for i in 0..pointer_shape_data.len() {
-      if pointer_shape_data[i] != 0 {
-        all_zero = false;
-        break;
+    assert!(pointer_shape_data.iter().any(|&b| b != 0));
+  }
+
+  #[test]
+  fn warm_up() {
+    let manager = Manager::default().unwrap();
+    let mut capturer = manager.contexts[0].simple_capturer().unwrap();
+
+    // no sleep before the first capture
+    let info = capturer
+      .warm_up(Instant::now() + Duration::from_secs(1))
+      .unwrap();
+    assert!(info.desktop_updated());
+    assert!(capturer.buffer().iter().any(|&b| b != 0));
+  }
+
+  #[test]
+  fn capture_with() {
+    let manager = Manager::default().unwrap();
+    let mut capturer = manager.contexts[0].simple_capturer().unwrap();
+    capturer
+      .warm_up(Instant::now() + Duration::from_secs(1))
+      .unwrap();
+
+    // only the region is overwritten
+    let (width, _) = capturer.frame_size().unwrap();
+    capturer.buffer_mut().fill(0);
+    let args = CaptureArgs {
+      timeout_ms: Some(0),
+      region: Some(Rect::new(0, 0, 2, 1)),
+      include_cursor: false,
+    };
+    match capturer.capture_with(&args) {
+      Ok(_) => {
+        let buffer = capturer.buffer();
+        assert!(buffer[8..width as usize * 4].iter().all(|&b| b == 0));
       }
+      // nothing changed within the zero timeout
+      Err(e) => assert!(e.windows.is_some()),
     }
-    assert!(!all_zero);
+  }
+
+  #[test]
+  fn auto_grow() {
+    let manager = Manager::default().unwrap();
+    let mut capturer = manager.contexts[0].simple_capturer().unwrap();
+    let len = capturer.buffer.len();
+
+    let texture_desc = capturer.texture_desc;
+
+    // simulate a resolution increase
+    capturer.buffer.truncate(len / 2);
+    capturer.texture_desc.Height /= 2;
+    assert!(capturer.safe_capture().is_err());
+
+    // the texture is recreated with the buffer
+    capturer.set_auto_grow(true);
+    thread::sleep(Duration::from_millis(100));
+    capturer.capture().unwrap();
+    assert_eq!(capturer.buffer().len(), len);
+    assert_eq!(capturer.texture_desc.Height, texture_desc.Height);
+  }
+
+  #[test]
+  fn apply_move_rects() {
+    let manager = Manager::default().unwrap();
+    let mut capturer = manager.contexts[0].simple_capturer().unwrap();
+    capturer.set_apply_move_rects(true);
+
+    // the first frame updates the whole desktop
+    thread::sleep(Duration::from_millis(100));
+    let info = capturer.safe_capture().unwrap();
+    assert!(info.desktop_updated());
+    assert!(capturer.buffer().iter().any(|&b| b != 0));
+
+    thread::sleep(Duration::from_millis(100));
+    capturer.safe_capture_with_pointer_shape().unwrap();
+  }
+
+  #[test]
+  fn capture_with_move_rects() {
+    let manager = Manager::default().unwrap();
+    let mut capturer = manager.contexts[0].simple_capturer().unwrap();
+    capturer.set_apply_move_rects(true);
+    capturer
+      .warm_up(Instant::now() + Duration::from_secs(1))
+      .unwrap();
+    assert!(!capturer.full_copy);
+
+    // a region capture leaves the rest of the buffer behind the desktop
+    capturer.buffer_mut().fill(0);
+    let args = CaptureArgs {
+      region: Some(Rect::new(0, 0, 2, 1)),
+      ..Default::default()
+    };
+    thread::sleep(Duration::from_millis(100));
+    capturer.capture_with(&args).unwrap();
+    assert!(capturer.full_copy);
+
+    // so the next capture copies the whole frame
+    thread::sleep(Duration::from_millis(100));
+    capturer.capture().unwrap();
+    assert!(!capturer.full_copy);
+    let len = capturer.buffer().len();
+    assert!(capturer.buffer()[len / 2..].iter().any(|&b| b != 0));
+  }
+
+  #[test]
+  fn reserve_pointer_shape_buffer() {
+    let manager = Manager::default().unwrap();
+    let mut capturer = manager.contexts[0].simple_capturer().unwrap();
+    capturer.reserve_pointer_shape_buffer(MAX_POINTER_SHAPE_SIZE);
+    assert!(capturer.memory_usage().pointer_shape_buffer >= MAX_POINTER_SHAPE_SIZE);
+
+    // the first frame after duplicating the output contains the pointer shape
+    thread::sleep(Duration::from_millis(100));
+    let (_, pointer_shape_info) = capturer.safe_capture_with_pointer_shape().unwrap();
+    let stats = capturer.pointer_shape_stats();
+    assert_eq!(stats.shapes, pointer_shape_info.is_some() as u64);
+    assert_eq!(stats.growths, 0);
+    assert_eq!(stats.max_size, capturer.pointer_shape_buffer().len());
   }
 }
@@ -0,0 +1,214 @@
+use super::model::MemoryUsage;
+use super::observer::{CaptureObserver, Observers};
+use crate::duplication_context::DuplicationContext;
+use crate::model::Result;
+use crate::utils::OutDuplDescExt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use windows::core::ComInterface;
+use windows::Win32::Graphics::Direct3D11::{ID3D11Texture2D, D3D11_TEXTURE2D_DESC};
+use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_FRAME_INFO;
+
+/// How much of the frame being copied by a [`SlicedCapturer`] is in its buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceProgress {
+  /// `copied` of `total` slices of the frame are in the buffer.
+  Partial { copied: u32, total: u32 },
+  /// The whole frame is in the buffer.
+  Complete(DXGI_OUTDUPL_FRAME_INFO),
+}
+
+/// The frame being copied.
+struct Pending {
+  info: DXGI_OUTDUPL_FRAME_INFO,
+  next: u32,
+  total: u32,
+}
+
+/// Capture screen to a `Vec<u8>`, splitting the CPU copy of each frame into horizontal slices
+/// performed across successive calls, so no single call blocks for a whole frame copy.
+///
+/// The frame is copied to the readable texture and released when its first slice is captured,
+/// later slices only read the texture.
+/// While a frame is partial, the buffer mixes rows of it and of the previous frame.
+pub struct SlicedCapturer<'a> {
+  buffer: Vec<u8>,
+  ctx: &'a DuplicationContext,
+  texture: ID3D11Texture2D,
+  texture_desc: D3D11_TEXTURE2D_DESC,
+  slices: u32,
+  pending: Option<Pending>,
+  /// How long the last slice took, to predict the next one.
+  slice_time: Duration,
+  observers: Observers,
+}
+
+impl<'a> SlicedCapturer<'a> {
+  /// Split each frame into `slices` slices, at least 1.
+  pub fn new(ctx: &'a DuplicationContext, slices: u32) -> Result<Self> {
+    let (texture, desc, texture_desc) = ctx.create_readable_texture()?;
+    Ok(Self {
+      buffer: vec![0u8; desc.calc_buffer_size()],
+      ctx,
+      texture,
+      texture_desc,
+      slices: slices.max(1),
+      pending: None,
+      slice_time: Duration::ZERO,
+      observers: Observers::default(),
+    })
+  }
+
+  /// Get the buffer of the last captured slice, in the format of the readable texture.
+  pub fn buffer(&self) -> &[u8] {
+    &self.buffer
+  }
+
+  pub fn slices(&self) -> u32 {
+    self.slices
+  }
+
+  /// Takes effect from the next frame.
+  pub fn set_slices(&mut self, slices: u32) {
+    self.slices = slices.max(1);
+  }
+
+  /// See [`Capturer::memory_usage`](super::model::Capturer::memory_usage).
+  pub fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      buffers: self.buffer.capacity(),
+      staging_textures: MemoryUsage::texture_bytes(&self.texture_desc),
+      ..Default::default()
+    }
+  }
+
+  /// Return `true` if a frame is partially copied.
+  pub fn is_partial(&self) -> bool {
+    self.pending.is_some()
+  }
+
+  /// Register an observer notified when a frame is complete or capturing fails.
+  pub fn add_observer(&mut self, observer: Arc<dyn CaptureObserver>) {
+    self.observers.add(observer);
+  }
+
+  /// Copy the next slice, acquiring a new frame first if the previous one is complete.
+  pub fn capture(&mut self) -> Result<SliceProgress> {
+    let result = self.capture_slice();
+    match &result {
+      Ok(SliceProgress::Complete(info)) => self.observers.frame(self.ctx.id(), info),
+      Err(e) => self.observers.error(e),
+      _ => {}
+    }
+    result
+  }
+
+  /// Copy slices until the frame is complete or the next slice is expected to exceed `budget`,
+  /// judging by how long the previous slice took. At least one slice is copied.
+  pub fn capture_within(&mut self, budget: Duration) -> Result<SliceProgress> {
+    let start = Instant::now();
+    loop {
+      let progress = self.capture()?;
+      if matches!(progress, SliceProgress::Complete(_))
+        || start.elapsed() + self.slice_time > budget
+      {
+        return Ok(progress);
+      }
+    }
+  }
+
+  fn capture_slice(&mut self) -> Result<SliceProgress> {
+    let start = Instant::now();
+    let mut pending = match self.pending.take() {
+      Some(pending) => pending,
+      None => {
+        self.ctx.check_dest(&self.buffer, &self.texture_desc)?;
+        let frame = self.ctx.acquire()?;
+        self.ctx.copy_resource(&self.texture, &frame.texture()?)?;
+        let info = *frame.info();
+        frame.release()?;
+        Pending {
+          info,
+          next: 0,
+          total: self.slices,
+        }
+      }
+    };
+
+    let height = self.texture_desc.Height;
+    let rows = slice_rows(pending.next, pending.total, height);
+    self.ctx.copy_surface_rows(
+      &self.texture.cast().unwrap(),
+      &mut self.buffer,
+      &self.texture_desc,
+      rows,
+    )?;
+    pending.next += 1;
+    self.slice_time = start.elapsed();
+
+    if pending.next == pending.total {
+      return Ok(SliceProgress::Complete(pending.info));
+    }
+    let progress = SliceProgress::Partial {
+      copied: pending.next,
+      total: pending.total,
+    };
+    self.pending = Some(pending);
+    Ok(progress)
+  }
+}
+
+/// The rows of slice `index` of `total` slices of `height` rows, which differ by at most one row.
+fn slice_rows(index: u32, total: u32, height: u32) -> std::ops::Range<u32> {
+  let row = |i: u32| (i as u64 * height as u64 / total as u64) as u32;
+  row(index)..row(index + 1)
+}
+
+impl DuplicationContext {
+  pub fn sliced_capturer(&self, slices: u32) -> Result<SlicedCapturer<'_>> {
+    SlicedCapturer::new(self, slices)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{slice_rows, SliceProgress};
+  use crate::{manager::Manager, utils::FrameInfoExt};
+  use std::{thread, time::Duration};
+
+  #[test]
+  fn slices() {
+    let rows: Vec<_> = (0..3).map(|i| slice_rows(i, 3, 10)).collect();
+    assert_eq!(rows, [0..3, 3..6, 6..10]);
+    assert_eq!(slice_rows(0, 1, 10), 0..10);
+  }
+
+  #[test]
+  fn sliced_capturer() {
+    let manager = Manager::default().unwrap();
+    let mut capturer = manager.contexts[0].sliced_capturer(4).unwrap();
+
+    // sleep for a while before capture to wait system to update the screen
+    thread::sleep(Duration::from_millis(100));
+
+    for copied in 1..4 {
+      assert_eq!(
+        capturer.capture().unwrap(),
+        SliceProgress::Partial { copied, total: 4 }
+      );
+    }
+    let SliceProgress::Complete(info) = capturer.capture().unwrap() else {
+      panic!("the frame should be complete");
+    };
+    assert!(info.desktop_updated());
+    assert!(!capturer.is_partial());
+    assert!(capturer.buffer().iter().any(|&b| b != 0));
+
+    // a generous budget copies the whole frame in one call
+    thread::sleep(Duration::from_millis(100));
+    assert!(matches!(
+      capturer.capture_within(Duration::from_secs(1)).unwrap(),
+      SliceProgress::Complete(_)
+    ));
+  }
+}
@@ -0,0 +1,381 @@
+//! Rolling capture statistics for long-running services, written as JSON lines by [`StatsLog`].
+
+use super::observer::CaptureObserver;
+use crate::duplication_context::{qpc_duration, qpc_now};
+use crate::error::Error;
+use crate::model::MonitorId;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_FRAME_INFO;
+
+/// The statistics of one interval of a [`StatsLog`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RollingStats {
+  /// The end of the interval, in milliseconds since the Unix epoch.
+  pub timestamp_ms: u64,
+  pub interval: Duration,
+  /// Notified frames, including pointer-only updates.
+  pub frames: u64,
+  pub fps: f64,
+  /// Presented frames which weren't captured, see `AccumulatedFrames`,
+  /// plus the drops reported by [`StatsLog::add_dropped`].
+  pub dropped: u64,
+  pub errors: u64,
+  pub last_error: Option<String>,
+  pub mode_changes: u64,
+  /// Percentiles of the time from presenting a frame to notifying it, `None` without desktop updates.
+  pub latency: Option<LatencyPercentiles>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+  pub p50: Duration,
+  pub p95: Duration,
+  pub p99: Duration,
+  pub max: Duration,
+}
+
+impl LatencyPercentiles {
+  /// Nearest-rank percentiles of the samples, or `None` if there are none.
+  pub fn new(mut samples: Vec<Duration>) -> Option<Self> {
+    if samples.is_empty() {
+      return None;
+    }
+    samples.sort();
+    let percentile = |p: usize| samples[(p * samples.len()).div_ceil(100).max(1) - 1];
+    Some(Self {
+      p50: percentile(50),
+      p95: percentile(95),
+      p99: percentile(99),
+      max: samples[samples.len() - 1],
+    })
+  }
+}
+
+impl RollingStats {
+  /// One JSON object without a trailing newline. Durations are in milliseconds.
+  pub fn to_json(&self) -> String {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let mut json = format!(
+      "{{\"timestamp_ms\":{},\"interval_ms\":{:.3},\"frames\":{},\"fps\":{:.2},\"dropped\":{},\"errors\":{},\"mode_changes\":{},\"latency_ms\":",
+      self.timestamp_ms,
+      ms(self.interval),
+      self.frames,
+      self.fps,
+      self.dropped,
+      self.errors,
+      self.mode_changes,
+    );
+    match self.latency {
+      Some(l) => write!(
+        json,
+        "{{\"p50\":{:.3},\"p95\":{:.3},\"p99\":{:.3},\"max\":{:.3}}}",
+        ms(l.p50),
+        ms(l.p95),
+        ms(l.p99),
+        ms(l.max)
+      )
+      .unwrap(),
+      None => json.push_str("null"),
+    }
+    json.push_str(",\"last_error\":");
+    match self.last_error {
+      Some(ref e) => write_json_string(&mut json, e),
+      None => json.push_str("null"),
+    }
+    json.push('}');
+    json
+  }
+}
+
+fn write_json_string(json: &mut String, s: &str) {
+  json.push('"');
+  for c in s.chars() {
+    match c {
+      '"' => json.push_str("\\\""),
+      '\\' => json.push_str("\\\\"),
+      '\n' => json.push_str("\\n"),
+      '\r' => json.push_str("\\r"),
+      '\t' => json.push_str("\\t"),
+      c if c < ' ' => write!(json, "\\u{:04x}", c as u32).unwrap(),
+      c => json.push(c),
+    }
+  }
+  json.push('"');
+}
+
+/// Where a [`StatsLog`] delivers each interval.
+pub enum StatsSink {
+  /// Write one JSON line per interval, see [`RollingStats::to_json`].
+  /// Write errors are ignored so a full disk doesn't affect capturing.
+  Writer(Box<dyn Write + Send>),
+  Callback(Box<dyn FnMut(&RollingStats) + Send>),
+}
+
+impl StatsSink {
+  fn deliver(&mut self, stats: &RollingStats) {
+    match self {
+      StatsSink::Writer(writer) => {
+        let mut line = stats.to_json();
+        line.push('\n');
+        writer
+          .write_all(line.as_bytes())
+          .and_then(|_| writer.flush())
+          .ok();
+      }
+      StatsSink::Callback(callback) => callback(stats),
+    }
+  }
+}
+
+/// The counters of the current interval.
+struct Window {
+  started: Instant,
+  frames: u64,
+  dropped: u64,
+  errors: u64,
+  last_error: Option<String>,
+  mode_changes: u64,
+  latencies: Vec<Duration>,
+}
+
+impl Window {
+  fn new(started: Instant) -> Self {
+    Self {
+      started,
+      frames: 0,
+      dropped: 0,
+      errors: 0,
+      last_error: None,
+      mode_changes: 0,
+      latencies: Vec::new(),
+    }
+  }
+
+  fn frame(&mut self, accumulated_frames: u32, latency: Option<Duration>) {
+    self.frames += 1;
+    self.dropped += accumulated_frames.saturating_sub(1) as u64;
+    self.latencies.extend(latency);
+  }
+
+  fn finish(self, now: Instant, timestamp: SystemTime) -> RollingStats {
+    let interval = now.duration_since(self.started);
+    RollingStats {
+      timestamp_ms: timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64,
+      interval,
+      frames: self.frames,
+      fps: if interval.is_zero() {
+        0.0
+      } else {
+        self.frames as f64 / interval.as_secs_f64()
+      },
+      dropped: self.dropped,
+      errors: self.errors,
+      last_error: self.last_error,
+      mode_changes: self.mode_changes,
+      latency: LatencyPercentiles::new(self.latencies),
+    }
+  }
+}
+
+struct State {
+  window: Window,
+  stopped: bool,
+}
+
+struct Shared {
+  state: Mutex<State>,
+  /// Notified when stopped.
+  stopped: Condvar,
+}
+
+/// A [`CaptureObserver`] which collects frames, drops, errors and latencies
+/// and delivers them to a [`StatsSink`] every interval from a background thread,
+/// so the performance of long-running captures can be inspected afterwards.
+///
+/// Intervals are delivered even if nothing is captured, so stalls are visible.
+/// The last, partial interval is delivered on drop.
+pub struct StatsLog {
+  shared: Arc<Shared>,
+  handle: Option<JoinHandle<()>>,
+}
+
+impl StatsLog {
+  /// Deliver the statistics every `interval`, at least 1 millisecond.
+  pub fn new(sink: StatsSink, interval: Duration) -> Self {
+    let shared = Arc::new(Shared {
+      state: Mutex::new(State {
+        window: Window::new(Instant::now()),
+        stopped: false,
+      }),
+      stopped: Condvar::new(),
+    });
+    let handle = {
+      let shared = shared.clone();
+      thread::spawn(move || run(&shared, sink, interval.max(Duration::from_millis(1))))
+    };
+    Self {
+      shared,
+      handle: Some(handle),
+    }
+  }
+
+  /// Write JSON lines to `writer`, e.g. a file.
+  pub fn to_writer(writer: impl Write + Send + 'static, interval: Duration) -> Self {
+    Self::new(StatsSink::Writer(Box::new(writer)), interval)
+  }
+
+  /// Count frames dropped outside of the observed capturer, e.g.
+  /// [`SupervisedCapturer::dropped_frames`](crate::capturer::supervised::SupervisedCapturer::dropped_frames)
+  /// since the last call.
+  pub fn add_dropped(&self, count: u64) {
+    self.update(|window| window.dropped += count);
+  }
+
+  fn update(&self, f: impl FnOnce(&mut Window)) {
+    f(&mut self.shared.state.lock().unwrap().window);
+  }
+}
+
+fn run(shared: &Shared, mut sink: StatsSink, interval: Duration) {
+  loop {
+    let state = shared.state.lock().unwrap();
+    let deadline = state.window.started + interval;
+    let timeout = deadline.saturating_duration_since(Instant::now());
+    let mut state = shared
+      .stopped
+      .wait_timeout_while(state, timeout, |state| !state.stopped)
+      .unwrap()
+      .0;
+    let now = Instant::now();
+    let window = mem::replace(&mut state.window, Window::new(now));
+    let stopped = state.stopped;
+    // deliver without blocking the observed capturers
+    drop(state);
+    sink.deliver(&window.finish(now, SystemTime::now()));
+    if stopped {
+      return;
+    }
+  }
+}
+
+impl CaptureObserver for StatsLog {
+  fn on_frame(&self, _id: MonitorId, info: &DXGI_OUTDUPL_FRAME_INFO) {
+    let latency = (info.LastPresentTime != 0).then(|| {
+      let (now, frequency) = qpc_now();
+      qpc_duration(now - info.LastPresentTime, frequency)
+    });
+    self.update(|window| window.frame(info.AccumulatedFrames, latency));
+  }
+
+  fn on_error(&self, err: &Error) {
+    let message = err.to_string();
+    self.update(|window| {
+      window.errors += 1;
+      window.last_error = Some(message);
+    });
+  }
+
+  fn on_mode_change(&self, _id: MonitorId, _width: u32, _height: u32) {
+    self.update(|window| window.mode_changes += 1);
+  }
+}
+
+impl Drop for StatsLog {
+  fn drop(&mut self) {
+    self.shared.state.lock().unwrap().stopped = true;
+    self.shared.stopped.notify_all();
+    if let Some(handle) = self.handle.take() {
+      handle.join().ok();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{LatencyPercentiles, RollingStats, StatsLog, StatsSink, Window};
+  use crate::capturer::observer::CaptureObserver;
+  use crate::error::Error;
+  use crate::model::MonitorId;
+  use std::sync::{Arc, Mutex};
+  use std::thread;
+  use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+  use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_FRAME_INFO;
+
+  #[test]
+  fn rolling_stats() {
+    let ms = Duration::from_millis;
+    let start = Instant::now();
+    let mut window = Window::new(start);
+    window.frame(1, Some(ms(10)));
+    // 2 presented frames weren't captured
+    window.frame(3, Some(ms(30)));
+    window.frame(1, None);
+    window.errors += 1;
+    window.last_error = Some("Access \"lost\"".to_string());
+    let stats = window.finish(start + ms(500), UNIX_EPOCH + ms(1234));
+    assert_eq!(stats.frames, 3);
+    assert_eq!(stats.dropped, 2);
+    assert_eq!(stats.fps, 6.0);
+    assert_eq!(
+      stats.latency,
+      Some(LatencyPercentiles {
+        p50: ms(10),
+        p95: ms(30),
+        p99: ms(30),
+        max: ms(30)
+      })
+    );
+    assert_eq!(
+      stats.to_json(),
+      "{\"timestamp_ms\":1234,\"interval_ms\":500.000,\"frames\":3,\"fps\":6.00,\"dropped\":2,\"errors\":1,\"mode_changes\":0,\"latency_ms\":{\"p50\":10.000,\"p95\":30.000,\"p99\":30.000,\"max\":30.000},\"last_error\":\"Access \\\"lost\\\"\"}"
+    );
+    assert_eq!(
+      RollingStats::default().to_json(),
+      "{\"timestamp_ms\":0,\"interval_ms\":0.000,\"frames\":0,\"fps\":0.00,\"dropped\":0,\"errors\":0,\"mode_changes\":0,\"latency_ms\":null,\"last_error\":null}"
+    );
+  }
+
+  #[test]
+  fn stats_log() {
+    let delivered = Arc::new(Mutex::new(Vec::new()));
+    let log = {
+      let delivered = delivered.clone();
+      StatsLog::new(
+        StatsSink::Callback(Box::new(move |stats| {
+          delivered.lock().unwrap().push(stats.clone())
+        })),
+        Duration::from_millis(50),
+      )
+    };
+    let id = MonitorId {
+      adapter: 0,
+      output: 0,
+    };
+    log.on_frame(id, &DXGI_OUTDUPL_FRAME_INFO::default());
+    log.on_error(&Error::new("AcquireNextFrame"));
+    log.add_dropped(3);
+    // empty intervals are delivered too
+    thread::sleep(Duration::from_millis(180));
+    drop(log);
+
+    let delivered = delivered.lock().unwrap();
+    assert!(delivered.len() >= 3);
+    assert_eq!(delivered.iter().map(|s| s.frames).sum::<u64>(), 1);
+    assert_eq!(delivered.iter().map(|s| s.dropped).sum::<u64>(), 3);
+    assert_eq!(delivered[0].last_error.as_deref(), Some("AcquireNextFrame"));
+    assert!(delivered.iter().all(|s| s.timestamp_ms > 0
+      && s.timestamp_ms
+        <= SystemTime::now()
+          .duration_since(UNIX_EPOCH)
+          .unwrap()
+          .as_millis() as u64));
+  }
+}
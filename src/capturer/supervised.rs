@@ -0,0 +1,884 @@
+use super::bus::{bus, BusReceiver, BusSender, Droppable};
+use super::latest::FramePublisher;
+use super::model::{Capturer, MemoryUsage};
+use super::observer::{CaptureObserver, Observers};
+use super::queue::FrameQueue;
+use crate::error::{Error, ErrorKind};
+use crate::frame::{Frame, ProvenanceStamp};
+use crate::manager::Manager;
+use crate::model::{
+  AdaptiveTimeout, Backpressure, CaptureMode, FrameExpiry, LatencyMode, MmcssTask, MonitorId,
+  MonitorSelector, Result, ThreadPriority,
+};
+use crate::utils::FrameInfoExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use windows::core::HSTRING;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Graphics::Dxgi::{
+  DXGI_ERROR_ACCESS_DENIED, DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_DEVICE_REMOVED,
+  DXGI_ERROR_DEVICE_RESET, DXGI_ERROR_MODE_CHANGE_IN_PROGRESS, DXGI_ERROR_SESSION_DISCONNECTED,
+  DXGI_ERROR_WAIT_TIMEOUT,
+};
+use windows::Win32::System::Threading::{
+  AvRevertMmThreadCharacteristics, AvSetMmThreadCharacteristicsW, GetCurrentThread,
+  SetThreadPriority, THREAD_PRIORITY, THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_HIGHEST,
+  THREAD_PRIORITY_NORMAL, THREAD_PRIORITY_TIME_CRITICAL,
+};
+
+/// Decide how [`SupervisedCapturer`] rebuilds the capturer after a recoverable error.
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+  /// Give up after this many consecutive failed restarts. `None` means retry forever.
+  pub max_restarts: Option<u32>,
+  /// Wait before each restart, e.g. for a mode change to finish.
+  pub delay: Duration,
+  /// The timeout of `AcquireNextFrame`, in milliseconds.
+  /// This also bounds how long [`SupervisedCapturer::stop`] waits for the worker.
+  pub timeout_ms: u32,
+  /// Adapt the timeout to the desktop activity instead of using `timeout_ms` after the first capture.
+  /// `idle_ms` then bounds how long [`SupervisedCapturer::stop`] waits. Disabled by default.
+  pub adaptive_timeout: Option<AdaptiveTimeout>,
+  pub latency_mode: LatencyMode,
+  /// Pause instead of reconnecting while the workstation is locked, see [`desktop::is_locked`](crate::desktop::is_locked),
+  /// and rebuild the capturer when it is unlocked.
+  /// [`SupervisorEvent::Paused`] and [`SupervisorEvent::Resumed`] are sent instead of failed reconnects.
+  /// Disabled by default.
+  #[cfg(feature = "desktop")]
+  pub pause_when_locked: bool,
+  /// Retry quickly while an exclusive fullscreen application takes or releases the output,
+  /// sending [`SupervisorEvent::FullscreenTransition`] instead of [`SupervisorEvent::Reconnecting`].
+  /// `None` handles such failures like other recoverable errors.
+  pub fullscreen: Option<TransitionBackoff>,
+  /// The priority of the worker thread, raise it to reduce frame jitter while the system is under load.
+  pub thread_priority: ThreadPriority,
+  /// Register the worker thread with MMCSS, e.g. [`MmcssTask::Capture`] for real-time streaming.
+  /// Disabled by default.
+  ///
+  /// If the priority can't be set or the task can't be registered, e.g. because the MMCSS service is disabled,
+  /// the error is reported to the observers and capturing continues.
+  pub mmcss_task: Option<MmcssTask>,
+  /// Mark or drop frames which are too old when received, e.g. after the consumer stalled.
+  /// Stale frames are received as [`SupervisorEvent::StaleFrame`]. Disabled by default.
+  /// Frames pushed to a [`FrameQueue`] are not affected, see [`FrameQueue::set_max_age`].
+  pub frame_expiry: Option<FrameExpiry>,
+}
+
+/// How [`SupervisedCapturer`] retries across an exclusive fullscreen transition,
+/// which briefly breaks duplication with `DXGI_ERROR_ACCESS_LOST` or a mode change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransitionBackoff {
+  /// The first retry delay, doubled after each failed retry up to `max_delay`.
+  pub initial_delay: Duration,
+  pub max_delay: Duration,
+  /// After this long the failure is no longer treated as a transition and reconnects as usual.
+  pub max_duration: Duration,
+  /// A failure this soon after recovering continues the previous transition without a new event,
+  /// e.g. while a game switches the mode twice.
+  pub settle: Duration,
+}
+
+impl Default for TransitionBackoff {
+  fn default() -> Self {
+    Self {
+      initial_delay: Duration::from_millis(16),
+      max_delay: Duration::from_millis(500),
+      max_duration: Duration::from_secs(5),
+      settle: Duration::from_secs(1),
+    }
+  }
+}
+
+impl Default for RestartPolicy {
+  fn default() -> Self {
+    Self {
+      max_restarts: None,
+      delay: Duration::from_millis(500),
+      timeout_ms: crate::manager::DEFAULT_TIMEOUT_MS,
+      adaptive_timeout: None,
+      latency_mode: LatencyMode::default(),
+      #[cfg(feature = "desktop")]
+      pause_when_locked: false,
+      fullscreen: Some(TransitionBackoff::default()),
+      thread_priority: ThreadPriority::default(),
+      mmcss_task: None,
+      frame_expiry: None,
+    }
+  }
+}
+
+/// Events delivered by [`SupervisedCapturer`].
+#[derive(Debug)]
+pub enum SupervisorEvent {
+  /// The capturer is (re)built and frames will follow.
+  Started(MonitorId),
+  /// A frame with a desktop update.
+  /// In [`CaptureMode::Pull`] this may repeat the previous image if the desktop didn't change.
+  /// Not sent if frames are delivered to a [`FrameQueue`] or a [`FramePublisher`].
+  Frame(Frame),
+  /// A frame older than [`RestartPolicy::frame_expiry`] when received, sent instead of [`SupervisorEvent::Frame`]
+  /// unless expired frames are dropped.
+  StaleFrame(Frame),
+  /// The capturer failed with a recoverable error and will be rebuilt,
+  /// `attempt` starts from 1 and resets after a successful restart.
+  Reconnecting { error: Error, attempt: u32 },
+  /// Capturing is paused because the workstation is locked, see [`RestartPolicy`].
+  Paused,
+  /// The workstation is unlocked, [`SupervisorEvent::Started`] follows once the capturer is rebuilt.
+  Resumed,
+  /// An exclusive fullscreen application takes or releases the output, see [`RestartPolicy::fullscreen`].
+  /// The capturer is rebuilt silently and [`SupervisorEvent::Started`] follows.
+  FullscreenTransition,
+  /// The worker exited because of an unrecoverable error or too many restarts.
+  Stopped(Error),
+}
+
+impl Droppable for SupervisorEvent {
+  fn droppable(&self) -> bool {
+    matches!(
+      self,
+      SupervisorEvent::Frame(_) | SupervisorEvent::StaleFrame(_)
+    )
+  }
+}
+
+/// Capture a monitor in a worker thread and rebuild the capturer when
+/// the desktop switches, the display mode changes or the device is removed.
+///
+/// Monitors are re-scanned on every restart, so the selected monitor may get a new [`MonitorId`].
+pub struct SupervisedCapturer {
+  receiver: BusReceiver<SupervisorEvent>,
+  control: Arc<Control>,
+  handle: Option<JoinHandle<()>>,
+  expiry: Option<FrameExpiry>,
+  expired: AtomicU64,
+}
+
+impl SupervisedCapturer {
+  /// `backpressure` decides what happens to frames when events are not received fast enough.
+  pub fn new(selector: MonitorSelector, policy: RestartPolicy, backpressure: Backpressure) -> Self {
+    Self::spawn(selector, policy, backpressure, FrameSink::Events)
+  }
+
+  /// Push frames to `queue` instead of sending [`SupervisorEvent::Frame`].
+  /// Status events are still received from this capturer.
+  pub fn with_frame_queue(
+    selector: MonitorSelector,
+    policy: RestartPolicy,
+    queue: FrameQueue,
+  ) -> Self {
+    Self::spawn(
+      selector,
+      policy,
+      Backpressure::default(),
+      FrameSink::Queue(queue),
+    )
+  }
+
+  /// Publish frames to the [`LatestFrame`](super::latest::LatestFrame) of `publisher`
+  /// instead of sending [`SupervisorEvent::Frame`], e.g. for previews which only show the most recent frame.
+  /// Frames are skipped while every other slot is being read.
+  /// Status events are still received from this capturer.
+  pub fn with_latest_frame(
+    selector: MonitorSelector,
+    policy: RestartPolicy,
+    publisher: FramePublisher,
+  ) -> Self {
+    Self::spawn(
+      selector,
+      policy,
+      Backpressure::default(),
+      FrameSink::Latest(publisher),
+    )
+  }
+
+  fn spawn(
+    selector: MonitorSelector,
+    policy: RestartPolicy,
+    backpressure: Backpressure,
+    sink: FrameSink,
+  ) -> Self {
+    let (sender, receiver) = bus(backpressure);
+    let expiry = policy.frame_expiry;
+    let control = Arc::new(Control {
+      state: Mutex::new(ControlState {
+        mode: CaptureMode::default(),
+        requested: false,
+        stopped: false,
+      }),
+      changed: Condvar::new(),
+      observers: Mutex::new(Arc::default()),
+      memory: Mutex::new(MemoryUsage::default()),
+    });
+    let worker = Worker {
+      selector,
+      policy,
+      sender,
+      control: control.clone(),
+      sink,
+      recycled: None,
+      size: None,
+      started_at: None,
+      transition: None,
+      stamp: None,
+    };
+    let handle = thread::spawn(move || worker.supervise());
+    Self {
+      receiver,
+      control,
+      handle: Some(handle),
+      expiry,
+      expired: AtomicU64::new(0),
+    }
+  }
+
+  /// Wait for the next event. Return `None` if the worker has exited.
+  pub fn recv(&self) -> Option<SupervisorEvent> {
+    self.receive(None).ok().flatten()
+  }
+
+  /// Wait for the next event at most `timeout`.
+  /// Return `Ok(None)` on timeout and `Err` if the worker has exited.
+  pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<SupervisorEvent>> {
+    self
+      .receive(Some(timeout))
+      .map_err(|_| Error::new("Supervised capturer stopped"))
+  }
+
+  /// Receive the next event, marking or dropping expired frames.
+  fn receive(&self, timeout: Option<Duration>) -> std::result::Result<Option<SupervisorEvent>, ()> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+      let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+      let frame = match self.receiver.recv(timeout)? {
+        Some(SupervisorEvent::Frame(frame)) => frame,
+        event => return Ok(event),
+      };
+      match self.expiry {
+        Some(expiry) if frame.is_stale(expiry.max_age) => {
+          if !expiry.drop {
+            return Ok(Some(SupervisorEvent::StaleFrame(frame)));
+          }
+          self.expired.fetch_add(1, Ordering::Relaxed);
+        }
+        _ => return Ok(Some(SupervisorEvent::Frame(frame))),
+      }
+    }
+  }
+
+  /// Return the next event without blocking.
+  /// Return `Ok(None)` if there is no event and `Err` if the worker has exited.
+  pub fn try_recv(&self) -> Result<Option<SupervisorEvent>> {
+    self.recv_timeout(Duration::ZERO)
+  }
+
+  /// How many frames are dropped or coalesced by the backpressure policy.
+  pub fn dropped_frames(&self) -> u64 {
+    self.receiver.dropped()
+  }
+
+  /// How many frames are dropped because they expired, see [`RestartPolicy::frame_expiry`].
+  pub fn expired_frames(&self) -> u64 {
+    self.expired.load(Ordering::Relaxed)
+  }
+
+  /// Get a manual-reset event which is signaled while events are queued or the worker has exited,
+  /// i.e. while [`SupervisedCapturer::try_recv`] returns immediately.
+  /// Wait for it with `WaitForMultipleObjects` or `MsgWaitForMultipleObjects` and drain the events.
+  ///
+  /// The event is owned by this capturer, don't close it.
+  /// Frames pushed to a [`FrameQueue`] don't signal it.
+  pub fn frame_event(&self) -> Result<HANDLE> {
+    self.receiver.event()
+  }
+
+  pub fn mode(&self) -> CaptureMode {
+    self.control.state.lock().unwrap().mode
+  }
+
+  /// Switch between capturing continuously and capturing on [`SupervisedCapturer::request_frame`].
+  pub fn set_mode(&self, mode: CaptureMode) {
+    self.control.update(|state| state.mode = mode);
+  }
+
+  /// In [`CaptureMode::Pull`], ask the worker to capture one frame.
+  /// Requests made before the frame is delivered are merged.
+  pub fn request_frame(&self) {
+    self.control.update(|state| state.requested = true);
+  }
+
+  /// Report the memory held by the worker as of its last capture: the capturer,
+  /// the frame repeated in pull mode and the buffer kept for reuse.
+  /// Frames in the events, the [`FrameQueue`] or the [`LatestFrame`](super::latest::LatestFrame) are not included.
+  pub fn memory_usage(&self) -> MemoryUsage {
+    *self.control.memory.lock().unwrap()
+  }
+
+  /// Register an observer notified of frames, errors and mode changes in the worker.
+  pub fn add_observer(&self, observer: Arc<dyn CaptureObserver>) {
+    let mut observers = self.control.observers.lock().unwrap();
+    // copy on write, so the worker only clones the `Arc` per frame
+    Arc::make_mut(&mut observers).add(observer);
+  }
+
+  /// Stop the worker and wait for it to exit.
+  pub fn stop(mut self) {
+    self.shutdown();
+  }
+
+  fn shutdown(&mut self) {
+    self.control.update(|state| state.stopped = true);
+    // release the worker if it is blocked by backpressure
+    self.receiver.close();
+    if let Some(handle) = self.handle.take() {
+      handle.join().ok();
+    }
+  }
+}
+
+impl Drop for SupervisedCapturer {
+  fn drop(&mut self) {
+    self.shutdown();
+  }
+}
+
+/// The scheduling of the worker thread, the MMCSS registration is reverted on drop.
+struct Scheduling {
+  mmcss: Option<HANDLE>,
+}
+
+impl Scheduling {
+  /// Apply the policy to the current thread, reporting failures to `observers`.
+  fn apply(policy: &RestartPolicy, observers: &Observers) -> Self {
+    let priority = thread_priority(policy.thread_priority);
+    if priority != THREAD_PRIORITY_NORMAL
+      && !unsafe { SetThreadPriority(GetCurrentThread(), priority) }.as_bool()
+    {
+      observers.error(&Error::windows(
+        "SetThreadPriority",
+        windows::core::Error::from_win32(),
+      ));
+    }
+
+    let mmcss = policy.mmcss_task.and_then(|task| {
+      let mut index = 0;
+      unsafe { AvSetMmThreadCharacteristicsW(&HSTRING::from(task.name()), &mut index) }
+        .map_err(|e| observers.error(&Error::windows("AvSetMmThreadCharacteristicsW", e)))
+        .ok()
+    });
+    Self { mmcss }
+  }
+}
+
+impl Drop for Scheduling {
+  fn drop(&mut self) {
+    if let Some(handle) = self.mmcss {
+      unsafe { AvRevertMmThreadCharacteristics(handle) };
+    }
+  }
+}
+
+fn thread_priority(priority: ThreadPriority) -> THREAD_PRIORITY {
+  match priority {
+    ThreadPriority::Normal => THREAD_PRIORITY_NORMAL,
+    ThreadPriority::AboveNormal => THREAD_PRIORITY_ABOVE_NORMAL,
+    ThreadPriority::Highest => THREAD_PRIORITY_HIGHEST,
+    ThreadPriority::TimeCritical => THREAD_PRIORITY_TIME_CRITICAL,
+  }
+}
+
+/// Return `true` if the error is typical for an exclusive fullscreen transition.
+fn is_transition(err: &Error) -> bool {
+  err.kind == ErrorKind::InactiveOutput
+    || matches!(
+      err.windows.as_ref().map(|e| e.code()),
+      Some(DXGI_ERROR_ACCESS_LOST | DXGI_ERROR_MODE_CHANGE_IN_PROGRESS)
+    )
+}
+
+/// Return `true` if the error is caused by a desktop switch, mode change or device loss,
+/// which can be fixed by rebuilding the capturer.
+fn should_restart(err: &Error) -> bool {
+  if err.kind == ErrorKind::InactiveOutput {
+    // the output may be temporarily zero-sized during a mode change
+    return true;
+  }
+  matches!(
+    err.windows.as_ref().map(|e| e.code()),
+    Some(
+      DXGI_ERROR_ACCESS_LOST
+        | DXGI_ERROR_ACCESS_DENIED
+        | DXGI_ERROR_DEVICE_REMOVED
+        | DXGI_ERROR_DEVICE_RESET
+        | DXGI_ERROR_MODE_CHANGE_IN_PROGRESS
+        | DXGI_ERROR_SESSION_DISCONNECTED
+    )
+  )
+}
+
+struct ControlState {
+  mode: CaptureMode,
+  /// A frame is requested in pull mode.
+  requested: bool,
+  stopped: bool,
+}
+
+/// Shared between the capturer and its worker.
+struct Control {
+  state: Mutex<ControlState>,
+  /// Notified when the state is updated.
+  changed: Condvar,
+  observers: Mutex<Arc<Observers>>,
+  /// Reported by the worker after each capture.
+  memory: Mutex<MemoryUsage>,
+}
+
+impl Control {
+  fn update(&self, f: impl FnOnce(&mut ControlState)) {
+    f(&mut self.state.lock().unwrap());
+    self.changed.notify_all();
+  }
+
+  fn observers(&self) -> Arc<Observers> {
+    self.observers.lock().unwrap().clone()
+  }
+
+  fn stopped(&self) -> bool {
+    self.state.lock().unwrap().stopped
+  }
+
+  /// Wait until the worker should capture.
+  /// Return the mode to capture in, or `None` if stopped.
+  fn wait(&self) -> Option<CaptureMode> {
+    let mut state = self.state.lock().unwrap();
+    loop {
+      if state.stopped {
+        return None;
+      }
+      if state.mode == CaptureMode::Push || state.requested {
+        return Some(state.mode);
+      }
+      state = self.changed.wait(state).unwrap();
+    }
+  }
+
+  /// The requested frame is delivered.
+  fn fulfill(&self) {
+    self.state.lock().unwrap().requested = false;
+  }
+}
+
+/// What to do after a failure, see [`RestartPolicy::fullscreen`].
+enum TransitionStep {
+  /// Not a transition, reconnect as usual.
+  Reconnect,
+  /// Retry silently after the delay.
+  Retry(Duration),
+  /// The transition lasts too long, keep reconnecting as usual.
+  Expired,
+  /// The receiver is dropped.
+  Stop,
+}
+
+/// Where the worker delivers frames.
+enum FrameSink {
+  Events,
+  Queue(FrameQueue),
+  Latest(FramePublisher),
+}
+
+struct Worker {
+  selector: MonitorSelector,
+  policy: RestartPolicy,
+  sender: BusSender<SupervisorEvent>,
+  control: Arc<Control>,
+  sink: FrameSink,
+  /// The buffer of a frame overwritten in the queue or the latest frame slot, reused for the next frame.
+  recycled: Option<Vec<u8>>,
+  /// The frame size of the last opened monitor, to detect mode changes.
+  size: Option<(u32, u32)>,
+  /// When the capturer was last built.
+  started_at: Option<Instant>,
+  /// The start of the current fullscreen transition and the next retry delay.
+  transition: Option<(Instant, Duration)>,
+  /// Stamps frames of all runs, so they share one source.
+  stamp: Option<ProvenanceStamp>,
+}
+
+impl Worker {
+  fn supervise(mut self) {
+    let _scheduling = Scheduling::apply(&self.policy, &self.control.observers());
+    let mut attempt = 0;
+    while !self.control.stopped() {
+      let err = match self.run(&mut attempt) {
+        // stopped or the receiver is dropped
+        Ok(()) => return,
+        Err(err) => err,
+      };
+      #[cfg(feature = "desktop")]
+      if self.policy.pause_when_locked && self.locked() {
+        if !self.pause() {
+          return;
+        }
+        attempt = 0;
+        continue;
+      }
+      self.control.observers().error(&err);
+      let expired = match self.transition_step(&err) {
+        TransitionStep::Retry(delay) => {
+          thread::sleep(delay);
+          continue;
+        }
+        TransitionStep::Stop => return,
+        TransitionStep::Expired => true,
+        TransitionStep::Reconnect => false,
+      };
+      // while reconnecting, monitors may still be missing or inactive, keep retrying
+      let restart = should_restart(&err) || attempt > 0 || expired;
+      if !restart || self.policy.max_restarts.is_some_and(|max| attempt >= max) {
+        self.sender.send(SupervisorEvent::Stopped(err));
+        return;
+      }
+      attempt += 1;
+      if !self.sender.send(SupervisorEvent::Reconnecting {
+        error: err,
+        attempt,
+      }) {
+        return;
+      }
+      thread::sleep(self.policy.delay);
+    }
+  }
+
+  /// Decide how to retry if the error is part of a fullscreen transition.
+  fn transition_step(&mut self, err: &Error) -> TransitionStep {
+    let Some(backoff) = self.policy.fullscreen else {
+      return TransitionStep::Reconnect;
+    };
+    let now = Instant::now();
+    // any failure continues a transition which hasn't recovered or recovered just now
+    let continued = self
+      .transition
+      .is_some_and(|(start, _)| match self.started_at {
+        Some(started) => started < start || now - started < backoff.settle,
+        None => true,
+      });
+    let (start, delay) = match self.transition {
+      Some(transition) if continued => transition,
+      _ => {
+        if !is_transition(err) {
+          return TransitionStep::Reconnect;
+        }
+        if !self.sender.send(SupervisorEvent::FullscreenTransition) {
+          return TransitionStep::Stop;
+        }
+        (now, backoff.initial_delay)
+      }
+    };
+    if now - start > backoff.max_duration {
+      return TransitionStep::Expired;
+    }
+    self.transition = Some((start, (delay * 2).min(backoff.max_delay)));
+    TransitionStep::Retry(delay)
+  }
+
+  /// Whether the workstation is locked.
+  /// If that can't be detected the error is reported to the observers and it counts as unlocked.
+  #[cfg(feature = "desktop")]
+  fn locked(&self) -> bool {
+    crate::desktop::is_locked()
+      .inspect_err(|e| self.control.observers().error(e))
+      .unwrap_or(false)
+  }
+
+  /// Wait until the workstation is unlocked.
+  /// Return `false` if stopped or the receiver is dropped.
+  #[cfg(feature = "desktop")]
+  fn pause(&self) -> bool {
+    if !self.sender.send(SupervisorEvent::Paused) {
+      return false;
+    }
+    while self.locked() {
+      if self.control.stopped() {
+        return false;
+      }
+      thread::sleep(self.policy.delay);
+    }
+    self.sender.send(SupervisorEvent::Resumed)
+  }
+
+  /// Build the capturer and deliver frames until stopped or an error occurs.
+  fn run(&mut self, attempt: &mut u32) -> Result<()> {
+    let manager = Manager::new(self.policy.timeout_ms)?;
+    let ctx = manager.select(&self.selector)?;
+    ctx.set_latency_mode(self.policy.latency_mode);
+    let (width, height) = ctx.frame_size()?;
+    let format = ctx.texture_format()?;
+    let mut capturer = ctx.simple_capturer()?;
+    match &mut self.stamp {
+      Some(stamp) => stamp.rebind(ctx)?,
+      None => self.stamp = Some(ProvenanceStamp::new(ctx)?),
+    }
+
+    *attempt = 0;
+    self.started_at = Some(Instant::now());
+    let id = ctx.id();
+    if self.size != Some((width, height)) {
+      self.size = Some((width, height));
+      self.control.observers().mode_change(id, width, height);
+    }
+    if !self.sender.send(SupervisorEvent::Started(id)) {
+      return Ok(());
+    }
+
+    // the most recent frame in pull mode, repeated if the desktop didn't change
+    let mut last: Option<Frame> = None;
+    let mut last_update = Instant::now();
+    while let Some(mode) = self.control.wait() {
+      let pull = mode == CaptureMode::Pull;
+      if let Some(adaptive) = &self.policy.adaptive_timeout {
+        ctx.set_timeout_ms(adaptive.timeout_ms(last_update.elapsed()));
+      }
+      let info = match capturer.capture() {
+        Ok(info) => Some(info),
+        Err(e) if e.windows.as_ref().map(|e| e.code()) == Some(DXGI_ERROR_WAIT_TIMEOUT) => None,
+        Err(e) => return Err(e),
+      };
+
+      let frame = match info {
+        Some(info) if info.desktop_updated() => {
+          last_update = Instant::now();
+          let mut buffer = self.recycled.take().unwrap_or_default();
+          buffer.clear();
+          buffer.extend_from_slice(capturer.buffer());
+          let frame = Frame {
+            buffer,
+            width,
+            height,
+            info,
+            format,
+            provenance: self.stamp.as_mut().map(ProvenanceStamp::stamp),
+          };
+          last = pull.then(|| frame.clone());
+          frame
+        }
+        _ => match last {
+          // the desktop didn't change, so the repeated image is as fresh as a new capture
+          Some(ref last) if pull => Frame {
+            provenance: self.stamp.as_mut().map(ProvenanceStamp::stamp),
+            ..last.clone()
+          },
+          _ => continue,
+        },
+      };
+      if pull {
+        self.control.fulfill();
+      }
+      self.control.observers().frame(id, &frame.info);
+      if !self.deliver(frame) {
+        return Ok(());
+      }
+      *self.control.memory.lock().unwrap() = capturer.memory_usage()
+        + MemoryUsage {
+          buffers: last.as_ref().map_or(0, |frame| frame.buffer.capacity()),
+          pools: self.recycled.as_ref().map_or(0, Vec::capacity),
+          ..Default::default()
+        };
+    }
+    Ok(())
+  }
+
+  /// Return `false` if the receiver is dropped.
+  fn deliver(&mut self, frame: Frame) -> bool {
+    match self.sink {
+      FrameSink::Queue(ref queue) => {
+        self.recycled = queue.push(frame).map(|frame| frame.buffer);
+        true
+      }
+      FrameSink::Latest(ref mut publisher) => {
+        let recycled = &mut self.recycled;
+        publisher.publish_with(|slot| *recycled = slot.replace(frame).map(|frame| frame.buffer));
+        true
+      }
+      FrameSink::Events => self.sender.send(SupervisorEvent::Frame(frame)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{is_transition, should_restart, RestartPolicy, SupervisedCapturer, SupervisorEvent};
+  use crate::capturer::latest::LatestFrame;
+  use crate::capturer::queue::FrameQueue;
+  use crate::{
+    error::{Error, ErrorKind},
+    model::{Backpressure, CaptureMode, FrameExpiry, MmcssTask, MonitorSelector, ThreadPriority},
+  };
+  use std::thread;
+  use std::time::{Duration, Instant};
+  use windows::Win32::Foundation::WAIT_OBJECT_0;
+  use windows::Win32::Graphics::Dxgi::{
+    DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_INVALID_CALL,
+  };
+  use windows::Win32::System::Threading::WaitForSingleObject;
+
+  #[test]
+  fn restart_errors() {
+    assert!(should_restart(&Error::windows(
+      "AcquireNextFrame",
+      DXGI_ERROR_ACCESS_LOST.into()
+    )));
+    assert!(!should_restart(&Error::windows(
+      "AcquireNextFrame",
+      DXGI_ERROR_INVALID_CALL.into()
+    )));
+    assert!(!should_restart(&Error::new("Monitor not found")));
+
+    assert!(is_transition(&Error::windows(
+      "AcquireNextFrame",
+      DXGI_ERROR_ACCESS_LOST.into()
+    )));
+    assert!(is_transition(&Error::of_kind(
+      ErrorKind::InactiveOutput,
+      "Output has a zero-sized desktop area"
+    )));
+    assert!(!is_transition(&Error::windows(
+      "AcquireNextFrame",
+      DXGI_ERROR_DEVICE_REMOVED.into()
+    )));
+  }
+
+  #[test]
+  fn supervised_capturer() {
+    let capturer = SupervisedCapturer::new(
+      MonitorSelector::Primary,
+      RestartPolicy::default(),
+      Backpressure::Latest,
+    );
+    let event = capturer.frame_event().unwrap();
+    assert_eq!(unsafe { WaitForSingleObject(event, 5000) }, WAIT_OBJECT_0);
+    assert!(matches!(
+      capturer.try_recv().unwrap(),
+      Some(SupervisorEvent::Started(_))
+    ));
+    match capturer.recv_timeout(Duration::from_secs(5)).unwrap() {
+      Some(SupervisorEvent::Frame(frame)) => {
+        assert_eq!(
+          frame.buffer.len(),
+          frame.width as usize * frame.height as usize * 4
+        );
+      }
+      event => panic!("unexpected event: {:?}", event),
+    }
+    capturer.stop();
+  }
+
+  #[test]
+  fn realtime_worker() {
+    let capturer = SupervisedCapturer::new(
+      MonitorSelector::Primary,
+      RestartPolicy {
+        thread_priority: ThreadPriority::Highest,
+        mmcss_task: Some(MmcssTask::Capture),
+        ..Default::default()
+      },
+      Backpressure::Latest,
+    );
+    assert!(matches!(capturer.recv(), Some(SupervisorEvent::Started(_))));
+    assert!(matches!(
+      capturer.recv_timeout(Duration::from_secs(5)).unwrap(),
+      Some(SupervisorEvent::Frame(_))
+    ));
+    capturer.stop();
+  }
+
+  #[test]
+  fn frame_expiry() {
+    let capturer = SupervisedCapturer::new(
+      MonitorSelector::Primary,
+      RestartPolicy {
+        frame_expiry: Some(FrameExpiry {
+          max_age: Duration::from_millis(50),
+          drop: false,
+        }),
+        ..Default::default()
+      },
+      Backpressure::Block(4),
+    );
+    assert!(matches!(capturer.recv(), Some(SupervisorEvent::Started(_))));
+    // the first frame is captured while the consumer stalls
+    thread::sleep(Duration::from_millis(500));
+    match capturer.recv_timeout(Duration::from_secs(5)).unwrap() {
+      Some(SupervisorEvent::StaleFrame(frame)) => {
+        assert!(frame.age().unwrap() > Duration::from_millis(50))
+      }
+      event => panic!("unexpected event: {:?}", event),
+    }
+    assert_eq!(capturer.expired_frames(), 0);
+    capturer.stop();
+  }
+
+  #[test]
+  fn frame_queue() {
+    let queue = FrameQueue::new(2);
+    let capturer = SupervisedCapturer::with_frame_queue(
+      MonitorSelector::Primary,
+      RestartPolicy::default(),
+      queue.clone(),
+    );
+    assert!(matches!(capturer.recv(), Some(SupervisorEvent::Started(_))));
+    let frame = queue.pop_timeout(Duration::from_secs(5)).unwrap();
+    assert!(frame.buffer.iter().any(|&b| b != 0));
+    // reported right after the frame is delivered
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while capturer.memory_usage().buffers < frame.buffer.len() && Instant::now() < deadline {
+      thread::sleep(Duration::from_millis(10));
+    }
+    assert!(capturer.memory_usage().buffers >= frame.buffer.len());
+    capturer.stop();
+  }
+
+  #[test]
+  fn latest_frame() {
+    let (publisher, latest) = LatestFrame::new();
+    let capturer = SupervisedCapturer::with_latest_frame(
+      MonitorSelector::Primary,
+      RestartPolicy::default(),
+      publisher,
+    );
+    assert!(matches!(capturer.recv(), Some(SupervisorEvent::Started(_))));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while latest.seq() == 0 && Instant::now() < deadline {
+      thread::sleep(Duration::from_millis(10));
+    }
+    assert!(latest
+      .read(|_, frame| frame.buffer.iter().any(|&b| b != 0))
+      .unwrap());
+    capturer.stop();
+  }
+
+  #[test]
+  fn pull_mode() {
+    let capturer = SupervisedCapturer::new(
+      MonitorSelector::Primary,
+      RestartPolicy::default(),
+      Backpressure::Block(4),
+    );
+    capturer.set_mode(CaptureMode::Pull);
+    assert_eq!(capturer.mode(), CaptureMode::Pull);
+    assert!(matches!(capturer.recv(), Some(SupervisorEvent::Started(_))));
+    // drain frames captured before switching
+    while let Ok(Some(_)) = capturer.recv_timeout(Duration::from_millis(500)) {}
+
+    for _ in 0..2 {
+      capturer.request_frame();
+      assert!(matches!(
+        capturer.recv_timeout(Duration::from_secs(5)).unwrap(),
+        Some(SupervisorEvent::Frame(_))
+      ));
+    }
+    capturer.stop();
+  }
+}
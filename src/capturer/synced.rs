@@ -0,0 +1,371 @@
+use super::bus::{bus, BusReceiver, BusSender, Droppable};
+use super::model::Capturer;
+use crate::error::Error;
+use crate::frame::{Frame, ProvenanceStamp};
+use crate::manager::Manager;
+use crate::model::{Backpressure, MonitorId, Result};
+use crate::utils::FrameInfoExt;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use windows::Win32::Graphics::Dxgi::DXGI_ERROR_WAIT_TIMEOUT;
+
+/// How many times a worker re-acquires before the first frame with a desktop image arrives.
+const FIRST_FRAME_RETRIES: u32 = 10;
+
+/// Frames captured from every monitor at the same tick.
+#[derive(Debug, Clone)]
+pub struct FrameSet {
+  /// Index of the tick since the capturer started.
+  /// Ticks are skipped if capturing takes longer than the interval.
+  pub tick: u64,
+  /// When the workers were triggered.
+  pub time: Instant,
+  /// One frame per monitor, in the order the monitors are given.
+  /// If a monitor didn't change since the last tick, its previous frame is repeated,
+  /// shared with the earlier sets instead of copied.
+  pub frames: Vec<(MonitorId, Arc<Frame>)>,
+}
+
+/// Events delivered by [`SyncedCapturer`].
+#[derive(Debug)]
+pub enum SyncedEvent {
+  Frames(FrameSet),
+  /// All workers exited because a monitor failed.
+  Stopped(Error),
+}
+
+impl Droppable for SyncedEvent {
+  fn droppable(&self) -> bool {
+    matches!(self, SyncedEvent::Frames(_))
+  }
+}
+
+/// Capture multiple monitors in worker threads which are triggered at the same tick,
+/// so every interval produces one coherent [`FrameSet`] instead of free-running loops that drift apart.
+///
+/// The timeout of each monitor should be shorter than the interval,
+/// otherwise an idle monitor delays the whole set.
+pub struct SyncedCapturer {
+  receiver: BusReceiver<SyncedEvent>,
+  trigger: Arc<Trigger>,
+  handle: Option<JoinHandle<()>>,
+}
+
+impl SyncedCapturer {
+  /// Capture all monitors every `interval`, with half of the interval as the timeout.
+  pub fn new(interval: Duration, backpressure: Backpressure) -> Result<Self> {
+    let ids: Vec<MonitorId> = Manager::default()?
+      .contexts
+      .iter()
+      .map(|ctx| ctx.id())
+      .collect();
+    let timeout_ms = (interval.as_millis() / 2).min(u32::MAX as u128) as u32;
+    Self::with_monitors(ids, interval, timeout_ms, backpressure)
+  }
+
+  /// Capture the monitors every `interval`, waiting at most `timeout_ms` for a new frame of each monitor.
+  pub fn with_monitors(
+    ids: Vec<MonitorId>,
+    interval: Duration,
+    timeout_ms: u32,
+    backpressure: Backpressure,
+  ) -> Result<Self> {
+    if ids.is_empty() {
+      return Err(Error::new("No monitor to capture"));
+    }
+    let (sender, receiver) = bus(backpressure);
+    let trigger = Arc::new(Trigger {
+      state: Mutex::new(TriggerState {
+        tick: None,
+        stopped: false,
+      }),
+      changed: Condvar::new(),
+    });
+    let coordinator = Coordinator {
+      ids,
+      interval,
+      timeout_ms,
+      sender,
+      trigger: trigger.clone(),
+    };
+    let handle = thread::spawn(move || coordinator.run());
+    Ok(Self {
+      receiver,
+      trigger,
+      handle: Some(handle),
+    })
+  }
+
+  /// Wait for the next event. Return `None` if the workers have exited.
+  pub fn recv(&self) -> Option<SyncedEvent> {
+    self.receiver.recv(None).ok().flatten()
+  }
+
+  /// Wait for the next event at most `timeout`.
+  /// Return `Ok(None)` on timeout and `Err` if the workers have exited.
+  pub fn recv_timeout(&self, timeout: Duration) -> Result<Option<SyncedEvent>> {
+    self
+      .receiver
+      .recv(Some(timeout))
+      .map_err(|_| Error::new("Synced capturer stopped"))
+  }
+
+  /// How many frame sets are dropped or coalesced by the backpressure policy.
+  pub fn dropped_sets(&self) -> u64 {
+    self.receiver.dropped()
+  }
+
+  /// Stop the workers and wait for them to exit.
+  pub fn stop(mut self) {
+    self.shutdown();
+  }
+
+  fn shutdown(&mut self) {
+    self.trigger.stop();
+    // release the coordinator if it is blocked by backpressure
+    self.receiver.close();
+    if let Some(handle) = self.handle.take() {
+      handle.join().ok();
+    }
+  }
+}
+
+impl Drop for SyncedCapturer {
+  fn drop(&mut self) {
+    self.shutdown();
+  }
+}
+
+struct TriggerState {
+  /// The latest tick, `None` before the first tick.
+  tick: Option<u64>,
+  stopped: bool,
+}
+
+/// Shared by the coordinator and the workers to start each tick at the same time.
+struct Trigger {
+  state: Mutex<TriggerState>,
+  /// Notified when a tick starts or the capturer is stopped.
+  changed: Condvar,
+}
+
+impl Trigger {
+  fn fire(&self, tick: u64) {
+    self.state.lock().unwrap().tick = Some(tick);
+    self.changed.notify_all();
+  }
+
+  fn stop(&self) {
+    self.state.lock().unwrap().stopped = true;
+    self.changed.notify_all();
+  }
+
+  fn stopped(&self) -> bool {
+    self.state.lock().unwrap().stopped
+  }
+
+  /// Wait for a tick after `last`. Return `None` if stopped.
+  fn wait(&self, last: Option<u64>) -> Option<u64> {
+    let mut state = self.state.lock().unwrap();
+    loop {
+      if state.stopped {
+        return None;
+      }
+      if state.tick != last {
+        return state.tick;
+      }
+      state = self.changed.wait(state).unwrap();
+    }
+  }
+}
+
+/// Return the tick to fire next, skipping ticks which are already missed.
+fn next_tick(last: u64, elapsed: Duration, interval: Duration) -> u64 {
+  let current = (elapsed.as_nanos() / interval.as_nanos().max(1)) as u64;
+  current.max(last + 1)
+}
+
+type Reply = (usize, Result<Arc<Frame>>);
+
+struct Coordinator {
+  ids: Vec<MonitorId>,
+  interval: Duration,
+  timeout_ms: u32,
+  sender: BusSender<SyncedEvent>,
+  trigger: Arc<Trigger>,
+}
+
+impl Coordinator {
+  fn run(self) {
+    let (reply_sender, replies) = mpsc::channel();
+    let workers: Vec<_> = self
+      .ids
+      .iter()
+      .enumerate()
+      .map(|(index, &id)| {
+        let trigger = self.trigger.clone();
+        let replies = reply_sender.clone();
+        let timeout_ms = self.timeout_ms;
+        // duplication contexts can't be sent to other threads, open each monitor in its worker
+        thread::spawn(move || {
+          if let Err(e) = work(id, timeout_ms, &trigger, |frame| {
+            replies.send((index, Ok(frame))).is_ok()
+          }) {
+            replies.send((index, Err(e))).ok();
+          }
+        })
+      })
+      .collect();
+    drop(reply_sender);
+
+    if let Err(e) = self.tick(&replies) {
+      self.sender.send(SyncedEvent::Stopped(e));
+    }
+    self.trigger.stop();
+    for worker in workers {
+      worker.join().ok();
+    }
+  }
+
+  /// Trigger the workers and deliver frame sets until stopped or a worker fails.
+  fn tick(&self, replies: &mpsc::Receiver<Reply>) -> Result<()> {
+    let start = Instant::now();
+    let mut tick = 0;
+    loop {
+      let deadline = start + Duration::from_nanos((self.interval.as_nanos() * tick as u128) as u64);
+      if let Some(delay) = deadline.checked_duration_since(Instant::now()) {
+        thread::sleep(delay);
+      }
+      if self.trigger.stopped() {
+        return Ok(());
+      }
+      let time = Instant::now();
+      self.trigger.fire(tick);
+
+      let mut frames: Vec<Option<Arc<Frame>>> = vec![None; self.ids.len()];
+      for _ in 0..self.ids.len() {
+        let (index, frame) = replies
+          .recv()
+          .map_err(|_| Error::new("Synced capture worker exited"))?;
+        frames[index] = Some(frame?);
+      }
+      // every worker replies once per tick
+      let frames = self
+        .ids
+        .iter()
+        .zip(frames)
+        .filter_map(|(id, frame)| Some((*id, frame?)))
+        .collect();
+      if !self
+        .sender
+        .send(SyncedEvent::Frames(FrameSet { tick, time, frames }))
+      {
+        return Ok(());
+      }
+      tick = next_tick(tick, start.elapsed(), self.interval);
+    }
+  }
+}
+
+/// Capture the monitor at every tick and pass the frame to `deliver`
+/// until stopped or `deliver` returns `false`.
+fn work(
+  id: MonitorId,
+  timeout_ms: u32,
+  trigger: &Trigger,
+  mut deliver: impl FnMut(Arc<Frame>) -> bool,
+) -> Result<()> {
+  let ctx = Manager::open(id, timeout_ms)?;
+  let (width, height) = ctx.frame_size()?;
+  let format = ctx.texture_format()?;
+  let mut capturer = ctx.simple_capturer()?;
+  let mut stamp = ProvenanceStamp::new(&ctx)?;
+
+  // the most recent frame, repeated if the desktop didn't change
+  let mut last: Option<Arc<Frame>> = None;
+  let mut tick = None;
+  while let Some(current) = trigger.wait(tick) {
+    tick = Some(current);
+    let mut retries = FIRST_FRAME_RETRIES;
+    let frame = loop {
+      match capturer.capture() {
+        Ok(info) if info.desktop_updated() => {
+          // reuse the buffer if the sets holding the previous frame are dropped
+          let mut buffer = last
+            .take()
+            .and_then(|frame| Arc::try_unwrap(frame).ok())
+            .map(|frame| frame.buffer)
+            .unwrap_or_default();
+          buffer.clear();
+          buffer.extend_from_slice(capturer.buffer());
+          let frame = Arc::new(Frame {
+            buffer,
+            width,
+            height,
+            info,
+            format,
+            provenance: Some(stamp.stamp()),
+          });
+          last = Some(frame.clone());
+          break frame;
+        }
+        Ok(_) => {}
+        Err(e) if e.windows.as_ref().map(|e| e.code()) == Some(DXGI_ERROR_WAIT_TIMEOUT) => {}
+        Err(e) => return Err(e),
+      }
+      if let Some(frame) = &last {
+        break frame.clone();
+      }
+      if retries == 0 {
+        return Err(Error::new("No desktop image").with_context(ctx.error_context()));
+      }
+      retries -= 1;
+    };
+    if !deliver(frame) {
+      break;
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{next_tick, SyncedCapturer, SyncedEvent};
+  use crate::model::Backpressure;
+  use std::time::Duration;
+
+  #[test]
+  fn ticks() {
+    let interval = Duration::from_millis(10);
+    assert_eq!(next_tick(0, Duration::from_millis(3), interval), 1);
+    assert_eq!(next_tick(1, Duration::from_millis(12), interval), 2);
+    // ticks 2 and 3 are missed
+    assert_eq!(next_tick(1, Duration::from_millis(41), interval), 4);
+  }
+
+  #[test]
+  fn synced_capturer() {
+    let capturer = SyncedCapturer::new(Duration::from_millis(50), Backpressure::Block(4)).unwrap();
+    let mut last_tick = None;
+    for _ in 0..3 {
+      match capturer.recv_timeout(Duration::from_secs(5)).unwrap() {
+        Some(SyncedEvent::Frames(set)) => {
+          assert!(!set.frames.is_empty());
+          if let Some(last) = last_tick {
+            assert!(set.tick > last);
+          }
+          last_tick = Some(set.tick);
+          for (_, frame) in &set.frames {
+            assert_eq!(
+              frame.buffer.len(),
+              frame.width as usize * frame.height as usize * 4
+            );
+          }
+        }
+        event => panic!("unexpected event: {:?}", event),
+      }
+    }
+    capturer.stop();
+  }
+}
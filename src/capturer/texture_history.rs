@@ -0,0 +1,208 @@
+use super::model::MemoryUsage;
+use crate::duplication_context::DuplicationContext;
+use crate::error::Error;
+use crate::model::Result;
+use crate::utils::FrameInfoExt;
+use windows::Win32::Graphics::Direct3D::D3D11_SRV_DIMENSION_TEXTURE2DARRAY;
+use windows::Win32::Graphics::Direct3D11::{
+  ID3D11ShaderResourceView, ID3D11Texture2D, D3D11_BIND_SHADER_RESOURCE, D3D11_CPU_ACCESS_FLAG,
+  D3D11_RESOURCE_MISC_FLAG, D3D11_SHADER_RESOURCE_VIEW_DESC, D3D11_SHADER_RESOURCE_VIEW_DESC_0,
+  D3D11_TEX2D_ARRAY_SRV, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
+};
+use windows::Win32::Graphics::Dxgi::{Common::DXGI_SAMPLE_DESC, DXGI_OUTDUPL_FRAME_INFO};
+
+/// Keep the last frames on the GPU in a texture array, e.g. for shaders blending or denoising frames.
+///
+/// Each desktop update is copied into the slice of the oldest frame, nothing is copied to the CPU.
+/// The array is recreated, dropping the kept frames, when the desktop size or format changes.
+pub struct TextureHistory<'a> {
+  ctx: &'a DuplicationContext,
+  texture: ID3D11Texture2D,
+  texture_desc: D3D11_TEXTURE2D_DESC,
+  view: ID3D11ShaderResourceView,
+  /// The slice of the next frame.
+  next: u32,
+  len: u32,
+}
+
+impl<'a> TextureHistory<'a> {
+  /// Keep at most `frames` frames, at least 1.
+  pub fn new(ctx: &'a DuplicationContext, frames: u32) -> Result<Self> {
+    let (width, height) = ctx.frame_size()?;
+    let (texture, texture_desc, view) = Self::allocate(ctx, width, height, frames.max(1))?;
+    Ok(Self {
+      ctx,
+      texture,
+      texture_desc,
+      view,
+      next: 0,
+      len: 0,
+    })
+  }
+
+  fn allocate(
+    ctx: &DuplicationContext,
+    width: u32,
+    height: u32,
+    frames: u32,
+  ) -> Result<(
+    ID3D11Texture2D,
+    D3D11_TEXTURE2D_DESC,
+    ID3D11ShaderResourceView,
+  )> {
+    let texture_desc = D3D11_TEXTURE2D_DESC {
+      BindFlags: D3D11_BIND_SHADER_RESOURCE,
+      CPUAccessFlags: D3D11_CPU_ACCESS_FLAG::default(),
+      MiscFlags: D3D11_RESOURCE_MISC_FLAG::default(),
+      Usage: D3D11_USAGE_DEFAULT,
+      Width: width,
+      Height: height,
+      MipLevels: 1,
+      ArraySize: frames,
+      Format: ctx.format(),
+      SampleDesc: DXGI_SAMPLE_DESC {
+        Count: 1,
+        Quality: 0,
+      },
+    };
+    let texture = ctx.create_texture(&texture_desc)?;
+
+    let view_desc = D3D11_SHADER_RESOURCE_VIEW_DESC {
+      Format: texture_desc.Format,
+      ViewDimension: D3D11_SRV_DIMENSION_TEXTURE2DARRAY,
+      Anonymous: D3D11_SHADER_RESOURCE_VIEW_DESC_0 {
+        Texture2DArray: D3D11_TEX2D_ARRAY_SRV {
+          MostDetailedMip: 0,
+          MipLevels: 1,
+          FirstArraySlice: 0,
+          ArraySize: frames,
+        },
+      },
+    };
+    let mut view = None;
+    unsafe {
+      ctx
+        .device()
+        .CreateShaderResourceView(&texture, Some(&view_desc), Some(&mut view))
+    }
+    .map_err(|e| ctx.windows_error("CreateShaderResourceView", e))?;
+    Ok((texture, texture_desc, view.unwrap()))
+  }
+
+  /// The texture array, with one slice per kept frame. Sample it on [`DuplicationContext::device`].
+  pub fn texture(&self) -> &ID3D11Texture2D {
+    &self.texture
+  }
+
+  pub fn texture_desc(&self) -> D3D11_TEXTURE2D_DESC {
+    self.texture_desc
+  }
+
+  /// A view of all slices as a `Texture2DArray`.
+  pub fn shader_resource_view(&self) -> &ID3D11ShaderResourceView {
+    &self.view
+  }
+
+  /// How many frames can be kept.
+  pub fn capacity(&self) -> u32 {
+    self.texture_desc.ArraySize
+  }
+
+  /// How many frames are kept.
+  pub fn len(&self) -> u32 {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// The slice of the frame `age` frames before the newest one, `None` if it's not kept.
+  pub fn slice(&self, age: u32) -> Option<u32> {
+    (age < self.len).then(|| (self.next + self.capacity() - 1 - age) % self.capacity())
+  }
+
+  /// Report the estimated size of the texture array.
+  pub fn memory_usage(&self) -> MemoryUsage {
+    MemoryUsage {
+      staging_textures: MemoryUsage::texture_bytes(&self.texture_desc)
+        * self.texture_desc.ArraySize as usize,
+      ..Default::default()
+    }
+  }
+
+  /// Acquire the next frame and copy it into the array if the desktop is updated.
+  pub fn capture(&mut self) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    let frame = self.ctx.acquire()?;
+    let info = *frame.info();
+    if info.desktop_updated() {
+      let texture = frame.texture()?;
+      let mut desc = D3D11_TEXTURE2D_DESC::default();
+      unsafe { texture.GetDesc(&mut desc) };
+      if desc.Format != self.texture_desc.Format {
+        return Err(
+          Error::new("The desktop format doesn't match the texture array")
+            .with_context(self.ctx.error_context()),
+        );
+      }
+      if (desc.Width, desc.Height) != (self.texture_desc.Width, self.texture_desc.Height) {
+        self.recreate(desc.Width, desc.Height)?;
+      }
+      unsafe {
+        self.ctx.device_context().CopySubresourceRegion(
+          &self.texture,
+          self.next,
+          0,
+          0,
+          0,
+          &texture,
+          0,
+          None,
+        )
+      };
+      self.next = (self.next + 1) % self.capacity();
+      self.len = (self.len + 1).min(self.capacity());
+    }
+    frame.release()?;
+    Ok(info)
+  }
+
+  fn recreate(&mut self, width: u32, height: u32) -> Result<()> {
+    let (texture, texture_desc, view) = Self::allocate(self.ctx, width, height, self.capacity())?;
+    self.texture = texture;
+    self.texture_desc = texture_desc;
+    self.view = view;
+    self.next = 0;
+    self.len = 0;
+    Ok(())
+  }
+}
+
+impl DuplicationContext {
+  pub fn texture_history(&self, frames: u32) -> Result<TextureHistory<'_>> {
+    TextureHistory::new(self, frames)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::manager::Manager;
+  use std::{thread, time::Duration};
+
+  #[test]
+  fn texture_history() {
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    let mut history = ctx.texture_history(3).unwrap();
+    assert_eq!(history.capacity(), 3);
+    assert!(history.is_empty());
+    assert!(history.memory_usage().staging_textures > 0);
+
+    // the first frame updates the whole desktop
+    thread::sleep(Duration::from_millis(100));
+    history.capture().unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history.slice(0), Some(0));
+    assert_eq!(history.slice(1), None);
+  }
+}
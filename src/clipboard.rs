@@ -0,0 +1,225 @@
+//! Place frames on the Windows clipboard as bitmaps, e.g. for screenshot tools.
+//! Enable the `clipboard` feature to use this module.
+
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::model::{Rect, Result};
+use std::time::Duration;
+use std::{mem, ptr, slice, thread};
+use windows::Win32::Foundation::{HANDLE, HWND};
+use windows::Win32::Graphics::Dxgi::Common::{
+  DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+};
+use windows::Win32::Graphics::Gdi::{
+  BITMAPINFOHEADER, BITMAPV5HEADER, BI_BITFIELDS, BI_RGB, LCS_GM_IMAGES,
+};
+use windows::Win32::System::DataExchange::{
+  CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData,
+};
+use windows::Win32::System::Memory::{
+  GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE,
+};
+use windows::Win32::System::Ole::{CF_DIB, CF_DIBV5, CLIPBOARD_FORMAT};
+
+/// Retry opening the clipboard while another application holds it.
+const OPEN_RETRIES: u32 = 10;
+const OPEN_RETRY_DELAY: Duration = Duration::from_millis(10);
+/// `LCS_sRGB`, the color space of desktop pixels.
+const LCS_SRGB: u32 = 0x7352_4742;
+
+impl Frame {
+  /// Place the whole frame on the clipboard, see [`Frame::copy_region_to_clipboard`].
+  pub fn copy_to_clipboard(&self) -> Result<()> {
+    self.copy_region_to_clipboard(&Rect::new(0, 0, self.width as i32, self.height as i32))
+  }
+
+  /// Replace the clipboard content with `rect` of the frame as an opaque 32-bit `CF_DIBV5`,
+  /// and a 24-bit `CF_DIB` for applications which don't read `CF_DIBV5`.
+  /// The rect is clipped to the frame. Only 8-bit BGRA frames are supported.
+  pub fn copy_region_to_clipboard(&self, rect: &Rect) -> Result<()> {
+    if self.format != DXGI_FORMAT_B8G8R8A8_UNORM && self.format != DXGI_FORMAT_B8G8R8A8_UNORM_SRGB {
+      return Err(Error::new(format!(
+        "Can't copy frames of format {:?} to the clipboard",
+        self.format
+      )));
+    }
+    let rect = rect
+      .intersect(&Rect::new(0, 0, self.width as i32, self.height as i32))
+      .ok_or_else(|| Error::new("The region is outside the frame"))?;
+    let dibv5 = pack_dibv5(self, &rect);
+    let dib = pack_dib(self, &rect);
+
+    let _clipboard = Clipboard::open()?;
+    if !unsafe { EmptyClipboard() }.as_bool() {
+      return Err(Error::windows(
+        "EmptyClipboard",
+        windows::core::Error::from_win32(),
+      ));
+    }
+    set_data(CF_DIBV5, &dibv5)?;
+    set_data(CF_DIB, &dib)
+  }
+}
+
+/// View a header as bytes.
+fn header_bytes<T>(header: &T) -> &[u8] {
+  unsafe { slice::from_raw_parts((header as *const T).cast(), mem::size_of::<T>()) }
+}
+
+/// A `BITMAPV5HEADER` followed by bottom-up BGRA rows with the alpha set to 255.
+fn pack_dibv5(frame: &Frame, rect: &Rect) -> Vec<u8> {
+  let size_image = rect.width() * rect.height() * 4;
+  let header = BITMAPV5HEADER {
+    bV5Size: mem::size_of::<BITMAPV5HEADER>() as u32,
+    bV5Width: rect.width() as i32,
+    bV5Height: rect.height() as i32,
+    bV5Planes: 1,
+    bV5BitCount: 32,
+    bV5Compression: BI_BITFIELDS,
+    bV5SizeImage: size_image,
+    bV5RedMask: 0x00FF_0000,
+    bV5GreenMask: 0x0000_FF00,
+    bV5BlueMask: 0x0000_00FF,
+    bV5AlphaMask: 0xFF00_0000,
+    bV5CSType: LCS_SRGB,
+    bV5Intent: LCS_GM_IMAGES as u32,
+    ..Default::default()
+  };
+  let mut dib = header_bytes(&header).to_vec();
+  dib.reserve(size_image as usize);
+  for row in rows(frame, rect) {
+    for &[b, g, r, _] in row {
+      dib.extend_from_slice(&[b, g, r, 0xFF]);
+    }
+  }
+  dib
+}
+
+/// A `BITMAPINFOHEADER` followed by bottom-up BGR rows padded to 4 bytes.
+fn pack_dib(frame: &Frame, rect: &Rect) -> Vec<u8> {
+  let stride = (rect.width() * 3 + 3) & !3;
+  let header = BITMAPINFOHEADER {
+    biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
+    biWidth: rect.width() as i32,
+    biHeight: rect.height() as i32,
+    biPlanes: 1,
+    biBitCount: 24,
+    biCompression: BI_RGB.0 as u32,
+    biSizeImage: stride * rect.height(),
+    ..Default::default()
+  };
+  let mut dib = header_bytes(&header).to_vec();
+  dib.reserve((stride * rect.height()) as usize);
+  let padding = (stride - rect.width() * 3) as usize;
+  for row in rows(frame, rect) {
+    for &[b, g, r, _] in row {
+      dib.extend_from_slice(&[b, g, r]);
+    }
+    dib.extend_from_slice(&[0; 3][..padding]);
+  }
+  dib
+}
+
+/// The pixels of `rect` row by row from the bottom, as DIBs store them.
+fn rows<'a>(frame: &'a Frame, rect: &'a Rect) -> impl Iterator<Item = &'a [[u8; 4]]> {
+  frame
+    .as_pixels()
+    .chunks_exact(frame.width.max(1) as usize)
+    .skip(rect.top as usize)
+    .take(rect.height() as usize)
+    .rev()
+    .map(|row| &row[rect.left as usize..rect.right as usize])
+}
+
+/// Copy `data` to global memory and hand it to the clipboard.
+fn set_data(format: CLIPBOARD_FORMAT, data: &[u8]) -> Result<()> {
+  unsafe {
+    let memory =
+      GlobalAlloc(GMEM_MOVEABLE, data.len()).map_err(|e| Error::windows("GlobalAlloc", e))?;
+    let target = GlobalLock(memory);
+    if target.is_null() {
+      let e = windows::core::Error::from_win32();
+      GlobalFree(memory).ok();
+      return Err(Error::windows("GlobalLock", e));
+    }
+    ptr::copy_nonoverlapping(data.as_ptr(), target.cast(), data.len());
+    GlobalUnlock(memory);
+    // the clipboard owns the memory only if the data is set
+    if let Err(e) = SetClipboardData(format.0 as u32, HANDLE(memory.0)) {
+      GlobalFree(memory).ok();
+      return Err(Error::windows("SetClipboardData", e));
+    }
+  }
+  Ok(())
+}
+
+/// The open clipboard of the current thread, closed on drop.
+struct Clipboard;
+
+impl Clipboard {
+  fn open() -> Result<Self> {
+    for _ in 0..OPEN_RETRIES {
+      if unsafe { OpenClipboard(HWND(0)) }.as_bool() {
+        return Ok(Self);
+      }
+      thread::sleep(OPEN_RETRY_DELAY);
+    }
+    Err(Error::windows(
+      "OpenClipboard",
+      windows::core::Error::from_win32(),
+    ))
+  }
+}
+
+impl Drop for Clipboard {
+  fn drop(&mut self) {
+    unsafe { CloseClipboard() };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{pack_dib, pack_dibv5};
+  use crate::model::Rect;
+  use crate::test_utils::{generate, gradient};
+  use std::mem;
+  use windows::Win32::Graphics::Gdi::{BITMAPINFOHEADER, BITMAPV5HEADER};
+  use windows::Win32::System::DataExchange::IsClipboardFormatAvailable;
+  use windows::Win32::System::Ole::{CF_DIB, CF_DIBV5};
+
+  #[test]
+  fn pack() {
+    let frame = generate(4, 3, |x, y| [x as u8, y as u8, 0xA0]);
+    let rect = Rect::new(1, 1, 4, 3);
+
+    let dib = pack_dib(&frame, &rect);
+    let header = mem::size_of::<BITMAPINFOHEADER>();
+    // 3 pixels of 3 bytes are padded to 12 bytes
+    assert_eq!(dib.len(), header + 12 * 2);
+    assert_eq!(i32::from_le_bytes(dib[4..8].try_into().unwrap()), 3);
+    assert_eq!(i32::from_le_bytes(dib[8..12].try_into().unwrap()), 2);
+    // bottom-up, the first row is y = 2
+    assert_eq!(dib[header..header + 3], [1, 2, 0xA0]);
+    assert_eq!(dib[header + 9..header + 12], [0; 3]);
+    assert_eq!(dib[header + 12..header + 15], [1, 1, 0xA0]);
+
+    let dibv5 = pack_dibv5(&frame, &rect);
+    let header = mem::size_of::<BITMAPV5HEADER>();
+    assert_eq!(dibv5.len(), header + 3 * 2 * 4);
+    assert_eq!(dibv5[header..header + 4], [1, 2, 0xA0, 0xFF]);
+    assert_eq!(dibv5[header + 20..header + 24], [3, 1, 0xA0, 0xFF]);
+  }
+
+  #[test]
+  fn copy_to_clipboard() {
+    let frame = gradient(64, 32);
+    assert!(frame
+      .copy_region_to_clipboard(&Rect::new(100, 100, 200, 200))
+      .is_err());
+    frame.copy_to_clipboard().unwrap();
+    unsafe {
+      assert!(IsClipboardFormatAvailable(CF_DIBV5.0 as u32).as_bool());
+      assert!(IsClipboardFormatAvailable(CF_DIB.0 as u32).as_bool());
+    }
+  }
+}
@@ -0,0 +1,274 @@
+//! Sample pixels, average colors and adjust colors of BGRA32 pixel buffers.
+
+use crate::error::Error;
+use crate::model::{ChannelAdjustment, ColorAdjustment, Rect, Result};
+use windows::Win32::Graphics::Dxgi::Common::{
+  DXGI_FORMAT, DXGI_FORMAT_B8G8R8A8_TYPELESS, DXGI_FORMAT_B8G8R8A8_UNORM,
+  DXGI_FORMAT_B8G8R8A8_UNORM_SRGB, DXGI_FORMAT_R8G8B8A8_UNORM,
+};
+
+/// Return an error if pixels of `format` are not 8-bit BGRA, e.g. FP16 frames of HDR desktops.
+/// `what` names the operation in the error.
+pub(crate) fn check_bgra8(format: DXGI_FORMAT, what: &str) -> Result<()> {
+  match format {
+    DXGI_FORMAT_B8G8R8A8_UNORM
+    | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+    | DXGI_FORMAT_B8G8R8A8_TYPELESS => Ok(()),
+    _ => Err(Error::new(format!(
+      "Can't {} of format {:?}, only 8-bit BGRA is supported",
+      what, format
+    ))),
+  }
+}
+
+/// Get the `[b, g, r, a]` pixel at (`x`, `y`) of a BGRA32 `buffer` of `width` pixels per row,
+/// or `None` if it is outside the buffer.
+pub fn pixel_at(buffer: &[u8], width: u32, x: u32, y: u32) -> Option<[u8; 4]> {
+  if x >= width {
+    return None;
+  }
+  let offset = (y as usize * width as usize + x as usize) * 4;
+  buffer.get(offset..offset + 4)?.try_into().ok()
+}
+
+/// Average the pixels of `rect` in a BGRA32 `buffer` of `width` pixels per row, taking every `step`-th
+/// pixel of every `step`-th row. Return `[b, g, r, a]`, or `None` if `rect` has no pixel in the buffer.
+pub fn average_color(buffer: &[u8], width: u32, rect: &Rect, step: u32) -> Option<[u8; 4]> {
+  let height = (buffer.len() / 4 / width.max(1) as usize) as u32;
+  let rect = rect.intersect(&Rect::new(0, 0, width as i32, height as i32))?;
+  let step = step.max(1) as usize;
+
+  let mut sums = [0u64; 4];
+  let mut count = 0u64;
+  let line_bytes = width as usize * 4;
+  for y in (rect.top as usize..rect.bottom as usize).step_by(step) {
+    let start = y * line_bytes + rect.left as usize * 4;
+    let row = &buffer[start..start + rect.width() as usize * 4];
+    if step == 1 {
+      sum_pixels(row, &mut sums);
+      count += rect.width() as u64;
+    } else {
+      for pixel in row.chunks_exact(4).step_by(step) {
+        add_pixel(pixel, &mut sums);
+        count += 1;
+      }
+    }
+  }
+  Some(sums.map(|sum| ((sum + count / 2) / count) as u8))
+}
+
+fn add_pixel(pixel: &[u8], sums: &mut [u64; 4]) {
+  for (sum, &value) in sums.iter_mut().zip(pixel) {
+    *sum += value as u64;
+  }
+}
+
+/// Add the channels of contiguous BGRA32 pixels to `sums`.
+#[cfg(target_arch = "x86_64")]
+fn sum_pixels(pixels: &[u8], sums: &mut [u64; 4]) {
+  use std::arch::x86_64::{
+    __m128i, _mm_add_epi16, _mm_add_epi32, _mm_loadu_si128, _mm_setzero_si128, _mm_storeu_si128,
+    _mm_unpackhi_epi16, _mm_unpackhi_epi8, _mm_unpacklo_epi16, _mm_unpacklo_epi8,
+  };
+
+  let chunks = pixels.chunks_exact(16);
+  let rest = chunks.remainder();
+  // SSE2 is always available on x86_64.
+  // Each lane sums one channel, a row of pixels can't overflow 32 bits.
+  let mut lanes = [0u32; 4];
+  unsafe {
+    let zero = _mm_setzero_si128();
+    let mut acc = _mm_setzero_si128();
+    for chunk in chunks {
+      let v = _mm_loadu_si128(chunk.as_ptr() as *const __m128i);
+      // widen 4 pixels to 16 bits and add them pairwise
+      let pair = _mm_add_epi16(_mm_unpacklo_epi8(v, zero), _mm_unpackhi_epi8(v, zero));
+      acc = _mm_add_epi32(acc, _mm_unpacklo_epi16(pair, zero));
+      acc = _mm_add_epi32(acc, _mm_unpackhi_epi16(pair, zero));
+    }
+    _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, acc);
+  }
+  for (sum, lane) in sums.iter_mut().zip(lanes) {
+    *sum += lane as u64;
+  }
+  sum_pixels_scalar(rest, sums);
+}
+
+/// Add the channels of contiguous BGRA32 pixels to `sums`.
+#[cfg(target_arch = "aarch64")]
+fn sum_pixels(pixels: &[u8], sums: &mut [u64; 4]) {
+  use std::arch::aarch64::{vaddvq_u32, vdupq_n_u32, vld4q_u8, vpadalq_u16, vpaddlq_u8};
+
+  let chunks = pixels.chunks_exact(64);
+  let rest = chunks.remainder();
+  // NEON is always available on aarch64.
+  // Load 16 pixels split into channels, each lane of `acc[c]` sums channel `c` of 4 pixels.
+  unsafe {
+    let mut acc = [vdupq_n_u32(0); 4];
+    for chunk in chunks {
+      let v = vld4q_u8(chunk.as_ptr());
+      acc[0] = vpadalq_u16(acc[0], vpaddlq_u8(v.0));
+      acc[1] = vpadalq_u16(acc[1], vpaddlq_u8(v.1));
+      acc[2] = vpadalq_u16(acc[2], vpaddlq_u8(v.2));
+      acc[3] = vpadalq_u16(acc[3], vpaddlq_u8(v.3));
+    }
+    for (sum, lanes) in sums.iter_mut().zip(acc) {
+      *sum += vaddvq_u32(lanes) as u64;
+    }
+  }
+  sum_pixels_scalar(rest, sums);
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn sum_pixels(pixels: &[u8], sums: &mut [u64; 4]) {
+  sum_pixels_scalar(pixels, sums);
+}
+
+fn sum_pixels_scalar(pixels: &[u8], sums: &mut [u64; 4]) {
+  for pixel in pixels.chunks_exact(4) {
+    add_pixel(pixel, sums);
+  }
+}
+
+/// Lookup tables of a [`ColorAdjustment`], to adjust pixels with one lookup per channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLut {
+  adjustment: ColorAdjustment,
+  /// Blue, green and red tables.
+  tables: [[u8; 256]; 3],
+}
+
+impl ColorLut {
+  pub fn new(adjustment: ColorAdjustment) -> Self {
+    Self {
+      adjustment,
+      tables: [adjustment.blue, adjustment.green, adjustment.red].map(|channel| table(&channel)),
+    }
+  }
+
+  pub fn adjustment(&self) -> ColorAdjustment {
+    self.adjustment
+  }
+
+  /// Adjust pixels of a BGRA8 or RGBA8 `format` in place, alpha is kept.
+  /// Return `false` and keep the pixels for other formats.
+  pub fn apply(&self, pixels: &mut [u8], format: DXGI_FORMAT) -> bool {
+    let [blue, green, red] = &self.tables;
+    let [first, third] = match format {
+      DXGI_FORMAT_B8G8R8A8_UNORM
+      | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+      | DXGI_FORMAT_B8G8R8A8_TYPELESS => [blue, red],
+      DXGI_FORMAT_R8G8B8A8_UNORM => [red, blue],
+      _ => return false,
+    };
+    for pixel in pixels.chunks_exact_mut(4) {
+      pixel[0] = first[pixel[0] as usize];
+      pixel[1] = green[pixel[1] as usize];
+      pixel[2] = third[pixel[2] as usize];
+    }
+    true
+  }
+}
+
+fn table(channel: &ChannelAdjustment) -> [u8; 256] {
+  let gamma = 1.0 / channel.gamma.max(f32::EPSILON);
+  std::array::from_fn(|value| {
+    let value = (value as f32 / 255.0).powf(gamma);
+    let value = (value - 0.5) * channel.contrast + 0.5 + channel.brightness;
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{average_color, pixel_at, sum_pixels, sum_pixels_scalar, ColorLut};
+  use crate::model::{ChannelAdjustment, ColorAdjustment, Rect};
+  use crate::test_utils::{generate, noise};
+  use windows::Win32::Graphics::Dxgi::Common::{
+    DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_FORMAT_R8G8B8A8_UNORM,
+  };
+
+  #[test]
+  fn sums() {
+    // an odd pixel count to cover the remainder
+    let frame = noise(37, 1, 42);
+    let mut fast = [0u64; 4];
+    let mut scalar = [0u64; 4];
+    sum_pixels(&frame.buffer, &mut fast);
+    sum_pixels_scalar(&frame.buffer, &mut scalar);
+    assert_eq!(fast, scalar);
+  }
+
+  #[test]
+  fn pixels() {
+    let frame = generate(4, 2, |x, y| [x as u8, y as u8, 0]);
+    assert_eq!(pixel_at(&frame.buffer, 4, 3, 1), Some([3, 1, 0, 0xFF]));
+    assert_eq!(pixel_at(&frame.buffer, 4, 4, 0), None);
+    assert_eq!(pixel_at(&frame.buffer, 4, 0, 2), None);
+  }
+
+  #[test]
+  fn average_colors() {
+    // left half black, right half white
+    let frame = generate(8, 4, |x, _| if x < 4 { [0; 3] } else { [0xFF; 3] });
+    let all = Rect::new(0, 0, 8, 4);
+    assert_eq!(
+      average_color(&frame.buffer, 8, &all, 1),
+      Some([128, 128, 128, 0xFF])
+    );
+    assert_eq!(
+      average_color(&frame.buffer, 8, &Rect::new(4, 0, 100, 100), 1),
+      Some([0xFF; 4])
+    );
+    // every other pixel: columns 0, 2, 4 and 6
+    assert_eq!(
+      average_color(&frame.buffer, 8, &all, 2),
+      Some([128, 128, 128, 0xFF])
+    );
+    // columns 0 and 3 are black, 6 is white
+    assert_eq!(
+      average_color(&frame.buffer, 8, &all, 3),
+      Some([85, 85, 85, 0xFF])
+    );
+    assert_eq!(
+      average_color(&frame.buffer, 8, &Rect::new(8, 0, 10, 4), 1),
+      None
+    );
+  }
+
+  #[test]
+  fn color_lut() {
+    let neutral = ColorLut::new(ColorAdjustment::default());
+    let frame = noise(8, 8, 3);
+    let mut pixels = frame.buffer.clone();
+    assert!(neutral.apply(&mut pixels, frame.format));
+    assert_eq!(pixels, frame.buffer);
+
+    // brighten red only, swapped channels in RGBA
+    let lut = ColorLut::new(ColorAdjustment {
+      red: ChannelAdjustment {
+        brightness: 0.5,
+        ..Default::default()
+      },
+      ..Default::default()
+    });
+    let mut pixels = vec![0x10, 0x20, 0x30, 0x40];
+    assert!(lut.apply(&mut pixels, DXGI_FORMAT_B8G8R8A8_UNORM));
+    assert_eq!(pixels, [0x10, 0x20, 0xB0, 0x40]);
+    let mut pixels = vec![0x30, 0x20, 0x10, 0x40];
+    assert!(lut.apply(&mut pixels, DXGI_FORMAT_R8G8B8A8_UNORM));
+    assert_eq!(pixels, [0xB0, 0x20, 0x10, 0x40]);
+    assert!(!lut.apply(&mut pixels, DXGI_FORMAT_R16G16B16A16_FLOAT));
+
+    // gamma and contrast
+    let lut = ColorLut::new(ColorAdjustment::uniform(ChannelAdjustment {
+      gamma: 2.0,
+      contrast: 2.0,
+      ..Default::default()
+    }));
+    let mut pixels = vec![0, 64, 255, 0xFF];
+    lut.apply(&mut pixels, DXGI_FORMAT_B8G8R8A8_UNORM);
+    // 64 / 255 = 0.25, sqrt = 0.5, contrast keeps the middle gray
+    assert_eq!(pixels, [0, 128, 255, 0xFF]);
+  }
+}
@@ -0,0 +1,31 @@
+//! Initialize COM for subsystems which create COM objects on the calling thread.
+
+use std::marker::PhantomData;
+use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_MULTITHREADED};
+
+/// Keep COM initialized on the current thread while alive.
+pub(crate) struct ComGuard {
+  initialized: bool,
+  /// COM must be uninitialized on the thread which initialized it.
+  _thread: PhantomData<*const ()>,
+}
+
+impl ComGuard {
+  /// Initialize COM in the multithreaded apartment.
+  /// If the thread already initialized COM with another apartment, that apartment is used
+  /// and nothing is uninitialized on drop.
+  pub(crate) fn new() -> Self {
+    Self {
+      initialized: unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }.is_ok(),
+      _thread: PhantomData,
+    }
+  }
+}
+
+impl Drop for ComGuard {
+  fn drop(&mut self) {
+    if self.initialized {
+      unsafe { CoUninitialize() };
+    }
+  }
+}
@@ -0,0 +1,196 @@
+//! Match input events to the first frame presented after them,
+//! e.g. to measure input-to-photon latency or to wait for a UI reaction in automated tests.
+
+use crate::duplication_context::{qpc_duration, qpc_now};
+use crate::model::InputEvent;
+use std::collections::VecDeque;
+use std::time::Duration;
+use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_FRAME_INFO;
+
+/// How many recorded frames [`InputCorrelator`] keeps for events registered late.
+pub const DEFAULT_FRAME_HISTORY: usize = 256;
+
+/// An input event and the first frame presented at or after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventMatch {
+  pub event: InputEvent,
+  /// The index passed to [`InputCorrelator::record_frame`].
+  pub frame: u64,
+  /// The QPC `LastPresentTime` of the frame.
+  pub present_time: i64,
+  /// From the event to the present of the frame.
+  pub latency: Duration,
+}
+
+/// Register input events and captured frames in any order and get each event's frame.
+///
+/// Frames are identified by an index chosen by the caller, e.g. a frame counter.
+#[derive(Debug, Clone)]
+pub struct InputCorrelator {
+  /// Events which no recorded frame is presented after, ordered by time.
+  pending: VecDeque<InputEvent>,
+  /// Recent frames as `(index, present time)`, ordered by present time.
+  frames: VecDeque<(u64, i64)>,
+  /// The present time of the latest forgotten frame, earlier events can't be matched.
+  horizon: i64,
+  history: usize,
+  frequency: i64,
+}
+
+impl Default for InputCorrelator {
+  fn default() -> Self {
+    Self::new(DEFAULT_FRAME_HISTORY)
+  }
+}
+
+impl InputCorrelator {
+  /// Keep the latest `history` frames to match events registered after their frame.
+  pub fn new(history: usize) -> Self {
+    Self::with_frequency(history, qpc_now().1)
+  }
+
+  fn with_frequency(history: usize, frequency: i64) -> Self {
+    Self {
+      pending: VecDeque::new(),
+      frames: VecDeque::new(),
+      horizon: 0,
+      history: history.max(1),
+      frequency,
+    }
+  }
+
+  /// Events still waiting for a frame, ordered by time.
+  pub fn pending(&self) -> impl Iterator<Item = &InputEvent> {
+    self.pending.iter()
+  }
+
+  /// Register an event. Return its match if a recorded frame is already presented after it,
+  /// otherwise it is returned by a later [`InputCorrelator::record_frame`].
+  /// Events before the kept frames are ignored.
+  pub fn record_event(&mut self, event: InputEvent) -> Option<EventMatch> {
+    if event.time <= self.horizon {
+      return None;
+    }
+    match self.frame_at(event.time) {
+      Some((frame, present_time)) => Some(self.matched(event, frame, present_time)),
+      None => {
+        let index = self.pending.partition_point(|e| e.time <= event.time);
+        self.pending.insert(index, event);
+        None
+      }
+    }
+  }
+
+  /// Record a captured frame and return the pending events presented by it, ordered by time.
+  /// Frames without a present time, e.g. pointer-only updates, match nothing.
+  pub fn record_frame(&mut self, frame: u64, info: &DXGI_OUTDUPL_FRAME_INFO) -> Vec<EventMatch> {
+    let present_time = info.LastPresentTime;
+    if present_time == 0 {
+      return Vec::new();
+    }
+    let index = self.frames.partition_point(|(_, t)| *t <= present_time);
+    self.frames.insert(index, (frame, present_time));
+    while self.frames.len() > self.history {
+      if let Some((_, time)) = self.frames.pop_front() {
+        self.horizon = time;
+      }
+    }
+
+    let count = self.pending.partition_point(|e| e.time <= present_time);
+    let events: Vec<_> = self.pending.drain(..count).collect();
+    events
+      .into_iter()
+      // a frame recorded out of order may not be the first one after the event,
+      // or may be forgotten right away
+      .filter_map(|event| {
+        let (frame, present_time) = self.frame_at(event.time)?;
+        Some(self.matched(event, frame, present_time))
+      })
+      .collect()
+  }
+
+  /// The first recorded frame presented at or after the QPC `time`, as `(index, present time)`.
+  /// Return `None` if it is not recorded yet or already forgotten.
+  pub fn frame_at(&self, time: i64) -> Option<(u64, i64)> {
+    if time <= self.horizon {
+      return None;
+    }
+    let index = self.frames.partition_point(|(_, t)| *t < time);
+    self.frames.get(index).copied()
+  }
+
+  /// Forget pending events before the QPC `time`, e.g. events whose frame was never captured.
+  pub fn discard_before(&mut self, time: i64) {
+    let count = self.pending.partition_point(|e| e.time < time);
+    self.pending.drain(..count);
+  }
+
+  fn matched(&self, event: InputEvent, frame: u64, present_time: i64) -> EventMatch {
+    EventMatch {
+      event,
+      frame,
+      present_time,
+      latency: qpc_duration(present_time - event.time, self.frequency),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::InputCorrelator;
+  use crate::model::{InputEvent, InputKind, Point};
+  use std::time::Duration;
+  use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_FRAME_INFO;
+
+  fn key(time: i64) -> InputEvent {
+    InputEvent {
+      time,
+      position: Point::default(),
+      kind: InputKind::KeyDown(0x41),
+    }
+  }
+
+  fn info(present_time: i64) -> DXGI_OUTDUPL_FRAME_INFO {
+    DXGI_OUTDUPL_FRAME_INFO {
+      LastPresentTime: present_time,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn correlation() {
+    // one QPC tick per millisecond, keep 2 frames
+    let mut correlator = InputCorrelator::with_frequency(2, 1000);
+    assert!(correlator.record_event(key(100)).is_none());
+    assert!(correlator.record_event(key(130)).is_none());
+    assert!(correlator.record_event(key(200)).is_none());
+    assert!(correlator.record_frame(0, &info(0)).is_empty());
+
+    let matches = correlator.record_frame(1, &info(150));
+    assert_eq!(matches.len(), 2);
+    assert_eq!((matches[0].event.time, matches[0].frame), (100, 1));
+    assert_eq!(matches[0].latency, Duration::from_millis(50));
+    assert_eq!(matches[1].latency, Duration::from_millis(20));
+    assert_eq!(correlator.pending().count(), 1);
+
+    // events registered after their frame match the recorded frames
+    let late = correlator.record_event(key(140)).unwrap();
+    assert_eq!((late.frame, late.present_time), (1, 150));
+
+    let matches = correlator.record_frame(2, &info(210));
+    assert_eq!(matches[0].frame, 2);
+    assert_eq!(correlator.pending().count(), 0);
+
+    // old frames are forgotten
+    correlator.record_frame(3, &info(260));
+    assert_eq!(correlator.frame_at(100), None);
+    assert_eq!(correlator.frame_at(200), Some((2, 210)));
+    assert_eq!(correlator.frame_at(300), None);
+    assert!(correlator.record_event(key(120)).is_none());
+    assert_eq!(correlator.pending().count(), 0);
+
+    assert!(correlator.record_event(key(300)).is_none());
+    correlator.discard_before(301);
+    assert_eq!(correlator.pending().count(), 0);
+  }
+}
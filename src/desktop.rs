@@ -0,0 +1,147 @@
+//! Helpers for capturing from a Windows service.
+//!
+//! Desktop duplication only works when the calling thread is attached to the input desktop,
+//! which is not the case for services. Call [`attach_input_desktop`] on the capturing thread
+//! before creating the [`Manager`](crate::manager::Manager).
+//!
+//! When the input desktop switches (e.g. UAC prompt, lock screen, login screen),
+//! capturing fails with `DXGI_ERROR_ACCESS_LOST`.
+//! Call [`attach_input_desktop`] again and refresh the manager to continue capturing.
+
+use crate::error::Error;
+use crate::model::Result;
+use std::cell::RefCell;
+use windows::Win32::Foundation::{E_ACCESSDENIED, GENERIC_ALL, HANDLE};
+use windows::Win32::System::StationsAndDesktops::{
+  CloseDesktop, GetThreadDesktop, GetUserObjectInformationW, OpenInputDesktop, SetThreadDesktop,
+  DESKTOP_ACCESS_FLAGS, DESKTOP_CONTROL_FLAGS, HDESK, UOI_NAME,
+};
+use windows::Win32::System::Threading::GetCurrentThreadId;
+
+/// An opened desktop handle, closed on drop.
+struct Desktop(HDESK);
+
+impl Desktop {
+  fn open_input() -> Result<Self> {
+    unsafe {
+      OpenInputDesktop(
+        DESKTOP_CONTROL_FLAGS(0),
+        false,
+        DESKTOP_ACCESS_FLAGS(GENERIC_ALL.0),
+      )
+    }
+    .map(Self)
+    .map_err(|e| Error::windows("OpenInputDesktop", e))
+  }
+}
+
+impl Drop for Desktop {
+  fn drop(&mut self) {
+    unsafe { CloseDesktop(self.0) };
+  }
+}
+
+/// The input desktop a thread is attached to by [`attach_input_desktop`].
+struct Attached {
+  desktop: Desktop,
+  /// The desktop of the thread before it was first attached, not owned.
+  original: HDESK,
+}
+
+impl Drop for Attached {
+  fn drop(&mut self) {
+    // a desktop can't be closed while a thread is assigned to it
+    unsafe { SetThreadDesktop(self.original) };
+  }
+}
+
+thread_local! {
+  /// Detached and closed when the thread exits.
+  static ATTACHED_DESKTOP: RefCell<Option<Attached>> = const { RefCell::new(None) };
+}
+
+/// Attach the current thread to the current input desktop.
+///
+/// The thread must not own any windows or hooks, otherwise `SetThreadDesktop` fails.
+/// Calling this again after a desktop switch releases the previously attached desktop.
+/// The attached desktop is released when the thread exits.
+pub fn attach_input_desktop() -> Result<()> {
+  let original = unsafe { GetThreadDesktop(GetCurrentThreadId()) }
+    .map_err(|e| Error::windows("GetThreadDesktop", e))?;
+  let desktop = Desktop::open_input()?;
+  if !unsafe { SetThreadDesktop(desktop.0) }.as_bool() {
+    return Err(Error::windows(
+      "SetThreadDesktop",
+      windows::core::Error::from_win32(),
+    ));
+  }
+
+  ATTACHED_DESKTOP.with(|attached| {
+    let mut attached = attached.borrow_mut();
+    match attached.as_mut() {
+      // the thread is already switched, only close the previous desktop
+      Some(attached) => attached.desktop = desktop,
+      None => *attached = Some(Attached { desktop, original }),
+    }
+  });
+  Ok(())
+}
+
+/// Return the name of the current input desktop, e.g. `Default` or `Winlogon`.
+/// This can be polled to detect desktop switches.
+pub fn input_desktop_name() -> Result<String> {
+  let desktop = Desktop::open_input()?;
+  let mut name = [0u16; 256];
+  let mut len = 0;
+  if !unsafe {
+    GetUserObjectInformationW(
+      HANDLE(desktop.0 .0),
+      UOI_NAME,
+      Some(name.as_mut_ptr() as *mut _),
+      std::mem::size_of_val(&name) as u32,
+      Some(&mut len),
+    )
+  }
+  .as_bool()
+  {
+    return Err(Error::windows(
+      "GetUserObjectInformationW",
+      windows::core::Error::from_win32(),
+    ));
+  }
+  let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+  Ok(String::from_utf16_lossy(&name[..len]))
+}
+
+/// Return `true` if the input desktop is not the default desktop,
+/// e.g. while the workstation is locked or a UAC prompt is shown.
+/// Capturing fails with `DXGI_ERROR_ACCESS_LOST` until the default desktop is back.
+///
+/// Opening a secure input desktop is denied for processes not running as system, which also counts as locked.
+/// Other failures are returned as errors.
+pub fn is_locked() -> Result<bool> {
+  match input_desktop_name() {
+    Ok(name) => Ok(!name.eq_ignore_ascii_case("Default")),
+    Err(e) if e.windows.as_ref().map(|e| e.code()) == Some(E_ACCESSDENIED) => Ok(true),
+    Err(e) => Err(e),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{attach_input_desktop, input_desktop_name, is_locked};
+
+  #[test]
+  fn input_desktop() {
+    assert_eq!(input_desktop_name().unwrap(), "Default");
+    assert!(!is_locked().unwrap());
+    attach_input_desktop().unwrap();
+    // attach again to release the previous desktop
+    attach_input_desktop().unwrap();
+
+    // the desktop is released when the thread exits
+    std::thread::spawn(|| attach_input_desktop().unwrap())
+      .join()
+      .unwrap();
+  }
+}
@@ -0,0 +1,340 @@
+//! Measure the capture latency of a monitor on the current machine.
+//! Enable the `diagnostics` feature to use this module.
+
+use crate::capturer::model::Capturer;
+use crate::duplication_context::DuplicationContext;
+use crate::error::Error;
+use crate::model::Result;
+use crate::utils::OutputDescExt;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::core::{w, PCWSTR};
+use windows::Win32::Foundation::{
+  GetLastError, ERROR_CLASS_ALREADY_EXISTS, HWND, LPARAM, LRESULT, RECT, WPARAM,
+};
+use windows::Win32::Graphics::Dxgi::Common::{
+  DXGI_MODE_ROTATION_IDENTITY, DXGI_MODE_ROTATION_UNSPECIFIED,
+};
+use windows::Win32::Graphics::Dxgi::DXGI_ERROR_WAIT_TIMEOUT;
+use windows::Win32::Graphics::Gdi::{
+  FillRect, GdiFlush, GetDC, GetStockObject, ReleaseDC, BLACK_BRUSH, HBRUSH, WHITE_BRUSH,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::WindowsAndMessaging::{
+  CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, PeekMessageW, RegisterClassW,
+  ShowWindow, TranslateMessage, MSG, PM_REMOVE, SW_SHOWNOACTIVATE, WNDCLASSW, WS_EX_NOACTIVATE,
+  WS_EX_TOOLWINDOW, WS_EX_TOPMOST, WS_POPUP,
+};
+
+const CLASS_NAME: PCWSTR = w!("RustyDuplicationLatencyPattern");
+
+/// How [`measure_capture_latency`] measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyOptions {
+  /// How many pattern changes are measured.
+  pub samples: u32,
+  /// How long to wait for a change to be captured before it counts as a timeout.
+  pub timeout: Duration,
+  /// The pause between changes, so the compositor is idle when a change is drawn.
+  pub interval: Duration,
+  /// The width and height of the pattern window in pixels.
+  pub size: u32,
+}
+
+impl Default for LatencyOptions {
+  fn default() -> Self {
+    Self {
+      samples: 30,
+      timeout: Duration::from_secs(1),
+      interval: Duration::from_millis(50),
+      size: 64,
+    }
+  }
+}
+
+/// Latencies from drawing a pattern change to capturing a frame which shows it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyDistribution {
+  /// Ordered from the fastest to the slowest.
+  pub samples: Vec<Duration>,
+  /// Changes which weren't captured within [`LatencyOptions::timeout`].
+  pub timeouts: u32,
+}
+
+impl LatencyDistribution {
+  /// Sort the samples.
+  pub fn new(mut samples: Vec<Duration>, timeouts: u32) -> Self {
+    samples.sort();
+    Self { samples, timeouts }
+  }
+
+  pub fn min(&self) -> Option<Duration> {
+    self.samples.first().copied()
+  }
+
+  pub fn max(&self) -> Option<Duration> {
+    self.samples.last().copied()
+  }
+
+  pub fn mean(&self) -> Option<Duration> {
+    let count = self.samples.len() as u32;
+    (count > 0).then(|| self.samples.iter().sum::<Duration>() / count)
+  }
+
+  pub fn median(&self) -> Option<Duration> {
+    self.percentile(50.0)
+  }
+
+  /// The nearest-rank percentile, `percentile` is clamped to `[0, 100]`.
+  pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+    if self.samples.is_empty() {
+      return None;
+    }
+    let rank = (percentile.clamp(0.0, 100.0) / 100.0 * self.samples.len() as f64).ceil() as usize;
+    Some(self.samples[rank.clamp(1, self.samples.len()) - 1])
+  }
+}
+
+impl fmt::Display for LatencyDistribution {
+  fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let ms = |d: Option<Duration>| d.unwrap_or_default().as_secs_f64() * 1000.0;
+    write!(
+      fmt,
+      "{} samples: min {:.1} ms, median {:.1} ms, mean {:.1} ms, p95 {:.1} ms, max {:.1} ms",
+      self.samples.len(),
+      ms(self.min()),
+      ms(self.median()),
+      ms(self.mean()),
+      ms(self.percentile(95.0)),
+      ms(self.max()),
+    )?;
+    if self.timeouts > 0 {
+      write!(fmt, ", {} timeouts", self.timeouts)?;
+    }
+    Ok(())
+  }
+}
+
+/// Show a small topmost window in the top-left corner of the monitor, toggle it between black and white
+/// and measure the time until each change is captured. This includes composition, the frame interval
+/// of the monitor and the copy to system memory, so it is the latency applications see.
+///
+/// The monitor must not be rotated and the process should be per-monitor DPI aware
+/// so the window is placed on the monitor's pixels. The corner must not be covered by other topmost windows.
+/// The timeout of the context is changed while measuring and restored afterwards.
+pub fn measure_capture_latency(
+  ctx: &DuplicationContext,
+  options: &LatencyOptions,
+) -> Result<LatencyDistribution> {
+  let desc = ctx.dxgi_output_desc()?;
+  if desc.Rotation != DXGI_MODE_ROTATION_IDENTITY && desc.Rotation != DXGI_MODE_ROTATION_UNSPECIFIED
+  {
+    return Err(Error::new("Latency can't be measured on rotated monitors"));
+  }
+  let rect = desc.rect();
+  let size = options.size.clamp(2, rect.width().min(rect.height()));
+  let window = PatternWindow::new(rect.left, rect.top, size)?;
+  let mut capturer = ctx.simple_capturer()?;
+
+  let timeout_ms = ctx.timeout_ms();
+  ctx.set_timeout_ms(1);
+  let result = (|| {
+    // wait until the window is shown, which may take much longer than a change
+    window.fill(false);
+    if wait_for(
+      &mut capturer,
+      &window,
+      size,
+      false,
+      options.timeout.max(Duration::from_secs(2)),
+    )?
+    .is_none()
+    {
+      return Err(Error::new(
+        "The pattern window is not captured, is the corner of the monitor covered?",
+      ));
+    }
+
+    let mut samples = Vec::with_capacity(options.samples as usize);
+    let mut timeouts = 0;
+    for sample in 0..options.samples {
+      let white = sample % 2 == 0;
+      pause(&window, options.interval);
+      window.fill(white);
+      match wait_for(&mut capturer, &window, size, white, options.timeout)? {
+        Some(latency) => samples.push(latency),
+        None => timeouts += 1,
+      }
+    }
+    Ok(LatencyDistribution::new(samples, timeouts))
+  })();
+  ctx.set_timeout_ms(timeout_ms);
+  result
+}
+
+/// Capture until the center of the window is white or black,
+/// return the time since the call or `None` if `timeout` passed.
+fn wait_for(
+  capturer: &mut impl Capturer,
+  window: &PatternWindow,
+  size: u32,
+  white: bool,
+  timeout: Duration,
+) -> Result<Option<Duration>> {
+  let start = Instant::now();
+  while start.elapsed() < timeout {
+    window.pump();
+    match capturer.capture() {
+      Ok(_) => {}
+      Err(e) if e.windows.as_ref().map(|e| e.code()) == Some(DXGI_ERROR_WAIT_TIMEOUT) => continue,
+      Err(e) => return Err(e),
+    }
+    let matched = capturer
+      .pixel_at(size / 2, size / 2)?
+      .is_some_and(|[b, g, r, _]| {
+        [b, g, r]
+          .iter()
+          .all(|&c| if white { c >= 0xC0 } else { c < 0x40 })
+      });
+    if matched {
+      return Ok(Some(start.elapsed()));
+    }
+  }
+  Ok(None)
+}
+
+/// Sleep while keeping the window responsive.
+fn pause(window: &PatternWindow, duration: Duration) {
+  let start = Instant::now();
+  while start.elapsed() < duration {
+    window.pump();
+    thread::sleep(Duration::from_millis(1));
+  }
+}
+
+unsafe extern "system" fn window_proc(
+  hwnd: HWND,
+  message: u32,
+  wparam: WPARAM,
+  lparam: LPARAM,
+) -> LRESULT {
+  DefWindowProcW(hwnd, message, wparam, lparam)
+}
+
+/// A borderless topmost window filled with black or white, destroyed on drop.
+struct PatternWindow {
+  hwnd: HWND,
+  size: u32,
+}
+
+impl PatternWindow {
+  fn new(x: i32, y: i32, size: u32) -> Result<Self> {
+    unsafe {
+      let instance = GetModuleHandleW(None).map_err(|e| Error::windows("GetModuleHandleW", e))?;
+      let class = WNDCLASSW {
+        lpfnWndProc: Some(window_proc),
+        hInstance: instance,
+        hbrBackground: HBRUSH(GetStockObject(BLACK_BRUSH).0),
+        lpszClassName: CLASS_NAME,
+        ..Default::default()
+      };
+      if RegisterClassW(&class) == 0 && GetLastError() != ERROR_CLASS_ALREADY_EXISTS {
+        return Err(Error::windows(
+          "RegisterClassW",
+          windows::core::Error::from_win32(),
+        ));
+      }
+      let hwnd = CreateWindowExW(
+        WS_EX_TOPMOST | WS_EX_TOOLWINDOW | WS_EX_NOACTIVATE,
+        CLASS_NAME,
+        w!(""),
+        WS_POPUP,
+        x,
+        y,
+        size as i32,
+        size as i32,
+        None,
+        None,
+        instance,
+        None,
+      );
+      if hwnd.0 == 0 {
+        return Err(Error::windows(
+          "CreateWindowExW",
+          windows::core::Error::from_win32(),
+        ));
+      }
+      ShowWindow(hwnd, SW_SHOWNOACTIVATE);
+      Ok(Self { hwnd, size })
+    }
+  }
+
+  fn fill(&self, white: bool) {
+    let brush = if white { WHITE_BRUSH } else { BLACK_BRUSH };
+    let rect = RECT {
+      left: 0,
+      top: 0,
+      right: self.size as i32,
+      bottom: self.size as i32,
+    };
+    unsafe {
+      let dc = GetDC(self.hwnd);
+      FillRect(dc, &rect, HBRUSH(GetStockObject(brush).0));
+      ReleaseDC(self.hwnd, dc);
+      GdiFlush();
+    }
+  }
+
+  /// Handle pending messages of the thread.
+  fn pump(&self) {
+    let mut message = MSG::default();
+    unsafe {
+      while PeekMessageW(&mut message, HWND(0), 0, 0, PM_REMOVE).as_bool() {
+        TranslateMessage(&message);
+        DispatchMessageW(&message);
+      }
+    }
+  }
+}
+
+impl Drop for PatternWindow {
+  fn drop(&mut self) {
+    unsafe { DestroyWindow(self.hwnd) };
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{measure_capture_latency, LatencyDistribution, LatencyOptions};
+  use crate::manager::Manager;
+  use std::time::Duration;
+
+  #[test]
+  fn distribution() {
+    let ms = Duration::from_millis;
+    let distribution = LatencyDistribution::new(vec![ms(30), ms(10), ms(20), ms(40)], 1);
+    assert_eq!(distribution.min(), Some(ms(10)));
+    assert_eq!(distribution.max(), Some(ms(40)));
+    assert_eq!(distribution.mean(), Some(ms(25)));
+    assert_eq!(distribution.median(), Some(ms(20)));
+    assert_eq!(distribution.percentile(95.0), Some(ms(40)));
+    assert_eq!(distribution.percentile(0.0), Some(ms(10)));
+    assert_eq!(
+      distribution.to_string(),
+      "4 samples: min 10.0 ms, median 20.0 ms, mean 25.0 ms, p95 40.0 ms, max 40.0 ms, 1 timeouts"
+    );
+    assert_eq!(LatencyDistribution::default().median(), None);
+  }
+
+  #[test]
+  fn measure() {
+    let manager = Manager::default().unwrap();
+    let options = LatencyOptions {
+      samples: 4,
+      ..Default::default()
+    };
+    let distribution = measure_capture_latency(&manager.contexts[0], &options).unwrap();
+    assert_eq!(distribution.samples.len() as u32 + distribution.timeouts, 4);
+  }
+}
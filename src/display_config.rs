@@ -0,0 +1,290 @@
+//! Detect monitors in clone mode with the DisplayConfig API, so multi-monitor tools can skip duplicates.
+//! Enable the `display-config` feature to use this module.
+//!
+//! The monitor device paths of the API identify monitors by their hardware id from the EDID
+//! and their connector, unlike GDI device names which are assigned at boot.
+//!
+//! Cloned monitors on one adapter share a source, which DXGI enumerates as a single output.
+//! Cloned monitors on different adapters are separate outputs showing the same desktop area,
+//! capturing all of them wastes resources.
+
+use crate::duplication_context::DuplicationContext;
+use crate::error::Error;
+use crate::manager::MonitorHandle;
+use crate::model::{Rect, Result};
+use crate::utils::{from_wide, OutputDescExt};
+use std::mem;
+use windows::Win32::Devices::Display::{
+  DisplayConfigGetDeviceInfo, GetDisplayConfigBufferSizes, QueryDisplayConfig,
+  DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME, DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME,
+  DISPLAYCONFIG_DEVICE_INFO_HEADER, DISPLAYCONFIG_MODE_INFO, DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE,
+  DISPLAYCONFIG_PATH_INFO, DISPLAYCONFIG_SOURCE_DEVICE_NAME, DISPLAYCONFIG_TARGET_DEVICE_NAME,
+  QDC_ONLY_ACTIVE_PATHS,
+};
+use windows::Win32::Foundation::{ERROR_INSUFFICIENT_BUFFER, ERROR_SUCCESS, LUID};
+
+/// Monitors showing the same desktop area in clone mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloneGroup {
+  /// The desktop area of the group.
+  pub rect: Rect,
+  /// Sorted GDI device names of the sources, e.g. `\\.\DISPLAY1`,
+  /// which match [`OutputDescExt::device_name`] of the outputs.
+  pub device_names: Vec<String>,
+  /// How many monitors show the area, at least 2.
+  pub monitors: usize,
+}
+
+impl CloneGroup {
+  /// Whether the output `device_name` shows the same content as the first output of the group,
+  /// so it can be skipped.
+  pub fn is_duplicate(&self, device_name: &str) -> bool {
+    self
+      .device_names
+      .iter()
+      .skip(1)
+      .any(|name| name == device_name)
+  }
+}
+
+/// Get all clone groups of the active display configuration.
+pub fn clone_groups() -> Result<Vec<CloneGroup>> {
+  let (paths, modes) = query_active_paths()?;
+  let mut sources = Vec::with_capacity(paths.len());
+  for path in &paths {
+    let source = path.sourceInfo;
+    let Some(mode) = modes.get(unsafe { source.Anonymous.modeInfoIdx } as usize) else {
+      continue;
+    };
+    if mode.infoType != DISPLAYCONFIG_MODE_INFO_TYPE_SOURCE {
+      continue;
+    }
+    let mode = unsafe { mode.Anonymous.sourceMode };
+    let rect = Rect::new(
+      mode.position.x,
+      mode.position.y,
+      mode.position.x + mode.width as i32,
+      mode.position.y + mode.height as i32,
+    );
+    sources.push((source_name(source.adapterId, source.id)?, rect));
+  }
+  Ok(group_sources(&sources))
+}
+
+/// Get the clone group of the output `device_name`, `None` if it's not cloned.
+pub fn clone_group_of(device_name: &str) -> Result<Option<CloneGroup>> {
+  Ok(
+    clone_groups()?
+      .into_iter()
+      .find(|group| group.device_names.iter().any(|name| name == device_name)),
+  )
+}
+
+/// Get the GDI device name of the source and the monitor device path of each active monitor,
+/// e.g. `\\.\DISPLAY1` and `\\?\DISPLAY#DEL4321#5&1a2b3c4d&0&UID4352#{e6f07b5f-...}`.
+pub fn monitor_device_paths() -> Result<Vec<(String, String)>> {
+  let (paths, _) = query_active_paths()?;
+  paths
+    .iter()
+    .map(|path| {
+      Ok((
+        source_name(path.sourceInfo.adapterId, path.sourceInfo.id)?,
+        monitor_device_path(path.targetInfo.adapterId, path.targetInfo.id)?,
+      ))
+    })
+    .collect()
+}
+
+/// Get the monitor device paths of the output `device_name`, more than one in clone mode.
+pub fn monitor_device_paths_of(device_name: &str) -> Result<Vec<String>> {
+  Ok(
+    monitor_device_paths()?
+      .into_iter()
+      .filter(|(name, _)| name == device_name)
+      .map(|(_, path)| path)
+      .collect(),
+  )
+}
+
+/// Group the source name and desktop area of each active path by the area.
+/// Each path leads to a monitor, groups of a single monitor are dropped.
+fn group_sources(sources: &[(String, Rect)]) -> Vec<CloneGroup> {
+  let mut groups: Vec<CloneGroup> = Vec::new();
+  for (name, rect) in sources {
+    let index = match groups.iter().position(|group| group.rect == *rect) {
+      Some(index) => index,
+      None => {
+        groups.push(CloneGroup {
+          rect: *rect,
+          device_names: Vec::new(),
+          monitors: 0,
+        });
+        groups.len() - 1
+      }
+    };
+    let group = &mut groups[index];
+    group.monitors += 1;
+    if !group.device_names.contains(name) {
+      group.device_names.push(name.clone());
+    }
+  }
+  groups.retain(|group| group.monitors > 1);
+  for group in &mut groups {
+    group.device_names.sort();
+  }
+  groups
+}
+
+fn query_active_paths() -> Result<(Vec<DISPLAYCONFIG_PATH_INFO>, Vec<DISPLAYCONFIG_MODE_INFO>)> {
+  loop {
+    let mut path_count = 0;
+    let mut mode_count = 0;
+    let result = unsafe {
+      GetDisplayConfigBufferSizes(QDC_ONLY_ACTIVE_PATHS, &mut path_count, &mut mode_count)
+    };
+    if result != ERROR_SUCCESS {
+      return Err(Error::new(format!(
+        "GetDisplayConfigBufferSizes failed with {}",
+        result.0
+      )));
+    }
+
+    let mut paths = vec![DISPLAYCONFIG_PATH_INFO::default(); path_count as usize];
+    let mut modes = vec![DISPLAYCONFIG_MODE_INFO::default(); mode_count as usize];
+    let result = unsafe {
+      QueryDisplayConfig(
+        QDC_ONLY_ACTIVE_PATHS,
+        &mut path_count,
+        paths.as_mut_ptr(),
+        &mut mode_count,
+        modes.as_mut_ptr(),
+        None,
+      )
+    };
+    match result {
+      ERROR_SUCCESS => {
+        paths.truncate(path_count as usize);
+        modes.truncate(mode_count as usize);
+        return Ok((paths, modes));
+      }
+      // the configuration changed between the calls
+      ERROR_INSUFFICIENT_BUFFER => continue,
+      _ => {
+        return Err(Error::new(format!(
+          "QueryDisplayConfig failed with {}",
+          result.0
+        )))
+      }
+    }
+  }
+}
+
+fn source_name(adapter_id: LUID, id: u32) -> Result<String> {
+  let mut name = DISPLAYCONFIG_SOURCE_DEVICE_NAME {
+    header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+      r#type: DISPLAYCONFIG_DEVICE_INFO_GET_SOURCE_NAME,
+      size: mem::size_of::<DISPLAYCONFIG_SOURCE_DEVICE_NAME>() as u32,
+      adapterId: adapter_id,
+      id,
+    },
+    ..Default::default()
+  };
+  let result = unsafe { DisplayConfigGetDeviceInfo(&mut name.header) };
+  if result != 0 {
+    return Err(Error::new(format!(
+      "DisplayConfigGetDeviceInfo failed with {}",
+      result
+    )));
+  }
+  Ok(from_wide(&name.viewGdiDeviceName))
+}
+
+fn monitor_device_path(adapter_id: LUID, id: u32) -> Result<String> {
+  let mut name = DISPLAYCONFIG_TARGET_DEVICE_NAME {
+    header: DISPLAYCONFIG_DEVICE_INFO_HEADER {
+      r#type: DISPLAYCONFIG_DEVICE_INFO_GET_TARGET_NAME,
+      size: mem::size_of::<DISPLAYCONFIG_TARGET_DEVICE_NAME>() as u32,
+      adapterId: adapter_id,
+      id,
+    },
+    ..Default::default()
+  };
+  let result = unsafe { DisplayConfigGetDeviceInfo(&mut name.header) };
+  if result != 0 {
+    return Err(Error::new(format!(
+      "DisplayConfigGetDeviceInfo failed with {}",
+      result
+    )));
+  }
+  Ok(from_wide(&name.monitorDevicePath))
+}
+
+impl DuplicationContext {
+  /// Get the clone group of this monitor, `None` if it's not cloned.
+  pub fn clone_group(&self) -> Result<Option<CloneGroup>> {
+    clone_group_of(&self.dxgi_output_desc()?.device_name())
+      .map_err(|e| e.with_context(self.error_context()))
+  }
+
+  /// Get the monitor device paths of this monitor, more than one in clone mode.
+  pub fn monitor_device_paths(&self) -> Result<Vec<String>> {
+    monitor_device_paths_of(&self.dxgi_output_desc()?.device_name())
+      .map_err(|e| e.with_context(self.error_context()))
+  }
+}
+
+impl MonitorHandle {
+  /// Get the clone group of this monitor, `None` if it's not cloned.
+  pub fn clone_group(&self) -> Result<Option<CloneGroup>> {
+    clone_group_of(&self.dxgi_output_desc().device_name())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{clone_groups, group_sources, monitor_device_paths};
+  use crate::manager::Manager;
+  use crate::model::Rect;
+
+  #[test]
+  fn groups() {
+    let left = Rect::new(0, 0, 1920, 1080);
+    let right = Rect::new(1920, 0, 3840, 1080);
+    let sources = [
+      // two monitors on one source
+      (r"\\.\DISPLAY2".to_string(), left),
+      (r"\\.\DISPLAY2".to_string(), left),
+      // a clone on another adapter
+      (r"\\.\DISPLAY1".to_string(), left),
+      (r"\\.\DISPLAY3".to_string(), right),
+    ];
+    let groups = group_sources(&sources);
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].rect, left);
+    assert_eq!(groups[0].device_names, [r"\\.\DISPLAY1", r"\\.\DISPLAY2"]);
+    assert_eq!(groups[0].monitors, 3);
+    assert!(!groups[0].is_duplicate(r"\\.\DISPLAY1"));
+    assert!(groups[0].is_duplicate(r"\\.\DISPLAY2"));
+    assert!(!groups[0].is_duplicate(r"\\.\DISPLAY3"));
+  }
+
+  #[test]
+  fn clone_group() {
+    let groups = clone_groups().unwrap();
+    let manager = Manager::default().unwrap();
+    // usually not in clone mode
+    if let Some(group) = manager.contexts[0].clone_group().unwrap() {
+      assert!(groups.contains(&group));
+    }
+  }
+
+  #[test]
+  fn device_paths() {
+    let paths = monitor_device_paths().unwrap();
+    assert!(paths.iter().all(|(_, path)| !path.is_empty()));
+    let manager = Manager::default().unwrap();
+    let own = manager.contexts[0].monitor_device_paths().unwrap();
+    assert!(!own.is_empty());
+    assert!(own.iter().all(|path| paths.iter().any(|(_, p)| p == path)));
+  }
+}
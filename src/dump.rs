@@ -0,0 +1,238 @@
+//! Write 8-bit BGRA pixels as BMP or binary PPM files without dependencies,
+//! for quick debugging dumps. See the `image` feature for PNG and JPEG.
+
+use crate::color::check_bgra8;
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::model::Result;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// The size of `BITMAPFILEHEADER` and `BITMAPINFOHEADER`.
+const BMP_HEADER_SIZE: u32 = 14 + 40;
+
+fn io_error(message: &str, e: io::Error) -> Error {
+  Error::new(format!("{}: {}", message, e))
+}
+
+/// Check that `src` holds `height` rows of `pitch` bytes, each starting with `width` pixels.
+/// The last row may not be padded to the pitch.
+fn check_layout(src: &[u8], pitch: usize, width: u32, height: u32) -> Result<()> {
+  let line_bytes = width as usize * 4;
+  if pitch < line_bytes {
+    return Err(Error::new(format!(
+      "The pitch {} is less than the row size {}",
+      pitch, line_bytes
+    )));
+  }
+  let len = (height as usize).saturating_sub(1) * pitch + line_bytes;
+  if height > 0 && src.len() < len {
+    return Err(Error::new(format!(
+      "The buffer holds {} bytes, expected at least {}",
+      src.len(),
+      len
+    )));
+  }
+  Ok(())
+}
+
+/// The rows of `width` `[b, g, r, a]` pixels from top to bottom.
+fn rows(
+  src: &[u8],
+  pitch: usize,
+  width: u32,
+  height: u32,
+) -> impl DoubleEndedIterator<Item = &[u8]> {
+  let line_bytes = width as usize * 4;
+  (0..height as usize).map(move |row| &src[row * pitch..row * pitch + line_bytes])
+}
+
+/// Write an opaque 24-bit BMP with bottom-up rows padded to 4 bytes.
+/// `src` holds `height` rows of `pitch` bytes, each starting with `width` BGRA pixels.
+pub fn write_bmp(
+  mut writer: impl Write,
+  src: &[u8],
+  pitch: usize,
+  width: u32,
+  height: u32,
+) -> Result<()> {
+  check_layout(src, pitch, width, height)?;
+  let stride = (width * 3 + 3) & !3;
+  let size_image = stride * height;
+
+  let mut header = Vec::with_capacity(BMP_HEADER_SIZE as usize);
+  // BITMAPFILEHEADER
+  header.extend_from_slice(b"BM");
+  header.extend_from_slice(&(BMP_HEADER_SIZE + size_image).to_le_bytes());
+  header.extend_from_slice(&[0; 4]);
+  header.extend_from_slice(&BMP_HEADER_SIZE.to_le_bytes());
+  // BITMAPINFOHEADER, a positive height means bottom-up rows
+  header.extend_from_slice(&40u32.to_le_bytes());
+  header.extend_from_slice(&(width as i32).to_le_bytes());
+  header.extend_from_slice(&(height as i32).to_le_bytes());
+  header.extend_from_slice(&1u16.to_le_bytes());
+  header.extend_from_slice(&24u16.to_le_bytes());
+  // BI_RGB, image size, resolution and palette
+  header.extend_from_slice(&0u32.to_le_bytes());
+  header.extend_from_slice(&size_image.to_le_bytes());
+  header.extend_from_slice(&[0; 16]);
+
+  let padding = (stride - width * 3) as usize;
+  let mut line = Vec::with_capacity(stride as usize);
+  (|| {
+    writer.write_all(&header)?;
+    for row in rows(src, pitch, width, height).rev() {
+      line.clear();
+      line.extend(
+        row
+          .chunks_exact(4)
+          .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]),
+      );
+      line.extend_from_slice(&[0; 3][..padding]);
+      writer.write_all(&line)?;
+    }
+    writer.flush()
+  })()
+  .map_err(|e| io_error("Failed to write BMP", e))
+}
+
+/// Write a binary PPM (`P6`) with top-down RGB rows, the alpha channel is dropped.
+/// `src` holds `height` rows of `pitch` bytes, each starting with `width` BGRA pixels.
+pub fn write_ppm(
+  mut writer: impl Write,
+  src: &[u8],
+  pitch: usize,
+  width: u32,
+  height: u32,
+) -> Result<()> {
+  check_layout(src, pitch, width, height)?;
+  let mut line = Vec::with_capacity(width as usize * 3);
+  (|| {
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    for row in rows(src, pitch, width, height) {
+      line.clear();
+      line.extend(
+        row
+          .chunks_exact(4)
+          .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]]),
+      );
+      writer.write_all(&line)?;
+    }
+    writer.flush()
+  })()
+  .map_err(|e| io_error("Failed to write PPM", e))
+}
+
+impl Frame {
+  /// See [`write_bmp`]. Only 8-bit BGRA frames are supported.
+  pub fn write_bmp(&self, writer: impl Write) -> Result<()> {
+    check_bgra8(self.format, "dump frames")?;
+    write_bmp(
+      writer,
+      &self.buffer,
+      self.width as usize * 4,
+      self.width,
+      self.height,
+    )
+  }
+
+  /// See [`write_ppm`]. Only 8-bit BGRA frames are supported.
+  pub fn write_ppm(&self, writer: impl Write) -> Result<()> {
+    check_bgra8(self.format, "dump frames")?;
+    write_ppm(
+      writer,
+      &self.buffer,
+      self.width as usize * 4,
+      self.width,
+      self.height,
+    )
+  }
+
+  /// Save the frame as a BMP file, see [`Frame::write_bmp`].
+  pub fn save_bmp(&self, path: impl AsRef<Path>) -> Result<()> {
+    self.write_bmp(create(path.as_ref())?)
+  }
+
+  /// Save the frame as a binary PPM file, see [`Frame::write_ppm`].
+  pub fn save_ppm(&self, path: impl AsRef<Path>) -> Result<()> {
+    self.write_ppm(create(path.as_ref())?)
+  }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+  File::create(path)
+    .map(BufWriter::new)
+    .map_err(|e| io_error(&format!("Failed to create {:?}", path), e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{write_bmp, write_ppm};
+  use crate::test_utils::generate;
+
+  #[test]
+  fn bmp() {
+    let frame = generate(3, 2, |x, y| [x as u8, y as u8, 0xA0]);
+    let mut bmp = Vec::new();
+    frame.write_bmp(&mut bmp).unwrap();
+    // 3 pixels of 3 bytes are padded to 12 bytes
+    assert_eq!(bmp.len(), 54 + 12 * 2);
+    assert_eq!(bmp[..2], *b"BM");
+    assert_eq!(u32::from_le_bytes(bmp[2..6].try_into().unwrap()), 78);
+    assert_eq!(i32::from_le_bytes(bmp[18..22].try_into().unwrap()), 3);
+    assert_eq!(i32::from_le_bytes(bmp[22..26].try_into().unwrap()), 2);
+    // bottom-up, the first row is y = 1
+    assert_eq!(bmp[54..57], [0, 1, 0xA0]);
+    assert_eq!(bmp[60..63], [2, 1, 0xA0]);
+    assert_eq!(bmp[63..66], [0; 3]);
+    assert_eq!(bmp[66..69], [0, 0, 0xA0]);
+  }
+
+  #[test]
+  fn ppm() {
+    let frame = generate(2, 2, |x, y| [x as u8, y as u8, 0xA0]);
+    let mut ppm = Vec::new();
+    frame.write_ppm(&mut ppm).unwrap();
+    let header = b"P6\n2 2\n255\n";
+    assert_eq!(ppm[..header.len()], *header);
+    // top-down RGB
+    assert_eq!(
+      ppm[header.len()..],
+      [0xA0, 0, 0, 0xA0, 0, 1, 0xA0, 1, 0, 0xA0, 1, 1]
+    );
+  }
+
+  #[test]
+  fn pitch() {
+    // 1x2 pixels with a pitch of 8 bytes, the last row isn't padded
+    let src = [1, 2, 3, 4, 0xEE, 0xEE, 0xEE, 0xEE, 5, 6, 7, 8];
+    let mut ppm = Vec::new();
+    write_ppm(&mut ppm, &src, 8, 1, 2).unwrap();
+    assert_eq!(ppm[ppm.len() - 6..], [3, 2, 1, 7, 6, 5]);
+    let mut bmp = Vec::new();
+    write_bmp(&mut bmp, &src, 8, 1, 2).unwrap();
+    assert_eq!(bmp[54..], [5, 6, 7, 0, 1, 2, 3, 0]);
+
+    assert!(write_ppm(Vec::new(), &src, 2, 1, 2).is_err());
+    assert!(write_bmp(Vec::new(), &src[..8], 8, 1, 2).is_err());
+  }
+
+  #[test]
+  fn save() {
+    let frame = generate(4, 4, |_, _| [0; 3]);
+    for (name, signature) in [
+      ("rusty-duplication-dump.bmp", &b"BM"[..]),
+      ("rusty-duplication-dump.ppm", &b"P6"[..]),
+    ] {
+      let path = std::env::temp_dir().join(name);
+      if name.ends_with("bmp") {
+        frame.save_bmp(&path).unwrap();
+      } else {
+        frame.save_ppm(&path).unwrap();
+      }
+      assert!(std::fs::read(&path).unwrap().starts_with(signature));
+      std::fs::remove_file(&path).ok();
+    }
+  }
+}
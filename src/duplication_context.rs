@@ -1,34 +1,134 @@
-use crate::error::Error;
-use crate::{model::Result, utils::FrameInfoExt};
-use std::ptr;
-use windows::Win32::Graphics::Dxgi::DXGI_OUTDUPL_DESC;
+use crate::color::ColorLut;
+use crate::error::{Error, ErrorContext, ErrorKind};
+use crate::model::{
+  CaptureArgs, CaptureOptions, ColorAdjustment, FrameLatency, FrameStatistics, LatencyMode,
+  MonitorId, MonitorSummary, TextureOptions,
+};
+use crate::pointer::{draw_pointer_clipped, CursorState, PointerCache};
+use crate::swizzle::{needs_swizzle, Swizzler};
+use crate::utils::{
+  output_desc1, AdapterDescExt, FormatExt, MonitorInfoExt, OutDuplDescExt, OutputDescExt,
+};
+use crate::{
+  model::{Rect, Result},
+  utils::FrameInfoExt,
+};
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use std::sync::Mutex;
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+use std::{hint, ptr, slice, thread};
+use windows::Win32::Graphics::Dxgi::{DXGI_FRAME_STATISTICS, DXGI_OUTDUPL_DESC};
 use windows::Win32::Graphics::Gdi::{GetMonitorInfoW, MONITORINFO};
+use windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+use windows::Win32::UI::HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
 use windows::{
-  core::ComInterface,
+  core::{ComInterface, Interface},
   Win32::Graphics::{
     Direct3D11::{
       ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, D3D11_BIND_FLAG, D3D11_CPU_ACCESS_READ,
-      D3D11_RESOURCE_MISC_FLAG, D3D11_TEXTURE2D_DESC, D3D11_USAGE_STAGING,
+      D3D11_FORMAT_SUPPORT_TEXTURE2D, D3D11_RESOURCE_MISC_FLAG, D3D11_TEXTURE2D_DESC,
+      D3D11_USAGE_STAGING,
     },
     Dxgi::{
-      Common::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_SAMPLE_DESC},
-      IDXGIOutput1, IDXGIOutputDuplication, IDXGIResource, IDXGISurface1, DXGI_MAPPED_RECT,
-      DXGI_MAP_READ, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_POINTER_SHAPE_INFO, DXGI_OUTPUT_DESC,
-      DXGI_RESOURCE_PRIORITY_MAXIMUM,
+      Common::{
+        DXGI_FORMAT, DXGI_FORMAT_B8G8R8A8_TYPELESS, DXGI_FORMAT_B8G8R8A8_UNORM,
+        DXGI_FORMAT_B8G8R8A8_UNORM_SRGB, DXGI_FORMAT_R10G10B10A2_TYPELESS,
+        DXGI_FORMAT_R10G10B10A2_UINT, DXGI_FORMAT_R10G10B10A2_UNORM,
+        DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_FORMAT_R16G16B16A16_SINT,
+        DXGI_FORMAT_R16G16B16A16_SNORM, DXGI_FORMAT_R16G16B16A16_TYPELESS,
+        DXGI_FORMAT_R16G16B16A16_UINT, DXGI_FORMAT_R16G16B16A16_UNORM, DXGI_FORMAT_R8G8B8A8_SINT,
+        DXGI_FORMAT_R8G8B8A8_SNORM, DXGI_FORMAT_R8G8B8A8_TYPELESS, DXGI_FORMAT_R8G8B8A8_UINT,
+        DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM_SRGB, DXGI_FORMAT_UNKNOWN,
+        DXGI_SAMPLE_DESC,
+      },
+      IDXGIAdapter1, IDXGIDevice, IDXGIOutput, IDXGIOutput1, IDXGIOutputDuplication, IDXGIResource,
+      IDXGISurface1, DXGI_ADAPTER_DESC1, DXGI_ERROR_WAIT_TIMEOUT, DXGI_MAPPED_RECT, DXGI_MAP_READ,
+      DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_POINTER_SHAPE_INFO, DXGI_OUTPUT_DESC,
+      DXGI_OUTPUT_DESC1,
     },
   },
 };
 
-/// Stateless.
+/// Immediate device contexts used by duplication contexts, as `(pointer, thread, count)`.
+/// An immediate context is not thread-safe, so each one may only be used by one thread.
+static DEVICE_CONTEXTS: Mutex<Vec<(usize, ThreadId, usize)>> = Mutex::new(Vec::new());
+
+/// The current thread uses an immediate device context until this is dropped.
+struct DeviceContextClaim(usize);
+
+impl DeviceContextClaim {
+  /// Fail if another thread uses `device_context`.
+  fn new(device_context: &ID3D11DeviceContext) -> Result<Self> {
+    let key = device_context.as_raw() as usize;
+    let thread = thread::current().id();
+    let mut claims = DEVICE_CONTEXTS.lock().unwrap();
+    match claims.iter_mut().find(|(k, _, _)| *k == key) {
+      Some((_, owner, _)) if *owner != thread => {
+        return Err(Error::new(
+          "The device context is used by another thread, create a device per thread, e.g. by Manager::open",
+        ))
+      }
+      Some((_, _, count)) => *count += 1,
+      None => claims.push((key, thread, 1)),
+    }
+    Ok(Self(key))
+  }
+}
+
+impl Drop for DeviceContextClaim {
+  fn drop(&mut self) {
+    let mut claims = DEVICE_CONTEXTS.lock().unwrap();
+    if let Some(index) = claims.iter().position(|(k, _, _)| *k == self.0) {
+      claims[index].2 -= 1;
+      if claims[index].2 == 0 {
+        claims.swap_remove(index);
+      }
+    }
+  }
+}
+
+/// Polls of `AcquireNextFrame(0)` in [`LatencyMode::UltraLow`] before yielding the thread between polls.
+const SPIN_POLLS: u32 = 64;
+
+/// Duplicates one output on a D3D11 device and copies its frames.
+/// Contexts claim their device context in a shared registry, so it is only used on one thread.
+///
+/// Besides the timeout and latency mode, it remembers the fallback format of readable textures
+/// and the last pointer shape and position, which DXGI only reports when they change.
 pub struct DuplicationContext {
+  id: MonitorId,
   device: ID3D11Device,
   device_context: ID3D11DeviceContext,
-  timeout_ms: u32,
+  timeout_ms: Cell<u32>,
+  latency_mode: Cell<LatencyMode>,
   output: IDXGIOutput1,
   output_duplication: IDXGIOutputDuplication,
+  /// The description of `output_duplication`, which is fixed until it is recreated.
+  outdupl_desc: DXGI_OUTDUPL_DESC,
+  texture_options: TextureOptions,
+  /// The format of the last readable texture, if the preferred format failed.
+  texture_fallback: Cell<Option<DXGI_FORMAT>>,
+  /// Draws frames to `DXGI_FORMAT_R8G8B8A8_UNORM` readable textures, created on first use.
+  swizzler: RefCell<Option<Swizzler>>,
+  /// The pointer reported by any capture, for [`CaptureOptions::include_cursor`].
+  pointer: RefCell<PointerCache>,
+  /// Applied to 8-bit frames while they are copied.
+  color_lut: RefCell<Option<ColorLut>>,
+  /// `None` if the context is created by the deprecated [`DuplicationContext::new`].
+  _claim: Option<DeviceContextClaim>,
 }
 
 impl DuplicationContext {
+  /// The [`id`](DuplicationContext::id) of the context is `MonitorId::default()`,
+  /// use [`DuplicationContext::try_new`] to set it.
+  ///
+  /// Unlike [`DuplicationContext::try_new`], this doesn't check whether `device_context`
+  /// is used by a duplication context on another thread.
+  #[deprecated(
+    note = "use `try_new` which returns an error if the device context is used by another thread"
+  )]
   pub fn new(
     device: ID3D11Device,
     device_context: ID3D11DeviceContext,
@@ -36,119 +136,660 @@ impl DuplicationContext {
     output_duplication: IDXGIOutputDuplication,
     timeout_ms: u32,
   ) -> Self {
-    Self {
+    Self::with_claim(
+      MonitorId::default(),
+      device,
+      device_context,
+      output,
+      output_duplication,
+      timeout_ms,
+      None,
+    )
+  }
+
+  /// Contexts are not `Send` and can be used concurrently from several threads
+  /// as long as each thread duplicates on its own device, see [`Manager::open`](crate::manager::Manager::open).
+  /// `id` is returned by [`DuplicationContext::id`] and reported in errors.
+  ///
+  /// Return an error if `device_context` is used by a duplication context on another thread.
+  pub fn try_new(
+    id: MonitorId,
+    device: ID3D11Device,
+    device_context: ID3D11DeviceContext,
+    output: IDXGIOutput1,
+    output_duplication: IDXGIOutputDuplication,
+    timeout_ms: u32,
+  ) -> Result<Self> {
+    let claim = DeviceContextClaim::new(&device_context)?;
+    Ok(Self::with_claim(
+      id,
       device,
       device_context,
+      output,
+      output_duplication,
       timeout_ms,
+      Some(claim),
+    ))
+  }
+
+  fn with_claim(
+    id: MonitorId,
+    device: ID3D11Device,
+    device_context: ID3D11DeviceContext,
+    output: IDXGIOutput1,
+    output_duplication: IDXGIOutputDuplication,
+    timeout_ms: u32,
+    claim: Option<DeviceContextClaim>,
+  ) -> Self {
+    let mut outdupl_desc = DXGI_OUTDUPL_DESC::default();
+    unsafe { output_duplication.GetDesc(&mut outdupl_desc) };
+    Self {
+      id,
+      device,
+      device_context,
+      timeout_ms: Cell::new(timeout_ms),
+      latency_mode: Cell::new(LatencyMode::default()),
       output,
       output_duplication,
+      outdupl_desc,
+      texture_options: TextureOptions::default(),
+      texture_fallback: Cell::new(None),
+      swizzler: RefCell::new(None),
+      pointer: RefCell::new(PointerCache::new()),
+      color_lut: RefCell::new(None),
+      _claim: claim,
     }
   }
 
+  pub fn texture_options(&self) -> TextureOptions {
+    self.texture_options
+  }
+
+  /// Set the parameters of readable textures created afterwards, and how frames are copied.
+  pub fn set_texture_options(&mut self, options: TextureOptions) {
+    self.texture_options = options;
+    self.texture_fallback.set(None);
+  }
+
+  pub fn id(&self) -> MonitorId {
+    self.id
+  }
+
+  /// The device which frames are duplicated on, e.g. to process frames with shaders.
+  pub fn device(&self) -> &ID3D11Device {
+    &self.device
+  }
+
+  /// The LUID of the adapter which duplicates the output, see [`AdapterDescExt::luid`].
+  pub fn adapter_luid(&self) -> Result<i64> {
+    let adapter: IDXGIAdapter1 = self
+      .device
+      .cast::<IDXGIDevice>()
+      .and_then(|device| unsafe { device.GetParent() })
+      .map_err(|e| self.windows_error("IDXGIDevice::GetParent", e))?;
+    let mut desc = DXGI_ADAPTER_DESC1::default();
+    unsafe { adapter.GetDesc1(&mut desc) }
+      .map_err(|e| self.windows_error("IDXGIAdapter1::GetDesc1", e))?;
+    Ok(desc.luid())
+  }
+
+  /// The immediate context of [`DuplicationContext::device`], which is not thread-safe.
+  /// Don't use it on other threads.
+  pub fn device_context(&self) -> &ID3D11DeviceContext {
+    &self.device_context
+  }
+
+  /// The timeout of acquiring a frame, in milliseconds.
+  pub fn timeout_ms(&self) -> u32 {
+    self.timeout_ms.get()
+  }
+
+  /// Change the timeout while capturers borrow this context, e.g. by an [`AdaptiveTimeout`](crate::model::AdaptiveTimeout).
+  pub fn set_timeout_ms(&self, timeout_ms: u32) {
+    self.timeout_ms.set(timeout_ms);
+  }
+
+  pub fn latency_mode(&self) -> LatencyMode {
+    self.latency_mode.get()
+  }
+
+  /// Change how frames are waited for, also while capturers borrow this context.
+  pub fn set_latency_mode(&self, mode: LatencyMode) {
+    self.latency_mode.set(mode);
+  }
+
+  /// The pointer reported by frames of any capturer of this context.
+  /// The shape is only known after a capture which gets the pointer shape,
+  /// e.g. with [`CaptureOptions::include_cursor`].
+  pub fn cursor_state(&self) -> CursorState {
+    self.pointer.borrow().state().clone()
+  }
+
+  pub fn color_adjustment(&self) -> Option<ColorAdjustment> {
+    self.color_lut.borrow().as_ref().map(|lut| lut.adjustment())
+  }
+
+  /// Adjust brightness, contrast and gamma of frames while they are copied to system memory,
+  /// also while capturers borrow this context. Only BGRA8 and RGBA8 frames are adjusted.
+  pub fn set_color_adjustment(&self, adjustment: Option<ColorAdjustment>) {
+    *self.color_lut.borrow_mut() = adjustment.map(ColorLut::new);
+  }
+
+  /// Apply the color adjustment to copied pixels of `format`,
+  /// e.g. in custom capturers which copy mapped surfaces themselves.
+  /// Return an error if an adjustment is set and `format` is not BGRA8 or RGBA8.
+  pub fn adjust_colors(&self, pixels: &mut [u8], format: DXGI_FORMAT) -> Result<()> {
+    if !self.apply_color_adjustment(pixels, format) {
+      return Err(Error::new(format!(
+        "Can't adjust colors of format {:?}, only BGRA8 and RGBA8 are supported",
+        format
+      )));
+    }
+    Ok(())
+  }
+
+  /// Like [`DuplicationContext::adjust_colors`], but keep pixels of other formats,
+  /// so captures of them still succeed. Return `false` if an adjustment is set and the pixels are kept.
+  pub(crate) fn apply_color_adjustment(&self, pixels: &mut [u8], format: DXGI_FORMAT) -> bool {
+    match self.color_lut.borrow().as_ref() {
+      Some(lut) => lut.apply(pixels, format),
+      None => true,
+    }
+  }
+
+  /// Describe the adapter and output of this context, used to attach context to errors.
+  /// Fields which can't be retrieved are `None`.
+  pub fn error_context(&self) -> ErrorContext {
+    let output: Option<IDXGIOutput> = self.output.cast().ok();
+    let adapter: Option<IDXGIAdapter1> = unsafe { self.output.GetParent() }.ok();
+    ErrorContext::collect(self.id, adapter.as_ref(), output.as_ref())
+  }
+
+  /// Wrap a failed call with the [`DuplicationContext::error_context`], e.g. in custom capturers.
+  /// `DXGI_ERROR_WAIT_TIMEOUT` only means that no frame is ready and is returned without a context,
+  /// so polling doesn't query the adapter and the monitor on every timeout.
+  pub fn windows_error(&self, message: &str, err: windows::core::Error) -> Error {
+    if err.code() == DXGI_ERROR_WAIT_TIMEOUT {
+      return Error::windows(message, err);
+    }
+    Error::windows_with_context(message, err, self.error_context())
+  }
+
   pub fn monitor_info(&self) -> Result<MONITORINFO> {
     let h_monitor = self.dxgi_output_desc()?.Monitor;
-    let mut info = MONITORINFO::default();
-    info.cbSize = std::mem::size_of::<MONITORINFO>() as u32;
+    let mut info = MONITORINFO {
+      cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+      ..Default::default()
+    };
     if unsafe { GetMonitorInfoW(h_monitor, &mut info).as_bool() } {
       Ok(info)
     } else {
-      Err(Error::new("GetMonitorInfoW"))
+      Err(Error::new("GetMonitorInfoW").with_context(self.error_context()))
     }
   }
 
+  /// Collect name, position, scale and refresh rate of the monitor in a single call.
+  pub fn summary(&self) -> Result<MonitorSummary> {
+    let output_desc = self.dxgi_output_desc()?;
+    let info = self.monitor_info()?;
+    let refresh_rate = self.dxgi_outdupl_desc().ModeDesc.RefreshRate;
+
+    let mut dpi_x = 0;
+    let mut dpi_y = 0;
+    unsafe {
+      GetDpiForMonitor(
+        output_desc.Monitor,
+        MDT_EFFECTIVE_DPI,
+        &mut dpi_x,
+        &mut dpi_y,
+      )
+    }
+    .map_err(|e| self.windows_error("GetDpiForMonitor", e))?;
+
+    Ok(MonitorSummary {
+      id: self.id,
+      name: output_desc.device_name(),
+      rect: info.monitor_rect(),
+      work_area: info.work_area(),
+      primary: info.is_primary(),
+      scale: dpi_x as f32 / 96.0, // 96 DPI is 100% scale
+      refresh_rate: if refresh_rate.Denominator == 0 {
+        0.0
+      } else {
+        refresh_rate.Numerator as f64 / refresh_rate.Denominator as f64
+      },
+    })
+  }
+
+  /// Block until the next vertical blank of the output,
+  /// e.g. to acquire a frame right after scanout.
+  pub fn wait_for_vblank(&self) -> Result<()> {
+    unsafe { self.output.WaitForVBlank() }.map_err(|e| self.windows_error("WaitForVBlank", e))
+  }
+
+  /// Estimate the time until the next vertical blank from the frame statistics and the refresh rate.
+  ///
+  /// The frame statistics are usually only available while a fullscreen application presents to the output,
+  /// otherwise an error is returned and [`DuplicationContext::wait_for_vblank`] can be used instead.
+  pub fn time_to_next_vblank(&self) -> Result<Duration> {
+    let period = self
+      .refresh_period()
+      .ok_or_else(|| Error::new("Unknown refresh rate").with_context(self.error_context()))?;
+    let stats = self.frame_statistics()?;
+    let (now, frequency) = qpc_now();
+    Ok(time_to_next_period(
+      qpc_duration(now - stats.sync_qpc_time, frequency),
+      period,
+    ))
+  }
+
+  /// Get the frame statistics of the output, e.g. the QPC time of the latest vertical blank.
+  ///
+  /// Like [`DuplicationContext::time_to_next_vblank`], this usually fails
+  /// unless a fullscreen application presents to the output.
+  pub fn frame_statistics(&self) -> Result<FrameStatistics> {
+    let mut stats = DXGI_FRAME_STATISTICS::default();
+    unsafe { self.output.GetFrameStatistics(&mut stats) }
+      .map_err(|e| self.windows_error("GetFrameStatistics", e))?;
+    Ok(stats.into())
+  }
+
+  /// Measure the delays of an acquired frame by correlating its `LastPresentTime`
+  /// with the current QPC time and the frame statistics of the output.
+  /// Call this right after the frame is acquired.
+  ///
+  /// Return `None` if the frame doesn't contain a desktop image presented by the compositor.
+  /// `vblank_to_present` is `None` if the frame statistics are not available.
+  pub fn frame_latency(&self, info: &DXGI_OUTDUPL_FRAME_INFO) -> Option<FrameLatency> {
+    let (now, frequency) = qpc_now();
+    let sync_qpc_time = self.frame_statistics().ok().map(|s| s.sync_qpc_time);
+    frame_latency(
+      info.LastPresentTime,
+      now,
+      frequency,
+      sync_qpc_time,
+      self.refresh_period(),
+    )
+  }
+
+  fn refresh_period(&self) -> Option<Duration> {
+    let refresh_rate = self.dxgi_outdupl_desc().ModeDesc.RefreshRate;
+    if refresh_rate.Numerator == 0 || refresh_rate.Denominator == 0 {
+      return None;
+    }
+    Some(Duration::from_secs_f64(
+      refresh_rate.Denominator as f64 / refresh_rate.Numerator as f64,
+    ))
+  }
+
   /// This is usually used to get the screen's position and size.
   pub fn dxgi_output_desc(&self) -> Result<DXGI_OUTPUT_DESC> {
     let mut desc = DXGI_OUTPUT_DESC::default();
     unsafe { self.output.GetDesc(&mut desc) }
-      .map_err(|e| Error::windows("DXGI_OUTPUT_DESC.GetDesc", e))?;
+      .map_err(|e| self.windows_error("DXGI_OUTPUT_DESC.GetDesc", e))?;
     Ok(desc)
   }
 
+  /// Like [`DuplicationContext::dxgi_output_desc`] with the bits per color, color space and luminance
+  /// of the monitor, or `None` before Windows 10 1803, see [`OutputDesc1Ext`](crate::utils::OutputDesc1Ext).
+  pub fn dxgi_output_desc1(&self) -> Result<Option<DXGI_OUTPUT_DESC1>> {
+    output_desc1(&self.output)
+      .transpose()
+      .map_err(|e| self.windows_error("IDXGIOutput6.GetDesc1", e))
+  }
+
   /// This is usually used to get the screen's pixel width/height and buffer size.
+  /// The description doesn't change during the duplication, so it's only retrieved once.
   pub fn dxgi_outdupl_desc(&self) -> DXGI_OUTDUPL_DESC {
-    let mut desc = DXGI_OUTDUPL_DESC::default();
-    unsafe { self.output_duplication.GetDesc(&mut desc) };
-    desc
+    self.outdupl_desc
+  }
+
+  /// Return the pixel `(width, height)` of the captured frame, with the screen rotation applied.
+  pub fn frame_size(&self) -> Result<(u32, u32)> {
+    let dupl_desc = self.dxgi_outdupl_desc();
+    let output_desc = self.dxgi_output_desc()?;
+    if output_desc.Rotation.0 == 2 || output_desc.Rotation.0 == 4 {
+      Ok((dupl_desc.ModeDesc.Height, dupl_desc.ModeDesc.Width))
+    } else {
+      Ok((dupl_desc.ModeDesc.Width, dupl_desc.ModeDesc.Height))
+    }
   }
 
   pub fn create_readable_texture(
     &self,
   ) -> Result<(ID3D11Texture2D, DXGI_OUTDUPL_DESC, D3D11_TEXTURE2D_DESC)> {
     let dupl_desc = self.dxgi_outdupl_desc();
-    let output_desc = self.dxgi_output_desc()?;
+    let (width, height) = self.frame_size()?;
+    if width == 0 || height == 0 {
+      return Err(
+        Error::of_kind(ErrorKind::InactiveOutput, "Output has a zero-sized mode")
+          .with_context(self.error_context()),
+      );
+    }
 
     // create a readable texture description
-    let texture_desc = D3D11_TEXTURE2D_DESC {
+    let format = self.staging_format()?;
+    let mut texture_desc = D3D11_TEXTURE2D_DESC {
       BindFlags: D3D11_BIND_FLAG::default(),
       CPUAccessFlags: D3D11_CPU_ACCESS_READ,
       MiscFlags: D3D11_RESOURCE_MISC_FLAG::default(),
       Usage: D3D11_USAGE_STAGING, // A resource that supports data transfer (copy) from the GPU to the CPU.
-      Width: if output_desc.Rotation.0 == 2 || output_desc.Rotation.0 == 4 {
-        dupl_desc.ModeDesc.Height
-      } else {
-        dupl_desc.ModeDesc.Width
-      },
-      Height: if output_desc.Rotation.0 == 2 || output_desc.Rotation.0 == 4 {
-        dupl_desc.ModeDesc.Width
-      } else {
-        dupl_desc.ModeDesc.Height
-      },
+      Width: width,
+      Height: height,
       MipLevels: 1,
       ArraySize: 1,
-      Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+      Format: format,
       SampleDesc: DXGI_SAMPLE_DESC {
         Count: 1,
         Quality: 0,
       },
     };
 
-    // create a readable texture in GPU memory
-    let mut readable_texture: Option<ID3D11Texture2D> = None.clone();
-    unsafe {
-      self
-        .device
-        .CreateTexture2D(&texture_desc, None, Some(&mut readable_texture))
-    }
-    .map_err(|e| Error::windows("CreateTexture2D", e))?;
-    let readable_texture = readable_texture.unwrap();
+    // create a readable texture in GPU memory,
+    // some adapters reject the preferred format even if it is reported as supported
+    // only the final failure is reported
+    let readable_texture = match self.try_create_texture(&texture_desc) {
+      Ok(texture) => texture,
+      Err(e) => {
+        let fallback = self
+          .fallback_formats(format)
+          .into_iter()
+          .find_map(|format| {
+            let desc = D3D11_TEXTURE2D_DESC {
+              Format: format,
+              ..texture_desc
+            };
+            self
+              .try_create_texture(&desc)
+              .ok()
+              .map(|texture| (texture, desc))
+          });
+        let Some((texture, desc)) = fallback else {
+          return Err(self.windows_error("CreateTexture2D", e));
+        };
+        texture_desc = desc;
+        self.texture_fallback.set(Some(desc.Format));
+        texture
+      }
+    };
     // Lower priorities causes stuff to be needlessly copied from gpu to ram,
     // causing huge ram usage on some systems.
     // https://github.com/bryal/dxgcap-rs/blob/208d93368bc64aed783791242410459c878a10fb/src/lib.rs#L225
-    unsafe { readable_texture.SetEvictionPriority(DXGI_RESOURCE_PRIORITY_MAXIMUM.0) };
+    unsafe { readable_texture.SetEvictionPriority(self.texture_options.eviction_priority) };
 
+    // the pixels of swizzled textures are BGRA
+    if self.swizzles(texture_desc.Format) {
+      texture_desc.Format = DXGI_FORMAT_B8G8R8A8_UNORM;
+    }
     Ok((readable_texture, dupl_desc, texture_desc))
   }
 
+  /// Recreate a readable texture from [`DuplicationContext::create_readable_texture`]
+  /// if the frame size changed, e.g. after a resolution increase.
+  /// Return whether the texture was recreated.
+  pub fn refresh_readable_texture(
+    &self,
+    texture: &mut ID3D11Texture2D,
+    texture_desc: &mut D3D11_TEXTURE2D_DESC,
+  ) -> Result<bool> {
+    if self.frame_size()? == (texture_desc.Width, texture_desc.Height) {
+      return Ok(false);
+    }
+    // the preferred format may work in the new mode
+    self.texture_fallback.set(None);
+    let (new_texture, _, new_desc) = self.create_readable_texture()?;
+    *texture = new_texture;
+    *texture_desc = new_desc;
+    Ok(true)
+  }
+
+  /// Create a texture on the device of this context.
+  pub fn create_texture(&self, desc: &D3D11_TEXTURE2D_DESC) -> Result<ID3D11Texture2D> {
+    self
+      .try_create_texture(desc)
+      .map_err(|e| self.windows_error("CreateTexture2D", e))
+  }
+
+  /// Like [`DuplicationContext::create_texture`] without reporting failures.
+  fn try_create_texture(
+    &self,
+    desc: &D3D11_TEXTURE2D_DESC,
+  ) -> windows::core::Result<ID3D11Texture2D> {
+    let mut texture: Option<ID3D11Texture2D> = None;
+    unsafe { self.device.CreateTexture2D(desc, None, Some(&mut texture)) }?;
+    Ok(texture.unwrap())
+  }
+
+  /// Formats to try in order if creating a readable texture of `format` fails.
+  /// Only formats of the BGRA8 group are tried automatically,
+  /// since the desktop image can only be copied to textures of the same group,
+  /// and `DXGI_FORMAT_R8G8B8A8_UNORM` which frames are drawn to swizzled.
+  fn fallback_formats(&self, format: DXGI_FORMAT) -> Vec<DXGI_FORMAT> {
+    let mut formats = Vec::new();
+    if format == DXGI_FORMAT_B8G8R8A8_UNORM || format == DXGI_FORMAT_B8G8R8A8_UNORM_SRGB {
+      formats.extend([
+        DXGI_FORMAT_B8G8R8A8_UNORM,
+        DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+        DXGI_FORMAT_B8G8R8A8_TYPELESS,
+        DXGI_FORMAT_R8G8B8A8_UNORM,
+      ]);
+    }
+    formats.extend(
+      self
+        .texture_options
+        .fallback_format
+        .filter(|&fallback| self.copies_to(fallback)),
+    );
+    let mut unique = Vec::with_capacity(formats.len());
+    for candidate in formats {
+      if candidate != format && !unique.contains(&candidate) {
+        unique.push(candidate);
+      }
+    }
+    unique
+  }
+
+  /// The pixel format of the duplicated frames, e.g. `DXGI_FORMAT_B8G8R8A8_UNORM`.
+  pub fn format(&self) -> DXGI_FORMAT {
+    match self.dxgi_outdupl_desc().ModeDesc.Format {
+      DXGI_FORMAT_UNKNOWN => DXGI_FORMAT_B8G8R8A8_UNORM,
+      format => format,
+    }
+  }
+
+  /// The formats `DuplicateOutput1` accepts which the device also supports as 2D textures,
+  /// e.g. to choose the formats for [`Manager::with_formats`](crate::manager::Manager::with_formats).
+  /// Whether the output is actually duplicated in one of them is only known after duplicating it.
+  pub fn supported_formats(&self) -> Vec<DXGI_FORMAT> {
+    [
+      DXGI_FORMAT_B8G8R8A8_UNORM,
+      DXGI_FORMAT_R10G10B10A2_UNORM,
+      DXGI_FORMAT_R16G16B16A16_FLOAT,
+    ]
+    .into_iter()
+    .filter(|&format| self.supports_texture_format(format))
+    .collect()
+  }
+
+  fn supports_texture_format(&self, format: DXGI_FORMAT) -> bool {
+    unsafe { self.device.CheckFormatSupport(format) }
+      .is_ok_and(|support| support & D3D11_FORMAT_SUPPORT_TEXTURE2D.0 as u32 != 0)
+  }
+
+  /// The format of the captured pixels, which is the [staging format](DuplicationContext::staging_format)
+  /// except for `DXGI_FORMAT_R8G8B8A8_UNORM` textures holding swizzled BGRA pixels.
+  pub fn texture_format(&self) -> Result<DXGI_FORMAT> {
+    let format = self.staging_format()?;
+    if self.swizzles(format) {
+      return Ok(DXGI_FORMAT_B8G8R8A8_UNORM);
+    }
+    Ok(format)
+  }
+
+  /// The format of readable textures created by this context.
+  ///
+  /// It's the duplication format if it is not `DXGI_FORMAT_B8G8R8A8_UNORM`.
+  /// Otherwise it's `DXGI_FORMAT_B8G8R8A8_UNORM_SRGB` if requested by the texture options and supported,
+  /// `DXGI_FORMAT_B8G8R8A8_UNORM` if supported, or the fallback format of the texture options.
+  ///
+  /// If creating a readable texture of that format failed,
+  /// it's the fallback format which [`DuplicationContext::create_readable_texture`] used instead.
+  /// BGRA8 frames are drawn to `DXGI_FORMAT_R8G8B8A8_UNORM` textures swizzled by a pixel shader.
+  pub fn staging_format(&self) -> Result<DXGI_FORMAT> {
+    if let Some(format) = self.texture_fallback.get() {
+      return Ok(format);
+    }
+    let format = self.format();
+    if format != DXGI_FORMAT_B8G8R8A8_UNORM {
+      return Ok(format);
+    }
+    if self.texture_options.srgb && self.supports_texture_format(DXGI_FORMAT_B8G8R8A8_UNORM_SRGB) {
+      return Ok(DXGI_FORMAT_B8G8R8A8_UNORM_SRGB);
+    }
+    let supported = self.supports_texture_format(DXGI_FORMAT_B8G8R8A8_UNORM);
+    match (supported, self.texture_options.fallback_format) {
+      (true, _) => Ok(DXGI_FORMAT_B8G8R8A8_UNORM),
+      (false, Some(format)) if self.copies_to(format) => Ok(format),
+      _ if self.supports_texture_format(DXGI_FORMAT_R8G8B8A8_UNORM) => {
+        Ok(DXGI_FORMAT_R8G8B8A8_UNORM)
+      }
+      _ => Err(
+        Error::new(
+          "DXGI_FORMAT_B8G8R8A8_UNORM is not supported and no compatible fallback format is set",
+        )
+        .with_context(self.error_context()),
+      ),
+    }
+  }
+
+  /// Whether frames can be copied to readable textures of the `staging` format,
+  /// which must be in the typeless family of the duplication format or be swizzled.
+  fn copies_to(&self, staging: DXGI_FORMAT) -> bool {
+    typeless(staging) == typeless(self.format()) || self.swizzles(staging)
+  }
+
+  /// Whether BGRA8 frames are drawn swizzled to readable textures of the `staging` format.
+  fn swizzles(&self, staging: DXGI_FORMAT) -> bool {
+    staging == DXGI_FORMAT_R8G8B8A8_UNORM && self.format() == DXGI_FORMAT_B8G8R8A8_UNORM
+  }
+
+  /// Whether frames are copied with `MapDesktopSurface`, see [`TextureOptions::map_system_memory`].
+  pub(crate) fn maps_desktop_surface(&self) -> bool {
+    self.texture_options.map_system_memory
+      && self
+        .dxgi_outdupl_desc()
+        .DesktopImageInSystemMemory
+        .as_bool()
+  }
+
   fn acquire_next_frame(
     &self,
     readable_texture: &ID3D11Texture2D,
+    timeout_ms: u32,
   ) -> Result<(IDXGISurface1, DXGI_OUTDUPL_FRAME_INFO)> {
     // acquire GPU texture
+    let (resource, frame_info) = self.acquire_resource(timeout_ms)?;
+    let texture: ID3D11Texture2D = resource.cast().unwrap();
+
+    // copy GPU texture to readable texture, releasing the frame on failure
+    if let Err(e) = self.copy_resource(readable_texture, &texture) {
+      self.release_frame().ok();
+      return Err(e);
+    }
+
+    Ok((readable_texture.cast().unwrap(), frame_info))
+  }
+
+  /// Acquire the next frame without copying it. The frame must be released with `release_frame`.
+  pub(crate) fn acquire_resource(
+    &self,
+    timeout_ms: u32,
+  ) -> Result<(IDXGIResource, DXGI_OUTDUPL_FRAME_INFO)> {
     let mut frame_info = DXGI_OUTDUPL_FRAME_INFO::default();
-    let mut resource: Option<IDXGIResource> = None.clone();
-    unsafe {
-      self
-        .output_duplication
-        .AcquireNextFrame(self.timeout_ms, &mut frame_info, &mut resource)
+    let mut resource: Option<IDXGIResource> = None;
+    let mut timeout_ms = timeout_ms;
+    if let LatencyMode::UltraLow { spin } = self.latency_mode() {
+      let start = Instant::now();
+      for poll in 0.. {
+        match unsafe {
+          self
+            .output_duplication
+            .AcquireNextFrame(0, &mut frame_info, &mut resource)
+        } {
+          Ok(()) => break,
+          Err(e) if e.code() == DXGI_ERROR_WAIT_TIMEOUT => {}
+          Err(e) => return Err(self.acquire_error(e)),
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= spin {
+          timeout_ms = timeout_ms.saturating_sub(elapsed.as_millis().min(u32::MAX as u128) as u32);
+          break;
+        }
+        if poll < SPIN_POLLS {
+          hint::spin_loop();
+        } else {
+          thread::yield_now();
+        }
+      }
     }
-    .map_err(|e| Error::windows("AcquireNextFrame", e))?;
-    let texture: ID3D11Texture2D = resource.unwrap().cast().unwrap();
+    if resource.is_none() {
+      unsafe {
+        self
+          .output_duplication
+          .AcquireNextFrame(timeout_ms, &mut frame_info, &mut resource)
+      }
+      .map_err(|e| self.acquire_error(e))?;
+    }
+    self.pointer.borrow_mut().update_position(&frame_info);
+    Ok((resource.unwrap(), frame_info))
+  }
 
-    // copy GPU texture to readable texture
-    unsafe { self.device_context.CopyResource(readable_texture, &texture) };
+  /// Classify timeouts while the display is off as [`ErrorKind::DisplayOff`].
+  fn acquire_error(&self, err: windows::core::Error) -> Error {
+    #[cfg(feature = "power")]
+    if err.code() == DXGI_ERROR_WAIT_TIMEOUT
+      && crate::power::display_state().ok() == Some(crate::power::DisplayState::Off)
+    {
+      return Error::windows_of_kind(
+        ErrorKind::DisplayOff,
+        "AcquireNextFrame",
+        "The display is turned off",
+        err,
+        self.error_context(),
+      );
+    }
+    self.windows_error("AcquireNextFrame", err)
+  }
 
-    Ok((readable_texture.cast().unwrap(), frame_info))
+  /// Copy `src` to `dest` on the immediate context, e.g. an acquired frame to a readable texture.
+  /// BGRA8 frames are drawn swizzled to `DXGI_FORMAT_R8G8B8A8_UNORM` textures,
+  /// see [`DuplicationContext::staging_format`], which changes the pipeline state of the immediate context.
+  pub fn copy_resource(&self, dest: &ID3D11Texture2D, src: &ID3D11Texture2D) -> Result<()> {
+    if !needs_swizzle(dest, src) {
+      unsafe { self.device_context.CopyResource(dest, src) };
+      return Ok(());
+    }
+    let mut swizzler = self.swizzler.borrow_mut();
+    if swizzler.is_none() {
+      *swizzler = Some(Swizzler::new(self)?);
+    }
+    swizzler.as_mut().unwrap().copy(self, dest, src)
   }
 
-  fn release_frame(&self) -> Result<()> {
-    unsafe { self.output_duplication.ReleaseFrame() }.map_err(|e| Error::windows("ReleaseFrame", e))
+  pub(crate) fn output_duplication(&self) -> &IDXGIOutputDuplication {
+    &self.output_duplication
+  }
+
+  pub(crate) fn release_frame(&self) -> Result<()> {
+    unsafe { self.output_duplication.ReleaseFrame() }
+      .map_err(|e| self.windows_error("ReleaseFrame", e))
   }
 
   pub fn next_frame(
     &self,
     readable_texture: &ID3D11Texture2D,
   ) -> Result<(IDXGISurface1, DXGI_OUTDUPL_FRAME_INFO)> {
-    let (surface, frame_info) = self.acquire_next_frame(readable_texture)?;
+    let (surface, frame_info) = self.acquire_next_frame(readable_texture, self.timeout_ms())?;
     self.release_frame()?;
     Ok((surface, frame_info))
   }
@@ -164,11 +805,20 @@ impl DuplicationContext {
     DXGI_OUTDUPL_FRAME_INFO,
     Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
   )> {
-    let (surface, frame_info) = self.acquire_next_frame(readable_texture)?;
+    let (surface, frame_info) = self.acquire_next_frame(readable_texture, self.timeout_ms())?;
+    let pointer_shape_info = self.pointer_shape(&frame_info, pointer_shape_buffer);
+    self.release_frame()?;
+    Ok((surface, frame_info, pointer_shape_info?))
+  }
 
+  /// Get the pointer shape of the acquired frame if it is updated.
+  pub(crate) fn pointer_shape(
+    &self,
+    frame_info: &DXGI_OUTDUPL_FRAME_INFO,
+    pointer_shape_buffer: &mut Vec<u8>,
+  ) -> Result<Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>> {
     if !frame_info.mouse_updated().shape_updated {
-      self.release_frame()?;
-      return Ok((surface, frame_info, None));
+      return Ok(None);
     }
 
     // resize buffer if needed
@@ -180,63 +830,203 @@ impl DuplicationContext {
     // get pointer shape
     let mut size: u32 = 0;
     let mut pointer_shape_info = DXGI_OUTDUPL_POINTER_SHAPE_INFO::default();
-    match unsafe {
-      self
-        .output_duplication
-        .GetFramePointerShape(
-          pointer_shape_buffer.len() as u32,
-          pointer_shape_buffer.as_mut_ptr() as *mut _,
-          &mut size,
-          &mut pointer_shape_info,
-        )
-        .map_err(|e| Error::windows("GetFramePointerShape", e))
-    } {
-      Ok(_) => {
-        self.release_frame()?;
-        Ok((surface, frame_info, Some(pointer_shape_info)))
-      }
-      Err(e) => {
-        self.release_frame()?;
-        return Err(e);
-      }
+    unsafe {
+      self.output_duplication.GetFramePointerShape(
+        pointer_shape_buffer.len() as u32,
+        pointer_shape_buffer.as_mut_ptr() as *mut _,
+        &mut size,
+        &mut pointer_shape_info,
+      )
     }
+    .map_err(|e| self.windows_error("GetFramePointerShape", e))?;
+    self
+      .pointer
+      .borrow_mut()
+      .update_shape(&pointer_shape_info, &pointer_shape_buffer[..size as usize]);
+    Ok(Some(pointer_shape_info))
   }
 
-  pub fn capture(
+  /// Map the surface and copy its pixels to `dest`, row by row if the pitch differs from the row size.
+  pub(crate) fn copy_surface(
     &self,
+    frame: &IDXGISurface1,
     dest: *mut u8,
     len: usize,
-    readable_texture: &ID3D11Texture2D,
     texture_desc: &D3D11_TEXTURE2D_DESC,
-  ) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
-    let (frame, frame_info) = self.next_frame(readable_texture)?;
+  ) -> Result<()> {
     let mut mapped_surface = DXGI_MAPPED_RECT::default();
-    let line_bytes = texture_desc.Width as usize * 4;
-
     unsafe {
       frame
         .Map(&mut mapped_surface, DXGI_MAP_READ)
-        .map_err(|e| Error::windows("Map", e))?;
-      if mapped_surface.Pitch as usize == line_bytes {
+        .map_err(|e| self.windows_error("Map", e))?;
+      Self::copy_rect(
+        &mapped_surface,
+        dest,
+        len,
+        texture_desc,
+        self.color_lut.borrow().as_ref(),
+      );
+      frame.Unmap().map_err(|e| self.windows_error("Unmap", e))?;
+    }
+
+    Ok(())
+  }
+
+  /// Map the surface and copy `rows` of its pixels to the same rows of `dest`,
+  /// which must hold all pixels of the `texture_desc`.
+  pub fn copy_surface_rows(
+    &self,
+    frame: &IDXGISurface1,
+    dest: &mut [u8],
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+    rows: Range<u32>,
+  ) -> Result<()> {
+    let rect = Rect::new(
+      0,
+      rows.start as i32,
+      texture_desc.Width as i32,
+      rows.end as i32,
+    );
+    self.copy_surface_rect(frame, dest, texture_desc, &rect)
+  }
+
+  /// Map the surface and copy the pixels of `rect` to the same area of `dest`,
+  /// which must hold all pixels of the `texture_desc`. `rect` is clipped to the texture.
+  pub fn copy_surface_rect(
+    &self,
+    frame: &IDXGISurface1,
+    dest: &mut [u8],
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+    rect: &Rect,
+  ) -> Result<()> {
+    let bounds = Rect::new(0, 0, texture_desc.Width as i32, texture_desc.Height as i32);
+    let Some(rect) = rect.intersect(&bounds) else {
+      return Ok(());
+    };
+    let bytes_per_pixel = texture_desc.Format.bytes_per_pixel();
+    let line_bytes = texture_desc.Width as usize * bytes_per_pixel;
+    let offset = rect.left as usize * bytes_per_pixel;
+    let bytes = rect.width() as usize * bytes_per_pixel;
+    let mut mapped_surface = DXGI_MAPPED_RECT::default();
+    unsafe { frame.Map(&mut mapped_surface, DXGI_MAP_READ) }
+      .map_err(|e| self.windows_error("Map", e))?;
+    let pitch = mapped_surface.Pitch as usize;
+    for y in rect.top as usize..rect.bottom as usize {
+      let src =
+        unsafe { slice::from_raw_parts(mapped_surface.pBits.add(y * pitch + offset), bytes) };
+      let start = y * line_bytes + offset;
+      let row = &mut dest[start..start + bytes];
+      row.copy_from_slice(src);
+      self.apply_color_adjustment(row, texture_desc.Format);
+    }
+    unsafe { frame.Unmap() }.map_err(|e| self.windows_error("Unmap", e))
+  }
+
+  /// Copy the pixels of a mapped rect to `dest`, adjusting each row by `lut` while it's in cache.
+  ///
+  /// # Safety
+  ///
+  /// `dest` must be valid for `len` bytes and `rect` must hold `texture_desc.Height` rows.
+  unsafe fn copy_rect(
+    mapped_surface: &DXGI_MAPPED_RECT,
+    dest: *mut u8,
+    len: usize,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+    lut: Option<&ColorLut>,
+  ) {
+    let line_bytes = texture_desc.Width as usize * texture_desc.Format.bytes_per_pixel();
+    unsafe {
+      if mapped_surface.Pitch as usize == line_bytes && lut.is_none() {
         ptr::copy_nonoverlapping(mapped_surface.pBits, dest, len);
       } else {
         // https://github.com/DiscreteTom/rusty-duplication/issues/7
-        for i in 0..texture_desc.Height {
-          let src = mapped_surface
-            .pBits
-            .offset((i * mapped_surface.Pitch as u32) as isize);
-          let dest = dest.offset((i * line_bytes as u32) as isize);
-          ptr::copy_nonoverlapping(src, dest, mapped_surface.Pitch as usize);
+        for i in 0..texture_desc.Height as usize {
+          let src = mapped_surface.pBits.add(i * mapped_surface.Pitch as usize);
+          let dest = dest.add(i * line_bytes);
+          ptr::copy_nonoverlapping(src, dest, line_bytes);
+          if let Some(lut) = lut {
+            lut.apply(
+              slice::from_raw_parts_mut(dest, line_bytes),
+              texture_desc.Format,
+            );
+          }
         }
       }
-      frame.Unmap().map_err(|e| Error::windows("Unmap", e))?;
     }
+  }
+
+  /// Acquire a frame and copy the desktop image in system memory to `dest` before releasing it,
+  /// getting the pointer shape if `pointer_shape_buffer` is `Some`.
+  fn capture_desktop_surface(
+    &self,
+    dest: &mut [u8],
+    len: usize,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+    pointer_shape_buffer: Option<&mut Vec<u8>>,
+  ) -> Result<(
+    DXGI_OUTDUPL_FRAME_INFO,
+    Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
+  )> {
+    let (_resource, frame_info) = self.acquire_resource(self.timeout_ms())?;
+
+    let result = (|| {
+      let pointer_shape_info = match pointer_shape_buffer {
+        Some(buffer) => self.pointer_shape(&frame_info, buffer)?,
+        None => None,
+      };
+      self.copy_desktop_surface(dest, len, texture_desc)?;
+      Ok((frame_info, pointer_shape_info))
+    })();
+    self.release_frame()?;
+    result
+  }
+
+  /// Copy the desktop image in system memory of the acquired frame to `dest`,
+  /// which must hold `len` bytes, see [`DuplicationContext::maps_desktop_surface`].
+  pub(crate) fn copy_desktop_surface(
+    &self,
+    dest: &mut [u8],
+    len: usize,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+  ) -> Result<()> {
+    unsafe {
+      let mapped_surface = self
+        .output_duplication
+        .MapDesktopSurface()
+        .map_err(|e| self.windows_error("MapDesktopSurface", e))?;
+      Self::copy_rect(
+        &mapped_surface,
+        dest.as_mut_ptr(),
+        len,
+        texture_desc,
+        self.color_lut.borrow().as_ref(),
+      );
+      self
+        .output_duplication
+        .UnMapDesktopSurface()
+        .map_err(|e| self.windows_error("UnMapDesktopSurface", e))
+    }
+  }
+
+  #[deprecated(note = "use `capture_to_slice` which checks the buffer length")]
+  #[allow(clippy::not_unsafe_ptr_arg_deref)]
+  pub fn capture(
+    &self,
+    dest: *mut u8,
+    len: usize,
+    readable_texture: &ID3D11Texture2D,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+  ) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    let (frame, frame_info) = self.next_frame(readable_texture)?;
+    self.copy_surface(&frame, dest, len, texture_desc)?;
 
     Ok(frame_info)
   }
 
   /// If mouse is updated, the `Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>` is `Some`.
   /// and this will resize `pointer_shape_buffer` if needed and update it.
+  #[deprecated(note = "use `capture_to_slice_with_pointer_shape` which checks the buffer length")]
+  #[allow(clippy::not_unsafe_ptr_arg_deref)]
   pub fn capture_with_pointer_shape(
     &self,
     dest: *mut u8,
@@ -250,39 +1040,283 @@ impl DuplicationContext {
   )> {
     let (frame, frame_info, pointer_shape_info) =
       self.next_frame_with_pointer_shape(readable_texture, pointer_shape_buffer)?;
-    let mut mapped_surface = DXGI_MAPPED_RECT::default();
-    let line_bytes = texture_desc.Width as usize * 4;
+    self.copy_surface(&frame, dest, len, texture_desc)?;
 
-    unsafe {
-      frame
-        .Map(&mut mapped_surface, DXGI_MAP_READ)
-        .map_err(|e| Error::windows("Map", e))?;
-      if mapped_surface.Pitch as usize == line_bytes {
-        ptr::copy_nonoverlapping(mapped_surface.pBits, dest, len);
+    Ok((frame_info, pointer_shape_info))
+  }
+
+  /// Return an error if `dest` can't hold the readable texture, otherwise the bytes it needs.
+  pub fn check_dest(&self, dest: &[u8], texture_desc: &D3D11_TEXTURE2D_DESC) -> Result<usize> {
+    let len = texture_desc.Width as usize
+      * texture_desc.Height as usize
+      * texture_desc.Format.bytes_per_pixel();
+    if dest.len() < len {
+      return Err(Error::new("Invalid buffer length").with_context(self.error_context()));
+    }
+    Ok(len)
+  }
+
+  /// Capture a frame into `dest`, which must hold all pixels of the `texture_desc`.
+  pub fn capture_to_slice(
+    &self,
+    dest: &mut [u8],
+    readable_texture: &ID3D11Texture2D,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+  ) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    let len = self.check_dest(dest, texture_desc)?;
+    if self.maps_desktop_surface() {
+      return Ok(
+        self
+          .capture_desktop_surface(dest, len, texture_desc, None)?
+          .0,
+      );
+    }
+    let (frame, frame_info) = self.next_frame(readable_texture)?;
+    self.copy_surface(&frame, dest.as_mut_ptr(), len, texture_desc)?;
+
+    Ok(frame_info)
+  }
+
+  /// Like [`DuplicationContext::capture_to_slice`], and capture the pointer shape.
+  /// If mouse is updated, the `Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>` is `Some`.
+  /// and this will resize `pointer_shape_buffer` if needed and update it.
+  pub fn capture_to_slice_with_pointer_shape(
+    &self,
+    dest: &mut [u8],
+    readable_texture: &ID3D11Texture2D,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+    pointer_shape_buffer: &mut Vec<u8>,
+  ) -> Result<(
+    DXGI_OUTDUPL_FRAME_INFO,
+    Option<DXGI_OUTDUPL_POINTER_SHAPE_INFO>,
+  )> {
+    let len = self.check_dest(dest, texture_desc)?;
+    if self.maps_desktop_surface() {
+      return self.capture_desktop_surface(dest, len, texture_desc, Some(pointer_shape_buffer));
+    }
+    let (frame, frame_info, pointer_shape_info) =
+      self.next_frame_with_pointer_shape(readable_texture, pointer_shape_buffer)?;
+    self.copy_surface(&frame, dest.as_mut_ptr(), len, texture_desc)?;
+
+    Ok((frame_info, pointer_shape_info))
+  }
+
+  /// Capture a single frame into `dest` without creating a long-lived capturer.
+  /// The readable texture is created and released within this call,
+  /// so this is suitable for screenshot-style usage where setup per shot is acceptable.
+  pub fn capture_into(
+    &self,
+    dest: &mut [u8],
+    options: &CaptureOptions,
+  ) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    let (texture, desc, texture_desc) = self.create_readable_texture()?;
+    let len = desc.calc_buffer_size();
+    if dest.len() < len {
+      return Err(Error::new("Invalid buffer length").with_context(self.error_context()));
+    }
+
+    if options.include_cursor {
+      self.check_pointer_format(&texture_desc)?;
+    }
+
+    let timeout_ms = options.timeout_ms.unwrap_or(self.timeout_ms());
+    let mut retries = options.retries;
+    let mut pointer_shape_buffer = Vec::new();
+    loop {
+      let (frame, frame_info) = match self.acquire_next_frame(&texture, timeout_ms) {
+        Ok(acquired) => acquired,
+        // nothing changed within the timeout, retry like a frame without a desktop update
+        Err(e)
+          if retries > 0
+            && e.windows.as_ref().map(|e| e.code()) == Some(DXGI_ERROR_WAIT_TIMEOUT) =>
+        {
+          retries -= 1;
+          continue;
+        }
+        Err(e) => return Err(e),
+      };
+      let pointer_shape = if options.include_cursor {
+        self.pointer_shape(&frame_info, &mut pointer_shape_buffer)
       } else {
-        // https://github.com/DiscreteTom/rusty-duplication/issues/7
-        for i in 0..texture_desc.Height {
-          let src = mapped_surface
-            .pBits
-            .offset((i * mapped_surface.Pitch as u32) as isize);
-          let dest = dest.offset((i * line_bytes as u32) as isize);
-          ptr::copy_nonoverlapping(src, dest, mapped_surface.Pitch as usize);
+        Ok(None)
+      };
+      self.release_frame()?;
+      pointer_shape?;
+      if frame_info.desktop_updated() || retries == 0 {
+        self.copy_surface(&frame, dest.as_mut_ptr(), len, &texture_desc)?;
+        if options.include_cursor {
+          self.draw_pointer(
+            &mut dest[..len],
+            texture_desc.Width,
+            &Rect::new(0, 0, texture_desc.Width as i32, texture_desc.Height as i32),
+          );
         }
+        return Ok(frame_info);
       }
-      frame.Unmap().map_err(|e| Error::windows("Unmap", e))?;
+      retries -= 1;
     }
+  }
 
-    Ok((frame_info, pointer_shape_info))
+  /// Like [`DuplicationContext::capture_to_slice`] with per-call overrides,
+  /// see [`Capturer::capture_with`](crate::capturer::model::Capturer::capture_with).
+  /// Move rects are not applied and frames are always copied through the readable texture,
+  /// so `dest` must not be updated with move rects of the next frame.
+  pub fn capture_to_slice_with(
+    &self,
+    dest: &mut [u8],
+    readable_texture: &ID3D11Texture2D,
+    texture_desc: &D3D11_TEXTURE2D_DESC,
+    args: &CaptureArgs,
+  ) -> Result<DXGI_OUTDUPL_FRAME_INFO> {
+    let len = self.check_dest(dest, texture_desc)?;
+    if args.include_cursor {
+      self.check_pointer_format(texture_desc)?;
+    }
+    let timeout_ms = args.timeout_ms.unwrap_or(self.timeout_ms());
+    let (frame, frame_info) = self.acquire_next_frame(readable_texture, timeout_ms)?;
+    // the shape is only decoded for drawing, the raw shape isn't returned
+    let pointer_shape = if args.include_cursor {
+      self.pointer_shape(&frame_info, &mut Vec::new())
+    } else {
+      Ok(None)
+    };
+    self.release_frame()?;
+    pointer_shape?;
+
+    let (width, height) = (texture_desc.Width, texture_desc.Height);
+    let bounds = Rect::new(0, 0, width as i32, height as i32);
+    let region = match args.region {
+      Some(region) => {
+        let region = region.intersect(&bounds);
+        if let Some(region) = &region {
+          self.copy_surface_rect(&frame, dest, texture_desc, region)?;
+        }
+        region
+      }
+      None => {
+        self.copy_surface(&frame, dest.as_mut_ptr(), len, texture_desc)?;
+        Some(bounds)
+      }
+    };
+    if let (true, Some(region)) = (args.include_cursor, region) {
+      self.draw_pointer(&mut dest[..len], width, &region);
+    }
+    Ok(frame_info)
+  }
+
+  /// Return an error if the pointer can't be drawn onto frames of the `texture_desc`.
+  fn check_pointer_format(&self, texture_desc: &D3D11_TEXTURE2D_DESC) -> Result<()> {
+    if texture_desc.Format != DXGI_FORMAT_B8G8R8A8_UNORM
+      && texture_desc.Format != DXGI_FORMAT_B8G8R8A8_UNORM_SRGB
+    {
+      return Err(
+        Error::new("Drawing the pointer requires DXGI_FORMAT_B8G8R8A8_UNORM")
+          .with_context(self.error_context()),
+      );
+    }
+    Ok(())
+  }
+
+  /// Draw the last known pointer onto the `clip` area of a BGRA32 frame,
+  /// if it's visible and its shape is known.
+  fn draw_pointer(&self, dest: &mut [u8], width: u32, clip: &Rect) {
+    let pointer = self.pointer.borrow();
+    let state = pointer.state();
+    if let (true, Some(image)) = (state.visible, &state.decoded_image) {
+      draw_pointer_clipped(dest, width, image, state.position, clip);
+    }
+  }
+}
+
+/// The typeless format of the family of `format`, or `format` if it isn't in a family of duplication formats.
+/// `CopyResource` only copies between formats of the same family.
+fn typeless(format: DXGI_FORMAT) -> DXGI_FORMAT {
+  match format {
+    DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => DXGI_FORMAT_B8G8R8A8_TYPELESS,
+    DXGI_FORMAT_R8G8B8A8_UNORM
+    | DXGI_FORMAT_R8G8B8A8_UNORM_SRGB
+    | DXGI_FORMAT_R8G8B8A8_UINT
+    | DXGI_FORMAT_R8G8B8A8_SNORM
+    | DXGI_FORMAT_R8G8B8A8_SINT => DXGI_FORMAT_R8G8B8A8_TYPELESS,
+    DXGI_FORMAT_R10G10B10A2_UNORM | DXGI_FORMAT_R10G10B10A2_UINT => {
+      DXGI_FORMAT_R10G10B10A2_TYPELESS
+    }
+    DXGI_FORMAT_R16G16B16A16_FLOAT
+    | DXGI_FORMAT_R16G16B16A16_UNORM
+    | DXGI_FORMAT_R16G16B16A16_UINT
+    | DXGI_FORMAT_R16G16B16A16_SNORM
+    | DXGI_FORMAT_R16G16B16A16_SINT => DXGI_FORMAT_R16G16B16A16_TYPELESS,
+    format => format,
   }
 }
 
+/// Return the current QPC time and the QPC frequency.
+pub(crate) fn qpc_now() -> (i64, i64) {
+  let mut now = 0;
+  let mut frequency = 0;
+  unsafe {
+    QueryPerformanceCounter(&mut now);
+    QueryPerformanceFrequency(&mut frequency);
+  }
+  (now, frequency)
+}
+
+/// Convert QPC ticks to a duration, negative ticks are treated as zero.
+pub(crate) fn qpc_duration(ticks: i64, frequency: i64) -> Duration {
+  let nanos = ticks.max(0) as u128 * 1_000_000_000 / frequency.max(1) as u128;
+  Duration::from_nanos(nanos as u64)
+}
+
+fn frame_latency(
+  last_present_time: i64,
+  now: i64,
+  frequency: i64,
+  sync_qpc_time: Option<i64>,
+  period: Option<Duration>,
+) -> Option<FrameLatency> {
+  if last_present_time == 0 {
+    return None;
+  }
+  let vblank_to_present = sync_qpc_time.and_then(|sync| {
+    let ticks = last_present_time - sync;
+    match period {
+      // the latest vblank may be after the present, step back to the one before it
+      Some(period) => {
+        let period_ticks = (period.as_secs_f64() * frequency as f64).round() as i64;
+        (period_ticks > 0).then(|| qpc_duration(ticks.rem_euclid(period_ticks), frequency))
+      }
+      None => (ticks >= 0).then(|| qpc_duration(ticks, frequency)),
+    }
+  });
+  Some(FrameLatency {
+    present_to_capture: qpc_duration(now - last_present_time, frequency),
+    vblank_to_present,
+  })
+}
+
+/// Return the time until the next multiple of `period`, `elapsed` since a period start.
+fn time_to_next_period(elapsed: Duration, period: Duration) -> Duration {
+  let into_period = elapsed.as_nanos() % period.as_nanos().max(1);
+  period - Duration::from_nanos(into_period as u64)
+}
+
 #[cfg(test)]
 mod tests {
   use std::{thread, time::Duration};
 
+  use super::{frame_latency, time_to_next_period, DeviceContextClaim};
   use crate::{
     manager::Manager,
-    utils::{FrameInfoExt, MonitorInfoExt, OutDuplDescExt},
+    model::{CaptureOptions, ChannelAdjustment, ColorAdjustment, LatencyMode, TextureOptions},
+    utils::{FrameInfoExt, MonitorInfoExt, OutDuplDescExt, OutputDescExt},
+  };
+  use windows::Win32::Graphics::Direct3D11::D3D11_TEXTURE2D_DESC;
+  use windows::Win32::Graphics::Dxgi::{
+    Common::{
+      DXGI_FORMAT_B8G8R8A8_TYPELESS, DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+      DXGI_FORMAT_R10G10B10A2_UNORM, DXGI_FORMAT_R8G8B8A8_UNORM,
+    },
+    DXGI_RESOURCE_PRIORITY_NORMAL,
   };
 
   #[test]
@@ -305,20 +1339,18 @@ mod tests {
     // sleep for a while before capture to wait system to update the screen
     thread::sleep(Duration::from_millis(100));
 
+    // the buffer length is checked
+    assert!(manager.contexts[0]
+      .capture_to_slice(&mut buffer[..1], &texture, &texture_desc)
+      .is_err());
+
     let info = manager.contexts[0]
-      .capture(buffer.as_mut_ptr(), buffer.len(), &texture, &texture_desc)
+      .capture_to_slice(&mut buffer, &texture, &texture_desc)
       .unwrap();
     assert!(info.desktop_updated());
 
     // ensure buffer not all zero
-    let mut all_zero = true;
-    for i in 0..buffer.len() {
-      if buffer[i] != 0 {
-        all_zero = false;
-        break;
-      }
-    }
-    assert!(!all_zero);
+    assert!(buffer.iter().any(|&b| b != 0));
 
     // sleep for a while before capture to wait system to update the mouse
     thread::sleep(Duration::from_millis(1000));
@@ -326,9 +1358,8 @@ mod tests {
     // check pointer
     let mut pointer_shape_buffer = vec![0u8; info.PointerShapeBufferSize as usize];
     let (frame_info, pointer_shape_info) = manager.contexts[0]
-      .capture_with_pointer_shape(
-        buffer.as_mut_ptr(),
-        buffer.len(),
+      .capture_to_slice_with_pointer_shape(
+        &mut buffer,
         &texture,
         &texture_desc,
         &mut pointer_shape_buffer,
@@ -338,13 +1369,275 @@ mod tests {
     assert!(pointer_shape_info.is_some());
 
     // ensure pointer_shape_buffer not all zero
-    let mut all_zero = true;
-    for i in 0..pointer_shape_buffer.len() {
-      if pointer_shape_buffer[i] != 0 {
-        all_zero = false;
-        break;
-      }
+    assert!(pointer_shape_buffer.iter().any(|&b| b != 0));
+  }
+
+  #[test]
+  fn summary() {
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    let summary = ctx.summary().unwrap();
+    assert_eq!(summary.id, ctx.id());
+    assert!(summary.name.starts_with("\\\\.\\DISPLAY"));
+    assert!(!summary.rect.is_empty());
+    assert!(summary.scale >= 1.0);
+    assert!(summary.refresh_rate > 0.0);
+
+    if let Some(desc1) = ctx.dxgi_output_desc1().unwrap() {
+      assert_eq!(desc1.rect(), ctx.dxgi_output_desc().unwrap().rect());
+      assert!(desc1.BitsPerColor >= 6);
+    }
+  }
+
+  #[test]
+  fn capture_into() {
+    let manager = Manager::default().unwrap();
+    assert_ne!(manager.contexts.len(), 0);
+
+    let ctx = &manager.contexts[0];
+    let mut buffer = vec![0u8; ctx.dxgi_outdupl_desc().calc_buffer_size()];
+
+    // the buffer is too small
+    assert!(ctx
+      .capture_into(&mut buffer[1..], &CaptureOptions::default())
+      .is_err());
+
+    let info = ctx
+      .capture_into(
+        &mut buffer,
+        &CaptureOptions {
+          retries: 10,
+          ..Default::default()
+        },
+      )
+      .unwrap();
+    assert!(info.desktop_updated());
+
+    // ensure buffer not all zero
+    assert!(buffer.iter().any(|&b| b != 0));
+
+    // the pointer is drawn only on request
+    ctx
+      .capture_into(
+        &mut buffer,
+        &CaptureOptions {
+          include_cursor: true,
+          ..Default::default()
+        },
+      )
+      .unwrap();
+
+    // colors are adjusted while copying, alpha is kept
+    let black = ChannelAdjustment {
+      brightness: -1.0,
+      ..Default::default()
+    };
+    ctx.set_color_adjustment(Some(ColorAdjustment::uniform(black)));
+    assert_eq!(
+      ctx.color_adjustment(),
+      Some(ColorAdjustment::uniform(black))
+    );
+    ctx
+      .capture_into(
+        &mut buffer,
+        &CaptureOptions {
+          retries: 10,
+          ..Default::default()
+        },
+      )
+      .unwrap();
+    assert!(buffer.chunks_exact(4).all(|pixel| pixel[..3] == [0, 0, 0]));
+    ctx.set_color_adjustment(None);
+  }
+
+  #[test]
+  fn latency_mode() {
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    ctx.set_latency_mode(LatencyMode::UltraLow {
+      spin: Duration::from_millis(5),
+    });
+    let mut buffer = vec![0u8; ctx.dxgi_outdupl_desc().calc_buffer_size()];
+    let info = ctx
+      .capture_into(
+        &mut buffer,
+        &CaptureOptions {
+          retries: 10,
+          ..Default::default()
+        },
+      )
+      .unwrap();
+    assert!(info.desktop_updated());
+  }
+
+  #[test]
+  fn texture_options() {
+    let mut manager = Manager::default().unwrap();
+    let ctx = &mut manager.contexts[0];
+    ctx.set_texture_options(TextureOptions {
+      eviction_priority: DXGI_RESOURCE_PRIORITY_NORMAL.0,
+      map_system_memory: true,
+      ..Default::default()
+    });
+
+    let (texture, desc, texture_desc) = ctx.create_readable_texture().unwrap();
+    assert_eq!(texture_desc.Format, DXGI_FORMAT_B8G8R8A8_UNORM);
+    assert_eq!(
+      unsafe { texture.GetEvictionPriority() },
+      DXGI_RESOURCE_PRIORITY_NORMAL.0
+    );
+
+    // capture works whether or not the desktop image is in system memory
+    thread::sleep(Duration::from_millis(100));
+    let mut buffer = vec![0u8; desc.calc_buffer_size()];
+    ctx
+      .capture_to_slice(&mut buffer, &texture, &texture_desc)
+      .unwrap();
+    // so do partial updates
+    thread::sleep(Duration::from_millis(100));
+    ctx
+      .update_slice(&mut buffer, &texture, &texture_desc, None)
+      .unwrap();
+
+    // sRGB is marked on the readable texture only if supported
+    ctx.set_texture_options(TextureOptions {
+      srgb: true,
+      ..Default::default()
+    });
+    let (texture, _, texture_desc) = ctx.create_readable_texture().unwrap();
+    assert_eq!(texture_desc.Format, ctx.texture_format().unwrap());
+    if ctx.supports_texture_format(DXGI_FORMAT_B8G8R8A8_UNORM_SRGB) {
+      assert_eq!(texture_desc.Format, DXGI_FORMAT_B8G8R8A8_UNORM_SRGB);
     }
-    assert!(!all_zero);
+    ctx
+      .capture_to_slice(&mut buffer, &texture, &texture_desc)
+      .unwrap();
+
+    // BGRA8 formats fall back to each other, then to the fallback format of the options
+    ctx.set_texture_options(TextureOptions {
+      fallback_format: Some(DXGI_FORMAT_R8G8B8A8_UNORM),
+      ..Default::default()
+    });
+    assert_eq!(
+      ctx.fallback_formats(DXGI_FORMAT_B8G8R8A8_UNORM),
+      [
+        DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+        DXGI_FORMAT_B8G8R8A8_TYPELESS,
+        DXGI_FORMAT_R8G8B8A8_UNORM
+      ]
+    );
+    assert_eq!(
+      ctx.fallback_formats(DXGI_FORMAT_R10G10B10A2_UNORM),
+      [DXGI_FORMAT_R8G8B8A8_UNORM]
+    );
+    assert!(ctx.fallback_formats(DXGI_FORMAT_R8G8B8A8_UNORM).is_empty());
+
+    // fallback formats which frames can't be copied to are skipped
+    ctx.set_texture_options(TextureOptions {
+      fallback_format: Some(DXGI_FORMAT_R10G10B10A2_UNORM),
+      ..Default::default()
+    });
+    if ctx.format() == DXGI_FORMAT_B8G8R8A8_UNORM {
+      assert!(!ctx
+        .fallback_formats(DXGI_FORMAT_B8G8R8A8_UNORM)
+        .contains(&DXGI_FORMAT_R10G10B10A2_UNORM));
+    }
+  }
+
+  #[test]
+  fn swizzle() {
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    let (texture, desc, texture_desc) = ctx.create_readable_texture().unwrap();
+    let rgba = ctx
+      .create_texture(&D3D11_TEXTURE2D_DESC {
+        Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+        ..texture_desc
+      })
+      .unwrap();
+
+    // a frame drawn to an RGBA8 texture holds the same BGRA bytes
+    thread::sleep(Duration::from_millis(100));
+    let mut bgra_buffer = vec![0u8; desc.calc_buffer_size()];
+    let mut rgba_buffer = vec![0u8; desc.calc_buffer_size()];
+    let frame = ctx.acquire().unwrap();
+    frame
+      .copy_to_slice(&mut bgra_buffer, &texture, &texture_desc)
+      .unwrap();
+    frame
+      .copy_to_slice(&mut rgba_buffer, &rgba, &texture_desc)
+      .unwrap();
+    frame.release().unwrap();
+    assert!(bgra_buffer == rgba_buffer);
+  }
+
+  #[test]
+  fn vblank() {
+    let period = Duration::from_millis(16);
+    assert_eq!(
+      time_to_next_period(Duration::from_millis(4), period),
+      Duration::from_millis(12)
+    );
+    assert_eq!(
+      time_to_next_period(Duration::from_millis(36), period),
+      Duration::from_millis(12)
+    );
+    assert_eq!(time_to_next_period(Duration::ZERO, period), period);
+
+    let manager = Manager::default().unwrap();
+    manager.contexts[0].wait_for_vblank().unwrap();
+  }
+
+  #[test]
+  fn latency() {
+    // 1 tick is 1ms
+    let frequency = 1000;
+    assert!(frame_latency(0, 100, frequency, Some(90), None).is_none());
+
+    let latency = frame_latency(100, 130, frequency, Some(90), None).unwrap();
+    assert_eq!(latency.present_to_capture, Duration::from_millis(30));
+    assert_eq!(latency.vblank_to_present, Some(Duration::from_millis(10)));
+
+    // the latest vblank is after the present
+    let period = Some(Duration::from_millis(16));
+    let latency = frame_latency(100, 130, frequency, Some(110), period).unwrap();
+    assert_eq!(latency.vblank_to_present, Some(Duration::from_millis(6)));
+    let latency = frame_latency(100, 130, frequency, Some(110), None).unwrap();
+    assert_eq!(latency.vblank_to_present, None);
+    let latency = frame_latency(100, 130, frequency, None, period).unwrap();
+    assert_eq!(latency.vblank_to_present, None);
+
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    let mut buffer = vec![0u8; ctx.dxgi_outdupl_desc().calc_buffer_size()];
+    let info = ctx
+      .capture_into(
+        &mut buffer,
+        &CaptureOptions {
+          retries: 10,
+          ..Default::default()
+        },
+      )
+      .unwrap();
+    if info.LastPresentTime != 0 {
+      assert!(ctx.frame_latency(&info).is_some());
+    }
+  }
+
+  #[test]
+  fn device_context_claims() {
+    let manager = Manager::default().unwrap();
+    let device_context = manager.contexts[0].device_context().clone();
+    // the same thread may create more contexts on the device
+    drop(DeviceContextClaim::new(&device_context).unwrap());
+
+    let other = device_context.clone();
+    let claimed = thread::spawn(move || DeviceContextClaim::new(&other).is_ok());
+    assert!(!claimed.join().unwrap());
+
+    // released with the last context
+    drop(manager);
+    let claimed = thread::spawn(move || DeviceContextClaim::new(&device_context).is_ok());
+    assert!(claimed.join().unwrap());
   }
 }
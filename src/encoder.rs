@@ -0,0 +1,434 @@
+//! Enumerate the video encoders available to the [`Recorder`](crate::recorder::Recorder) and pick one.
+//! Enable the `recorder` feature to use this module.
+
+use crate::error::Error;
+use crate::model::Result;
+use std::{ptr, slice};
+use windows::core::{ComInterface, GUID, PWSTR};
+use windows::Win32::Graphics::Direct3D::D3D_DRIVER_TYPE_UNKNOWN;
+use windows::Win32::Graphics::Direct3D11::{
+  D3D11CreateDevice, ID3D11Device, ID3D11Multithread, D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+  D3D11_CREATE_DEVICE_VIDEO_SUPPORT, D3D11_SDK_VERSION,
+};
+use windows::Win32::Graphics::Dxgi::{
+  CreateDXGIFactory1, IDXGIAdapter1, IDXGIFactory1, DXGI_ADAPTER_DESC1,
+};
+use windows::Win32::Media::MediaFoundation::{
+  eAVEncCommonRateControlMode_CBR, eAVEncCommonRateControlMode_PeakConstrainedVBR,
+  eAVEncCommonRateControlMode_Quality, eAVEncCommonRateControlMode_UnconstrainedVBR,
+  CODECAPI_AVEncCommonMaxBitRate, CODECAPI_AVEncCommonMeanBitRate,
+  CODECAPI_AVEncCommonRateControlMode, CODECAPI_AVEncMPVDefaultBPictureCount,
+  CODECAPI_AVEncMPVGOPSize, CODECAPI_AVEncVideoEncodeQP, CODECAPI_AVLowLatencyMode, IMFActivate,
+  IMFAttributes, IMFDXGIDeviceManager, IMFSinkWriter, IMFSinkWriterEx, MFCreateAttributes,
+  MFCreateDXGIDeviceManager, MFMediaType_Video, MFTEnumEx, MFT_ENUM_HARDWARE_URL_Attribute,
+  MFT_ENUM_HARDWARE_VENDOR_ID_Attribute, MFT_FRIENDLY_NAME_Attribute,
+  MFT_TRANSFORM_CLSID_Attribute, MFVideoFormat_H264, MFVideoFormat_HEVC,
+  MFT_CATEGORY_VIDEO_ENCODER, MFT_ENUM_FLAG, MFT_ENUM_FLAG_ALL, MFT_ENUM_FLAG_SORTANDFILTER,
+  MFT_REGISTER_TYPE_INFO, MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, MF_SINK_WRITER_D3D_MANAGER,
+};
+use windows::Win32::System::Com::CoTaskMemFree;
+
+/// The largest quantization parameter of H.264 and HEVC.
+const MAX_QP: u32 = 51;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VideoCodec {
+  #[default]
+  H264,
+  Hevc,
+}
+
+impl VideoCodec {
+  pub(crate) fn subtype(&self) -> GUID {
+    match self {
+      VideoCodec::H264 => MFVideoFormat_H264,
+      VideoCodec::Hevc => MFVideoFormat_HEVC,
+    }
+  }
+}
+
+/// A video encoder registered in Media Foundation, see [`video_encoders`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncoderInfo {
+  /// e.g. `NVIDIA H.264 Encoder MFT`, or the CLSID of encoders without a friendly name.
+  pub name: String,
+  /// The PCI vendor ID of a hardware encoder, e.g. `0x10DE` for NVENC,
+  /// `0x1002` for AMF and `0x8086` for Quick Sync.
+  pub vendor_id: Option<u32>,
+  pub hardware: bool,
+}
+
+impl EncoderInfo {
+  /// Select this encoder for [`RecorderOptions::encoder`](crate::recorder::RecorderOptions::encoder).
+  pub fn selection(&self) -> EncoderSelection {
+    match self.vendor_id {
+      Some(vendor_id) if self.hardware => EncoderSelection::Hardware { vendor_id },
+      _ => EncoderSelection::Software,
+    }
+  }
+}
+
+/// Which encoder a recorder uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EncoderSelection {
+  /// A hardware encoder if available, otherwise the software encoder.
+  #[default]
+  Auto,
+  Software,
+  /// The hardware encoder of the first GPU from the vendor.
+  /// Media Foundation picks hardware encoders by GPU, so the encoder is selected by its vendor,
+  /// check the chosen one with [`Recorder::encoder`](crate::recorder::Recorder::encoder).
+  Hardware {
+    vendor_id: u32,
+  },
+}
+
+/// How the encoder spends bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RateControl {
+  /// Constant bitrate at [`RecorderOptions::video_bitrate`](crate::recorder::RecorderOptions::video_bitrate),
+  /// suited to streaming.
+  #[default]
+  Cbr,
+  /// Variable bitrate averaging [`RecorderOptions::video_bitrate`](crate::recorder::RecorderOptions::video_bitrate),
+  /// with peaks limited to `max_bitrate` if set.
+  Vbr { max_bitrate: Option<u32> },
+  /// Constant quantization parameter from 0 (best) to 51, suited to archival.
+  /// The bitrate options are ignored.
+  Cqp { qp: u32 },
+}
+
+/// Tuning of the video encoder. Encoders ignore settings they don't support.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncoderSettings {
+  pub rate_control: RateControl,
+  /// Frames between key frames, `None` for the encoder default.
+  pub gop_size: Option<u32>,
+  /// B-frames between other frames, `None` for the encoder default.
+  /// B-frames add latency, use 0 for streaming.
+  pub b_frames: Option<u32>,
+  /// Trade compression for encoding each frame as soon as it arrives.
+  pub low_latency: bool,
+}
+
+impl EncoderSettings {
+  /// The encoding parameters of the video stream of a sink writer.
+  pub(crate) fn parameters(&self, bitrate: u32) -> Result<IMFAttributes> {
+    let mut values = vec![];
+    let mut qp = None;
+    match self.rate_control {
+      RateControl::Cbr => {
+        values.push((
+          &CODECAPI_AVEncCommonRateControlMode,
+          eAVEncCommonRateControlMode_CBR.0 as u32,
+        ));
+        values.push((&CODECAPI_AVEncCommonMeanBitRate, bitrate));
+      }
+      RateControl::Vbr { max_bitrate } => {
+        let mode = match max_bitrate {
+          Some(max_bitrate) => {
+            if max_bitrate < bitrate {
+              return Err(Error::new(format!(
+                "Max bitrate {} is less than the bitrate {}",
+                max_bitrate, bitrate
+              )));
+            }
+            values.push((&CODECAPI_AVEncCommonMaxBitRate, max_bitrate));
+            eAVEncCommonRateControlMode_PeakConstrainedVBR
+          }
+          None => eAVEncCommonRateControlMode_UnconstrainedVBR,
+        };
+        values.push((&CODECAPI_AVEncCommonRateControlMode, mode.0 as u32));
+        values.push((&CODECAPI_AVEncCommonMeanBitRate, bitrate));
+      }
+      RateControl::Cqp { qp: value } => {
+        if value > MAX_QP {
+          return Err(Error::new(format!("QP {} is out of 0..={}", value, MAX_QP)));
+        }
+        values.push((
+          &CODECAPI_AVEncCommonRateControlMode,
+          eAVEncCommonRateControlMode_Quality.0 as u32,
+        ));
+        qp = Some(value);
+      }
+    }
+    if let Some(gop_size) = self.gop_size {
+      values.push((&CODECAPI_AVEncMPVGOPSize, gop_size));
+    }
+    if let Some(b_frames) = self.b_frames {
+      values.push((&CODECAPI_AVEncMPVDefaultBPictureCount, b_frames));
+    }
+    values.push((&CODECAPI_AVLowLatencyMode, self.low_latency as u32));
+
+    let mut attributes: Option<IMFAttributes> = None;
+    unsafe { MFCreateAttributes(&mut attributes, values.len() as u32 + 1) }
+      .map_err(|e| Error::windows("MFCreateAttributes", e))?;
+    let attributes = attributes.unwrap();
+    unsafe {
+      values
+        .iter()
+        .try_for_each(|(key, value)| attributes.SetUINT32(*key, *value))
+        .and_then(|_| match qp {
+          // the QP is a 64-bit value
+          Some(qp) => attributes.SetUINT64(&CODECAPI_AVEncVideoEncodeQP, qp as u64),
+          None => Ok(()),
+        })
+    }
+    .map_err(|e| Error::windows("IMFAttributes.Set", e))?;
+    Ok(attributes)
+  }
+}
+
+/// List the encoders of the codec, hardware encoders and better matches first.
+pub fn video_encoders(codec: VideoCodec) -> Result<Vec<EncoderInfo>> {
+  let output = MFT_REGISTER_TYPE_INFO {
+    guidMajorType: MFMediaType_Video,
+    guidSubtype: codec.subtype(),
+  };
+  let mut activates: *mut Option<IMFActivate> = ptr::null_mut();
+  let mut count = 0;
+  unsafe {
+    MFTEnumEx(
+      MFT_CATEGORY_VIDEO_ENCODER,
+      MFT_ENUM_FLAG(MFT_ENUM_FLAG_ALL.0 | MFT_ENUM_FLAG_SORTANDFILTER.0),
+      None,
+      Some(&output),
+      &mut activates,
+      &mut count,
+    )
+  }
+  .map_err(|e| Error::windows("MFTEnumEx", e))?;
+  if activates.is_null() {
+    return Ok(Vec::new());
+  }
+
+  // take the activates out of the array so they are released, then free the array
+  let activates: Vec<IMFActivate> = unsafe {
+    let taken = slice::from_raw_parts_mut(activates, count as usize)
+      .iter_mut()
+      .filter_map(Option::take)
+      .collect();
+    CoTaskMemFree(Some(activates as *const _));
+    taken
+  };
+  Ok(
+    activates
+      .iter()
+      .filter_map(|activate| activate.cast::<IMFAttributes>().ok())
+      .map(|attributes| encoder_info(&attributes))
+      .collect(),
+  )
+}
+
+/// The video encoder which a sink writer inserted for `stream`, `None` if it has none.
+pub(crate) fn stream_encoder(writer: &IMFSinkWriter, stream: u32) -> Option<EncoderInfo> {
+  let writer = writer.cast::<IMFSinkWriterEx>().ok()?;
+  (0..)
+    .map_while(|index| {
+      let mut category = GUID::default();
+      let mut transform = None;
+      unsafe { writer.GetTransformForStream(stream, index, Some(&mut category), &mut transform) }
+        .ok()?;
+      Some((category, transform))
+    })
+    .find(|(category, _)| *category == MFT_CATEGORY_VIDEO_ENCODER)
+    .and_then(|(_, transform)| transform)
+    .and_then(|transform| unsafe { transform.GetAttributes() }.ok())
+    .map(|attributes| encoder_info(&attributes))
+}
+
+fn encoder_info(attributes: &IMFAttributes) -> EncoderInfo {
+  let vendor_id = string_attribute(attributes, &MFT_ENUM_HARDWARE_VENDOR_ID_Attribute)
+    .and_then(|id| parse_vendor_id(&id));
+  let name = string_attribute(attributes, &MFT_FRIENDLY_NAME_Attribute)
+    .or_else(|| {
+      unsafe { attributes.GetGUID(&MFT_TRANSFORM_CLSID_Attribute) }
+        .ok()
+        .map(|clsid| format!("{:?}", clsid))
+    })
+    .unwrap_or_default();
+  EncoderInfo {
+    name,
+    vendor_id,
+    hardware: string_attribute(attributes, &MFT_ENUM_HARDWARE_URL_Attribute).is_some(),
+  }
+}
+
+/// Configure the sink writer attributes to use the selected encoder.
+pub(crate) fn configure(attributes: &IMFAttributes, selection: EncoderSelection) -> Result<()> {
+  let hardware = selection != EncoderSelection::Software;
+  unsafe { attributes.SetUINT32(&MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, hardware as u32) }
+    .map_err(|e| Error::windows("IMFAttributes.SetUINT32", e))?;
+  if let EncoderSelection::Hardware { vendor_id } = selection {
+    let manager = device_manager(&adapter_of_vendor(vendor_id)?)?;
+    unsafe { attributes.SetUnknown(&MF_SINK_WRITER_D3D_MANAGER, &manager) }
+      .map_err(|e| Error::windows("IMFAttributes.SetUnknown", e))?;
+  }
+  Ok(())
+}
+
+fn adapter_of_vendor(vendor_id: u32) -> Result<IDXGIAdapter1> {
+  let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>() }
+    .map_err(|e| Error::windows("CreateDXGIFactory1", e))?;
+  let mut index = 0;
+  while let Ok(adapter) = unsafe { factory.EnumAdapters1(index) } {
+    let mut desc = DXGI_ADAPTER_DESC1::default();
+    unsafe { adapter.GetDesc1(&mut desc) }.map_err(|e| Error::windows("GetDesc1", e))?;
+    if desc.VendorId == vendor_id {
+      return Ok(adapter);
+    }
+    index += 1;
+  }
+  Err(Error::new(format!("No GPU from vendor {:#06X}", vendor_id)))
+}
+
+/// Create a device manager on the adapter, so the sink writer uses its hardware encoder.
+fn device_manager(adapter: &IDXGIAdapter1) -> Result<IMFDXGIDeviceManager> {
+  let mut device: Option<ID3D11Device> = None;
+  unsafe {
+    D3D11CreateDevice(
+      adapter,
+      D3D_DRIVER_TYPE_UNKNOWN,
+      None,
+      D3D11_CREATE_DEVICE_VIDEO_SUPPORT | D3D11_CREATE_DEVICE_BGRA_SUPPORT,
+      None,
+      D3D11_SDK_VERSION,
+      Some(&mut device),
+      None,
+      None,
+    )
+  }
+  .map_err(|e| Error::windows("D3D11CreateDevice", e))?;
+  let device = device.unwrap();
+  // the device is used by the encoder threads
+  if let Ok(multithread) = device.cast::<ID3D11Multithread>() {
+    unsafe { multithread.SetMultithreadProtected(true) };
+  }
+
+  let mut token = 0;
+  let mut manager: Option<IMFDXGIDeviceManager> = None;
+  unsafe { MFCreateDXGIDeviceManager(&mut token, &mut manager) }
+    .map_err(|e| Error::windows("MFCreateDXGIDeviceManager", e))?;
+  let manager = manager.unwrap();
+  unsafe { manager.ResetDevice(&device, token) }
+    .map_err(|e| Error::windows("IMFDXGIDeviceManager.ResetDevice", e))?;
+  Ok(manager)
+}
+
+fn string_attribute(attributes: &IMFAttributes, key: &GUID) -> Option<String> {
+  let mut value = PWSTR::null();
+  let mut len = 0;
+  unsafe { attributes.GetAllocatedString(key, &mut value, &mut len) }.ok()?;
+  let string = unsafe { value.to_string() }.ok();
+  unsafe { CoTaskMemFree(Some(value.0 as *const _)) };
+  string
+}
+
+/// Parse vendor IDs like `VEN_10DE`.
+fn parse_vendor_id(id: &str) -> Option<u32> {
+  u32::from_str_radix(id.strip_prefix("VEN_")?, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    parse_vendor_id, video_encoders, EncoderSelection, EncoderSettings, RateControl, VideoCodec,
+  };
+  use windows::Win32::Media::MediaFoundation::{
+    eAVEncCommonRateControlMode_PeakConstrainedVBR, eAVEncCommonRateControlMode_Quality,
+    CODECAPI_AVEncCommonMaxBitRate, CODECAPI_AVEncCommonRateControlMode,
+    CODECAPI_AVEncMPVDefaultBPictureCount, CODECAPI_AVEncMPVGOPSize, CODECAPI_AVEncVideoEncodeQP,
+    CODECAPI_AVLowLatencyMode, MFShutdown, MFStartup, MFSTARTUP_FULL, MF_VERSION,
+  };
+
+  #[test]
+  fn vendor_ids() {
+    assert_eq!(parse_vendor_id("VEN_10DE"), Some(0x10DE));
+    assert_eq!(parse_vendor_id("VEN_8086"), Some(0x8086));
+    assert_eq!(parse_vendor_id("10DE"), None);
+  }
+
+  #[test]
+  fn parameters() {
+    let settings = EncoderSettings {
+      rate_control: RateControl::Vbr {
+        max_bitrate: Some(12_000_000),
+      },
+      gop_size: Some(60),
+      b_frames: Some(0),
+      low_latency: true,
+    };
+    let attributes = settings.parameters(8_000_000).unwrap();
+    unsafe {
+      assert_eq!(
+        attributes
+          .GetUINT32(&CODECAPI_AVEncCommonRateControlMode)
+          .unwrap(),
+        eAVEncCommonRateControlMode_PeakConstrainedVBR.0 as u32
+      );
+      assert_eq!(
+        attributes
+          .GetUINT32(&CODECAPI_AVEncCommonMaxBitRate)
+          .unwrap(),
+        12_000_000
+      );
+      assert_eq!(attributes.GetUINT32(&CODECAPI_AVEncMPVGOPSize).unwrap(), 60);
+      assert_eq!(
+        attributes
+          .GetUINT32(&CODECAPI_AVEncMPVDefaultBPictureCount)
+          .unwrap(),
+        0
+      );
+      assert_eq!(attributes.GetUINT32(&CODECAPI_AVLowLatencyMode).unwrap(), 1);
+    }
+
+    let settings = EncoderSettings {
+      rate_control: RateControl::Cqp { qp: 23 },
+      ..Default::default()
+    };
+    let attributes = settings.parameters(8_000_000).unwrap();
+    unsafe {
+      assert_eq!(
+        attributes
+          .GetUINT32(&CODECAPI_AVEncCommonRateControlMode)
+          .unwrap(),
+        eAVEncCommonRateControlMode_Quality.0 as u32
+      );
+      assert_eq!(
+        attributes.GetUINT64(&CODECAPI_AVEncVideoEncodeQP).unwrap(),
+        23
+      );
+      assert!(attributes.GetUINT32(&CODECAPI_AVEncMPVGOPSize).is_err());
+    }
+
+    let invalid = |rate_control| {
+      EncoderSettings {
+        rate_control,
+        ..Default::default()
+      }
+      .parameters(8_000_000)
+      .is_err()
+    };
+    assert!(invalid(RateControl::Cqp { qp: 52 }));
+    assert!(invalid(RateControl::Vbr {
+      max_bitrate: Some(1_000_000)
+    }));
+  }
+
+  #[test]
+  fn encoders() {
+    unsafe { MFStartup(MF_VERSION, MFSTARTUP_FULL) }.unwrap();
+
+    let encoders = video_encoders(VideoCodec::H264).unwrap();
+    // the software H.264 encoder is always available
+    let software = encoders.iter().find(|e| !e.hardware).unwrap();
+    assert!(!software.name.is_empty());
+    assert_eq!(software.selection(), EncoderSelection::Software);
+
+    unsafe { MFShutdown() }.unwrap();
+  }
+}
@@ -1,32 +1,587 @@
+use crate::model::{MonitorId, Result};
+use crate::utils::{AdapterDescExt, OutputDescExt};
+use std::cell::Cell;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use windows::core::HRESULT;
+use windows::Win32::Foundation::{
+  D3D11_ERROR_DEFERRED_CONTEXT_MAP_WITHOUT_INITIAL_DISCARD, D3D11_ERROR_FILE_NOT_FOUND,
+  D3D11_ERROR_TOO_MANY_UNIQUE_STATE_OBJECTS, D3D11_ERROR_TOO_MANY_UNIQUE_VIEW_OBJECTS,
+  E_ACCESSDENIED, E_FAIL, E_INVALIDARG, E_NOINTERFACE, E_NOTIMPL, E_OUTOFMEMORY, E_POINTER,
+  E_UNEXPECTED,
+};
+use windows::Win32::Graphics::Dxgi::{
+  IDXGIAdapter1, IDXGIOutput, DXGI_ADAPTER_DESC1, DXGI_ERROR_ACCESS_DENIED, DXGI_ERROR_ACCESS_LOST,
+  DXGI_ERROR_ALREADY_EXISTS, DXGI_ERROR_CANNOT_PROTECT_CONTENT, DXGI_ERROR_DEVICE_HUNG,
+  DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_DEVICE_RESET, DXGI_ERROR_DRIVER_INTERNAL_ERROR,
+  DXGI_ERROR_FRAME_STATISTICS_DISJOINT, DXGI_ERROR_GRAPHICS_VIDPN_SOURCE_IN_USE,
+  DXGI_ERROR_INVALID_CALL, DXGI_ERROR_MODE_CHANGE_IN_PROGRESS, DXGI_ERROR_MORE_DATA,
+  DXGI_ERROR_NAME_ALREADY_EXISTS, DXGI_ERROR_NONEXCLUSIVE, DXGI_ERROR_NOT_CURRENT,
+  DXGI_ERROR_NOT_CURRENTLY_AVAILABLE, DXGI_ERROR_NOT_FOUND, DXGI_ERROR_REMOTE_CLIENT_DISCONNECTED,
+  DXGI_ERROR_REMOTE_OUTOFMEMORY, DXGI_ERROR_RESTRICT_TO_OUTPUT_STALE,
+  DXGI_ERROR_SDK_COMPONENT_MISSING, DXGI_ERROR_SESSION_DISCONNECTED, DXGI_ERROR_UNSUPPORTED,
+  DXGI_ERROR_WAIT_TIMEOUT, DXGI_ERROR_WAS_STILL_DRAWING, DXGI_OUTPUT_DESC,
+};
+
 #[derive(Debug)]
 pub struct Error {
+  pub kind: ErrorKind,
   pub message: String,
   pub windows: Option<windows::core::Error>,
+  /// Which adapter/monitor caused the error, if known.
+  /// Boxed to keep `Result<T>` small.
+  pub context: Option<Box<ErrorContext>>,
+  /// Why the output couldn't be duplicated, if this error is from duplicating an output.
+  pub duplication: Option<DuplicationFailure>,
+}
+
+/// Classify errors so they can be handled without matching messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+  /// A windows API failed, see `Error.windows`.
+  Windows,
+  /// The output has a zero-sized mode or is detached, e.g. a headless dummy plug.
+  InactiveOutput,
+  /// Too many applications are duplicating the output,
+  /// `DuplicateOutput` failed with `DXGI_ERROR_NOT_CURRENTLY_AVAILABLE`.
+  DuplicationLimitReached,
+  /// Acquiring a frame timed out because the display is turned off, e.g. by power saving.
+  /// `Error.windows` is still `DXGI_ERROR_WAIT_TIMEOUT`. Only detected with the `power` feature.
+  DisplayOff,
+  /// See `Error.message`.
+  Other,
+}
+
+/// Why an output couldn't be duplicated, classified from the `DuplicateOutput` error code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicationFailure {
+  /// The output is detached or has a zero-sized desktop area.
+  InactiveOutput,
+  /// The adapter, driver or desktop mode doesn't support duplication,
+  /// e.g. a basic display driver, an indirect display or a non-DWM desktop (`DXGI_ERROR_UNSUPPORTED`).
+  Unsupported,
+  /// The device was not created on the adapter of the output, e.g. on hybrid GPU laptops (`E_INVALIDARG`).
+  WrongAdapter,
+  /// Too many applications are duplicating the output (`DXGI_ERROR_NOT_CURRENTLY_AVAILABLE`).
+  LimitReached,
+  /// The remote desktop session is disconnected (`DXGI_ERROR_SESSION_DISCONNECTED`).
+  RemoteSession,
+  /// The secure desktop is shown, e.g. UAC prompts or the lock screen,
+  /// or the process is not in the interactive session (`E_ACCESSDENIED`).
+  SecureDesktop,
+  /// The display mode is changing, e.g. during a fullscreen transition
+  /// (`DXGI_ERROR_ACCESS_LOST`, `DXGI_ERROR_MODE_CHANGE_IN_PROGRESS`).
+  ModeChange,
+  /// The device was removed or reset, e.g. by a driver update.
+  DeviceLost,
+  /// Any other error code.
+  Unknown,
+}
+
+/// What an application can do about a [`DuplicationFailure`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remediation {
+  /// Skip the output, it doesn't show a desktop.
+  SkipOutput,
+  /// Capture the output rect with [`GdiCapturer`](crate::gdi::GdiCapturer).
+  UseGdiCapture,
+  /// Create the device on the adapter which owns the output, e.g. with [`Manager`](crate::manager::Manager).
+  UseOutputAdapter,
+  /// Close other screen recording or sharing applications.
+  CloseOtherCaptures,
+  /// Reconnect the remote session, or capture in the console session.
+  ReconnectSession,
+  /// Retry after the user leaves the secure desktop. Services must capture from a process
+  /// in the interactive session attached to the input desktop.
+  WaitForDesktop,
+  /// Retry shortly.
+  Retry,
+  /// Recreate the device and retry.
+  RecreateDevice,
+  /// Nothing specific is known, see the error code.
+  None,
+}
+
+impl DuplicationFailure {
+  /// Classify a `DuplicateOutput` error code.
+  pub fn from_hresult(code: HRESULT) -> DuplicationFailure {
+    match code {
+      DXGI_ERROR_UNSUPPORTED => DuplicationFailure::Unsupported,
+      E_INVALIDARG => DuplicationFailure::WrongAdapter,
+      DXGI_ERROR_NOT_CURRENTLY_AVAILABLE => DuplicationFailure::LimitReached,
+      DXGI_ERROR_SESSION_DISCONNECTED => DuplicationFailure::RemoteSession,
+      E_ACCESSDENIED | DXGI_ERROR_ACCESS_DENIED => DuplicationFailure::SecureDesktop,
+      DXGI_ERROR_ACCESS_LOST | DXGI_ERROR_MODE_CHANGE_IN_PROGRESS => DuplicationFailure::ModeChange,
+      DXGI_ERROR_DEVICE_REMOVED | DXGI_ERROR_DEVICE_RESET | DXGI_ERROR_DEVICE_HUNG => {
+        DuplicationFailure::DeviceLost
+      }
+      _ => DuplicationFailure::Unknown,
+    }
+  }
+
+  pub fn remediation(&self) -> Remediation {
+    match self {
+      DuplicationFailure::InactiveOutput => Remediation::SkipOutput,
+      DuplicationFailure::Unsupported => Remediation::UseGdiCapture,
+      DuplicationFailure::WrongAdapter => Remediation::UseOutputAdapter,
+      DuplicationFailure::LimitReached => Remediation::CloseOtherCaptures,
+      DuplicationFailure::RemoteSession => Remediation::ReconnectSession,
+      DuplicationFailure::SecureDesktop => Remediation::WaitForDesktop,
+      DuplicationFailure::ModeChange => Remediation::Retry,
+      DuplicationFailure::DeviceLost => Remediation::RecreateDevice,
+      DuplicationFailure::Unknown => Remediation::None,
+    }
+  }
+}
+
+impl std::fmt::Display for DuplicationFailure {
+  fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fmt.write_str(match self {
+      DuplicationFailure::InactiveOutput => "the output is not showing a desktop",
+      DuplicationFailure::Unsupported => "the display driver doesn't support duplication",
+      DuplicationFailure::WrongAdapter => "the output belongs to another graphics adapter",
+      DuplicationFailure::LimitReached => "too many applications are capturing the screen",
+      DuplicationFailure::RemoteSession => "the remote desktop session is disconnected",
+      DuplicationFailure::SecureDesktop => "the secure desktop or another session is shown",
+      DuplicationFailure::ModeChange => "the display mode is changing",
+      DuplicationFailure::DeviceLost => "the graphics device was removed or reset",
+      DuplicationFailure::Unknown => "unknown reason",
+    })
+  }
+}
+
+impl std::fmt::Display for Remediation {
+  fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fmt.write_str(match self {
+      Remediation::SkipOutput => "skip this output",
+      Remediation::UseGdiCapture => "capture this output with GDI",
+      Remediation::UseOutputAdapter => "capture on the graphics adapter driving this output",
+      Remediation::CloseOtherCaptures => "close other screen recording or sharing applications",
+      Remediation::ReconnectSession => {
+        "reconnect the remote session or capture in the console session"
+      }
+      Remediation::WaitForDesktop => "retry after the lock screen or UAC prompt is closed",
+      Remediation::Retry => "retry shortly",
+      Remediation::RecreateDevice => "recreate the device and retry",
+      Remediation::None => "check the error code",
+    })
+  }
+}
+
+/// Describe the adapter and output which caused an error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+  pub monitor_id: MonitorId,
+  /// Adapter description, e.g. `NVIDIA GeForce RTX 3060`.
+  pub adapter: Option<String>,
+  pub adapter_luid: Option<i64>,
+  /// Output device name, e.g. `\\.\DISPLAY1`.
+  pub device_name: Option<String>,
+}
+
+impl ErrorContext {
+  /// Query adapter and output descriptions.
+  /// Fields which can't be retrieved are `None`.
+  pub(crate) fn collect(
+    monitor_id: MonitorId,
+    adapter: Option<&IDXGIAdapter1>,
+    output: Option<&IDXGIOutput>,
+  ) -> ErrorContext {
+    let adapter_desc = adapter.and_then(|adapter| {
+      let mut desc = DXGI_ADAPTER_DESC1::default();
+      unsafe { adapter.GetDesc1(&mut desc) }.ok().map(|_| desc)
+    });
+    let device_name = output.and_then(|output| {
+      let mut desc = DXGI_OUTPUT_DESC::default();
+      unsafe { output.GetDesc(&mut desc) }
+        .ok()
+        .map(|_| desc.device_name())
+    });
+    ErrorContext {
+      monitor_id,
+      adapter: adapter_desc.as_ref().map(|desc| desc.description()),
+      adapter_luid: adapter_desc.as_ref().map(|desc| desc.luid()),
+      device_name,
+    }
+  }
+}
+
+impl std::fmt::Display for ErrorContext {
+  fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    std::write!(
+      fmt,
+      "adapter {} output {}",
+      self.monitor_id.adapter,
+      self.monitor_id.output
+    )?;
+    if let Some(ref device_name) = self.device_name {
+      std::write!(fmt, ", {}", device_name)?;
+    }
+    if let Some(ref adapter) = self.adapter {
+      std::write!(fmt, ", {}", adapter)?;
+    }
+    if let Some(luid) = self.adapter_luid {
+      std::write!(fmt, ", LUID {:#x}", luid)?;
+    }
+    Ok(())
+  }
 }
 
 impl Error {
   pub fn new(message: impl Into<String>) -> Error {
+    Error::of_kind(ErrorKind::Other, message)
+  }
+
+  pub fn of_kind(kind: ErrorKind, message: impl Into<String>) -> Error {
     Error {
+      kind,
       message: message.into(),
       windows: None,
+      context: None,
+      duplication: None,
     }
   }
 
+  /// Report the failure to the hook set by [`set_error_hook`].
   pub fn windows(message: impl Into<String>, err: windows::core::Error) -> Error {
-    Error {
+    let error = Error {
+      kind: ErrorKind::Windows,
       message: message.into(),
       windows: Some(err),
-    }
+      context: None,
+      duplication: None,
+    };
+    report(&error.message, &error);
+    error
+  }
+
+  /// Like [`Error::windows`] with [`Error::with_context`],
+  /// but the hook also receives the monitor of the context.
+  pub(crate) fn windows_with_context(
+    message: impl Into<String>,
+    err: windows::core::Error,
+    context: ErrorContext,
+  ) -> Error {
+    let error = Error {
+      kind: ErrorKind::Windows,
+      message: message.into(),
+      windows: Some(err),
+      context: Some(Box::new(context)),
+      duplication: None,
+    };
+    report(&error.message, &error);
+    error
+  }
+
+  /// Like [`Error::windows_with_context`] with another kind and message,
+  /// the hook receives `api` as the failed API.
+  #[cfg(feature = "power")]
+  pub(crate) fn windows_of_kind(
+    kind: ErrorKind,
+    api: &str,
+    message: impl Into<String>,
+    err: windows::core::Error,
+    context: ErrorContext,
+  ) -> Error {
+    let error = Error {
+      kind,
+      message: message.into(),
+      windows: Some(err),
+      context: Some(Box::new(context)),
+      duplication: None,
+    };
+    report(api, &error);
+    error
+  }
+
+  /// Attach the adapter/monitor which caused this error.
+  pub fn with_context(mut self, context: ErrorContext) -> Error {
+    self.context = Some(Box::new(context));
+    self
+  }
+
+  /// What the application can do about a failed duplication, `None` for other errors.
+  pub fn remediation(&self) -> Option<Remediation> {
+    self.duplication.map(|failure| failure.remediation())
+  }
+
+  /// Return the symbolic name of the windows error code, e.g. `DXGI_ERROR_ACCESS_LOST`.
+  pub fn hresult_name(&self) -> Option<&'static str> {
+    self
+      .windows
+      .as_ref()
+      .and_then(|err| hresult_name(err.code()))
   }
 }
 
 impl std::fmt::Display for Error {
   fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
     match self.windows {
-      Some(ref err) => std::write!(fmt, "{} ({})", self.message, err),
-      None => std::write!(fmt, "{}", self.message),
+      Some(ref err) => match hresult_name(err.code()) {
+        Some(name) => std::write!(fmt, "{} ({}: {})", self.message, name, err)?,
+        None => std::write!(fmt, "{} ({})", self.message, err)?,
+      },
+      None => std::write!(fmt, "{}", self.message)?,
+    }
+    match self.context {
+      Some(ref context) => std::write!(fmt, " [{}]", context),
+      None => Ok(()),
     }
   }
 }
 
 impl std::error::Error for Error {}
+
+/// A failed windows API call, passed to the hook set by [`set_error_hook`].
+#[derive(Debug, Clone, Copy)]
+pub struct ApiFailure<'a> {
+  /// The failed API, e.g. `AcquireNextFrame`.
+  pub api: &'a str,
+  pub hresult: HRESULT,
+  /// The monitor being opened or captured, if known.
+  pub monitor_id: Option<MonitorId>,
+  pub time: SystemTime,
+}
+
+type ErrorHook = Arc<dyn Fn(&ApiFailure) + Send + Sync>;
+
+static ERROR_HOOK: RwLock<Option<ErrorHook>> = RwLock::new(None);
+
+/// Call `hook` for every failed windows API call before the error is returned,
+/// e.g. to feed failure telemetry into a metrics system. Replace the previous hook.
+///
+/// The hook is called on the failing thread and must not call into this crate.
+/// `DXGI_ERROR_WAIT_TIMEOUT` is not reported, it only means that no frame is ready,
+/// unless the display is turned off, see [`ErrorKind::DisplayOff`].
+/// Failures which are not returned, e.g. of adapters tried before the attached one, are not reported either.
+pub fn set_error_hook(hook: impl Fn(&ApiFailure) + Send + Sync + 'static) {
+  *ERROR_HOOK.write().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(hook));
+}
+
+/// Remove the hook set by [`set_error_hook`].
+pub fn clear_error_hook() {
+  *ERROR_HOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+thread_local! {
+  /// Whether the current thread runs a [`probe`], whose errors are not reported.
+  static PROBING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restore the previous [`PROBING`] state, also if the probe panics.
+struct ProbeGuard(bool);
+
+impl Drop for ProbeGuard {
+  fn drop(&mut self) {
+    PROBING.with(|probing| probing.set(self.0));
+  }
+}
+
+/// Run `f` without reporting its errors to the hook and discard the error,
+/// for attempts whose failures are expected, e.g. trying preferred adapters.
+pub(crate) fn probe<T>(f: impl FnOnce() -> Result<T>) -> Option<T> {
+  let _guard = ProbeGuard(PROBING.with(|probing| probing.replace(true)));
+  f().ok()
+}
+
+fn report(api: &str, error: &Error) {
+  let Some(err) = &error.windows else {
+    return;
+  };
+  if PROBING.with(Cell::get) {
+    return;
+  }
+  if err.code() == DXGI_ERROR_WAIT_TIMEOUT && error.kind != ErrorKind::DisplayOff {
+    return;
+  }
+  // clone the hook so it runs without holding the lock
+  let hook = ERROR_HOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+  if let Some(hook) = hook {
+    hook(&ApiFailure {
+      api,
+      hresult: err.code(),
+      monitor_id: error.context.as_ref().map(|context| context.monitor_id),
+      time: SystemTime::now(),
+    });
+  }
+}
+
+macro_rules! hresult_names {
+  ($($code:ident),* $(,)?) => {
+    &[$(($code, stringify!($code))),*]
+  };
+}
+
+/// Known DXGI/D3D11/COM error codes and their names.
+const HRESULT_NAMES: &[(HRESULT, &str)] = hresult_names![
+  DXGI_ERROR_ACCESS_DENIED,
+  DXGI_ERROR_ACCESS_LOST,
+  DXGI_ERROR_ALREADY_EXISTS,
+  DXGI_ERROR_CANNOT_PROTECT_CONTENT,
+  DXGI_ERROR_DEVICE_HUNG,
+  DXGI_ERROR_DEVICE_REMOVED,
+  DXGI_ERROR_DEVICE_RESET,
+  DXGI_ERROR_DRIVER_INTERNAL_ERROR,
+  DXGI_ERROR_FRAME_STATISTICS_DISJOINT,
+  DXGI_ERROR_GRAPHICS_VIDPN_SOURCE_IN_USE,
+  DXGI_ERROR_INVALID_CALL,
+  DXGI_ERROR_MODE_CHANGE_IN_PROGRESS,
+  DXGI_ERROR_MORE_DATA,
+  DXGI_ERROR_NAME_ALREADY_EXISTS,
+  DXGI_ERROR_NONEXCLUSIVE,
+  DXGI_ERROR_NOT_CURRENT,
+  DXGI_ERROR_NOT_CURRENTLY_AVAILABLE,
+  DXGI_ERROR_NOT_FOUND,
+  DXGI_ERROR_REMOTE_CLIENT_DISCONNECTED,
+  DXGI_ERROR_REMOTE_OUTOFMEMORY,
+  DXGI_ERROR_RESTRICT_TO_OUTPUT_STALE,
+  DXGI_ERROR_SDK_COMPONENT_MISSING,
+  DXGI_ERROR_SESSION_DISCONNECTED,
+  DXGI_ERROR_UNSUPPORTED,
+  DXGI_ERROR_WAIT_TIMEOUT,
+  DXGI_ERROR_WAS_STILL_DRAWING,
+  D3D11_ERROR_DEFERRED_CONTEXT_MAP_WITHOUT_INITIAL_DISCARD,
+  D3D11_ERROR_FILE_NOT_FOUND,
+  D3D11_ERROR_TOO_MANY_UNIQUE_STATE_OBJECTS,
+  D3D11_ERROR_TOO_MANY_UNIQUE_VIEW_OBJECTS,
+  E_ACCESSDENIED,
+  E_FAIL,
+  E_INVALIDARG,
+  E_NOINTERFACE,
+  E_NOTIMPL,
+  E_OUTOFMEMORY,
+  E_POINTER,
+  E_UNEXPECTED,
+];
+
+/// Return the symbolic name of a DXGI/D3D11/COM error code, e.g. `DXGI_ERROR_ACCESS_LOST`.
+pub fn hresult_name(code: HRESULT) -> Option<&'static str> {
+  HRESULT_NAMES
+    .iter()
+    .find(|(c, _)| *c == code)
+    .map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    clear_error_hook, hresult_name, probe, set_error_hook, DuplicationFailure, Error, ErrorContext,
+    ErrorKind, Remediation,
+  };
+  use crate::model::MonitorId;
+  use std::sync::{Arc, Mutex};
+  use windows::Win32::{
+    Foundation::E_ACCESSDENIED,
+    Graphics::Dxgi::{DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_UNSUPPORTED, DXGI_ERROR_WAIT_TIMEOUT},
+  };
+
+  #[test]
+  fn hresult_names() {
+    assert_eq!(
+      hresult_name(DXGI_ERROR_ACCESS_LOST),
+      Some("DXGI_ERROR_ACCESS_LOST")
+    );
+    assert_eq!(hresult_name(E_ACCESSDENIED), Some("E_ACCESSDENIED"));
+    assert_eq!(hresult_name(windows::core::HRESULT(0)), None);
+
+    let err = Error::windows("AcquireNextFrame", DXGI_ERROR_ACCESS_LOST.into());
+    assert_eq!(err.kind, ErrorKind::Windows);
+    assert_eq!(err.hresult_name(), Some("DXGI_ERROR_ACCESS_LOST"));
+    assert_eq!(Error::new("Invalid buffer length").hresult_name(), None);
+    assert_eq!(Error::new("Invalid buffer length").kind, ErrorKind::Other);
+  }
+
+  #[test]
+  fn duplication_failures() {
+    let failure = DuplicationFailure::from_hresult(DXGI_ERROR_UNSUPPORTED);
+    assert_eq!(failure, DuplicationFailure::Unsupported);
+    assert_eq!(failure.remediation(), Remediation::UseGdiCapture);
+    assert_eq!(
+      DuplicationFailure::from_hresult(E_ACCESSDENIED).remediation(),
+      Remediation::WaitForDesktop
+    );
+    assert_eq!(
+      DuplicationFailure::from_hresult(windows::core::HRESULT(-1)),
+      DuplicationFailure::Unknown
+    );
+
+    let mut err = Error::windows("DuplicateOutput", DXGI_ERROR_UNSUPPORTED.into());
+    assert_eq!(err.remediation(), None);
+    err.duplication = Some(failure);
+    assert_eq!(err.remediation(), Some(Remediation::UseGdiCapture));
+  }
+
+  #[test]
+  fn context() {
+    let context = ErrorContext {
+      monitor_id: MonitorId {
+        adapter: 0,
+        output: 1,
+      },
+      adapter: Some("Test Adapter".to_string()),
+      adapter_luid: Some(0x1234),
+      device_name: Some("\\\\.\\DISPLAY2".to_string()),
+    };
+    let err = Error::new("DuplicateOutput").with_context(context.clone());
+    assert_eq!(err.context.as_deref(), Some(&context));
+    assert_eq!(
+      err.to_string(),
+      "DuplicateOutput [adapter 0 output 1, \\\\.\\DISPLAY2, Test Adapter, LUID 0x1234]"
+    );
+  }
+
+  #[test]
+  fn error_hook() {
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let sink = failures.clone();
+    set_error_hook(move |failure| {
+      // other tests may fail API calls concurrently
+      if failure.api.starts_with("ErrorHookTest") {
+        sink
+          .lock()
+          .unwrap()
+          .push((failure.api.to_string(), failure.hresult, failure.monitor_id));
+      }
+    });
+
+    let monitor_id = MonitorId {
+      adapter: 0,
+      output: 1,
+    };
+    Error::windows("ErrorHookTest1", DXGI_ERROR_ACCESS_LOST.into());
+    Error::windows("ErrorHookTest2", DXGI_ERROR_WAIT_TIMEOUT.into());
+    Error::windows_with_context(
+      "ErrorHookTest3",
+      E_ACCESSDENIED.into(),
+      ErrorContext {
+        monitor_id,
+        adapter: None,
+        adapter_luid: None,
+        device_name: None,
+      },
+    );
+    Error::new("ErrorHookTest4");
+    // swallowed errors of probes are not reported
+    let probed = probe::<()>(|| {
+      Err(Error::windows(
+        "ErrorHookTest5",
+        DXGI_ERROR_ACCESS_LOST.into(),
+      ))
+    });
+    assert!(probed.is_none());
+    clear_error_hook();
+    Error::windows("ErrorHookTest6", DXGI_ERROR_ACCESS_LOST.into());
+
+    assert_eq!(
+      *failures.lock().unwrap(),
+      [
+        ("ErrorHookTest1".to_string(), DXGI_ERROR_ACCESS_LOST, None),
+        (
+          "ErrorHookTest3".to_string(),
+          E_ACCESSDENIED,
+          Some(monitor_id)
+        ),
+      ]
+    );
+  }
+}
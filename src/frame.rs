@@ -0,0 +1,251 @@
+use crate::color;
+use crate::duplication_context::DuplicationContext;
+use crate::model::{MonitorId, Provenance, Rect, Result};
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+use windows::Win32::Graphics::Dxgi::{Common::DXGI_FORMAT, DXGI_OUTDUPL_FRAME_INFO};
+
+/// An owned captured frame.
+///
+/// Create frames outside this crate with [`Frame::new`], fields may be added in minor versions.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Frame {
+  /// Pixel data in `format`, row by row without padding.
+  pub buffer: Vec<u8>,
+  pub width: u32,
+  pub height: u32,
+  pub info: DXGI_OUTDUPL_FRAME_INFO,
+  /// Usually `DXGI_FORMAT_B8G8R8A8_UNORM`, or `DXGI_FORMAT_B8G8R8A8_UNORM_SRGB`
+  /// if the bytes are marked as sRGB-encoded, see [`TextureOptions::srgb`](crate::model::TextureOptions::srgb).
+  pub format: DXGI_FORMAT,
+  /// Where the frame was captured, `None` for frames which weren't captured by this crate,
+  /// e.g. generated or read from a stream. Derived frames like previews keep the provenance.
+  pub provenance: Option<Provenance>,
+}
+
+impl Frame {
+  /// Create a frame without provenance, see [`Frame::with_provenance`].
+  pub fn new(
+    buffer: Vec<u8>,
+    width: u32,
+    height: u32,
+    info: DXGI_OUTDUPL_FRAME_INFO,
+    format: DXGI_FORMAT,
+  ) -> Self {
+    Self {
+      buffer,
+      width,
+      height,
+      info,
+      format,
+      provenance: None,
+    }
+  }
+
+  /// Set where the frame was captured, e.g. with a [`ProvenanceStamp`].
+  pub fn with_provenance(self, provenance: Option<Provenance>) -> Self {
+    Self { provenance, ..self }
+  }
+
+  /// View the buffer as `[b, g, r, a]` pixels, row by row. The frame must be 8-bit BGRA.
+  pub fn as_pixels(&self) -> &[[u8; 4]] {
+    // `[u8; 4]` has the alignment of `u8`, so any byte slice can be viewed as pixels
+    unsafe { slice::from_raw_parts(self.buffer.as_ptr().cast(), self.buffer.len() / 4) }
+  }
+
+  /// See [`Frame::as_pixels`].
+  pub fn as_pixels_mut(&mut self) -> &mut [[u8; 4]] {
+    unsafe { slice::from_raw_parts_mut(self.buffer.as_mut_ptr().cast(), self.buffer.len() / 4) }
+  }
+
+  /// View the buffer as one `u32` per pixel, or `None` if the buffer is not 4-byte aligned.
+  /// The frame must be 8-bit BGRA.
+  ///
+  /// The bytes are read in native byte order, so on Windows (little-endian)
+  /// a pixel is `0xAARRGGBB`, e.g. `0xFFFF0000` is opaque red.
+  pub fn as_u32(&self) -> Option<&[u32]> {
+    let (prefix, pixels, _) = unsafe { self.buffer.align_to::<u32>() };
+    prefix.is_empty().then_some(pixels)
+  }
+
+  /// Iterate the rows of `[b, g, r, a]` pixels from top to bottom. The frame must be 8-bit BGRA.
+  pub fn rows(&self) -> impl Iterator<Item = &[[u8; 4]]> {
+    self.as_pixels().chunks_exact(self.width.max(1) as usize)
+  }
+
+  /// Iterate `(x, y, [b, g, r, a])` row by row. The frame must be 8-bit BGRA.
+  pub fn pixels(&self) -> impl Iterator<Item = (u32, u32, [u8; 4])> + '_ {
+    self.rows().enumerate().flat_map(|(y, row)| {
+      row
+        .iter()
+        .enumerate()
+        .map(move |(x, &pixel)| (x as u32, y as u32, pixel))
+    })
+  }
+
+  /// Get the `[b, g, r, a]` pixel at (`x`, `y`), or `None` if it is outside the frame.
+  /// Return an error if the frame is not 8-bit BGRA.
+  pub fn pixel_at(&self, x: u32, y: u32) -> Result<Option<[u8; 4]>> {
+    color::check_bgra8(self.format, "read pixels")?;
+    Ok(color::pixel_at(&self.buffer, self.width, x, y))
+  }
+
+  /// Average the pixels of `rect` as `[b, g, r, a]`, or `None` if `rect` is outside the frame.
+  /// Return an error if the frame is not 8-bit BGRA.
+  pub fn average_color(&self, rect: &Rect) -> Result<Option<[u8; 4]>> {
+    color::check_bgra8(self.format, "average pixels")?;
+    Ok(color::average_color(&self.buffer, self.width, rect, 1))
+  }
+
+  /// The time since the frame was captured, or `None` without provenance.
+  /// Zero if the system clock was set back since.
+  pub fn age(&self) -> Option<Duration> {
+    self.provenance.map(|provenance| {
+      SystemTime::now()
+        .duration_since(provenance.captured_at)
+        .unwrap_or_default()
+    })
+  }
+
+  /// Return `true` if the frame is older than `max_age`. Frames without provenance are never stale.
+  pub fn is_stale(&self, max_age: Duration) -> bool {
+    self.age().is_some_and(|age| age > max_age)
+  }
+}
+
+/// The source id of the next [`ProvenanceStamp`].
+static NEXT_SOURCE: AtomicU64 = AtomicU64::new(0);
+
+/// Create the [`Provenance`] of each frame captured by one source.
+#[derive(Debug, Clone)]
+pub struct ProvenanceStamp {
+  monitor: MonitorId,
+  adapter_luid: i64,
+  source: u64,
+  sequence: u64,
+}
+
+impl ProvenanceStamp {
+  /// Start a new source capturing from the context.
+  pub fn new(ctx: &DuplicationContext) -> Result<Self> {
+    Ok(Self::with_adapter(ctx.id(), ctx.adapter_luid()?))
+  }
+
+  fn with_adapter(monitor: MonitorId, adapter_luid: i64) -> Self {
+    Self {
+      monitor,
+      adapter_luid,
+      source: NEXT_SOURCE.fetch_add(1, Ordering::Relaxed),
+      sequence: 0,
+    }
+  }
+
+  /// Continue the source with a recreated context, keeping the source id and the sequence.
+  pub fn rebind(&mut self, ctx: &DuplicationContext) -> Result<()> {
+    self.adapter_luid = ctx.adapter_luid()?;
+    self.monitor = ctx.id();
+    Ok(())
+  }
+
+  pub fn source(&self) -> u64 {
+    self.source
+  }
+
+  /// The provenance of the next frame, captured now.
+  pub fn stamp(&mut self) -> Provenance {
+    let sequence = self.sequence;
+    self.sequence += 1;
+    Provenance {
+      monitor: self.monitor,
+      adapter_luid: self.adapter_luid,
+      source: self.source,
+      sequence,
+      captured_at: SystemTime::now(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::ProvenanceStamp;
+  use crate::model::{MonitorId, Rect};
+  use crate::test_utils::generate;
+  use std::time::Duration;
+  use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R16G16B16A16_FLOAT;
+
+  #[test]
+  fn pixel_views() {
+    let mut frame = generate(3, 2, |x, y| [x as u8, y as u8, 0xA0]);
+    assert_eq!(frame.as_pixels().len(), 6);
+    assert_eq!(frame.as_pixels()[4], [1, 1, 0xA0, 0xFF]);
+    if let Some(pixels) = frame.as_u32() {
+      assert_eq!(pixels[4], 0xFFA00101);
+    }
+
+    let rows: Vec<_> = frame.rows().collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1][2], [2, 1, 0xA0, 0xFF]);
+    assert_eq!(frame.pixels().nth(5), Some((2, 1, [2, 1, 0xA0, 0xFF])));
+    assert!(frame
+      .pixels()
+      .all(|(x, y, pixel)| frame.pixel_at(x, y).unwrap() == Some(pixel)));
+
+    frame.as_pixels_mut()[0] = [1, 2, 3, 4];
+    assert_eq!(frame.buffer[..4], [1, 2, 3, 4]);
+
+    // FP16 pixels can't be read as BGRA
+    frame.format = DXGI_FORMAT_R16G16B16A16_FLOAT;
+    assert!(frame.pixel_at(0, 0).is_err());
+    assert!(frame.average_color(&Rect::new(0, 0, 1, 1)).is_err());
+  }
+
+  #[test]
+  fn provenance() {
+    let monitor = MonitorId {
+      adapter: 0,
+      output: 1,
+    };
+    let mut first = ProvenanceStamp::with_adapter(monitor, 0x1234);
+    let mut second = ProvenanceStamp::with_adapter(monitor, 0x1234);
+    assert_ne!(first.source(), second.source());
+
+    let a = first.stamp();
+    let b = first.stamp();
+    assert_eq!((a.sequence, b.sequence), (0, 1));
+    assert_eq!(
+      (a.monitor, a.adapter_luid, a.source),
+      (monitor, 0x1234, first.source())
+    );
+    assert!(b.captured_at >= a.captured_at);
+    assert_eq!(second.stamp().sequence, 0);
+
+    // derived frames keep the provenance
+    let mut frame = generate(8, 8, |_, _| [0; 3]);
+    assert_eq!(frame.provenance, None);
+    frame.provenance = Some(b);
+    assert_eq!(frame.preview(2).provenance, Some(b));
+  }
+
+  #[test]
+  fn age() {
+    let mut frame = generate(1, 1, |_, _| [0; 3]);
+    assert_eq!(frame.age(), None);
+    assert!(!frame.is_stale(Duration::ZERO));
+
+    let mut provenance = ProvenanceStamp::with_adapter(
+      MonitorId {
+        adapter: 0,
+        output: 0,
+      },
+      0,
+    )
+    .stamp();
+    provenance.captured_at -= Duration::from_secs(2);
+    frame.provenance = Some(provenance);
+    assert!(frame.age().unwrap() >= Duration::from_secs(2));
+    assert!(frame.is_stale(Duration::from_secs(1)));
+    assert!(!frame.is_stale(Duration::from_secs(60)));
+  }
+}
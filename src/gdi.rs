@@ -0,0 +1,153 @@
+use crate::error::Error;
+use crate::model::{Rect, Result};
+use windows::Win32::Foundation::HWND;
+use windows::Win32::Graphics::Gdi::{
+  BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, CreatedHDC, DeleteDC, DeleteObject, GetDC,
+  GetDIBits, ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB, CAPTUREBLT,
+  DIB_RGB_COLORS, HBITMAP, HDC, HGDIOBJ, SRCCOPY,
+};
+
+/// Capture a desktop area with GDI `BitBlt`.
+///
+/// This is the compatibility path for outputs which can't be duplicated,
+/// e.g. indirect displays of USB docks or virtual display drivers on software adapters,
+/// see `Manager.unsupported`.
+/// It is much slower than desktop duplication and provides no frame info or pointer shape.
+pub struct GdiCapturer {
+  rect: Rect,
+  buffer: Vec<u8>,
+  screen_dc: HDC,
+  mem_dc: CreatedHDC,
+  bitmap: HBITMAP,
+  old_bitmap: HGDIOBJ,
+}
+
+impl GdiCapturer {
+  /// Create a capturer for the `rect` in virtual desktop coordinates.
+  pub fn new(rect: Rect) -> Result<Self> {
+    if rect.is_empty() {
+      return Err(Error::new("Empty region"));
+    }
+
+    unsafe {
+      let screen_dc = GetDC(HWND(0));
+      if screen_dc.is_invalid() {
+        return Err(Error::new("GetDC"));
+      }
+      let mem_dc = CreateCompatibleDC(screen_dc);
+      if mem_dc.is_invalid() {
+        ReleaseDC(HWND(0), screen_dc);
+        return Err(Error::new("CreateCompatibleDC"));
+      }
+      let bitmap = CreateCompatibleBitmap(screen_dc, rect.width() as i32, rect.height() as i32);
+      if bitmap.is_invalid() {
+        DeleteDC(mem_dc);
+        ReleaseDC(HWND(0), screen_dc);
+        return Err(Error::new("CreateCompatibleBitmap"));
+      }
+      let old_bitmap = SelectObject(mem_dc, bitmap);
+
+      Ok(Self {
+        rect,
+        buffer: vec![0u8; rect.width() as usize * rect.height() as usize * 4],
+        screen_dc,
+        mem_dc,
+        bitmap,
+        old_bitmap,
+      })
+    }
+  }
+
+  pub fn rect(&self) -> &Rect {
+    &self.rect
+  }
+
+  /// Get the buffer of the last captured frame.
+  /// The buffer is in BGRA32 format.
+  pub fn buffer(&self) -> &[u8] {
+    &self.buffer
+  }
+
+  /// Capture the area. The pixel data is stored in the `buffer`.
+  pub fn capture(&mut self) -> Result<()> {
+    let width = self.rect.width() as i32;
+    let height = self.rect.height() as i32;
+
+    unsafe {
+      if !BitBlt(
+        self.mem_dc,
+        0,
+        0,
+        width,
+        height,
+        self.screen_dc,
+        self.rect.left,
+        self.rect.top,
+        SRCCOPY | CAPTUREBLT,
+      )
+      .as_bool()
+      {
+        return Err(Error::new("BitBlt"));
+      }
+
+      let mut info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+          biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+          biWidth: width,
+          biHeight: -height, // top-down, same row order as desktop duplication
+          biPlanes: 1,
+          biBitCount: 32,
+          biCompression: BI_RGB.0 as u32,
+          ..Default::default()
+        },
+        ..Default::default()
+      };
+      if GetDIBits(
+        self.mem_dc,
+        self.bitmap,
+        0,
+        height as u32,
+        Some(self.buffer.as_mut_ptr() as *mut _),
+        &mut info,
+        DIB_RGB_COLORS,
+      ) == 0
+      {
+        return Err(Error::new("GetDIBits"));
+      }
+    }
+
+    // GDI leaves the alpha channel zeroed, make it opaque like desktop duplication does
+    for pixel in self.buffer.chunks_exact_mut(4) {
+      pixel[3] = 0xFF;
+    }
+
+    Ok(())
+  }
+}
+
+impl Drop for GdiCapturer {
+  fn drop(&mut self) {
+    unsafe {
+      SelectObject(self.mem_dc, self.old_bitmap);
+      DeleteObject(self.bitmap);
+      DeleteDC(self.mem_dc);
+      ReleaseDC(HWND(0), self.screen_dc);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::GdiCapturer;
+  use crate::model::Rect;
+
+  #[test]
+  fn gdi_capturer() {
+    assert!(GdiCapturer::new(Rect::new(0, 0, 0, 0)).is_err());
+
+    let mut capturer = GdiCapturer::new(Rect::new(0, 0, 100, 100)).unwrap();
+    capturer.capture().unwrap();
+    assert_eq!(capturer.buffer().len(), 100 * 100 * 4);
+    assert!(capturer.buffer().chunks_exact(4).all(|p| p[3] == 0xFF));
+  }
+}
@@ -0,0 +1,256 @@
+//! Draw click highlights and cursor trails onto frames, e.g. for tutorial recordings.
+//!
+//! DXGI doesn't report clicks, so the application feeds [`InputEvent`]s from its own input handling
+//! or a low-level mouse hook. Events are matched to frames by QPC time, see [`CursorHighlighter::apply`].
+
+use crate::duplication_context::{qpc_duration, qpc_now};
+use crate::frame::Frame;
+use crate::model::{InputEvent, InputKind, MouseButton, Point};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// How [`CursorHighlighter`] draws. Colors are BGRA, the alpha is the opacity of new marks.
+#[derive(Debug, Clone)]
+pub struct HighlightOptions {
+  /// The virtual desktop position of the top-left pixel of frames, e.g. the monitor position.
+  pub origin: Point,
+  /// How long positions stay in the trail. Zero disables the trail.
+  pub trail: Duration,
+  pub trail_radius: u32,
+  pub trail_color: [u8; 4],
+  /// How long a click is highlighted. Zero disables click highlights.
+  pub click: Duration,
+  /// The radius of a click ring when it fades out, it starts at half of it.
+  pub click_radius: u32,
+  pub left_color: [u8; 4],
+  pub right_color: [u8; 4],
+  pub middle_color: [u8; 4],
+}
+
+impl Default for HighlightOptions {
+  fn default() -> Self {
+    Self {
+      origin: Point::default(),
+      trail: Duration::from_millis(300),
+      trail_radius: 4,
+      trail_color: [0x00, 0xD7, 0xFF, 0x80],
+      click: Duration::from_millis(500),
+      click_radius: 24,
+      left_color: [0x00, 0xD7, 0xFF, 0xC0],
+      right_color: [0xFF, 0x90, 0x1E, 0xC0],
+      middle_color: [0x00, 0xFF, 0x7F, 0xC0],
+    }
+  }
+}
+
+/// Keep recent [`InputEvent`]s and draw them onto frames presented after them.
+#[derive(Debug, Clone)]
+pub struct CursorHighlighter {
+  pub options: HighlightOptions,
+  /// Ordered by time.
+  events: VecDeque<InputEvent>,
+  frequency: i64,
+}
+
+impl CursorHighlighter {
+  pub fn new(options: HighlightOptions) -> Self {
+    Self::with_frequency(options, qpc_now().1)
+  }
+
+  fn with_frequency(options: HighlightOptions, frequency: i64) -> Self {
+    Self {
+      options,
+      events: VecDeque::new(),
+      frequency,
+    }
+  }
+
+  /// How many events are kept.
+  pub fn len(&self) -> usize {
+    self.events.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.events.is_empty()
+  }
+
+  pub fn clear(&mut self) {
+    self.events.clear();
+  }
+
+  /// Add an event. Events may arrive slightly out of order, e.g. from several threads.
+  pub fn push(&mut self, event: InputEvent) {
+    let index = self.events.partition_point(|e| e.time <= event.time);
+    self.events.insert(index, event);
+  }
+
+  /// Draw the events before the frame's `LastPresentTime`, or before now if the frame has none,
+  /// and forget events too old for later frames.
+  pub fn apply(&mut self, frame: &mut Frame) {
+    let time = match frame.info.LastPresentTime {
+      0 => qpc_now().0,
+      time => time,
+    };
+    self.apply_at(frame, time);
+  }
+
+  /// Draw the events as they look at the QPC `time`.
+  pub fn apply_at(&mut self, frame: &mut Frame, time: i64) {
+    let frequency = self.frequency;
+    let age = |event: &InputEvent| qpc_duration(time - event.time, frequency);
+    let keep = self.options.trail.max(self.options.click);
+    while self.events.front().is_some_and(|e| age(e) > keep) {
+      self.events.pop_front();
+    }
+
+    let end = self.events.partition_point(|e| e.time <= time);
+    let origin = self.options.origin;
+    let local = |p: Point| Point::new(p.x - origin.x, p.y - origin.y);
+
+    // the trail connects positions from the oldest to the newest, fading with age
+    if !self.options.trail.is_zero() {
+      let radius = self.options.trail_radius.max(1) as i32;
+      let trail: Vec<_> = self
+        .events
+        .range(..end)
+        .filter(|e| age(e) <= self.options.trail)
+        .collect();
+      for pair in trail.windows(2) {
+        let fade = 1.0 - age(pair[1]).as_secs_f64() / self.options.trail.as_secs_f64();
+        let color = faded(self.options.trail_color, fade);
+        let (from, to) = (local(pair[0].position), local(pair[1].position));
+        let steps = ((to.x - from.x).abs().max((to.y - from.y).abs()) / radius).max(1);
+        for step in 1..=steps {
+          let center = Point::new(
+            from.x + (to.x - from.x) * step / steps,
+            from.y + (to.y - from.y) * step / steps,
+          );
+          draw_circle(frame, center, radius, 0, color);
+        }
+      }
+    }
+
+    // clicks are rings growing from half the radius while fading out
+    if !self.options.click.is_zero() {
+      let full = self.options.click_radius.max(2) as f64;
+      for event in self.events.range(..end) {
+        let InputKind::Down(button) = event.kind else {
+          continue;
+        };
+        let progress = age(event).as_secs_f64() / self.options.click.as_secs_f64();
+        if progress > 1.0 {
+          continue;
+        }
+        let color = match button {
+          MouseButton::Left => self.options.left_color,
+          MouseButton::Right => self.options.right_color,
+          MouseButton::Middle => self.options.middle_color,
+        };
+        let radius = (full * (0.5 + progress / 2.0)) as i32;
+        let thickness = (full / 8.0).max(1.0) as i32;
+        draw_circle(
+          frame,
+          local(event.position),
+          radius,
+          radius - thickness,
+          faded(color, 1.0 - progress),
+        );
+      }
+    }
+  }
+}
+
+/// Scale the alpha of a BGRA color by `fade` in `[0, 1]`.
+fn faded(color: [u8; 4], fade: f64) -> [u8; 4] {
+  let [b, g, r, a] = color;
+  [b, g, r, (a as f64 * fade.clamp(0.0, 1.0)).round() as u8]
+}
+
+/// Blend a BGRA color onto the pixels between `inner` (exclusive) and `outer` (inclusive) radius
+/// around `center`, clipped to the frame. An `inner` radius of zero fills the whole disc.
+fn draw_circle(frame: &mut Frame, center: Point, outer: i32, inner: i32, color: [u8; 4]) {
+  let alpha = color[3] as u32;
+  if alpha == 0 {
+    return;
+  }
+  let (width, height) = (frame.width as i32, frame.height as i32);
+  for y in (center.y - outer).max(0)..(center.y + outer + 1).min(height) {
+    for x in (center.x - outer).max(0)..(center.x + outer + 1).min(width) {
+      let distance = (x - center.x).pow(2) + (y - center.y).pow(2);
+      if distance > outer * outer || (inner > 0 && distance <= inner * inner) {
+        continue;
+      }
+      let offset = (y as usize * frame.width as usize + x as usize) * 4;
+      let Some(pixel) = frame.buffer.get_mut(offset..offset + 4) else {
+        return;
+      };
+      for channel in 0..3 {
+        pixel[channel] =
+          ((color[channel] as u32 * alpha + pixel[channel] as u32 * (255 - alpha) + 127) / 255)
+            as u8;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{CursorHighlighter, HighlightOptions};
+  use crate::model::{InputEvent, InputKind, MouseButton, Point};
+  use crate::test_utils::generate;
+  use std::time::Duration;
+
+  fn event(time: i64, x: i32, y: i32, kind: InputKind) -> InputEvent {
+    InputEvent {
+      time,
+      position: Point::new(x, y),
+      kind,
+    }
+  }
+
+  fn black() -> crate::frame::Frame {
+    generate(64, 64, |_, _| [0, 0, 0])
+  }
+
+  #[test]
+  fn highlights() {
+    // one QPC tick per millisecond, frames start at (100, 0) on the desktop
+    let options = HighlightOptions {
+      origin: Point::new(100, 0),
+      trail_color: [0xFF, 0, 0, 0xFF],
+      left_color: [0, 0xFF, 0, 0xFF],
+      click_radius: 8,
+      ..Default::default()
+    };
+    let mut highlighter = CursorHighlighter::with_frequency(options, 1000);
+    highlighter.push(event(100, 110, 10, InputKind::Move));
+    highlighter.push(event(200, 140, 10, InputKind::Down(MouseButton::Left)));
+    // out of order
+    highlighter.push(event(150, 130, 10, InputKind::Move));
+    highlighter.push(event(900, 150, 30, InputKind::Move));
+    assert_eq!(highlighter.len(), 4);
+
+    let mut frame = black();
+    highlighter.apply_at(&mut frame, 200);
+    // the trail is drawn between the positions, the click ring around the click
+    assert_ne!(frame.pixel_at(20, 10).unwrap().unwrap()[0], 0);
+    assert_ne!(frame.pixel_at(40, 6).unwrap().unwrap()[1], 0);
+    assert_eq!(frame.pixel_at(40, 0).unwrap().unwrap(), [0, 0, 0, 0xFF]);
+    // events after the frame are not drawn yet
+    assert_eq!(frame.pixel_at(50, 30).unwrap().unwrap(), [0, 0, 0, 0xFF]);
+
+    // old events fade out and are forgotten
+    let mut frame = black();
+    highlighter.apply_at(&mut frame, 2000);
+    assert!(highlighter.is_empty());
+    assert_eq!(frame.buffer, black().buffer);
+
+    // disabled highlights draw nothing
+    highlighter.options.trail = Duration::ZERO;
+    highlighter.options.click = Duration::ZERO;
+    highlighter.push(event(0, 110, 10, InputKind::Down(MouseButton::Right)));
+    let mut frame = black();
+    highlighter.apply_at(&mut frame, 10);
+    assert_eq!(frame.buffer, black().buffer);
+  }
+}
@@ -0,0 +1,188 @@
+//! Save frames as PNG or JPEG files with the Windows Imaging Component.
+//! Enable the `image` feature to use this module.
+
+use crate::com::ComGuard;
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::model::Result;
+use std::path::Path;
+use windows::core::{GUID, HSTRING};
+use windows::Win32::Foundation::GENERIC_WRITE;
+use windows::Win32::Graphics::Dxgi::Common::{
+  DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_B8G8R8A8_UNORM_SRGB,
+};
+use windows::Win32::Graphics::Imaging::{
+  CLSID_WICImagingFactory, GUID_ContainerFormatJpeg, GUID_ContainerFormatPng,
+  GUID_WICPixelFormat24bppBGR, IWICImagingFactory, WICBitmapEncoderNoCache,
+};
+use windows::Win32::System::Com::{CoCreateInstance, CLSCTX_INPROC_SERVER};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+  Png,
+  Jpeg,
+}
+
+impl ImageFormat {
+  /// Guess the format by the file extension, e.g. `png`, `jpg` or `jpeg`.
+  pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+    let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+      "png" => Some(ImageFormat::Png),
+      "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+      _ => None,
+    }
+  }
+
+  /// The file extension without the dot.
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ImageFormat::Png => "png",
+      ImageFormat::Jpeg => "jpg",
+    }
+  }
+
+  fn container(&self) -> GUID {
+    match self {
+      ImageFormat::Png => GUID_ContainerFormatPng,
+      ImageFormat::Jpeg => GUID_ContainerFormatJpeg,
+    }
+  }
+}
+
+impl Frame {
+  /// Save the frame in the format of the file extension, see [`ImageFormat::from_path`].
+  pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let format = ImageFormat::from_path(path)
+      .ok_or_else(|| Error::new(format!("Unknown image format of {:?}", path)))?;
+    self.save_as(path, format)
+  }
+
+  /// Save the frame as an opaque image, the alpha channel is dropped.
+  /// Only 8-bit BGRA frames are supported.
+  /// COM is initialized for the current thread if it is not yet.
+  pub fn save_as(&self, path: impl AsRef<Path>, format: ImageFormat) -> Result<()> {
+    if self.format != DXGI_FORMAT_B8G8R8A8_UNORM && self.format != DXGI_FORMAT_B8G8R8A8_UNORM_SRGB {
+      return Err(Error::new(format!(
+        "Can't save frames of format {:?}",
+        self.format
+      )));
+    }
+    let pixels = bgra_to_bgr(&self.buffer);
+
+    let _com = ComGuard::new();
+    let factory: IWICImagingFactory =
+      unsafe { CoCreateInstance(&CLSID_WICImagingFactory, None, CLSCTX_INPROC_SERVER) }
+        .map_err(|e| Error::windows("CoCreateInstance.WICImagingFactory", e))?;
+
+    let stream =
+      unsafe { factory.CreateStream() }.map_err(|e| Error::windows("CreateStream", e))?;
+    let url = HSTRING::from(path.as_ref().to_string_lossy().as_ref());
+    unsafe { stream.InitializeFromFilename(&url, GENERIC_WRITE.0) }
+      .map_err(|e| Error::windows("IWICStream.InitializeFromFilename", e))?;
+    let encoder = unsafe { factory.CreateEncoder(&format.container(), std::ptr::null()) }
+      .map_err(|e| Error::windows("CreateEncoder", e))?;
+    unsafe { encoder.Initialize(&stream, WICBitmapEncoderNoCache) }
+      .map_err(|e| Error::windows("IWICBitmapEncoder.Initialize", e))?;
+
+    let mut frame = None;
+    let mut options = None;
+    unsafe { encoder.CreateNewFrame(&mut frame, &mut options) }
+      .map_err(|e| Error::windows("CreateNewFrame", e))?;
+    let frame = frame.unwrap();
+    let mut pixel_format = GUID_WICPixelFormat24bppBGR;
+    unsafe {
+      frame
+        .Initialize(options.as_ref())
+        .and_then(|_| frame.SetSize(self.width, self.height))
+        .and_then(|_| frame.SetPixelFormat(&mut pixel_format))
+    }
+    .map_err(|e| Error::windows("IWICBitmapFrameEncode.Initialize", e))?;
+    // the encoder replaces the pixel format if it doesn't support it
+    if pixel_format != GUID_WICPixelFormat24bppBGR {
+      return Err(Error::new("The encoder doesn't support 24-bit BGR"));
+    }
+
+    unsafe {
+      frame
+        .WritePixels(self.height, self.width * 3, &pixels)
+        .and_then(|_| frame.Commit())
+        .and_then(|_| encoder.Commit())
+    }
+    .map_err(|e| Error::windows("IWICBitmapFrameEncode.WritePixels", e))
+  }
+}
+
+/// Drop the alpha channel of BGRA32 pixels.
+#[cfg(target_arch = "aarch64")]
+fn bgra_to_bgr(src: &[u8]) -> Vec<u8> {
+  use std::arch::aarch64::{uint8x16x3_t, vld4q_u8, vst3q_u8};
+
+  let mut dest = Vec::with_capacity(src.len() / 4 * 3);
+  let chunks = src.chunks_exact(64);
+  let rest = chunks.remainder();
+  // NEON is always available on aarch64.
+  // Load 16 pixels split into channels and store the first 3 channels interleaved.
+  let mut bgr = [0u8; 48];
+  for chunk in chunks {
+    unsafe {
+      let v = vld4q_u8(chunk.as_ptr());
+      vst3q_u8(bgr.as_mut_ptr(), uint8x16x3_t(v.0, v.1, v.2));
+    }
+    dest.extend_from_slice(&bgr);
+  }
+  bgra_to_bgr_scalar(rest, &mut dest);
+  dest
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+fn bgra_to_bgr(src: &[u8]) -> Vec<u8> {
+  let mut dest = Vec::with_capacity(src.len() / 4 * 3);
+  bgra_to_bgr_scalar(src, &mut dest);
+  dest
+}
+
+fn bgra_to_bgr_scalar(src: &[u8], dest: &mut Vec<u8>) {
+  dest.extend(
+    src
+      .chunks_exact(4)
+      .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]),
+  );
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{bgra_to_bgr, bgra_to_bgr_scalar, ImageFormat};
+  use crate::test_utils::{gradient, noise};
+
+  #[test]
+  fn formats() {
+    assert_eq!(ImageFormat::from_path("a.png"), Some(ImageFormat::Png));
+    assert_eq!(ImageFormat::from_path("a.JPEG"), Some(ImageFormat::Jpeg));
+    assert_eq!(ImageFormat::from_path("a.bmp"), None);
+    assert_eq!(ImageFormat::from_path("png"), None);
+    assert_eq!(bgra_to_bgr(&[1, 2, 3, 4, 5, 6, 7, 8]), [1, 2, 3, 5, 6, 7]);
+
+    // an odd pixel count to cover the remainder of the SIMD path
+    let frame = noise(37, 1, 42);
+    let mut scalar = Vec::new();
+    bgra_to_bgr_scalar(&frame.buffer, &mut scalar);
+    assert_eq!(bgra_to_bgr(&frame.buffer), scalar);
+  }
+
+  #[test]
+  fn save() {
+    let frame = gradient(64, 32);
+    for (name, signature) in [
+      ("rusty-duplication-image.png", &b"\x89PNG"[..]),
+      ("rusty-duplication-image.jpg", &b"\xFF\xD8"[..]),
+    ] {
+      let path = std::env::temp_dir().join(name);
+      frame.save(&path).unwrap();
+      assert!(std::fs::read(&path).unwrap().starts_with(signature));
+      std::fs::remove_file(&path).ok();
+    }
+    assert!(frame.save("image.bmp").is_err());
+  }
+}
@@ -0,0 +1,142 @@
+//! Detect black borders around the content, e.g. letterboxed video or fixed-aspect games,
+//! so recorders can crop to the content.
+
+use crate::frame::Frame;
+use crate::model::Rect;
+
+/// Find the area of a BGRA32 `buffer` of `width` x `height` pixels inside black borders.
+/// A border line has no pixel with a channel brighter than `threshold`.
+/// Return `None` if the whole buffer is black.
+pub fn content_rect(buffer: &[u8], width: u32, height: u32, threshold: u8) -> Option<Rect> {
+  let (width, height) = (width as usize, height as usize);
+  if width == 0 || buffer.len() < width * height * 4 {
+    return None;
+  }
+  let bright = |x: usize, y: usize| {
+    let pixel = &buffer[(y * width + x) * 4..][..3];
+    pixel.iter().any(|&channel| channel > threshold)
+  };
+  let row_bright = |y: usize| (0..width).any(|x| bright(x, y));
+
+  let top = (0..height).find(|&y| row_bright(y))?;
+  let bottom = (top..height).rev().find(|&y| row_bright(y)).unwrap() + 1;
+  let column_bright = |x: usize| (top..bottom).any(|y| bright(x, y));
+  let left = (0..width).find(|&x| column_bright(x)).unwrap();
+  let right = (left..width).rev().find(|&x| column_bright(x)).unwrap() + 1;
+  Some(Rect::new(
+    left as i32,
+    top as i32,
+    right as i32,
+    bottom as i32,
+  ))
+}
+
+impl Frame {
+  /// Find the area inside black borders, see [`content_rect`]. The frame must be 8-bit BGRA.
+  pub fn content_rect(&self, threshold: u8) -> Option<Rect> {
+    content_rect(&self.buffer, self.width, self.height, threshold)
+  }
+}
+
+/// Track the content rect across frames, so the crop doesn't follow every dark scene.
+///
+/// A new rect is reported after it's detected in `stable_frames` consecutive frames.
+/// Black frames keep the current rect.
+#[derive(Debug, Clone)]
+pub struct LetterboxDetector {
+  /// Channels up to this are black, to tolerate compression noise.
+  pub threshold: u8,
+  pub stable_frames: u32,
+  current: Option<Rect>,
+  candidate: Option<(Rect, u32)>,
+}
+
+impl Default for LetterboxDetector {
+  fn default() -> Self {
+    Self::new(16, 3)
+  }
+}
+
+impl LetterboxDetector {
+  pub fn new(threshold: u8, stable_frames: u32) -> Self {
+    Self {
+      threshold,
+      stable_frames,
+      current: None,
+      candidate: None,
+    }
+  }
+
+  /// The current content rect, `None` before content is detected.
+  pub fn content_rect(&self) -> Option<Rect> {
+    self.current
+  }
+
+  /// Detect the content rect of `frame` and return the current content rect.
+  pub fn update(&mut self, frame: &Frame) -> Option<Rect> {
+    let Some(rect) = frame.content_rect(self.threshold) else {
+      return self.current;
+    };
+    if self.current.is_none() || self.current == Some(rect) {
+      self.current = Some(rect);
+      self.candidate = None;
+      return self.current;
+    }
+    let count = match self.candidate {
+      Some((candidate, count)) if candidate == rect => count + 1,
+      _ => 1,
+    };
+    if count >= self.stable_frames {
+      self.current = Some(rect);
+      self.candidate = None;
+    } else {
+      self.candidate = Some((rect, count));
+    }
+    self.current
+  }
+
+  /// Forget the content rect, e.g. after a mode change.
+  pub fn reset(&mut self) {
+    self.current = None;
+    self.candidate = None;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::LetterboxDetector;
+  use crate::model::Rect;
+  use crate::test_utils::generate;
+
+  #[test]
+  fn content_rect() {
+    // 2 black rows at the top and bottom, 1 black column at the left and right
+    let frame = generate(6, 6, |x, y| {
+      if (1..5).contains(&x) && (2..4).contains(&y) {
+        [0, 0, 0x80]
+      } else {
+        [8, 8, 8]
+      }
+    });
+    assert_eq!(frame.content_rect(16), Some(Rect::new(1, 2, 5, 4)));
+    assert_eq!(frame.content_rect(0), Some(Rect::new(0, 0, 6, 6)));
+    assert_eq!(frame.content_rect(0xFF), None);
+  }
+
+  #[test]
+  fn detector() {
+    let letterbox = generate(4, 4, |_, y| if y == 0 { [0; 3] } else { [0xFF; 3] });
+    let full = generate(4, 4, |_, _| [0xFF; 3]);
+    let black = generate(4, 4, |_, _| [0; 3]);
+
+    let mut detector = LetterboxDetector::new(16, 2);
+    assert_eq!(detector.update(&black), None);
+    assert_eq!(detector.update(&letterbox), Some(Rect::new(0, 1, 4, 4)));
+    // black frames and a single different frame keep the rect
+    assert_eq!(detector.update(&black), Some(Rect::new(0, 1, 4, 4)));
+    assert_eq!(detector.update(&full), Some(Rect::new(0, 1, 4, 4)));
+    assert_eq!(detector.update(&full), Some(Rect::new(0, 0, 4, 4)));
+    detector.reset();
+    assert_eq!(detector.content_rect(), None);
+  }
+}
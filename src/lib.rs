@@ -1,6 +1,84 @@
+//! Capture the screen on Windows using the Desktop Duplication API.
+//!
+//! The core is always built and is the semver-stable boundary the rest of the crate builds on:
+//! - [`manager::Manager`] scans adapters and outputs and duplicates them,
+//! - [`duplication_context::DuplicationContext`] is one duplicated monitor,
+//! - [`acquired_frame::AcquiredFrame`] holds a frame until it is copied and released,
+//! - [`capturer::model::Capturer`] is the trait of capturers,
+//! - with the types of [`error`], [`model`] and [`utils`].
+//!
+//! Capturers and other subsystems only use the public API of the core, most behind cargo features.
+//! Capturers in other crates can be written the same way, see [`capturer`].
+
+pub mod acquired_frame;
+#[cfg(feature = "analysis")]
+pub mod ambient;
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "analysis")]
+pub mod blend;
 pub mod capturer;
+#[cfg(feature = "clipboard")]
+pub mod clipboard;
+pub mod color;
+#[cfg(any(feature = "audio", feature = "image"))]
+mod com;
+pub mod correlation;
+#[cfg(feature = "desktop")]
+pub mod desktop;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+#[cfg(feature = "display-config")]
+pub mod display_config;
+pub mod dump;
 pub mod duplication_context;
+#[cfg(feature = "recorder")]
+pub mod encoder;
 pub mod error;
+pub mod frame;
+pub mod gdi;
+#[cfg(feature = "analysis")]
+pub mod highlight;
+#[cfg(feature = "image")]
+pub mod image;
+#[cfg(feature = "analysis")]
+pub mod letterbox;
 pub mod manager;
+#[cfg(feature = "media-foundation")]
+pub mod media_foundation;
 pub mod model;
+#[cfg(feature = "analysis")]
+pub mod motion;
+#[cfg(feature = "analysis")]
+pub mod overlay;
+pub mod pacer;
+pub mod pointer;
+#[cfg(feature = "power")]
+pub mod power;
+pub mod preview;
+pub mod profile;
+#[cfg(feature = "recorder")]
+pub mod recorder;
+#[cfg(feature = "recorder")]
+pub mod replay;
+pub mod report;
+pub mod screenshot;
+#[cfg(feature = "threaded")]
+pub mod session;
+#[cfg(feature = "analysis")]
+pub mod snapshot;
+pub mod stream;
+mod swizzle;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+#[cfg(feature = "analysis")]
+pub mod tiles;
+#[cfg(feature = "threaded")]
+pub mod timeline;
 pub mod utils;
+#[cfg(feature = "analysis")]
+pub mod watch;
+#[cfg(feature = "window")]
+pub mod window;
+
+pub use screenshot::{capture_region, screenshot, screenshot_all};
@@ -1,29 +1,121 @@
 use crate::duplication_context::DuplicationContext;
-use crate::error::Error;
-use crate::model::Result;
+use crate::error::{probe, DuplicationFailure, Error, ErrorContext, ErrorKind};
+use crate::model::{AdapterPreference, MonitorId, MonitorSelector, Result, UnsupportedOutput};
+use crate::utils::{output_desc1, AdapterDescExt, MonitorInfoExt, OutputDescExt};
 use windows::core::ComInterface;
 use windows::Win32::Graphics::Direct3D::{D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL_9_1};
 use windows::Win32::Graphics::Direct3D11::{
   D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, D3D11_CREATE_DEVICE_FLAG, D3D11_SDK_VERSION,
 };
-use windows::Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIFactory1, IDXGIOutput1};
+use windows::Win32::Graphics::Dxgi::{
+  Common::DXGI_FORMAT, CreateDXGIFactory1, IDXGIAdapter1, IDXGIFactory1, IDXGIFactory6,
+  IDXGIOutput, IDXGIOutput1, IDXGIOutput5, IDXGIOutputDuplication, DXGI_ADAPTER_DESC1,
+  DXGI_ERROR_NOT_CURRENTLY_AVAILABLE, DXGI_ERROR_UNSUPPORTED, DXGI_GPU_PREFERENCE,
+  DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE, DXGI_GPU_PREFERENCE_MINIMUM_POWER, DXGI_OUTPUT_DESC,
+  DXGI_OUTPUT_DESC1,
+};
+
+/// The default timeout of `AcquireNextFrame`, in milliseconds.
+pub const DEFAULT_TIMEOUT_MS: u32 = 300;
+
+/// How [`Manager::with_options`] and [`Manager::open_with_options`] duplicate outputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagerOptions {
+  /// The timeout of `AcquireNextFrame`, in milliseconds.
+  pub timeout_ms: u32,
+  /// See [`Manager::with_adapter_preference`].
+  pub adapter_preference: AdapterPreference,
+  /// Duplication formats ordered from the most preferred, see [`Manager::with_formats`].
+  /// Empty to duplicate with `DuplicateOutput` in `DXGI_FORMAT_B8G8R8A8_UNORM`.
+  pub formats: Vec<DXGI_FORMAT>,
+}
+
+impl Default for ManagerOptions {
+  fn default() -> Self {
+    Self {
+      timeout_ms: DEFAULT_TIMEOUT_MS,
+      adapter_preference: AdapterPreference::default(),
+      formats: Vec::new(),
+    }
+  }
+}
+
+/// An adapter in the order of an [`AdapterPreference`], whose device is created on first use.
+struct PreferredAdapter {
+  /// The index of `EnumAdapters1`, not of `EnumAdapterByGpuPreference`.
+  index: u32,
+  luid: i64,
+  adapter: IDXGIAdapter1,
+  /// `None` before the first use, `Some(None)` if creating the device failed.
+  device: Option<Option<(ID3D11Device, ID3D11DeviceContext)>>,
+}
 
 pub struct Manager {
   pub contexts: Vec<DuplicationContext>,
+  /// Outputs on software adapters which don't support duplication, e.g. indirect displays.
+  /// They can be captured by [`GdiCapturer`](crate::gdi::GdiCapturer) instead.
+  pub unsupported: Vec<UnsupportedOutput>,
+  /// Outputs which failed to be duplicated, which don't stop the others from being duplicated.
+  /// Failures of a whole adapter are recorded with output 0.
+  pub failed: Vec<(MonitorId, Error)>,
   timeout_ms: u32,
+  adapter_preference: AdapterPreference,
+  formats: Vec<DXGI_FORMAT>,
 }
 
 impl Manager {
   /// Create a new manager and refresh monitors info.
+  #[allow(clippy::should_implement_trait)]
   pub fn default() -> Result<Manager> {
-    Manager::new(300)
+    Manager::new(DEFAULT_TIMEOUT_MS)
   }
 
   /// Create a new manager and refresh monitors info.
   pub fn new(timeout_ms: u32) -> Result<Manager> {
+    Manager::with_adapter_preference(timeout_ms, AdapterPreference::default())
+  }
+
+  /// Create a new manager and refresh monitors info.
+  /// Each output is duplicated by the first adapter in the `adapter_preference` order
+  /// which supports it, falling back to the adapter the output is attached to.
+  pub fn with_adapter_preference(
+    timeout_ms: u32,
+    adapter_preference: AdapterPreference,
+  ) -> Result<Manager> {
+    Manager::build(timeout_ms, adapter_preference, Vec::new())
+  }
+
+  /// Create a new manager and refresh monitors info.
+  /// Outputs are duplicated with `DuplicateOutput1` in the first supported format of `formats`,
+  /// ordered from the most preferred, see [`DuplicationContext::supported_formats`].
+  /// Fall back to `DXGI_FORMAT_B8G8R8A8_UNORM` if `DuplicateOutput1` is not available,
+  /// e.g. before Windows 10 1703 or if the process is not per monitor DPI aware.
+  pub fn with_formats(timeout_ms: u32, formats: &[DXGI_FORMAT]) -> Result<Manager> {
+    Manager::build(timeout_ms, AdapterPreference::default(), formats.to_vec())
+  }
+
+  /// Create a new manager and refresh monitors info,
+  /// e.g. to combine an adapter preference with duplication formats.
+  pub fn with_options(options: ManagerOptions) -> Result<Manager> {
+    Manager::build(
+      options.timeout_ms,
+      options.adapter_preference,
+      options.formats,
+    )
+  }
+
+  fn build(
+    timeout_ms: u32,
+    adapter_preference: AdapterPreference,
+    formats: Vec<DXGI_FORMAT>,
+  ) -> Result<Manager> {
     let mut manager = Manager {
       contexts: Vec::new(),
+      unsupported: Vec::new(),
+      failed: Vec::new(),
       timeout_ms,
+      adapter_preference,
+      formats,
     };
     match manager.refresh() {
       Ok(_) => Ok(manager),
@@ -32,8 +124,12 @@ impl Manager {
   }
 
   /// Refresh monitors info.
+  /// Outputs which fail are recorded in [`Manager::failed`],
+  /// only return an error if no output is duplicated or recorded as unsupported.
   pub fn refresh(&mut self) -> Result<()> {
     self.contexts.clear();
+    self.unsupported.clear();
+    self.failed.clear();
 
     let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>() }
       .map_err(|e| Error::windows("CreateDXGIFactory1", e))?;
@@ -49,62 +145,536 @@ impl Manager {
       for output_index in 0.. {
         match unsafe { adapter.EnumOutputs(output_index) } {
           Err(_) => break,
-          Ok(output) => outputs.push(output),
+          Ok(output) => {
+            // skip detached and zero-sized outputs,
+            // duplicating them fails later with confusing errors
+            let id = MonitorId {
+              adapter: adapter_index,
+              output: output_index,
+            };
+            match Self::output_desc(id, Some(&adapter), &output) {
+              Ok(desc) if desc.is_active() => outputs.push((output_index, output)),
+              Ok(_) => {}
+              Err(e) => self.failed.push((id, e)),
+            }
+          }
         }
       }
-      if outputs.len() > 0 {
-        adapter_outputs.push((adapter, outputs))
+      if !outputs.is_empty() {
+        adapter_outputs.push((adapter_index, adapter, outputs))
       }
     }
-    if adapter_outputs.len() == 0 {
-      return Err(Error::new("No output"));
+    if adapter_outputs.is_empty() {
+      return Err(match self.failed.pop() {
+        Some((_, e)) => e,
+        None => Error::new("No output"),
+      });
     }
 
+    let mut preferred_adapters = Self::preferred_adapters(&factory, self.adapter_preference);
+
     // prepare device and output
-    for (adapter, outputs) in adapter_outputs {
-      let mut device: Option<ID3D11Device> = None.clone();
-      let mut device_context: Option<ID3D11DeviceContext> = None.clone();
-      let mut feature_level = D3D_FEATURE_LEVEL_9_1;
-
-      // create device for each adapter
-      unsafe {
-        D3D11CreateDevice(
-          &adapter,
-          D3D_DRIVER_TYPE_UNKNOWN,
-          None,
-          D3D11_CREATE_DEVICE_FLAG(0),
-          None,
-          D3D11_SDK_VERSION,
-          Some(&mut device),
-          Some(&mut feature_level),
-          Some(&mut device_context),
-        )
+    for (adapter_index, adapter, outputs) in adapter_outputs {
+      let adapter_id = MonitorId {
+        adapter: adapter_index,
+        output: 0,
+      };
+      let mut adapter_desc = DXGI_ADAPTER_DESC1::default();
+      if let Err(e) = unsafe { adapter.GetDesc1(&mut adapter_desc) } {
+        self.failed.push((
+          adapter_id,
+          Error::windows_with_context(
+            "IDXGIAdapter1.GetDesc1",
+            e,
+            ErrorContext::collect(adapter_id, Some(&adapter), None),
+          ),
+        ));
+        continue;
       }
-      .map_err(|e| Error::windows("D3D11CreateDevice", e))?;
-      let device = device.unwrap();
-      let device_context = device_context.unwrap();
+
+      // the device of the adapter is only created if no preferred adapter duplicates an output
+      let mut attached_device = None;
 
       // create duplication output for each output
-      for output in outputs {
-        let output = output.cast::<IDXGIOutput1>().unwrap();
-        let output_duplication = unsafe { output.DuplicateOutput(&device) }
-          .map_err(|e| Error::windows("DuplicateOutput", e))?;
-        self.contexts.push(DuplicationContext::new(
-          device.clone(),
-          device_context.clone(),
-          output,
-          output_duplication,
+      for (output_index, output) in outputs {
+        let id = MonitorId {
+          adapter: adapter_index,
+          output: output_index,
+        };
+        let result = match Self::duplicate_preferred(
+          id,
+          adapter_desc.luid(),
+          &mut preferred_adapters,
+          &output,
           self.timeout_ms,
-        ))
+          &self.formats,
+        ) {
+          Some(context) => Ok(context),
+          None => {
+            let (device, device_context) = match attached_device {
+              Some(ref device) => device,
+              None => match Self::create_device(adapter_id, &adapter) {
+                Ok(device) => attached_device.insert(device),
+                Err(e) => {
+                  self.failed.push((id, e));
+                  continue;
+                }
+              },
+            };
+            Self::duplicate(
+              id,
+              device,
+              device_context,
+              &output,
+              self.timeout_ms,
+              &self.formats,
+            )
+          }
+        };
+        match result {
+          Ok(context) => self.contexts.push(context),
+          // indirect displays on software adapters may not support duplication,
+          // record them so they can be captured with GDI instead of failing all monitors
+          Err(e)
+            if adapter_desc.is_software()
+              && e.windows.as_ref().map(|e| e.code()) == Some(DXGI_ERROR_UNSUPPORTED) =>
+          {
+            match Self::output_desc(id, Some(&adapter), &output) {
+              Ok(output_desc) => self.unsupported.push(UnsupportedOutput {
+                id,
+                device_name: output_desc.device_name(),
+                rect: output_desc.rect(),
+              }),
+              Err(e) => self.failed.push((id, e)),
+            }
+          }
+          Err(e) => self.failed.push((id, e)),
+        }
+      }
+    }
+
+    if self.contexts.is_empty() && self.unsupported.is_empty() {
+      if let Some((_, e)) = self.failed.pop() {
+        return Err(e);
       }
     }
     Ok(())
   }
+
+  /// Enumerate adapters in the order of `adapter_preference`, without creating devices.
+  /// Adapters whose description can't be read are skipped.
+  fn preferred_adapters(
+    factory: &IDXGIFactory1,
+    adapter_preference: AdapterPreference,
+  ) -> Vec<PreferredAdapter> {
+    let gpu_preference: DXGI_GPU_PREFERENCE = match adapter_preference {
+      AdapterPreference::AttachedToOutput => return Vec::new(),
+      AdapterPreference::MinimumPower => DXGI_GPU_PREFERENCE_MINIMUM_POWER,
+      AdapterPreference::HighPerformance => DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE,
+    };
+    // IDXGIFactory6 requires Windows 10 1803, fall back to the attached adapter on older systems
+    let Ok(factory6) = factory.cast::<IDXGIFactory6>() else {
+      return Vec::new();
+    };
+    let luid = |adapter: &IDXGIAdapter1| {
+      let mut desc = DXGI_ADAPTER_DESC1::default();
+      unsafe { adapter.GetDesc1(&mut desc) }
+        .ok()
+        .map(|_| desc.luid())
+    };
+
+    // the indices of `EnumAdapters1` identify monitors, so match the adapters by LUID
+    let mut indices = Vec::new();
+    for index in 0.. {
+      let Ok(adapter) = (unsafe { factory.EnumAdapters1(index) }) else {
+        break;
+      };
+      if let Some(luid) = luid(&adapter) {
+        indices.push((luid, index));
+      }
+    }
+
+    let mut adapters = Vec::new();
+    for preference_index in 0.. {
+      let Ok(adapter) = (unsafe {
+        factory6.EnumAdapterByGpuPreference::<IDXGIAdapter1>(preference_index, gpu_preference)
+      }) else {
+        break;
+      };
+      let Some(luid) = luid(&adapter) else {
+        continue;
+      };
+      if let Some(&(_, index)) = indices.iter().find(|(l, _)| *l == luid) {
+        adapters.push(PreferredAdapter {
+          index,
+          luid,
+          adapter,
+          device: None,
+        });
+      }
+    }
+    adapters
+  }
+
+  /// Try to duplicate the output with adapters preferred over the attached adapter `attached_luid`,
+  /// creating their devices on first use.
+  /// Return `None` if no preferred adapter supports it.
+  fn duplicate_preferred(
+    id: MonitorId,
+    attached_luid: i64,
+    preferred_adapters: &mut [PreferredAdapter],
+    output: &IDXGIOutput,
+    timeout_ms: u32,
+    formats: &[DXGI_FORMAT],
+  ) -> Option<DuplicationContext> {
+    for preferred in preferred_adapters
+      .iter_mut()
+      .take_while(|preferred| preferred.luid != attached_luid)
+    {
+      let adapter_id = MonitorId {
+        adapter: preferred.index,
+        output: 0,
+      };
+      let device = preferred
+        .device
+        .get_or_insert_with(|| probe(|| Self::create_device(adapter_id, &preferred.adapter)));
+      if let Some((device, device_context)) = device {
+        if let Some(context) =
+          probe(|| Self::duplicate(id, device, device_context, output, timeout_ms, formats))
+        {
+          return Some(context);
+        }
+      }
+    }
+    None
+  }
+
+  /// Create a duplication context for a single monitor without scanning others.
+  /// Unlike [`DuplicationContext`], the `MonitorId` can be sent to other threads
+  /// to create a context there.
+  ///
+  /// Each call creates its own device, so monitors of any adapters can be captured
+  /// concurrently by one thread each. Contexts of one [`Manager`] share a device per adapter
+  /// and must stay on the thread of the manager.
+  pub fn open(id: MonitorId, timeout_ms: u32) -> Result<DuplicationContext> {
+    Self::open_with_options(
+      id,
+      &ManagerOptions {
+        timeout_ms,
+        ..Default::default()
+      },
+    )
+  }
+
+  /// Like [`Manager::open`], duplicating in the formats of `options`
+  /// by the first adapter in the order of `options.adapter_preference` which supports the output.
+  pub fn open_with_options(id: MonitorId, options: &ManagerOptions) -> Result<DuplicationContext> {
+    let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>() }
+      .map_err(|e| Error::windows("CreateDXGIFactory1", e))?;
+    let adapter = unsafe { factory.EnumAdapters1(id.adapter) }
+      .map_err(|e| Error::windows("EnumAdapters1", e))?;
+    let output = unsafe { adapter.EnumOutputs(id.output) }.map_err(|e| {
+      Error::windows_with_context(
+        "EnumOutputs",
+        e,
+        ErrorContext::collect(id, Some(&adapter), None),
+      )
+    })?;
+
+    let mut preferred_adapters = Self::preferred_adapters(&factory, options.adapter_preference);
+    if !preferred_adapters.is_empty() {
+      let mut adapter_desc = DXGI_ADAPTER_DESC1::default();
+      unsafe { adapter.GetDesc1(&mut adapter_desc) }.map_err(|e| {
+        Error::windows_with_context(
+          "IDXGIAdapter1.GetDesc1",
+          e,
+          ErrorContext::collect(id, Some(&adapter), Some(&output)),
+        )
+      })?;
+      if let Some(ctx) = Self::duplicate_preferred(
+        id,
+        adapter_desc.luid(),
+        &mut preferred_adapters,
+        &output,
+        options.timeout_ms,
+        &options.formats,
+      ) {
+        return Ok(ctx);
+      }
+    }
+    Self::create_device(id, &adapter).and_then(|(device, device_context)| {
+      Self::duplicate(
+        id,
+        &device,
+        &device_context,
+        &output,
+        options.timeout_ms,
+        &options.formats,
+      )
+    })
+  }
+
+  /// Check whether the monitor can be duplicated now.
+  /// Return `Ok(false)` if too many applications are already duplicating it.
+  pub fn is_duplication_available(id: MonitorId) -> Result<bool> {
+    match Self::open(id, DEFAULT_TIMEOUT_MS) {
+      Ok(_) => Ok(true),
+      Err(e) if e.kind == ErrorKind::DuplicationLimitReached => Ok(false),
+      Err(e) => Err(e),
+    }
+  }
+
+  /// Enumerate all outputs of all adapters without duplicating them,
+  /// including detached outputs. Duplicate them on demand by [`MonitorHandle::duplicate`],
+  /// so outputs which can't be duplicated, e.g. on the other GPU of a dual-GPU laptop,
+  /// only fail if they are captured.
+  pub fn scan() -> Result<Vec<MonitorHandle>> {
+    let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>() }
+      .map_err(|e| Error::windows("CreateDXGIFactory1", e))?;
+    let mut handles = Vec::new();
+    for adapter_index in 0.. {
+      let adapter = match unsafe { factory.EnumAdapters1(adapter_index) } {
+        Ok(adapter) => adapter,
+        Err(_) => break,
+      };
+      let mut adapter_desc = DXGI_ADAPTER_DESC1::default();
+      unsafe { adapter.GetDesc1(&mut adapter_desc) }
+        .map_err(|e| Error::windows("IDXGIAdapter1.GetDesc1", e))?;
+      for output_index in 0.. {
+        let output = match unsafe { adapter.EnumOutputs(output_index) } {
+          Ok(output) => output,
+          Err(_) => break,
+        };
+        let id = MonitorId {
+          adapter: adapter_index,
+          output: output_index,
+        };
+        handles.push(MonitorHandle {
+          id,
+          desc: Self::output_desc(id, Some(&adapter), &output)?,
+          adapter_desc,
+          adapter: adapter.clone(),
+          output,
+        });
+      }
+    }
+    Ok(handles)
+  }
+
+  /// Create a device on `adapter`, failures are reported with the context of the monitor `id`.
+  pub(crate) fn create_device(
+    id: MonitorId,
+    adapter: &IDXGIAdapter1,
+  ) -> Result<(ID3D11Device, ID3D11DeviceContext)> {
+    let mut device: Option<ID3D11Device> = None.clone();
+    let mut device_context: Option<ID3D11DeviceContext> = None.clone();
+    let mut feature_level = D3D_FEATURE_LEVEL_9_1;
+
+    unsafe {
+      D3D11CreateDevice(
+        adapter,
+        D3D_DRIVER_TYPE_UNKNOWN,
+        None,
+        D3D11_CREATE_DEVICE_FLAG(0),
+        None,
+        D3D11_SDK_VERSION,
+        Some(&mut device),
+        Some(&mut feature_level),
+        Some(&mut device_context),
+      )
+    }
+    .map_err(|e| {
+      Error::windows_with_context(
+        "D3D11CreateDevice",
+        e,
+        ErrorContext::collect(id, Some(adapter), None),
+      )
+    })?;
+    Ok((device.unwrap(), device_context.unwrap()))
+  }
+
+  pub(crate) fn duplicate(
+    id: MonitorId,
+    device: &ID3D11Device,
+    device_context: &ID3D11DeviceContext,
+    output: &IDXGIOutput,
+    timeout_ms: u32,
+    formats: &[DXGI_FORMAT],
+  ) -> Result<DuplicationContext> {
+    let adapter: Option<IDXGIAdapter1> = unsafe { output.GetParent() }.ok();
+    let context = || ErrorContext::collect(id, adapter.as_ref(), Some(output));
+    let desc = Self::output_desc(id, adapter.as_ref(), output)?;
+    let inactive = |message: &str| {
+      let mut err = Error::of_kind(ErrorKind::InactiveOutput, message).with_context(context());
+      err.duplication = Some(DuplicationFailure::InactiveOutput);
+      err
+    };
+    if !desc.is_attached() {
+      return Err(inactive("Output is not attached to the desktop"));
+    }
+    if !desc.is_active() {
+      return Err(inactive("Output has a zero-sized desktop area"));
+    }
+    let output1 = output.cast::<IDXGIOutput1>().unwrap();
+    let output_duplication = Self::duplicate_output(&output1, device, formats).map_err(|e| {
+      let code = e.code();
+      let mut err = Error::windows_with_context("DuplicateOutput", e, context());
+      err.duplication = Some(DuplicationFailure::from_hresult(code));
+      if code == DXGI_ERROR_NOT_CURRENTLY_AVAILABLE {
+        err.kind = ErrorKind::DuplicationLimitReached;
+      }
+      err
+    })?;
+    DuplicationContext::try_new(
+      id,
+      device.clone(),
+      device_context.clone(),
+      output1,
+      output_duplication,
+      timeout_ms,
+    )
+    .map_err(|e| e.with_context(context()))
+  }
+
+  /// Duplicate with `DuplicateOutput1` if `formats` is not empty and it's available,
+  /// otherwise with `DuplicateOutput`.
+  fn duplicate_output(
+    output: &IDXGIOutput1,
+    device: &ID3D11Device,
+    formats: &[DXGI_FORMAT],
+  ) -> windows::core::Result<IDXGIOutputDuplication> {
+    if !formats.is_empty() {
+      if let Ok(output) = output.cast::<IDXGIOutput5>() {
+        match unsafe { output.DuplicateOutput1(device, 0, formats) } {
+          Ok(duplication) => return Ok(duplication),
+          // too many duplications, falling back won't help
+          Err(e) if e.code() == DXGI_ERROR_NOT_CURRENTLY_AVAILABLE => return Err(e),
+          // e.g. the process is not per monitor DPI aware or no format is supported
+          Err(_) => {}
+        }
+      }
+    }
+    unsafe { output.DuplicateOutput(device) }
+  }
+
+  pub(crate) fn output_desc(
+    id: MonitorId,
+    adapter: Option<&IDXGIAdapter1>,
+    output: &IDXGIOutput,
+  ) -> Result<DXGI_OUTPUT_DESC> {
+    let mut desc = DXGI_OUTPUT_DESC::default();
+    unsafe { output.GetDesc(&mut desc) }.map_err(|e| {
+      Error::windows_with_context(
+        "DXGI_OUTPUT_DESC.GetDesc",
+        e,
+        ErrorContext::collect(id, adapter, Some(output)),
+      )
+    })?;
+    Ok(desc)
+  }
+
+  /// Find the duplication context matching the selector.
+  pub fn select(&self, selector: &MonitorSelector) -> Result<&DuplicationContext> {
+    match selector {
+      MonitorSelector::Primary => {
+        for ctx in &self.contexts {
+          if ctx.monitor_info()?.is_primary() {
+            return Ok(ctx);
+          }
+        }
+        Err(Error::new("No primary monitor"))
+      }
+      MonitorSelector::Index(index) => self
+        .contexts
+        .get(*index)
+        .ok_or_else(|| Error::new("Monitor index out of range")),
+      MonitorSelector::DeviceName(name) => {
+        for ctx in &self.contexts {
+          if ctx.dxgi_output_desc()?.device_name() == *name {
+            return Ok(ctx);
+          }
+        }
+        Err(Error::new("Monitor not found"))
+      }
+    }
+  }
+}
+
+/// An output found by [`Manager::scan`], which is not duplicated yet.
+#[derive(Clone)]
+pub struct MonitorHandle {
+  id: MonitorId,
+  adapter: IDXGIAdapter1,
+  output: IDXGIOutput,
+  adapter_desc: DXGI_ADAPTER_DESC1,
+  desc: DXGI_OUTPUT_DESC,
+}
+
+impl MonitorHandle {
+  pub fn id(&self) -> MonitorId {
+    self.id
+  }
+
+  /// The output description at the time of the scan.
+  pub fn dxgi_output_desc(&self) -> DXGI_OUTPUT_DESC {
+    self.desc
+  }
+
+  pub fn dxgi_adapter_desc(&self) -> DXGI_ADAPTER_DESC1 {
+    self.adapter_desc
+  }
+
+  /// Query the current `DXGI_OUTPUT_DESC1`, or `None` before Windows 10 1803,
+  /// see [`DuplicationContext::dxgi_output_desc1`].
+  pub fn dxgi_output_desc1(&self) -> Result<Option<DXGI_OUTPUT_DESC1>> {
+    output_desc1(&self.output).transpose().map_err(|e| {
+      Error::windows_with_context(
+        "IDXGIOutput6.GetDesc1",
+        e,
+        ErrorContext::collect(self.id, Some(&self.adapter), Some(&self.output)),
+      )
+    })
+  }
+
+  /// Whether the output is attached to the desktop with a non-empty area, which is required to duplicate it.
+  pub fn is_active(&self) -> bool {
+    self.desc.is_active()
+  }
+
+  /// Create a device on the adapter of the output and duplicate the output.
+  pub fn duplicate(&self, timeout_ms: u32) -> Result<DuplicationContext> {
+    self.duplicate_with_formats(timeout_ms, &[])
+  }
+
+  /// Like [`MonitorHandle::duplicate`], with formats as in [`Manager::with_formats`].
+  pub fn duplicate_with_formats(
+    &self,
+    timeout_ms: u32,
+    formats: &[DXGI_FORMAT],
+  ) -> Result<DuplicationContext> {
+    Manager::create_device(self.id, &self.adapter).and_then(|(device, device_context)| {
+      Manager::duplicate(
+        self.id,
+        &device,
+        &device_context,
+        &self.output,
+        timeout_ms,
+        formats,
+      )
+    })
+  }
 }
 
 #[cfg(test)]
 mod tests {
-  use super::Manager;
+  use super::{Manager, ManagerOptions, DEFAULT_TIMEOUT_MS};
+  use crate::{
+    capturer::model::Capturer,
+    model::{AdapterPreference, MonitorSelector},
+    utils::{FormatExt, MonitorInfoExt, OutDuplDescExt, OutputDescExt},
+  };
+  use std::thread;
+  use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_B8G8R8A8_UNORM;
 
   #[test]
   fn manager() {
@@ -113,4 +683,135 @@ mod tests {
     manager.refresh().unwrap();
     assert_ne!(manager.contexts.len(), 0);
   }
+
+  #[test]
+  fn select() {
+    let manager = Manager::default().unwrap();
+    let primary = manager.select(&MonitorSelector::Primary).unwrap();
+    assert!(primary.monitor_info().unwrap().is_primary());
+
+    let name = manager.contexts[0]
+      .dxgi_output_desc()
+      .unwrap()
+      .device_name();
+    let ctx = manager
+      .select(&MonitorSelector::DeviceName(name.clone()))
+      .unwrap();
+    assert_eq!(ctx.dxgi_output_desc().unwrap().device_name(), name);
+
+    assert!(manager.select(&MonitorSelector::Index(0)).is_ok());
+    assert!(manager
+      .select(&MonitorSelector::Index(manager.contexts.len()))
+      .is_err());
+  }
+
+  #[test]
+  fn adapter_preference() {
+    let count = Manager::default().unwrap().contexts.len();
+    for preference in [
+      AdapterPreference::AttachedToOutput,
+      AdapterPreference::MinimumPower,
+      AdapterPreference::HighPerformance,
+    ] {
+      let manager = Manager::with_adapter_preference(DEFAULT_TIMEOUT_MS, preference).unwrap();
+      assert_eq!(manager.contexts.len(), count);
+      let id = manager.contexts[0].id();
+      drop(manager);
+
+      let options = ManagerOptions {
+        adapter_preference: preference,
+        ..Default::default()
+      };
+      assert_eq!(Manager::open_with_options(id, &options).unwrap().id(), id);
+    }
+  }
+
+  #[test]
+  fn formats() {
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    assert_eq!(ctx.format(), DXGI_FORMAT_B8G8R8A8_UNORM);
+    let supported = ctx.supported_formats();
+    assert!(supported.contains(&DXGI_FORMAT_B8G8R8A8_UNORM));
+    drop(manager);
+
+    // one of the formats is used, BGRA if DuplicateOutput1 is not available
+    let manager = Manager::with_formats(DEFAULT_TIMEOUT_MS, &supported).unwrap();
+    let ctx = &manager.contexts[0];
+    assert!(supported.contains(&ctx.format()));
+    let (_, desc, texture_desc) = ctx.create_readable_texture().unwrap();
+    assert_eq!(texture_desc.Format, ctx.format());
+    assert_eq!(
+      desc.calc_buffer_size(),
+      texture_desc.Width as usize * texture_desc.Height as usize * ctx.format().bytes_per_pixel()
+    );
+    let format = ctx.format();
+    let id = ctx.id();
+    drop(manager);
+
+    // formats combine with the adapter preference, and are used by `open`
+    let options = ManagerOptions {
+      adapter_preference: AdapterPreference::HighPerformance,
+      formats: supported.clone(),
+      ..Default::default()
+    };
+    let manager = Manager::with_options(options.clone()).unwrap();
+    assert_eq!(manager.contexts[0].format(), format);
+    drop(manager);
+    assert_eq!(
+      Manager::open_with_options(id, &options).unwrap().format(),
+      format
+    );
+  }
+
+  #[test]
+  fn open() {
+    let id = Manager::default().unwrap().contexts[0].id();
+    let ctx = Manager::open(id, DEFAULT_TIMEOUT_MS).unwrap();
+    assert_eq!(ctx.id(), id);
+    drop(ctx);
+    assert!(Manager::is_duplication_available(id).unwrap());
+  }
+
+  #[test]
+  fn scan() {
+    let handles = Manager::scan().unwrap();
+    let manager = Manager::default().unwrap();
+    assert!(handles.len() >= manager.contexts.len());
+
+    let handle = handles.iter().find(|handle| handle.is_active()).unwrap();
+    let ctx = handle.duplicate(DEFAULT_TIMEOUT_MS).unwrap();
+    assert_eq!(ctx.id(), handle.id());
+    assert_eq!(
+      ctx.dxgi_output_desc().unwrap().device_name(),
+      handle.dxgi_output_desc().device_name()
+    );
+  }
+
+  #[test]
+  fn threads() {
+    // capture every monitor on its own thread and device concurrently
+    let ids: Vec<_> = Manager::default()
+      .unwrap()
+      .contexts
+      .iter()
+      .map(|ctx| ctx.id())
+      .collect();
+    let workers: Vec<_> = ids
+      .into_iter()
+      .map(|id| {
+        thread::spawn(move || {
+          let ctx = Manager::open(id, DEFAULT_TIMEOUT_MS).unwrap();
+          let mut capturer = ctx.simple_capturer().unwrap();
+          for _ in 0..3 {
+            capturer.safe_capture().ok();
+          }
+          assert_eq!(ctx.id(), id);
+        })
+      })
+      .collect();
+    for worker in workers {
+      worker.join().unwrap();
+    }
+  }
 }
@@ -0,0 +1,143 @@
+//! Wrap captured frames in `IMFSample`s for Media Foundation transforms and sink writers.
+//! Enable the `media-foundation` feature to use this module.
+//!
+//! Media Foundation must be started with `MFStartup` before creating samples.
+
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::model::Result;
+use std::ptr;
+use std::time::Duration;
+use windows::core::{ComInterface, GUID};
+use windows::Win32::Graphics::Direct3D11::ID3D11Texture2D;
+use windows::Win32::Media::MediaFoundation::{
+  IMF2DBuffer, IMFMediaBuffer, IMFSample, MFCreateDXGISurfaceBuffer, MFCreateMemoryBuffer,
+  MFCreateSample,
+};
+use windows::Win32::System::Performance::QueryPerformanceFrequency;
+
+/// Convert a QPC timestamp, e.g. `LastPresentTime` of the frame info,
+/// to the 100-nanosecond units of Media Foundation.
+pub fn qpc_to_hns(qpc: i64) -> i64 {
+  let mut frequency = 0;
+  unsafe { QueryPerformanceFrequency(&mut frequency) };
+  qpc_to_hns_with_frequency(qpc, frequency)
+}
+
+fn qpc_to_hns_with_frequency(qpc: i64, frequency: i64) -> i64 {
+  // split to avoid overflowing i64 with large counters
+  let frequency = frequency.max(1);
+  qpc / frequency * 10_000_000 + qpc % frequency * 10_000_000 / frequency
+}
+
+/// Copy pixels into a new sample with the time and duration in 100-nanosecond units.
+pub fn sample_from_buffer(pixels: &[u8], time: i64, duration: i64) -> Result<IMFSample> {
+  let len = u32::try_from(pixels.len()).map_err(|_| Error::new("Buffer too large"))?;
+  let buffer =
+    unsafe { MFCreateMemoryBuffer(len) }.map_err(|e| Error::windows("MFCreateMemoryBuffer", e))?;
+  unsafe {
+    let mut data = ptr::null_mut();
+    buffer
+      .Lock(&mut data, None, None)
+      .map_err(|e| Error::windows("IMFMediaBuffer.Lock", e))?;
+    ptr::copy_nonoverlapping(pixels.as_ptr(), data, pixels.len());
+    buffer
+      .Unlock()
+      .map_err(|e| Error::windows("IMFMediaBuffer.Unlock", e))?;
+    buffer
+      .SetCurrentLength(len)
+      .map_err(|e| Error::windows("IMFMediaBuffer.SetCurrentLength", e))?;
+  }
+  create_sample(&buffer, time, duration)
+}
+
+/// Wrap a GPU texture, e.g. from [`AcquiredFrame::texture`](crate::acquired_frame::AcquiredFrame::texture),
+/// in a new sample without copying, with the time and duration in 100-nanosecond units.
+///
+/// The sample references the texture, copy it first if it is only valid until the frame is released.
+pub fn sample_from_texture(
+  texture: &ID3D11Texture2D,
+  time: i64,
+  duration: i64,
+) -> Result<IMFSample> {
+  let buffer =
+    unsafe { MFCreateDXGISurfaceBuffer(&ID3D11Texture2D::IID as *const GUID, texture, 0, false) }
+      .map_err(|e| Error::windows("MFCreateDXGISurfaceBuffer", e))?;
+  // the current length is not set for DXGI buffers
+  let len = buffer
+    .cast::<IMF2DBuffer>()
+    .and_then(|buffer| unsafe { buffer.GetContiguousLength() })
+    .map_err(|e| Error::windows("IMF2DBuffer.GetContiguousLength", e))?;
+  unsafe { buffer.SetCurrentLength(len) }
+    .map_err(|e| Error::windows("IMFMediaBuffer.SetCurrentLength", e))?;
+  create_sample(&buffer, time, duration)
+}
+
+impl Frame {
+  /// Copy the frame into a new sample, timed at the `LastPresentTime` of the frame info.
+  pub fn to_mf_sample(&self, duration: Duration) -> Result<IMFSample> {
+    sample_from_buffer(
+      &self.buffer,
+      qpc_to_hns(self.info.LastPresentTime),
+      (duration.as_nanos() / 100) as i64,
+    )
+  }
+}
+
+fn create_sample(buffer: &IMFMediaBuffer, time: i64, duration: i64) -> Result<IMFSample> {
+  unsafe {
+    let sample = MFCreateSample().map_err(|e| Error::windows("MFCreateSample", e))?;
+    sample
+      .AddBuffer(buffer)
+      .map_err(|e| Error::windows("IMFSample.AddBuffer", e))?;
+    sample
+      .SetSampleTime(time)
+      .map_err(|e| Error::windows("IMFSample.SetSampleTime", e))?;
+    sample
+      .SetSampleDuration(duration)
+      .map_err(|e| Error::windows("IMFSample.SetSampleDuration", e))?;
+    Ok(sample)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{qpc_to_hns_with_frequency, sample_from_buffer};
+  use crate::test_utils::gradient;
+  use std::time::Duration;
+  use windows::Win32::Media::MediaFoundation::{MFShutdown, MFStartup, MFSTARTUP_FULL, MF_VERSION};
+
+  #[test]
+  fn timestamps() {
+    assert_eq!(
+      qpc_to_hns_with_frequency(10_000_000, 10_000_000),
+      10_000_000
+    );
+    assert_eq!(qpc_to_hns_with_frequency(3, 2), 15_000_000);
+    // no overflow with large counters
+    assert_eq!(
+      qpc_to_hns_with_frequency(i64::MAX / 2, 1_000_000_000),
+      (i64::MAX / 2) / 100
+    );
+  }
+
+  #[test]
+  fn samples() {
+    unsafe { MFStartup(MF_VERSION, MFSTARTUP_FULL) }.unwrap();
+
+    let sample = sample_from_buffer(&[1, 2, 3, 4], 100, 20).unwrap();
+    assert_eq!(unsafe { sample.GetSampleTime() }.unwrap(), 100);
+    assert_eq!(unsafe { sample.GetSampleDuration() }.unwrap(), 20);
+    assert_eq!(unsafe { sample.GetTotalLength() }.unwrap(), 4);
+
+    let frame = gradient(16, 16);
+    let sample = frame.to_mf_sample(Duration::from_millis(16)).unwrap();
+    assert_eq!(unsafe { sample.GetSampleDuration() }.unwrap(), 160_000);
+    assert_eq!(
+      unsafe { sample.GetTotalLength() }.unwrap() as usize,
+      frame.buffer.len()
+    );
+
+    unsafe { MFShutdown() }.unwrap();
+  }
+}
@@ -1,5 +1,12 @@
 use crate::error::Error;
 use std::result;
+use std::time::{Duration, SystemTime};
+use windows::Win32::Foundation::{BOOL, POINT, RECT};
+use windows::Win32::Graphics::Dxgi::{
+  Common::DXGI_FORMAT, DXGI_FRAME_STATISTICS, DXGI_OUTDUPL_DESC, DXGI_OUTDUPL_FRAME_INFO,
+  DXGI_OUTDUPL_MOVE_RECT, DXGI_OUTDUPL_POINTER_POSITION, DXGI_OUTDUPL_POINTER_SHAPE_INFO,
+  DXGI_OUTPUT_DESC, DXGI_RESOURCE_PRIORITY_MAXIMUM,
+};
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -7,3 +14,679 @@ pub struct MouseUpdateStatus {
   pub position_updated: bool,
   pub shape_updated: bool,
 }
+
+/// Options for one-shot captures like [`DuplicationContext::capture_into`](crate::duplication_context::DuplicationContext::capture_into).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CaptureOptions {
+  /// Override the timeout of the duplication context, in milliseconds.
+  pub timeout_ms: Option<u32>,
+  /// If the acquired frame doesn't contain a desktop update or no frame is acquired within the timeout,
+  /// acquire again at most `retries` times.
+  pub retries: u32,
+  /// DXGI never draws the pointer into the desktop image, so it's excluded by default.
+  /// If `true`, the last known pointer shape is drawn at the pointer position
+  /// by [`draw_pointer`](crate::pointer::draw_pointer), which requires a BGRA32 format.
+  /// The shape is known after a capture which got it, e.g. the first capture of the context.
+  pub include_cursor: bool,
+}
+
+/// Overrides for a single capture of a capturer, see [`Capturer::capture_with`](crate::capturer::model::Capturer::capture_with).
+/// The default arguments capture like [`Capturer::capture`](crate::capturer::model::Capturer::capture).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureArgs {
+  /// Override the timeout of the duplication context, in milliseconds.
+  pub timeout_ms: Option<u32>,
+  /// Only copy this area of the frame, in frame coordinates, e.g. for a thumbnail of a window.
+  /// The rest of the buffer keeps the previous capture.
+  pub region: Option<Rect>,
+  /// Draw the last known pointer, see [`CaptureOptions::include_cursor`].
+  /// The pointer is clipped to the region.
+  pub include_cursor: bool,
+}
+
+/// Brightness, contrast and gamma of one color channel, neutral by default.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelAdjustment {
+  /// Added to the normalized value, in `[-1, 1]`.
+  pub brightness: f32,
+  /// Scales the normalized value around the middle gray, `1` keeps it.
+  pub contrast: f32,
+  /// Values are raised to `1 / gamma` before contrast and brightness, `1` keeps them.
+  pub gamma: f32,
+}
+
+impl Default for ChannelAdjustment {
+  fn default() -> Self {
+    Self {
+      brightness: 0.0,
+      contrast: 1.0,
+      gamma: 1.0,
+    }
+  }
+}
+
+/// Per-channel adjustments applied while frames are copied,
+/// see [`DuplicationContext::set_color_adjustment`](crate::duplication_context::DuplicationContext::set_color_adjustment).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColorAdjustment {
+  pub red: ChannelAdjustment,
+  pub green: ChannelAdjustment,
+  pub blue: ChannelAdjustment,
+}
+
+impl ColorAdjustment {
+  /// The same adjustment for all channels.
+  pub fn uniform(channel: ChannelAdjustment) -> Self {
+    Self {
+      red: channel,
+      green: channel,
+      blue: channel,
+    }
+  }
+}
+
+/// Shorten the `AcquireNextFrame` timeout while the desktop is updated, for low latency,
+/// and lengthen it while nothing changes, for fewer wakeups. See [`AdaptiveTimeout::timeout_ms`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveTimeout {
+  /// The timeout while the desktop is updated, in milliseconds.
+  pub active_ms: u32,
+  /// The longest timeout while the desktop is idle, in milliseconds.
+  pub idle_ms: u32,
+  /// The timeout doubles for every `idle_after` without desktop updates.
+  pub idle_after: Duration,
+}
+
+impl Default for AdaptiveTimeout {
+  fn default() -> Self {
+    Self {
+      active_ms: 16,
+      idle_ms: 500,
+      idle_after: Duration::from_secs(1),
+    }
+  }
+}
+
+impl AdaptiveTimeout {
+  /// The timeout after `idle` without desktop updates:
+  /// `active_ms` until `idle_after`, then doubled for every `idle_after` up to `idle_ms`.
+  pub fn timeout_ms(&self, idle: Duration) -> u32 {
+    let steps = if self.idle_after.is_zero() {
+      u32::MAX
+    } else {
+      (idle.as_nanos() / self.idle_after.as_nanos()).min(u32::MAX as u128) as u32
+    };
+    if steps == 0 {
+      return self.active_ms;
+    }
+    // a u32 shifted by at most 32 bits fits in a u64, a zero timeout grows from 1 ms
+    let timeout = (self.active_ms.max(1) as u64) << steps.min(32);
+    timeout.min(self.idle_ms.max(self.active_ms) as u64) as u32
+  }
+}
+
+/// How [`DuplicationContext`](crate::duplication_context::DuplicationContext) waits for the next frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LatencyMode {
+  /// Block in `AcquireNextFrame` until a frame arrives or the timeout expires.
+  #[default]
+  Blocking,
+  /// Poll `AcquireNextFrame(0)` for at most `spin`, spinning between the first polls and yielding afterwards,
+  /// then block for the rest of the timeout. This picks up frames sooner than the blocking wait
+  /// at the cost of a busy CPU core, so keep `spin` around a frame interval.
+  UltraLow { spin: Duration },
+}
+
+/// Parameters of the readable texture created by
+/// [`DuplicationContext::create_readable_texture`](crate::duplication_context::DuplicationContext::create_readable_texture).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureOptions {
+  /// Eviction priority of the texture, `DXGI_RESOURCE_PRIORITY_MAXIMUM` by default.
+  /// Lower priorities may cause needless copies from GPU to RAM and huge RAM usage on some systems.
+  pub eviction_priority: u32,
+  /// If the desktop image is already in system memory (`DesktopImageInSystemMemory`),
+  /// copy it directly with `MapDesktopSurface` instead of through the readable texture.
+  pub map_system_memory: bool,
+  /// Create the texture as `DXGI_FORMAT_B8G8R8A8_UNORM_SRGB` instead of `DXGI_FORMAT_B8G8R8A8_UNORM`
+  /// if the device supports it, so color-managed consumers know the bytes are sRGB-encoded.
+  /// The bytes are the same, only the format is different.
+  pub srgb: bool,
+  /// Format of the texture if the adapter doesn't support `DXGI_FORMAT_B8G8R8A8_UNORM` textures,
+  /// or creating the texture fails with the other formats of the BGRA8 group.
+  /// The format must have 4 bytes per pixel and be in the typeless family of the duplication format,
+  /// or be `DXGI_FORMAT_R8G8B8A8_UNORM` for BGRA8 frames, otherwise it's ignored.
+  pub fallback_format: Option<DXGI_FORMAT>,
+}
+
+impl Default for TextureOptions {
+  fn default() -> Self {
+    Self {
+      eviction_priority: DXGI_RESOURCE_PRIORITY_MAXIMUM.0,
+      map_system_memory: false,
+      srgb: false,
+      fallback_format: None,
+    }
+  }
+}
+
+/// What a threaded capturer does with new frames when the consumer falls behind.
+/// Status events are never dropped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backpressure {
+  /// Queue at most N frames and block the capture loop until the consumer catches up.
+  Block(usize),
+  /// Queue at most N frames and drop the oldest queued frame.
+  DropOldest(usize),
+  /// Queue at most N frames and drop the new frame.
+  DropNewest(usize),
+  /// Only keep the latest frame.
+  #[default]
+  Latest,
+}
+
+/// How the consumer side of a threaded capturer treats frames which are too old when received,
+/// e.g. after a stall, so latency-sensitive consumers don't act on outdated screen content.
+/// The age is measured by [`Frame::age`](crate::frame::Frame::age), frames without provenance never expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameExpiry {
+  pub max_age: Duration,
+  /// Drop expired frames instead of marking them as stale.
+  pub drop: bool,
+}
+
+/// When a background capturer captures frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptureMode {
+  /// Capture continuously and deliver every desktop update.
+  #[default]
+  Push,
+  /// Only capture when a frame is requested.
+  Pull,
+}
+
+/// The scheduling priority of a background capture thread, see `SetThreadPriority`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ThreadPriority {
+  #[default]
+  Normal,
+  AboveNormal,
+  Highest,
+  /// Preempts almost every other thread, only for short captures which must not miss frames.
+  TimeCritical,
+}
+
+/// A task of the Multimedia Class Scheduler Service,
+/// which boosts registered threads above normal applications while the system is under load.
+/// See `AvSetMmThreadCharacteristicsW`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmcssTask {
+  Capture,
+  Games,
+  Playback,
+}
+
+impl MmcssTask {
+  /// The task name registered under the `SystemProfile\Tasks` registry key.
+  pub fn name(&self) -> &'static str {
+    match self {
+      MmcssTask::Capture => "Capture",
+      MmcssTask::Games => "Games",
+      MmcssTask::Playback => "Playback",
+    }
+  }
+}
+
+/// Select a monitor from the scanned duplication contexts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum MonitorSelector {
+  /// The primary monitor.
+  #[default]
+  Primary,
+  /// The monitor at the index of `Manager.contexts`.
+  Index(usize),
+  /// The monitor with the device name, e.g. `\\.\DISPLAY1`.
+  DeviceName(String),
+}
+
+/// Which adapter creates the D3D11 device used to duplicate an output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AdapterPreference {
+  /// The adapter the output is attached to.
+  #[default]
+  AttachedToOutput,
+  /// Try adapters from the integrated GPU to the discrete GPU,
+  /// see `DXGI_GPU_PREFERENCE_MINIMUM_POWER`.
+  MinimumPower,
+  /// Try adapters from the discrete GPU to the integrated GPU,
+  /// see `DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE`.
+  HighPerformance,
+}
+
+/// Identify a monitor by the index of its adapter and the index of the output on that adapter.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MonitorId {
+  pub adapter: u32,
+  pub output: u32,
+}
+
+/// Where a captured [`Frame`](crate::frame::Frame) comes from,
+/// to tell sources apart in pipelines which mix frames of several monitors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Provenance {
+  pub monitor: MonitorId,
+  /// The adapter which duplicated the frame, see [`AdapterDescExt::luid`](crate::utils::AdapterDescExt::luid).
+  pub adapter_luid: i64,
+  /// Unique in the process for each capturing source. Supervised capturers keep it across restarts.
+  pub source: u64,
+  /// The index of the frame in its source, starting from 0.
+  pub sequence: u64,
+  /// The wall-clock time when the frame was captured.
+  pub captured_at: SystemTime,
+}
+
+/// A point in virtual desktop coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+  pub x: i32,
+  pub y: i32,
+}
+
+impl Point {
+  pub fn new(x: i32, y: i32) -> Self {
+    Self { x, y }
+  }
+}
+
+/// A rectangle in virtual desktop coordinates. `right` and `bottom` are exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+  pub left: i32,
+  pub top: i32,
+  pub right: i32,
+  pub bottom: i32,
+}
+
+impl Rect {
+  pub fn new(left: i32, top: i32, right: i32, bottom: i32) -> Self {
+    Self {
+      left,
+      top,
+      right,
+      bottom,
+    }
+  }
+
+  /// Return the top-left corner.
+  pub fn position(&self) -> Point {
+    Point::new(self.left, self.top)
+  }
+
+  pub fn width(&self) -> u32 {
+    (self.right - self.left).max(0) as u32
+  }
+
+  pub fn height(&self) -> u32 {
+    (self.bottom - self.top).max(0) as u32
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.width() == 0 || self.height() == 0
+  }
+
+  pub fn contains(&self, point: &Point) -> bool {
+    point.x >= self.left && point.x < self.right && point.y >= self.top && point.y < self.bottom
+  }
+
+  /// Return the overlapping area of two rectangles, or `None` if they don't overlap.
+  pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+    let rect = Rect {
+      left: self.left.max(other.left),
+      top: self.top.max(other.top),
+      right: self.right.min(other.right),
+      bottom: self.bottom.min(other.bottom),
+    };
+    if rect.is_empty() {
+      None
+    } else {
+      Some(rect)
+    }
+  }
+}
+
+impl From<RECT> for Rect {
+  fn from(rect: RECT) -> Self {
+    Self::new(rect.left, rect.top, rect.right, rect.bottom)
+  }
+}
+
+/// A block of pixels moved within a frame, e.g. by scrolling, in frame coordinates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct MoveRect {
+  /// The top-left corner of the source block.
+  pub source: Point,
+  /// Where the block is moved to, with the same size as the source block.
+  pub destination: Rect,
+}
+
+impl From<DXGI_OUTDUPL_MOVE_RECT> for MoveRect {
+  fn from(rect: DXGI_OUTDUPL_MOVE_RECT) -> Self {
+    Self {
+      source: Point::new(rect.SourcePoint.x, rect.SourcePoint.y),
+      destination: rect.DestinationRect.into(),
+    }
+  }
+}
+
+/// Frame statistics of an output, see `DXGI_FRAME_STATISTICS`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStatistics {
+  /// How many times the output has been presented to.
+  pub present_count: u32,
+  /// The vertical blank count of the latest present.
+  pub present_refresh_count: u32,
+  /// The vertical blank count when the statistics were sampled.
+  pub sync_refresh_count: u32,
+  /// QPC time of the latest vertical blank.
+  pub sync_qpc_time: i64,
+}
+
+impl From<DXGI_FRAME_STATISTICS> for FrameStatistics {
+  fn from(stats: DXGI_FRAME_STATISTICS) -> Self {
+    Self {
+      present_count: stats.PresentCount,
+      present_refresh_count: stats.PresentRefreshCount,
+      sync_refresh_count: stats.SyncRefreshCount,
+      sync_qpc_time: stats.SyncQPCTime,
+    }
+  }
+}
+
+/// A `repr(C)` mirror of `DXGI_OUTDUPL_FRAME_INFO` with a layout owned by this crate,
+/// for consumers which shouldn't depend on the layouts of `windows` structs, e.g. in shared memory.
+/// Booleans are `u32`s which are non-zero if true.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct FrameInfo {
+  /// QPC time of the last desktop update, 0 if the desktop image is not updated.
+  pub last_present_time: i64,
+  /// QPC time of the last pointer update, 0 if the pointer is not updated.
+  pub last_mouse_update_time: i64,
+  pub accumulated_frames: u32,
+  pub rects_coalesced: u32,
+  pub protected_content_masked_out: u32,
+  pub pointer_visible: u32,
+  pub pointer_x: i32,
+  pub pointer_y: i32,
+  pub total_metadata_buffer_size: u32,
+  pub pointer_shape_buffer_size: u32,
+}
+
+impl From<&DXGI_OUTDUPL_FRAME_INFO> for FrameInfo {
+  fn from(info: &DXGI_OUTDUPL_FRAME_INFO) -> Self {
+    Self {
+      last_present_time: info.LastPresentTime,
+      last_mouse_update_time: info.LastMouseUpdateTime,
+      accumulated_frames: info.AccumulatedFrames,
+      rects_coalesced: info.RectsCoalesced.0 as u32,
+      protected_content_masked_out: info.ProtectedContentMaskedOut.0 as u32,
+      pointer_visible: info.PointerPosition.Visible.0 as u32,
+      pointer_x: info.PointerPosition.Position.x,
+      pointer_y: info.PointerPosition.Position.y,
+      total_metadata_buffer_size: info.TotalMetadataBufferSize,
+      pointer_shape_buffer_size: info.PointerShapeBufferSize,
+    }
+  }
+}
+
+impl From<&FrameInfo> for DXGI_OUTDUPL_FRAME_INFO {
+  fn from(info: &FrameInfo) -> Self {
+    DXGI_OUTDUPL_FRAME_INFO {
+      LastPresentTime: info.last_present_time,
+      LastMouseUpdateTime: info.last_mouse_update_time,
+      AccumulatedFrames: info.accumulated_frames,
+      RectsCoalesced: BOOL(info.rects_coalesced as i32),
+      ProtectedContentMaskedOut: BOOL(info.protected_content_masked_out as i32),
+      PointerPosition: DXGI_OUTDUPL_POINTER_POSITION {
+        Position: POINT {
+          x: info.pointer_x,
+          y: info.pointer_y,
+        },
+        Visible: BOOL(info.pointer_visible as i32),
+      },
+      TotalMetadataBufferSize: info.total_metadata_buffer_size,
+      PointerShapeBufferSize: info.pointer_shape_buffer_size,
+    }
+  }
+}
+
+/// A `repr(C)` mirror of `DXGI_OUTDUPL_POINTER_SHAPE_INFO`, see [`FrameInfo`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PointerShapeInfo {
+  /// `DXGI_OUTDUPL_POINTER_SHAPE_TYPE` of the shape.
+  pub kind: u32,
+  pub width: u32,
+  pub height: u32,
+  pub pitch: u32,
+  pub hot_spot_x: i32,
+  pub hot_spot_y: i32,
+}
+
+impl From<&DXGI_OUTDUPL_POINTER_SHAPE_INFO> for PointerShapeInfo {
+  fn from(info: &DXGI_OUTDUPL_POINTER_SHAPE_INFO) -> Self {
+    Self {
+      kind: info.Type,
+      width: info.Width,
+      height: info.Height,
+      pitch: info.Pitch,
+      hot_spot_x: info.HotSpot.x,
+      hot_spot_y: info.HotSpot.y,
+    }
+  }
+}
+
+/// A `repr(C)` mirror of `DXGI_OUTPUT_DESC` without the monitor handle, see [`FrameInfo`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OutputInfo {
+  /// Null-terminated UTF-16 device name, e.g. `\\.\DISPLAY1`.
+  pub device_name: [u16; 32],
+  /// Desktop coordinates of the output.
+  pub left: i32,
+  pub top: i32,
+  pub right: i32,
+  pub bottom: i32,
+  pub attached_to_desktop: u32,
+  /// `DXGI_MODE_ROTATION` of the output.
+  pub rotation: u32,
+}
+
+impl From<&DXGI_OUTPUT_DESC> for OutputInfo {
+  fn from(desc: &DXGI_OUTPUT_DESC) -> Self {
+    let rect = desc.DesktopCoordinates;
+    Self {
+      device_name: desc.DeviceName,
+      left: rect.left,
+      top: rect.top,
+      right: rect.right,
+      bottom: rect.bottom,
+      attached_to_desktop: desc.AttachedToDesktop.0 as u32,
+      rotation: desc.Rotation.0 as u32,
+    }
+  }
+}
+
+/// A `repr(C)` mirror of `DXGI_OUTDUPL_DESC`, see [`FrameInfo`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DuplicationInfo {
+  /// The size of the desktop image, not swapped by the rotation.
+  pub width: u32,
+  pub height: u32,
+  pub refresh_rate_numerator: u32,
+  pub refresh_rate_denominator: u32,
+  /// `DXGI_FORMAT` of the desktop image.
+  pub format: u32,
+  /// `DXGI_MODE_ROTATION` of the desktop image.
+  pub rotation: u32,
+  pub desktop_image_in_system_memory: u32,
+}
+
+impl From<&DXGI_OUTDUPL_DESC> for DuplicationInfo {
+  fn from(desc: &DXGI_OUTDUPL_DESC) -> Self {
+    let mode = &desc.ModeDesc;
+    Self {
+      width: mode.Width,
+      height: mode.Height,
+      refresh_rate_numerator: mode.RefreshRate.Numerator,
+      refresh_rate_denominator: mode.RefreshRate.Denominator,
+      format: mode.Format.0,
+      rotation: desc.Rotation.0 as u32,
+      desktop_image_in_system_memory: desc.DesktopImageInSystemMemory.0 as u32,
+    }
+  }
+}
+
+/// Delays of an acquired frame, measured by
+/// [`DuplicationContext::frame_latency`](crate::duplication_context::DuplicationContext::frame_latency).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLatency {
+  /// From the compositor present (`LastPresentTime`) to the measurement.
+  pub present_to_capture: Duration,
+  /// From the vertical blank before the present to the present.
+  pub vblank_to_present: Option<Duration>,
+}
+
+/// Everything a monitor picker usually needs, collected by
+/// [`DuplicationContext::summary`](crate::duplication_context::DuplicationContext::summary).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorSummary {
+  pub id: MonitorId,
+  /// Device name, e.g. `\\.\DISPLAY1`.
+  pub name: String,
+  /// Monitor rectangle in virtual desktop coordinates.
+  pub rect: Rect,
+  /// Work area rectangle in virtual desktop coordinates.
+  pub work_area: Rect,
+  pub primary: bool,
+  /// DPI scale factor, e.g. `1.5` for 144 DPI.
+  pub scale: f32,
+  /// Refresh rate in Hz.
+  pub refresh_rate: f64,
+}
+
+/// An output which can't be duplicated, e.g. an indirect display on a software adapter.
+/// Use [`GdiCapturer`](crate::gdi::GdiCapturer) with its `rect` instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedOutput {
+  pub id: MonitorId,
+  /// Output device name, e.g. `\\.\DISPLAY1`.
+  pub device_name: String,
+  /// Desktop coordinates of the output.
+  pub rect: Rect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+  Left,
+  Right,
+  Middle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+  Move,
+  Down(MouseButton),
+  Up(MouseButton),
+  /// A virtual-key code, e.g. from `KBDLLHOOKSTRUCT`.
+  KeyDown(u16),
+  KeyUp(u16),
+}
+
+/// An input event at a QPC `time`, e.g. from `QueryPerformanceCounter` in a low-level hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+  pub time: i64,
+  /// The pointer position in virtual desktop coordinates, also for key events.
+  pub position: Point,
+  pub kind: InputKind,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{AdaptiveTimeout, FrameInfo, Point, PointerShapeInfo, Rect};
+  use std::mem::size_of;
+  use std::time::Duration;
+  use windows::Win32::Foundation::{BOOL, POINT};
+  use windows::Win32::Graphics::Dxgi::{
+    DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_POINTER_POSITION, DXGI_OUTDUPL_POINTER_SHAPE_INFO,
+  };
+
+  #[test]
+  fn rect() {
+    let a = Rect::new(0, 0, 1920, 1080);
+    assert_eq!(a.position(), Point::new(0, 0));
+    assert_eq!(a.width(), 1920);
+    assert_eq!(a.height(), 1080);
+    assert!(!a.is_empty());
+    assert!(a.contains(&Point::new(0, 0)));
+    assert!(a.contains(&Point::new(1919, 1079)));
+    assert!(!a.contains(&Point::new(1920, 0)));
+    assert!(!a.contains(&Point::new(0, -1)));
+    assert!(Rect::new(10, 10, 5, 20).is_empty());
+
+    let b = Rect::new(1900, -100, 3000, 100);
+    assert_eq!(a.intersect(&b), Some(Rect::new(1900, 0, 1920, 100)));
+    assert_eq!(b.intersect(&a), Some(Rect::new(1900, 0, 1920, 100)));
+    assert_eq!(a.intersect(&Rect::new(1920, 0, 3840, 1080)), None);
+  }
+
+  #[test]
+  fn adaptive_timeout() {
+    let timeout = AdaptiveTimeout {
+      active_ms: 10,
+      idle_ms: 100,
+      idle_after: Duration::from_secs(1),
+    };
+    assert_eq!(timeout.timeout_ms(Duration::ZERO), 10);
+    assert_eq!(timeout.timeout_ms(Duration::from_millis(999)), 10);
+    assert_eq!(timeout.timeout_ms(Duration::from_secs(1)), 20);
+    assert_eq!(timeout.timeout_ms(Duration::from_secs(3)), 80);
+    assert_eq!(timeout.timeout_ms(Duration::from_secs(4)), 100);
+    assert_eq!(timeout.timeout_ms(Duration::from_secs(3600)), 100);
+  }
+
+  #[test]
+  fn ffi_mirrors() {
+    // the layouts are part of the shared memory protocol
+    assert_eq!(size_of::<FrameInfo>(), 48);
+    assert_eq!(size_of::<PointerShapeInfo>(), 24);
+
+    let info = FrameInfo::from(&DXGI_OUTDUPL_FRAME_INFO {
+      LastPresentTime: 10,
+      AccumulatedFrames: 2,
+      PointerPosition: DXGI_OUTDUPL_POINTER_POSITION {
+        Position: POINT { x: -5, y: 7 },
+        Visible: BOOL(1),
+      },
+      ..Default::default()
+    });
+    assert_eq!(info.last_present_time, 10);
+    assert_eq!(info.accumulated_frames, 2);
+    assert_eq!(
+      (info.pointer_visible, info.pointer_x, info.pointer_y),
+      (1, -5, 7)
+    );
+
+    let shape = PointerShapeInfo::from(&DXGI_OUTDUPL_POINTER_SHAPE_INFO {
+      Type: 2,
+      Width: 32,
+      Height: 32,
+      Pitch: 128,
+      HotSpot: POINT { x: 1, y: 2 },
+    });
+    assert_eq!((shape.kind, shape.pitch, shape.hot_spot_y), (2, 128, 2));
+  }
+}
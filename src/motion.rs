@@ -0,0 +1,228 @@
+//! Detect changes inside regions of interest, e.g. to wait for a UI update in test automation.
+
+use crate::frame::Frame;
+use crate::model::Rect;
+use crate::utils::FormatExt;
+
+/// Identifies a region registered by [`MotionDetector::add_region`].
+pub type RegionId = u32;
+
+/// The content of a region changed beyond its threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MotionEvent {
+  pub region: RegionId,
+  /// How many pixels of the region differ from its content at the previous event.
+  pub changed_pixels: u64,
+  /// The `LastPresentTime` of the frame, in QPC ticks.
+  pub time: i64,
+}
+
+#[derive(Debug, Clone)]
+struct Region {
+  id: RegionId,
+  rect: Rect,
+  threshold: u64,
+  /// The pixels of the rect clipped to the frame, at the previous event.
+  /// Empty before the first frame.
+  baseline: Vec<u8>,
+}
+
+/// Compare regions of frames against their content at the previous event and report changes.
+///
+/// Small changes accumulate until they exceed the threshold, so slow updates are not missed.
+/// Dirty rects skip regions which are known to be unchanged without diffing their pixels.
+#[derive(Debug, Clone, Default)]
+pub struct MotionDetector {
+  regions: Vec<Region>,
+  next_id: RegionId,
+  /// Channel differences up to this are ignored, e.g. to tolerate dithering.
+  pub tolerance: u8,
+  /// The frame size of the baselines.
+  size: (u32, u32),
+}
+
+impl MotionDetector {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Watch `rect` in frame coordinates and report when more than `threshold` pixels changed.
+  pub fn add_region(&mut self, rect: Rect, threshold: u64) -> RegionId {
+    let id = self.next_id;
+    self.next_id += 1;
+    self.regions.push(Region {
+      id,
+      rect,
+      threshold,
+      baseline: Vec::new(),
+    });
+    id
+  }
+
+  /// Return `false` if the region doesn't exist.
+  pub fn remove_region(&mut self, id: RegionId) -> bool {
+    let len = self.regions.len();
+    self.regions.retain(|region| region.id != id);
+    self.regions.len() != len
+  }
+
+  /// Diff the regions of `frame`. Pass the dirty rects of the frame if available,
+  /// regions which don't intersect any dirty rect are skipped.
+  ///
+  /// The first frame, and the first frame after a resolution change, only records the baselines.
+  pub fn process(&mut self, frame: &Frame, dirty_rects: Option<&[Rect]>) -> Vec<MotionEvent> {
+    let bounds = Rect::new(0, 0, frame.width as i32, frame.height as i32);
+    if self.size != (frame.width, frame.height) {
+      self.size = (frame.width, frame.height);
+      self
+        .regions
+        .iter_mut()
+        .for_each(|region| region.baseline.clear());
+    }
+    let bytes_per_pixel = frame.format.bytes_per_pixel();
+
+    let mut events = Vec::new();
+    for region in &mut self.regions {
+      let Some(rect) = region.rect.intersect(&bounds) else {
+        continue;
+      };
+      if region.baseline.is_empty() {
+        copy_region(frame, &rect, bytes_per_pixel, &mut region.baseline);
+        continue;
+      }
+      if dirty_rects.is_some_and(|rects| rects.iter().all(|r| r.intersect(&rect).is_none())) {
+        continue;
+      }
+      let changed_pixels = diff_region(
+        frame,
+        &rect,
+        bytes_per_pixel,
+        &region.baseline,
+        self.tolerance,
+      );
+      if changed_pixels > region.threshold {
+        copy_region(frame, &rect, bytes_per_pixel, &mut region.baseline);
+        events.push(MotionEvent {
+          region: region.id,
+          changed_pixels,
+          time: frame.info.LastPresentTime,
+        });
+      }
+    }
+    events
+  }
+}
+
+/// Copy the rows of `rect` to `dest`. `rect` must be inside the frame.
+fn copy_region(frame: &Frame, rect: &Rect, bytes_per_pixel: usize, dest: &mut Vec<u8>) {
+  dest.clear();
+  for row in rows(frame, rect, bytes_per_pixel) {
+    dest.extend_from_slice(row);
+  }
+}
+
+/// Count the pixels of `rect` which differ from `baseline` by more than `tolerance` in any channel.
+fn diff_region(
+  frame: &Frame,
+  rect: &Rect,
+  bytes_per_pixel: usize,
+  baseline: &[u8],
+  tolerance: u8,
+) -> u64 {
+  let row_bytes = rect.width() as usize * bytes_per_pixel;
+  rows(frame, rect, bytes_per_pixel)
+    .zip(baseline.chunks_exact(row_bytes))
+    .map(|(row, previous)| {
+      // compare whole rows first, most rows of a changed region are usually unchanged
+      if row == previous {
+        return 0;
+      }
+      row
+        .chunks_exact(bytes_per_pixel)
+        .zip(previous.chunks_exact(bytes_per_pixel))
+        .filter(|(a, b)| {
+          a.iter()
+            .zip(b.iter())
+            .any(|(a, b)| a.abs_diff(*b) > tolerance)
+        })
+        .count() as u64
+    })
+    .sum()
+}
+
+fn rows<'a>(
+  frame: &'a Frame,
+  rect: &Rect,
+  bytes_per_pixel: usize,
+) -> impl Iterator<Item = &'a [u8]> {
+  let line_bytes = frame.width as usize * bytes_per_pixel;
+  let offset = rect.left as usize * bytes_per_pixel;
+  let bytes = rect.width() as usize * bytes_per_pixel;
+  (rect.top as usize..rect.bottom as usize).map(move |y| {
+    let start = y * line_bytes + offset;
+    &frame.buffer[start..start + bytes]
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{MotionDetector, MotionEvent};
+  use crate::model::Rect;
+  use crate::test_utils::{generate, moving_box};
+
+  #[test]
+  fn motion_detector() {
+    let mut detector = MotionDetector::new();
+    let top_left = detector.add_region(Rect::new(0, 0, 8, 8), 6);
+    let bottom_right = detector.add_region(Rect::new(24, 24, 40, 40), 0);
+
+    // the first frame records the baselines
+    assert!(detector.process(&moving_box(32, 32, 2, 0), None).is_empty());
+    // the box moves by one pixel, 3 pixels are cleared and 3 are covered
+    assert!(detector.process(&moving_box(32, 32, 2, 1), None).is_empty());
+    // changes accumulate against the baseline: the box now covers 4 other pixels, 8 changed in total
+    let frame = moving_box(32, 32, 2, 2);
+    assert_eq!(
+      detector.process(&frame, None),
+      [MotionEvent {
+        region: top_left,
+        changed_pixels: 8,
+        time: 3,
+      }]
+    );
+    // the baseline is updated after an event
+    assert!(detector.process(&frame, None).is_empty());
+
+    // dirty rects outside the region skip it, though the pixels differ
+    let frame = moving_box(32, 32, 2, 26);
+    assert!(detector
+      .process(&frame, Some(&[Rect::new(0, 0, 10, 10)]))
+      .is_empty());
+    let events = detector.process(&frame, Some(&[Rect::new(20, 20, 30, 30)]));
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].region, bottom_right);
+    assert_eq!(events[0].changed_pixels, 4);
+
+    assert!(detector.remove_region(bottom_right));
+    assert!(!detector.remove_region(bottom_right));
+  }
+
+  #[test]
+  fn tolerance() {
+    let mut detector = MotionDetector::new();
+    detector.tolerance = 2;
+    let region = detector.add_region(Rect::new(0, 0, 4, 4), 0);
+    detector.process(&generate(4, 4, |_, _| [100; 3]), None);
+    assert!(detector
+      .process(&generate(4, 4, |_, _| [102; 3]), None)
+      .is_empty());
+    let events = detector.process(&generate(4, 4, |x, _| [100 + x as u8 * 2; 3]), None);
+    assert_eq!(events[0].region, region);
+    assert_eq!(events[0].changed_pixels, 8);
+
+    // a resolution change resets the baselines
+    assert!(detector
+      .process(&generate(8, 8, |_, _| [0; 3]), None)
+      .is_empty());
+  }
+}
@@ -0,0 +1,231 @@
+use crate::frame::Frame;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// Space between glyphs and lines, in font pixels.
+const SPACING: u32 = 1;
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Draw capture fps, dropped frames and per-stage latency onto frames,
+/// so the pipeline health is visible in previews and recordings during development.
+#[derive(Debug, Clone)]
+pub struct StatsOverlay {
+  /// Size of a font pixel, in frame pixels.
+  pub scale: u32,
+  frame_times: VecDeque<Instant>,
+  dropped: u64,
+  stages: Vec<(String, Duration)>,
+}
+
+impl Default for StatsOverlay {
+  fn default() -> Self {
+    Self {
+      scale: 2,
+      frame_times: VecDeque::new(),
+      dropped: 0,
+      stages: Vec::new(),
+    }
+  }
+}
+
+impl StatsOverlay {
+  /// Set the dropped frame count, e.g. from
+  /// [`SupervisedCapturer::dropped_frames`](crate::capturer::supervised::SupervisedCapturer::dropped_frames).
+  pub fn set_dropped(&mut self, dropped: u64) {
+    self.dropped = dropped;
+  }
+
+  /// Set the latency of a pipeline stage. Stages are drawn in the order they are first set.
+  pub fn set_stage(&mut self, name: &str, latency: Duration) {
+    match self.stages.iter_mut().find(|(n, _)| n == name) {
+      Some(stage) => stage.1 = latency,
+      None => self.stages.push((name.to_string(), latency)),
+    }
+  }
+
+  /// Frames per second over the last second.
+  pub fn fps(&self) -> f64 {
+    match (self.frame_times.front(), self.frame_times.back()) {
+      (Some(first), Some(last)) if last > first => {
+        (self.frame_times.len() - 1) as f64 / (*last - *first).as_secs_f64()
+      }
+      _ => 0.0,
+    }
+  }
+
+  /// The text lines drawn by [`StatsOverlay::apply`].
+  pub fn lines(&self) -> Vec<String> {
+    let mut lines = vec![
+      format!("FPS {:.1}", self.fps()),
+      format!("DROP {}", self.dropped),
+    ];
+    for (name, latency) in &self.stages {
+      lines.push(format!(
+        "{} {:.1}MS",
+        name.to_uppercase(),
+        latency.as_secs_f64() * 1000.0
+      ));
+    }
+    lines
+  }
+
+  /// Count the frame for fps and draw the stats at its top-left corner.
+  pub fn apply(&mut self, frame: &mut Frame) {
+    self.record(Instant::now());
+
+    let lines = self.lines();
+    let line_height = (GLYPH_HEIGHT + SPACING) * self.scale;
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0) as u32;
+    let width = columns * (GLYPH_WIDTH + SPACING) * self.scale + SPACING * self.scale;
+    let height = lines.len() as u32 * line_height + SPACING * self.scale;
+
+    // dark background for readability on any content
+    fill(frame, 0, 0, width, height, [0, 0, 0, 0xFF]);
+    for (i, line) in lines.iter().enumerate() {
+      draw_text(
+        frame,
+        SPACING * self.scale,
+        SPACING * self.scale + i as u32 * line_height,
+        self.scale,
+        line,
+      );
+    }
+  }
+
+  fn record(&mut self, now: Instant) {
+    self.frame_times.push_back(now);
+    while let Some(&first) = self.frame_times.front() {
+      if now.duration_since(first) <= FPS_WINDOW {
+        break;
+      }
+      self.frame_times.pop_front();
+    }
+  }
+}
+
+/// Fill a rectangle with a BGRA color, clipped to the frame.
+fn fill(frame: &mut Frame, x: u32, y: u32, width: u32, height: u32, color: [u8; 4]) {
+  let right = (x + width).min(frame.width);
+  let bottom = (y + height).min(frame.height);
+  for row in y.min(bottom)..bottom {
+    for column in x.min(right)..right {
+      let offset = (row as usize * frame.width as usize + column as usize) * 4;
+      frame.buffer[offset..offset + 4].copy_from_slice(&color);
+    }
+  }
+}
+
+/// Draw white text with the top-left corner at (`x`, `y`), clipped to the frame.
+fn draw_text(frame: &mut Frame, x: u32, y: u32, scale: u32, text: &str) {
+  for (i, c) in text.chars().enumerate() {
+    let left = x + i as u32 * (GLYPH_WIDTH + SPACING) * scale;
+    for (row, bits) in glyph(c).iter().enumerate() {
+      for column in 0..GLYPH_WIDTH {
+        if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+          fill(
+            frame,
+            left + column * scale,
+            y + row as u32 * scale,
+            scale,
+            scale,
+            [0xFF, 0xFF, 0xFF, 0xFF],
+          );
+        }
+      }
+    }
+  }
+}
+
+/// Return the 5x7 bitmap of a character, one byte per row, the highest of the 5 bits is the leftmost.
+fn glyph(c: char) -> [u8; 7] {
+  match c.to_ascii_uppercase() {
+    '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+    '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+    '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+    '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+    '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+    '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+    '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+    '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+    '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+    'A' => [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11],
+    'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+    'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+    'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+    'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+    'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+    'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+    'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+    'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+    'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+    'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+    'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+    'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+    'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+    'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+    'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+    'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+    'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+    'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+    'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+    'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+    'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+    'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+    'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+    'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+    'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+    '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+    ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+    '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+    '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+    '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+    ' ' => [0x00; 7],
+    _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{draw_text, StatsOverlay};
+  use crate::test_utils::filled;
+  use std::time::{Duration, Instant};
+
+  #[test]
+  fn stats() {
+    let mut overlay = StatsOverlay::default();
+    let start = Instant::now();
+    for i in 0..=30 {
+      overlay.record(start + Duration::from_millis(i * 20));
+    }
+    assert!((overlay.fps() - 50.0).abs() < 0.01);
+    // frames older than the window are forgotten
+    overlay.record(start + Duration::from_millis(1000 + 30 * 20));
+    assert!(overlay.frame_times.len() < 32);
+
+    overlay.set_dropped(3);
+    overlay.set_stage("capture", Duration::from_micros(4200));
+    overlay.set_stage("encode", Duration::from_millis(8));
+    overlay.set_stage("capture", Duration::from_micros(3100));
+    let lines = overlay.lines();
+    assert_eq!(lines[1], "DROP 3");
+    assert_eq!(lines[2..], ["CAPTURE 3.1MS", "ENCODE 8.0MS"]);
+  }
+
+  #[test]
+  fn draw() {
+    let mut frame = filled(8, 8, 0x80);
+    draw_text(&mut frame, 0, 0, 1, "1");
+    // the top row of '1' is 0x04, only the 3rd column is set
+    let top: Vec<u8> = (0..5).map(|x| frame.buffer[x * 4]).collect();
+    assert_eq!(top, [0x80, 0x80, 0xFF, 0x80, 0x80]);
+
+    // drawing is clipped to the frame
+    let mut overlay = StatsOverlay::default();
+    overlay.set_stage("a very long stage name", Duration::from_millis(1));
+    overlay.apply(&mut frame);
+    assert_eq!(frame.buffer.len(), 8 * 8 * 4);
+  }
+}
@@ -0,0 +1,167 @@
+//! Turn the variable rate of captured frames into a constant frame rate, e.g. for encoders which need CFR input.
+
+use crate::frame::Frame;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A frame in a slot of the constant rate stream.
+#[derive(Debug, Clone)]
+pub struct PacedFrame {
+  /// Shared with the previous slots while the desktop is static, so repeating doesn't copy the buffer.
+  pub frame: Arc<Frame>,
+  /// The slot, counted from the first frame.
+  pub index: u64,
+  /// `index` frame intervals, the timestamp for the encoder.
+  pub timestamp: Duration,
+  /// The frame was already emitted in an earlier slot.
+  pub repeated: bool,
+}
+
+/// Emit one frame per interval, starting at the first pushed frame.
+///
+/// Each slot gets the newest frame pushed before its time: frames pushed within one interval
+/// are dropped except the newest, and the last frame is repeated while nothing is pushed.
+/// Call [`FramePacer::fill`] regularly, e.g. after each capture timeout, to emit the repeats.
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+  interval: Duration,
+  start: Option<Instant>,
+  last: Option<Arc<Frame>>,
+  /// Whether `last` is not emitted yet.
+  fresh: bool,
+  next_index: u64,
+}
+
+impl FramePacer {
+  /// Emit `fps` frames per second, at least 1.
+  pub fn new(fps: u32) -> Self {
+    Self::with_interval(Duration::from_secs(1) / fps.max(1))
+  }
+
+  pub fn with_interval(interval: Duration) -> Self {
+    Self {
+      interval: interval.max(Duration::from_nanos(1)),
+      start: None,
+      last: None,
+      fresh: false,
+      next_index: 0,
+    }
+  }
+
+  pub fn interval(&self) -> Duration {
+    self.interval
+  }
+
+  /// How many slots are emitted.
+  pub fn emitted(&self) -> u64 {
+    self.next_index
+  }
+
+  /// The time of the next slot, `None` before the first frame.
+  pub fn next_slot(&self) -> Option<Instant> {
+    self
+      .start
+      .map(|start| start + self.slot_offset(self.next_index))
+  }
+
+  /// Add a frame captured at `time`, and return the slots up to `time`:
+  /// the slots before it repeat the previous frame, a slot at `time` gets this frame.
+  pub fn push(&mut self, frame: Frame, time: Instant) -> Vec<PacedFrame> {
+    let mut slots = Vec::new();
+    if self.start.is_none() {
+      self.start = Some(time);
+    }
+    self.emit(&mut slots, |slot| slot < time);
+    self.last = Some(Arc::new(frame));
+    self.fresh = true;
+    self.emit(&mut slots, |slot| slot <= time);
+    slots
+  }
+
+  /// Return the slots up to `now`, repeating the last frame if no new frame was pushed.
+  pub fn fill(&mut self, now: Instant) -> Vec<PacedFrame> {
+    let mut slots = Vec::new();
+    self.emit(&mut slots, |slot| slot <= now);
+    slots
+  }
+
+  /// Forget the frames and restart the slots at the next pushed frame, e.g. for a new recording.
+  pub fn reset(&mut self) {
+    *self = Self::with_interval(self.interval);
+  }
+
+  fn emit(&mut self, slots: &mut Vec<PacedFrame>, due: impl Fn(Instant) -> bool) {
+    let (Some(start), Some(frame)) = (self.start, &self.last) else {
+      return;
+    };
+    loop {
+      let offset = self.slot_offset(self.next_index);
+      if !due(start + offset) {
+        return;
+      }
+      slots.push(PacedFrame {
+        frame: frame.clone(),
+        index: self.next_index,
+        timestamp: offset,
+        repeated: !self.fresh,
+      });
+      self.fresh = false;
+      self.next_index += 1;
+    }
+  }
+
+  fn slot_offset(&self, index: u64) -> Duration {
+    Duration::from_nanos((self.interval.as_nanos() * index as u128).min(u64::MAX as u128) as u64)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::FramePacer;
+  use crate::test_utils::moving_box;
+  use std::sync::Arc;
+  use std::time::{Duration, Instant};
+
+  #[test]
+  fn pacing() {
+    let start = Instant::now();
+    let ms = Duration::from_millis;
+    let mut pacer = FramePacer::new(10);
+    assert!(pacer.fill(start).is_empty());
+
+    // the first frame starts slot 0
+    let slots = pacer.push(moving_box(8, 8, 2, 0), start);
+    assert_eq!(slots.len(), 1);
+    assert!(!slots[0].repeated);
+    assert_eq!(pacer.next_slot(), Some(start + ms(100)));
+
+    // a static desktop repeats the frame without copying
+    let slots = pacer.fill(start + ms(250));
+    assert_eq!(slots.len(), 2);
+    assert!(slots.iter().all(|slot| slot.repeated));
+    assert_eq!(slots[1].index, 2);
+    assert_eq!(slots[1].timestamp, ms(200));
+    assert!(Arc::ptr_eq(&slots[0].frame, &slots[1].frame));
+
+    // only the newest frame of an interval is emitted, at the next slot
+    assert!(pacer
+      .push(moving_box(8, 8, 2, 1), start + ms(260))
+      .is_empty());
+    assert!(pacer
+      .push(moving_box(8, 8, 2, 2), start + ms(280))
+      .is_empty());
+    let slots = pacer.fill(start + ms(300));
+    assert_eq!(slots.len(), 1);
+    assert!(!slots[0].repeated);
+    assert_eq!(slots[0].frame.buffer, moving_box(8, 8, 2, 2).buffer);
+
+    // slots missed before a frame repeat the previous frame
+    let slots = pacer.push(moving_box(8, 8, 2, 3), start + ms(500));
+    let repeated: Vec<_> = slots.iter().map(|slot| slot.repeated).collect();
+    assert_eq!(repeated, [true, false]);
+    assert_eq!(pacer.emitted(), 6);
+
+    pacer.reset();
+    assert_eq!(pacer.next_slot(), None);
+  }
+}
@@ -0,0 +1,374 @@
+//! Decode pointer shapes into BGRA32 images, e.g. to draw the pointer onto frames or send it to other processes.
+//! DXGI never draws the pointer into the desktop image, [`draw_pointer`] does.
+
+use crate::model::{Point, Rect};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use windows::Win32::Graphics::Dxgi::{
+  DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_POINTER_SHAPE_INFO, DXGI_OUTDUPL_POINTER_SHAPE_TYPE,
+  DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR, DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MASKED_COLOR,
+  DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MONOCHROME,
+};
+
+/// A pointer shape as a BGRA32 image with straight alpha.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PointerImage {
+  pub width: u32,
+  pub height: u32,
+  /// The position within the image which the pointer position points to.
+  pub hot_spot: Point,
+  /// Pixels row by row without padding.
+  pub buffer: Vec<u8>,
+}
+
+/// Decode a pointer shape from [`Capturer::pointer_shape_buffer`](crate::capturer::model::Capturer::pointer_shape_buffer).
+/// Return `None` if the type is unknown or `shape` is too short.
+///
+/// Pixels which invert the screen can't be represented in an image, they are drawn black.
+pub fn decode_pointer_shape(
+  info: &DXGI_OUTDUPL_POINTER_SHAPE_INFO,
+  shape: &[u8],
+) -> Option<PointerImage> {
+  let kind = DXGI_OUTDUPL_POINTER_SHAPE_TYPE(info.Type as i32);
+  let monochrome = kind == DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MONOCHROME;
+  if !monochrome
+    && kind != DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR
+    && kind != DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MASKED_COLOR
+  {
+    return None;
+  }
+  let pitch = info.Pitch as usize;
+  let width = info.Width;
+  // monochrome shapes hold the AND mask and then the XOR mask, each of half the height
+  let height = if monochrome {
+    info.Height / 2
+  } else {
+    info.Height
+  };
+  let row_bytes = if monochrome {
+    (width as usize).div_ceil(8)
+  } else {
+    width as usize * 4
+  };
+  if info.Height == 0 || shape.len() < pitch * (info.Height as usize - 1) + row_bytes {
+    return None;
+  }
+
+  let mut buffer = Vec::with_capacity(width as usize * height as usize * 4);
+  for y in 0..height as usize {
+    for x in 0..width as usize {
+      let pixel = if monochrome {
+        let bit = |row: usize| shape[row * pitch + x / 8] & (0x80 >> (x % 8)) != 0;
+        match (bit(y), bit(y + height as usize)) {
+          (true, false) => [0; 4],
+          (false, false) => [0, 0, 0, 0xFF],
+          (false, true) => [0xFF; 4],
+          (true, true) => [0, 0, 0, 0xFF],
+        }
+      } else {
+        let [b, g, r, a]: [u8; 4] = shape[y * pitch + x * 4..][..4].try_into().unwrap();
+        match (kind == DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR, a, [b, g, r]) {
+          (true, _, _) => [b, g, r, a],
+          // the masked pixel replaces the screen
+          (false, 0, _) => [b, g, r, 0xFF],
+          // the masked pixel is XORed with the screen, black keeps the screen
+          (false, _, [0, 0, 0]) => [0; 4],
+          (false, _, _) => [0, 0, 0, 0xFF],
+        }
+      };
+      buffer.extend_from_slice(&pixel);
+    }
+  }
+  Some(PointerImage {
+    width,
+    height,
+    hot_spot: Point::new(info.HotSpot.x, info.HotSpot.y),
+    buffer,
+  })
+}
+
+/// Identifies the content of a pointer shape: equal shapes have equal ids.
+pub type ShapeId = u64;
+
+/// How many decoded shapes [`PointerCache`] keeps, the pointer usually switches between a few.
+const CACHED_SHAPES: usize = 8;
+
+/// The pointer as reported by the frames seen so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CursorState {
+  /// `None` until a shape is reported or if it can't be decoded.
+  pub shape_id: Option<ShapeId>,
+  /// Shared with the cache, so the state is cheap to clone.
+  pub decoded_image: Option<Arc<PointerImage>>,
+  /// The top-left corner of the pointer image in output coordinates.
+  /// The last visible position is kept while the pointer is hidden.
+  pub position: Point,
+  pub visible: bool,
+}
+
+/// Track the pointer across frames and decode its shape only when the content changes.
+///
+/// DXGI reports the shape again e.g. when the pointer moves between windows with the same cursor,
+/// so senders can compare [`CursorState::shape_id`] to skip sending unchanged shapes.
+#[derive(Debug, Clone, Default)]
+pub struct PointerCache {
+  state: CursorState,
+  /// Recently decoded shapes, the most recent last.
+  shapes: Vec<(ShapeId, Arc<PointerImage>)>,
+}
+
+impl PointerCache {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn state(&self) -> &CursorState {
+    &self.state
+  }
+
+  /// Update the position and visibility from a frame, return whether they changed.
+  /// Frames without pointer updates are ignored.
+  pub fn update_position(&mut self, info: &DXGI_OUTDUPL_FRAME_INFO) -> bool {
+    if info.LastMouseUpdateTime == 0 {
+      return false;
+    }
+    let pointer = info.PointerPosition;
+    let visible = pointer.Visible.as_bool();
+    let position = if visible {
+      Point::new(pointer.Position.x, pointer.Position.y)
+    } else {
+      self.state.position
+    };
+    let changed = (visible, position) != (self.state.visible, self.state.position);
+    self.state.visible = visible;
+    self.state.position = position;
+    changed
+  }
+
+  /// Update the shape from [`Capturer::pointer_shape_buffer`](crate::capturer::model::Capturer::pointer_shape_buffer),
+  /// return whether it changed. Shapes seen recently are not decoded again.
+  pub fn update_shape(&mut self, info: &DXGI_OUTDUPL_POINTER_SHAPE_INFO, shape: &[u8]) -> bool {
+    let id = shape_id(info, shape);
+    if self.state.shape_id == Some(id) {
+      return false;
+    }
+    let image = match self.shapes.iter().position(|(cached, _)| *cached == id) {
+      Some(index) => {
+        let entry = self.shapes.remove(index);
+        self.shapes.push(entry);
+        self.shapes.last().map(|(_, image)| image.clone())
+      }
+      None => decode_pointer_shape(info, shape).map(|image| {
+        let image = Arc::new(image);
+        if self.shapes.len() == CACHED_SHAPES {
+          self.shapes.remove(0);
+        }
+        self.shapes.push((id, image.clone()));
+        image
+      }),
+    };
+    let changed = self.state.decoded_image.is_some() || image.is_some();
+    self.state.shape_id = image.is_some().then_some(id);
+    self.state.decoded_image = image;
+    changed
+  }
+}
+
+/// Hash the shape info and the bytes of the shape rows.
+fn shape_id(info: &DXGI_OUTDUPL_POINTER_SHAPE_INFO, shape: &[u8]) -> ShapeId {
+  let mut hasher = DefaultHasher::new();
+  (info.Type, info.Width, info.Height, info.Pitch).hash(&mut hasher);
+  (info.HotSpot.x, info.HotSpot.y).hash(&mut hasher);
+  let len = (info.Pitch as usize * info.Height as usize).min(shape.len());
+  shape[..len].hash(&mut hasher);
+  hasher.finish()
+}
+
+/// Blend `image` onto a BGRA32 `buffer` of `width` x `height` pixels without row padding,
+/// with the top-left corner of the image at `position`. Pixels outside the buffer are clipped.
+pub fn draw_pointer(
+  buffer: &mut [u8],
+  width: u32,
+  height: u32,
+  image: &PointerImage,
+  position: Point,
+) {
+  let bounds = Rect::new(0, 0, width as i32, height as i32);
+  draw_pointer_clipped(buffer, width, image, position, &bounds);
+}
+
+/// Like [`draw_pointer`], but only draw the pixels inside `clip`, which must be inside the buffer.
+pub(crate) fn draw_pointer_clipped(
+  buffer: &mut [u8],
+  width: u32,
+  image: &PointerImage,
+  position: Point,
+  clip: &Rect,
+) {
+  for y in 0..image.height as i32 {
+    let dest_y = position.y + y;
+    if dest_y < clip.top || dest_y >= clip.bottom {
+      continue;
+    }
+    for x in 0..image.width as i32 {
+      let dest_x = position.x + x;
+      if dest_x < clip.left || dest_x >= clip.right {
+        continue;
+      }
+      let src = &image.buffer[(y as usize * image.width as usize + x as usize) * 4..][..4];
+      let offset = (dest_y as usize * width as usize + dest_x as usize) * 4;
+      let Some(dest) = buffer.get_mut(offset..offset + 4) else {
+        return;
+      };
+      let alpha = src[3] as u32;
+      for channel in 0..3 {
+        dest[channel] =
+          ((src[channel] as u32 * alpha + dest[channel] as u32 * (255 - alpha) + 127) / 255) as u8;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    decode_pointer_shape, draw_pointer, draw_pointer_clipped, PointerCache, PointerImage,
+  };
+  use crate::model::{Point, Rect};
+  use std::sync::Arc;
+  use windows::Win32::Foundation::POINT;
+  use windows::Win32::Graphics::Dxgi::{
+    DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTDUPL_POINTER_SHAPE_INFO,
+    DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR, DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MASKED_COLOR,
+    DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MONOCHROME,
+  };
+
+  #[test]
+  fn monochrome() {
+    // 4x1 pixels: transparent, black, white and inverted
+    let info = DXGI_OUTDUPL_POINTER_SHAPE_INFO {
+      Type: DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MONOCHROME.0 as u32,
+      Width: 4,
+      Height: 2,
+      Pitch: 1,
+      HotSpot: POINT { x: 1, y: 0 },
+    };
+    let image = decode_pointer_shape(&info, &[0b1001_0000, 0b0011_0000]).unwrap();
+    assert_eq!((image.width, image.height), (4, 1));
+    assert_eq!(image.hot_spot, Point::new(1, 0));
+    assert_eq!(
+      image.buffer,
+      [[0; 4], [0, 0, 0, 0xFF], [0xFF; 4], [0, 0, 0, 0xFF]].concat()
+    );
+    assert!(decode_pointer_shape(&info, &[0]).is_none());
+  }
+
+  #[test]
+  fn colors() {
+    let mut info = DXGI_OUTDUPL_POINTER_SHAPE_INFO {
+      Type: DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR.0 as u32,
+      Width: 2,
+      Height: 1,
+      // padded rows
+      Pitch: 12,
+      HotSpot: POINT::default(),
+    };
+    let shape = [1, 2, 3, 0x80, 0, 0, 0, 0xFF, 9, 9, 9, 9];
+    let image = decode_pointer_shape(&info, &shape).unwrap();
+    assert_eq!(image.buffer, [1, 2, 3, 0x80, 0, 0, 0, 0xFF]);
+
+    info.Type = DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MASKED_COLOR.0 as u32;
+    let image = decode_pointer_shape(&info, &shape).unwrap();
+    assert_eq!(image.buffer, [0, 0, 0, 0xFF, 0, 0, 0, 0]);
+
+    info.Type = 3;
+    assert!(decode_pointer_shape(&info, &shape).is_none());
+  }
+
+  #[test]
+  fn draw() {
+    // a 2x1 image with an opaque and a half transparent pixel, drawn at the right edge of a 3x2 buffer
+    let image = PointerImage {
+      width: 2,
+      height: 1,
+      hot_spot: Point::default(),
+      buffer: vec![0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0x80],
+    };
+    let mut buffer = vec![0u8; 3 * 2 * 4];
+    draw_pointer(&mut buffer, 3, 2, &image, Point::new(1, 1));
+    assert_eq!(buffer[..16], [0; 16]);
+    assert_eq!(buffer[16..20], [0xFF, 0, 0, 0]);
+    assert_eq!(buffer[20..], [0, 0, 0x80, 0]);
+
+    // clipped entirely
+    draw_pointer(&mut buffer, 3, 2, &image, Point::new(-2, 0));
+    assert_eq!(buffer[..16], [0; 16]);
+
+    // clipped to the first column
+    let mut buffer = vec![0u8; 3 * 2 * 4];
+    draw_pointer_clipped(
+      &mut buffer,
+      3,
+      &image,
+      Point::new(0, 0),
+      &Rect::new(0, 0, 1, 2),
+    );
+    assert_eq!(buffer[..4], [0xFF, 0, 0, 0]);
+    assert_eq!(buffer[4..], [0; 20]);
+  }
+
+  #[test]
+  fn cache() {
+    let mut cache = PointerCache::new();
+    let info = DXGI_OUTDUPL_POINTER_SHAPE_INFO {
+      Type: DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR.0 as u32,
+      Width: 1,
+      Height: 1,
+      Pitch: 4,
+      HotSpot: POINT::default(),
+    };
+    let red = [0, 0, 0xFF, 0xFF];
+    let blue = [0xFF, 0, 0, 0xFF];
+
+    assert!(cache.update_shape(&info, &red));
+    let first = cache.state().clone();
+    assert!(first.shape_id.is_some());
+    assert_eq!(first.decoded_image.as_ref().unwrap().buffer, red);
+    // the same content is not decoded again
+    assert!(!cache.update_shape(&info, &red));
+    assert!(cache.update_shape(&info, &blue));
+    assert_ne!(cache.state().shape_id, first.shape_id);
+    // a recent shape is reused
+    assert!(cache.update_shape(&info, &red));
+    assert_eq!(cache.state().shape_id, first.shape_id);
+    assert!(Arc::ptr_eq(
+      cache.state().decoded_image.as_ref().unwrap(),
+      first.decoded_image.as_ref().unwrap()
+    ));
+    // unknown types have no image
+    assert!(cache.update_shape(&DXGI_OUTDUPL_POINTER_SHAPE_INFO { Type: 3, ..info }, &red));
+    assert_eq!(cache.state().shape_id, None);
+
+    let mut frame = DXGI_OUTDUPL_FRAME_INFO::default();
+    frame.PointerPosition.Position = POINT { x: 5, y: 6 };
+    frame.PointerPosition.Visible = true.into();
+    // frames without pointer updates are ignored
+    assert!(!cache.update_position(&frame));
+    frame.LastMouseUpdateTime = 1;
+    assert!(cache.update_position(&frame));
+    assert!(!cache.update_position(&frame));
+    assert_eq!(
+      (cache.state().visible, cache.state().position),
+      (true, Point::new(5, 6))
+    );
+    // the last position is kept while hidden
+    frame.PointerPosition.Visible = false.into();
+    frame.PointerPosition.Position = POINT::default();
+    assert!(cache.update_position(&frame));
+    assert_eq!(
+      (cache.state().visible, cache.state().position),
+      (false, Point::new(5, 6))
+    );
+  }
+}
@@ -0,0 +1,127 @@
+//! Detect whether the display is turned off, e.g. by power saving.
+//! While it is off no frames are presented and acquiring frames only times out,
+//! such timeouts are returned as [`ErrorKind::DisplayOff`](crate::error::ErrorKind::DisplayOff).
+//! Enable the `power` feature to use this module.
+
+use crate::duplication_context::DuplicationContext;
+use crate::error::Error;
+use crate::model::Result;
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+use windows::core::GUID;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::System::Power::{
+  PowerSettingRegisterNotification, DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS, POWERBROADCAST_SETTING,
+};
+use windows::Win32::UI::WindowsAndMessaging::DEVICE_NOTIFY_CALLBACK;
+
+/// `GUID_CONSOLE_DISPLAY_STATE`, the display state of the console session.
+const GUID_CONSOLE_DISPLAY_STATE: GUID = GUID::from_u128(0x6fe69556_704a_47a0_8f24_c28d936fda47);
+
+/// The power state of the display, see `GUID_CONSOLE_DISPLAY_STATE`.
+///
+/// Windows only reports the state of the console session, which applies to all monitors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisplayState {
+  Off,
+  On,
+  /// The display is dimmed and still presents frames.
+  Dimmed,
+  /// No notification is received yet.
+  #[default]
+  Unknown,
+}
+
+impl DisplayState {
+  fn from_u8(value: u8) -> Self {
+    match value {
+      0 => DisplayState::Off,
+      1 => DisplayState::On,
+      2 => DisplayState::Dimmed,
+      _ => DisplayState::Unknown,
+    }
+  }
+}
+
+/// The last notified [`DisplayState`], in the encoding of `GUID_CONSOLE_DISPLAY_STATE`.
+static STATE: AtomicU8 = AtomicU8::new(u8::MAX);
+/// Notifications are registered once per process and never unregistered.
+static REGISTRATION: OnceLock<std::result::Result<(), String>> = OnceLock::new();
+
+unsafe extern "system" fn on_power_setting(
+  _context: *const c_void,
+  _kind: u32,
+  setting: *const c_void,
+) -> u32 {
+  let setting = &*(setting as *const POWERBROADCAST_SETTING);
+  if setting.PowerSetting == GUID_CONSOLE_DISPLAY_STATE && setting.DataLength >= 4 {
+    let value = ptr::read_unaligned(setting.Data.as_ptr() as *const u32);
+    STATE.store(value.min(u8::MAX as u32) as u8, Ordering::Relaxed);
+  }
+  0
+}
+
+/// Register for display state notifications. Windows sends the current state right after.
+fn register() -> Result<()> {
+  REGISTRATION
+    .get_or_init(|| {
+      // the parameters must outlive the registration, which lives as long as the process
+      let params = Box::leak(Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+        Callback: Some(on_power_setting),
+        Context: ptr::null_mut(),
+      }));
+      let mut handle = ptr::null_mut();
+      let result = unsafe {
+        PowerSettingRegisterNotification(
+          &GUID_CONSOLE_DISPLAY_STATE,
+          DEVICE_NOTIFY_CALLBACK,
+          HANDLE(params as *mut _ as isize),
+          &mut handle,
+        )
+      };
+      if result.is_err() {
+        return Err(format!(
+          "PowerSettingRegisterNotification failed with {}",
+          result.0
+        ));
+      }
+      Ok(())
+    })
+    .clone()
+    .map_err(Error::new)
+}
+
+/// Get the power state of the display.
+/// The first call registers for notifications and may return [`DisplayState::Unknown`]
+/// until Windows sends the current state.
+pub fn display_state() -> Result<DisplayState> {
+  register()?;
+  Ok(DisplayState::from_u8(STATE.load(Ordering::Relaxed)))
+}
+
+impl DuplicationContext {
+  /// Get the power state of the display of this monitor, see [`display_state`].
+  pub fn display_state(&self) -> Result<DisplayState> {
+    display_state()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{display_state, DisplayState};
+  use std::{thread, time::Duration};
+
+  #[test]
+  fn display_states() {
+    assert_eq!(DisplayState::from_u8(0), DisplayState::Off);
+    assert_eq!(DisplayState::from_u8(2), DisplayState::Dimmed);
+    assert_eq!(DisplayState::from_u8(u8::MAX), DisplayState::Unknown);
+
+    display_state().unwrap();
+    // wait for the initial notification
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(display_state().unwrap(), DisplayState::On);
+  }
+}
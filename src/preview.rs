@@ -0,0 +1,104 @@
+//! Sub-sampled previews for UI thumbnails and activity detection.
+//! Pixels are picked by skipping, without filtering, so a preview costs a single cheap pass.
+
+use crate::frame::Frame;
+use crate::utils::FormatExt;
+
+/// The size of a preview taking every `step`-th pixel of every `step`-th row of a `width`x`height` image.
+pub fn preview_size(width: u32, height: u32, step: u32) -> (u32, u32) {
+  let step = step.max(1);
+  (width.div_ceil(step), height.div_ceil(step))
+}
+
+/// Copy every `step`-th pixel of every `step`-th row of `src` to `dest`, row by row without padding.
+/// `src` holds `height` rows of `pitch` bytes, each starting with `width` pixels.
+/// `dest` must hold the pixels of the [`preview_size`].
+pub(crate) fn subsample(
+  src: &[u8],
+  pitch: usize,
+  width: u32,
+  height: u32,
+  bytes_per_pixel: usize,
+  step: u32,
+  dest: &mut [u8],
+) {
+  let step = step.max(1) as usize;
+  let (preview_width, _) = preview_size(width, height, step as u32);
+  let preview_line_bytes = preview_width as usize * bytes_per_pixel;
+  let line_bytes = width as usize * bytes_per_pixel;
+
+  for (row, dest_row) in (0..height as usize)
+    .step_by(step)
+    .zip(dest.chunks_exact_mut(preview_line_bytes.max(1)))
+  {
+    let src_row = &src[row * pitch..row * pitch + line_bytes];
+    if step == 1 {
+      dest_row.copy_from_slice(src_row);
+      continue;
+    }
+    for (pixel, dest_pixel) in src_row
+      .chunks_exact(bytes_per_pixel)
+      .step_by(step)
+      .zip(dest_row.chunks_exact_mut(bytes_per_pixel))
+    {
+      dest_pixel.copy_from_slice(pixel);
+    }
+  }
+}
+
+impl Frame {
+  /// Take every `step`-th pixel of every `step`-th row, e.g. a quarter of the resolution with `step` 4.
+  pub fn preview(&self, step: u32) -> Frame {
+    let (width, height) = preview_size(self.width, self.height, step);
+    let bytes_per_pixel = self.format.bytes_per_pixel();
+    let mut buffer = vec![0; width as usize * height as usize * bytes_per_pixel];
+    subsample(
+      &self.buffer,
+      self.width as usize * bytes_per_pixel,
+      self.width,
+      self.height,
+      bytes_per_pixel,
+      step,
+      &mut buffer,
+    );
+    Frame {
+      buffer,
+      width,
+      height,
+      info: self.info,
+      format: self.format,
+      provenance: self.provenance,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{preview_size, subsample};
+  use crate::test_utils::generate;
+
+  #[test]
+  fn preview_sizes() {
+    assert_eq!(preview_size(1920, 1080, 4), (480, 270));
+    assert_eq!(preview_size(5, 5, 2), (3, 3));
+    assert_eq!(preview_size(5, 5, 0), (5, 5));
+  }
+
+  #[test]
+  fn subsampling() {
+    // 3x2 pixels of 1 byte with a pitch of 4 bytes
+    let src = [0, 1, 2, 0xEE, 10, 11, 12, 0xEE];
+    let mut dest = [0; 2];
+    subsample(&src, 4, 3, 2, 1, 2, &mut dest);
+    assert_eq!(dest, [0, 2]);
+
+    let frame = generate(7, 5, |x, y| [x as u8, y as u8, 0]);
+    let preview = frame.preview(3);
+    assert_eq!((preview.width, preview.height), (3, 2));
+    assert_eq!(
+      preview.pixel_at(2, 1).unwrap(),
+      frame.pixel_at(6, 3).unwrap()
+    );
+    assert_eq!(frame.preview(1).buffer, frame.buffer);
+  }
+}
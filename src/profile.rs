@@ -0,0 +1,245 @@
+//! Save and restore capture configurations, e.g. in the settings of an application.
+//! Enable the `serde` feature to serialize [`CaptureProfile`].
+//!
+//! Monitor ids change when adapters or outputs are added or removed,
+//! and GDI device names like `\\.\DISPLAY1` can be reassigned at boot or when monitors are hotplugged.
+//! With the `display-config` feature profiles are resolved by the monitor device path first,
+//! which is derived from the EDID of the monitor and its connector, then by the device name and the id.
+
+#[cfg(feature = "display-config")]
+use crate::display_config::monitor_device_paths;
+use crate::duplication_context::DuplicationContext;
+use crate::error::Error;
+use crate::manager::Manager;
+use crate::model::{CaptureOptions, MonitorId, MonitorSelector, Rect, Result};
+#[cfg(feature = "recorder")]
+use crate::recorder::RecorderOptions;
+use crate::utils::OutputDescExt;
+use std::time::Duration;
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT;
+
+/// The capture configuration of a monitor.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CaptureProfile {
+  /// The monitor when the profile was saved.
+  pub monitor: MonitorId,
+  /// The device name of the monitor, e.g. `\\.\DISPLAY1`, preferred over `monitor` when resolving.
+  pub device_name: String,
+  /// The monitor device path of the DisplayConfig API, preferred over `device_name` when resolving.
+  /// Only set and used with the `display-config` feature.
+  #[cfg_attr(feature = "serde", serde(default))]
+  pub monitor_path: Option<String>,
+  /// Capture only this area in frame coordinates. `None` captures the whole monitor.
+  pub region: Option<Rect>,
+  /// Capture at most this many frames per second. `None` captures every desktop update.
+  pub fps: Option<u32>,
+  /// The preferred duplication format, see [`Manager::with_formats`].
+  #[cfg_attr(feature = "serde", serde(with = "format"))]
+  pub format: Option<DXGI_FORMAT>,
+  /// See [`CaptureOptions::include_cursor`].
+  pub include_cursor: bool,
+  /// Record the captured frames, the size and frame rate are taken from the region and `fps`.
+  #[cfg(feature = "recorder")]
+  pub recording: Option<RecorderOptions>,
+}
+
+impl CaptureProfile {
+  /// Capture the whole monitor of `ctx` in its current format.
+  pub fn new(ctx: &DuplicationContext) -> Result<Self> {
+    #[cfg(feature = "display-config")]
+    let monitor_path = ctx.monitor_device_paths()?.into_iter().next();
+    #[cfg(not(feature = "display-config"))]
+    let monitor_path = None;
+    Ok(Self {
+      monitor: ctx.id(),
+      device_name: ctx.dxgi_output_desc()?.device_name(),
+      monitor_path,
+      region: None,
+      fps: None,
+      format: Some(ctx.format()),
+      include_cursor: false,
+      #[cfg(feature = "recorder")]
+      recording: None,
+    })
+  }
+
+  /// Select the monitor by its current device name, e.g. for [`SessionOptions`](crate::session::SessionOptions).
+  pub fn selector(&self) -> Result<MonitorSelector> {
+    Ok(MonitorSelector::DeviceName(self.current_device_name()?))
+  }
+
+  /// The device name the monitor of the profile has now, found by the monitor device path.
+  /// Falls back to the saved device name if the monitor isn't active or the path isn't known.
+  pub fn current_device_name(&self) -> Result<String> {
+    #[cfg(feature = "display-config")]
+    if let Some(path) = &self.monitor_path {
+      if let Some((name, _)) = monitor_device_paths()?
+        .into_iter()
+        .find(|(_, monitor_path)| monitor_path == path)
+      {
+        return Ok(name);
+      }
+    }
+    Ok(self.device_name.clone())
+  }
+
+  /// Scan monitors, duplicating them in the format of the profile if it's set.
+  pub fn manager(&self, timeout_ms: u32) -> Result<Manager> {
+    match self.format {
+      Some(format) => Manager::with_formats(timeout_ms, &[format]),
+      None => Manager::new(timeout_ms),
+    }
+  }
+
+  /// Find the monitor of the profile in `manager`, by the monitor device path, the device name and then by the id,
+  /// and clip the region to the current frame size.
+  pub fn resolve<'m>(&self, manager: &'m Manager) -> Result<ResolvedProfile<'m>> {
+    let device_name = self.current_device_name()?;
+    let by_name = manager.contexts.iter().find(|ctx| {
+      ctx
+        .dxgi_output_desc()
+        .is_ok_and(|desc| desc.device_name() == device_name)
+    });
+    let ctx = by_name
+      .or_else(|| manager.contexts.iter().find(|ctx| ctx.id() == self.monitor))
+      .ok_or_else(|| {
+        Error::new(format!(
+          "Monitor {} of the profile not found",
+          self.device_name
+        ))
+      })?;
+
+    let (width, height) = ctx.frame_size()?;
+    let region = clip_region(self.region, width, height).ok_or_else(|| {
+      Error::new("The region of the profile is outside the monitor")
+        .with_context(ctx.error_context())
+    })?;
+    Ok(ResolvedProfile {
+      ctx,
+      region,
+      interval: self
+        .fps
+        .filter(|&fps| fps > 0)
+        .map(|fps| Duration::from_secs(1) / fps),
+      options: CaptureOptions {
+        include_cursor: self.include_cursor,
+        ..Default::default()
+      },
+      #[cfg(feature = "recorder")]
+      recording: self.recording.map(|recording| RecorderOptions {
+        width: region.width(),
+        height: region.height(),
+        frame_rate: self.fps.unwrap_or(recording.frame_rate),
+        ..recording
+      }),
+    })
+  }
+}
+
+/// A [`CaptureProfile`] applied to the current monitors, see [`CaptureProfile::resolve`].
+pub struct ResolvedProfile<'m> {
+  pub ctx: &'m DuplicationContext,
+  /// The region to capture, the whole frame if the profile has no region.
+  pub region: Rect,
+  /// The time between captures, `None` to capture every desktop update.
+  pub interval: Option<Duration>,
+  pub options: CaptureOptions,
+  #[cfg(feature = "recorder")]
+  pub recording: Option<RecorderOptions>,
+}
+
+/// Clip `region` to a frame, `None` if nothing is left.
+fn clip_region(region: Option<Rect>, width: u32, height: u32) -> Option<Rect> {
+  let frame = Rect::new(0, 0, width as i32, height as i32);
+  match region {
+    Some(region) => region.intersect(&frame),
+    None => (!frame.is_empty()).then_some(frame),
+  }
+}
+
+/// Serialize `DXGI_FORMAT` as its value.
+#[cfg(feature = "serde")]
+mod format {
+  use serde::{Deserialize, Deserializer, Serialize, Serializer};
+  use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT;
+
+  pub fn serialize<S: Serializer>(
+    format: &Option<DXGI_FORMAT>,
+    serializer: S,
+  ) -> Result<S::Ok, S::Error> {
+    format.map(|format| format.0).serialize(serializer)
+  }
+
+  pub fn deserialize<'de, D: Deserializer<'de>>(
+    deserializer: D,
+  ) -> Result<Option<DXGI_FORMAT>, D::Error> {
+    Ok(Option::<u32>::deserialize(deserializer)?.map(DXGI_FORMAT))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{clip_region, CaptureProfile};
+  use crate::manager::{Manager, DEFAULT_TIMEOUT_MS};
+  use crate::model::{MonitorId, Rect};
+  use std::time::Duration;
+
+  #[test]
+  fn clip() {
+    assert_eq!(clip_region(None, 4, 3), Some(Rect::new(0, 0, 4, 3)));
+    assert_eq!(
+      clip_region(Some(Rect::new(2, -1, 8, 2)), 4, 3),
+      Some(Rect::new(2, 0, 4, 2))
+    );
+    assert_eq!(clip_region(Some(Rect::new(5, 0, 8, 2)), 4, 3), None);
+  }
+
+  #[test]
+  fn resolve() {
+    let manager = Manager::default().unwrap();
+    let mut profile = CaptureProfile::new(&manager.contexts[0]).unwrap();
+    profile.region = Some(Rect::new(0, 0, 16, 16));
+    profile.fps = Some(10);
+
+    let manager = profile.manager(DEFAULT_TIMEOUT_MS).unwrap();
+    let resolved = profile.resolve(&manager).unwrap();
+    assert_eq!(resolved.ctx.id(), profile.monitor);
+    assert_eq!(resolved.region, Rect::new(0, 0, 16, 16));
+    assert_eq!(resolved.interval, Some(Duration::from_millis(100)));
+
+    // the monitor device path is used if the device name was reassigned
+    #[cfg(feature = "display-config")]
+    {
+      assert!(profile.monitor_path.is_some());
+      let reassigned = CaptureProfile {
+        device_name: String::new(),
+        monitor: MonitorId {
+          adapter: u32::MAX,
+          output: 0,
+        },
+        ..profile.clone()
+      };
+      assert_eq!(
+        reassigned.resolve(&manager).unwrap().ctx.id(),
+        profile.monitor
+      );
+    }
+
+    // the id is used if the device name is gone, e.g. after a driver update
+    let renamed = CaptureProfile {
+      device_name: String::new(),
+      monitor_path: None,
+      ..profile.clone()
+    };
+    assert_eq!(renamed.resolve(&manager).unwrap().ctx.id(), profile.monitor);
+    let missing = CaptureProfile {
+      monitor: MonitorId {
+        adapter: u32::MAX,
+        output: 0,
+      },
+      ..renamed
+    };
+    assert!(missing.resolve(&manager).is_err());
+  }
+}
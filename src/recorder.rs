@@ -0,0 +1,647 @@
+//! Encode captured frames and loopback audio into an MP4 file with Media Foundation.
+//! Enable the `recorder` feature to use this module.
+//!
+//! Media Foundation must be started with `MFStartup` before creating a recorder.
+
+use crate::audio::{AudioBuffer, AudioFormat};
+use crate::duplication_context::qpc_now;
+use crate::encoder::{self, EncoderInfo, EncoderSelection, EncoderSettings, VideoCodec};
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::media_foundation::{qpc_to_hns, sample_from_buffer};
+use crate::model::Result;
+use crate::utils::FormatExt;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use windows::core::{GUID, HSTRING};
+use windows::Win32::Media::MediaFoundation::{
+  IMFAttributes, IMFMediaType, IMFSinkWriter, MFAudioFormat_AAC, MFAudioFormat_PCM,
+  MFCreateAttributes, MFCreateMediaType, MFCreateSinkWriterFromURL, MFMediaType_Audio,
+  MFMediaType_Video, MFVideoFormat_RGB32, MFVideoInterlace_Progressive, MF_LOW_LATENCY,
+  MF_MT_AUDIO_AVG_BYTES_PER_SECOND, MF_MT_AUDIO_BITS_PER_SAMPLE, MF_MT_AUDIO_BLOCK_ALIGNMENT,
+  MF_MT_AUDIO_NUM_CHANNELS, MF_MT_AUDIO_SAMPLES_PER_SECOND, MF_MT_AVG_BITRATE,
+  MF_MT_DEFAULT_STRIDE, MF_MT_FRAME_RATE, MF_MT_FRAME_SIZE, MF_MT_INTERLACE_MODE, MF_MT_MAJOR_TYPE,
+  MF_MT_PIXEL_ASPECT_RATIO, MF_MT_SUBTYPE, MF_SINK_WRITER_ALL_STREAMS, MF_SINK_WRITER_STATISTICS,
+};
+
+/// 100-nanosecond units per second, the time unit of Media Foundation.
+const HNS_PER_SECOND: i64 = 10_000_000;
+
+/// Re-anchor audio timestamps to the QPC clock when the device clock drifts further than this, 20ms.
+const MAX_AUDIO_DRIFT_HNS: i64 = 200_000;
+
+/// When a [`Recorder`] closes the current file and continues in a new one.
+/// The checks happen before each frame, so files may be slightly longer or larger.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rollover {
+  /// Maximum recorded time per file, excluding pauses.
+  pub max_duration: Option<Duration>,
+  /// Maximum file size in bytes, counted from the encoded data the sink writer has processed.
+  pub max_bytes: Option<u64>,
+}
+
+impl Rollover {
+  pub fn enabled(&self) -> bool {
+    self.max_duration.is_some() || self.max_bytes.is_some()
+  }
+}
+
+/// Parameters of a [`Recorder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RecorderOptions {
+  pub width: u32,
+  pub height: u32,
+  pub frame_rate: u32,
+  pub codec: VideoCodec,
+  /// Use [`video_encoders`](crate::encoder::video_encoders) to list the encoders to select from.
+  pub encoder: EncoderSelection,
+  /// Rate control, GOP, B-frames and latency of the video encoder.
+  pub encoder_settings: EncoderSettings,
+  /// Video bitrate in bits per second, the target of CBR and the average of VBR.
+  pub video_bitrate: u32,
+  /// The format of [`LoopbackCapture`](crate::audio::LoopbackCapture), or `None` to record video only.
+  pub audio: Option<AudioFormat>,
+  /// AAC bitrate in bytes per second, one of 12000, 16000, 20000 or 24000.
+  pub audio_bitrate: u32,
+  pub rollover: Rollover,
+}
+
+impl RecorderOptions {
+  /// Video only H.264 options with a bitrate of 8 Mbps.
+  pub fn new(width: u32, height: u32, frame_rate: u32) -> Self {
+    Self {
+      width,
+      height,
+      frame_rate,
+      codec: VideoCodec::default(),
+      encoder: EncoderSelection::default(),
+      encoder_settings: EncoderSettings::default(),
+      video_bitrate: 8_000_000,
+      audio: None,
+      audio_bitrate: 24000,
+      rollover: Rollover::default(),
+    }
+  }
+}
+
+/// Write frames as H.264 or HEVC and audio as AAC into one file, e.g. `capture.mp4`.
+/// The container is chosen by the file extension, Media Foundation has no MKV writer.
+///
+/// Frames and audio are placed on the QPC timeline of the capture,
+/// starting from the first frame or audio packet written.
+/// Write them as they arrive, the sink writer interleaves them.
+///
+/// With [`RecorderOptions::rollover`], files are numbered like `capture-000.mp4`, `capture-001.mp4`
+/// and each file starts at zero.
+pub struct Recorder {
+  options: RecorderOptions,
+  path: PathBuf,
+  segment: Segment,
+  timing: Timing,
+  last_video_time: Option<i64>,
+  audio_clock: AudioClock,
+  /// Reused buffer of 16-bit PCM for the AAC encoder.
+  pcm: Vec<u8>,
+}
+
+impl Recorder {
+  pub fn create(path: impl AsRef<Path>, options: RecorderOptions) -> Result<Self> {
+    let path = path.as_ref().to_path_buf();
+    let segment = Segment::create(
+      if options.rollover.enabled() {
+        segment_path(&path, 0)
+      } else {
+        path.clone()
+      },
+      0,
+      &options,
+    )?;
+    Ok(Self {
+      options,
+      path,
+      segment,
+      timing: Timing::default(),
+      last_video_time: None,
+      audio_clock: AudioClock::new(options.audio.map_or(0, |format| format.sample_rate)),
+      pcm: Vec::new(),
+    })
+  }
+
+  /// The video encoder which Media Foundation chose for the current file,
+  /// e.g. to check whether [`RecorderOptions::encoder`] got a hardware encoder.
+  /// `None` if the sink writer doesn't expose it.
+  pub fn encoder(&self) -> Option<EncoderInfo> {
+    encoder::stream_encoder(&self.segment.writer, self.segment.video_stream)
+  }
+
+  /// The file currently written.
+  pub fn path(&self) -> &Path {
+    &self.segment.path
+  }
+
+  /// The index of the current file, increased on every rollover.
+  pub fn segment(&self) -> u32 {
+    self.segment.index
+  }
+
+  /// Stop recording until [`Recorder::resume`]. Frames and audio written meanwhile are skipped,
+  /// and the paused time is cut from the recording instead of leaving a gap.
+  pub fn pause(&mut self) {
+    self.timing.pause(qpc_to_hns(qpc_now().0));
+  }
+
+  pub fn resume(&mut self) {
+    if self.timing.resume(qpc_to_hns(qpc_now().0)) {
+      self.audio_clock.reset();
+    }
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.timing.paused_at.is_some()
+  }
+
+  /// Encode a frame at its `LastPresentTime`, or at the current time if it has none.
+  /// Frames which are not newer than the previous frame are skipped.
+  pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    if frame.width != self.options.width
+      || frame.height != self.options.height
+      || frame.format.bytes_per_pixel() != 4
+    {
+      return Err(Error::new("Frame doesn't match the recorder"));
+    }
+    let time = match frame.info.LastPresentTime {
+      0 => qpc_to_hns(qpc_now().0),
+      qpc => qpc_to_hns(qpc),
+    };
+    let Some(time) = self.timing.relative(time) else {
+      return Ok(());
+    };
+    if self.last_video_time.is_some_and(|last| time <= last) {
+      return Ok(());
+    }
+    if self.should_roll(time)? {
+      self.roll(time)?;
+    }
+    self.last_video_time = Some(time);
+
+    let duration = HNS_PER_SECOND / self.options.frame_rate.max(1) as i64;
+    let sample = sample_from_buffer(&frame.buffer, time - self.segment.start, duration)?;
+    unsafe {
+      self
+        .segment
+        .writer
+        .WriteSample(self.segment.video_stream, &sample)
+    }
+    .map_err(|e| Error::windows("IMFSinkWriter.WriteSample", e))
+  }
+
+  /// Encode an audio packet from [`LoopbackCapture`](crate::audio::LoopbackCapture).
+  /// Packets recorded before the start of the recording or the current file are skipped.
+  pub fn write_audio(&mut self, buffer: &AudioBuffer) -> Result<()> {
+    let (Some(stream), Some(format)) = (self.segment.audio_stream, self.options.audio) else {
+      return Err(Error::new("The recorder has no audio stream"));
+    };
+    let time =
+      self
+        .audio_clock
+        .timestamp(buffer.time as i64, buffer.position, buffer.discontinuity);
+    let Some(time) = self.timing.relative(time) else {
+      return Ok(());
+    };
+    if time < self.segment.start {
+      return Ok(());
+    }
+
+    let data = match (format.float, format.bits_per_sample) {
+      (true, 32) => {
+        float_to_pcm16(&buffer.data, &mut self.pcm);
+        &self.pcm
+      }
+      (false, 16) => &buffer.data,
+      _ => return Err(Error::new("Unsupported audio format")),
+    };
+    let duration = buffer.frames as i64 * HNS_PER_SECOND / format.sample_rate.max(1) as i64;
+    let sample = sample_from_buffer(data, time - self.segment.start, duration)?;
+    unsafe { self.segment.writer.WriteSample(stream, &sample) }
+      .map_err(|e| Error::windows("IMFSinkWriter.WriteSample", e))
+  }
+
+  /// Flush the encoders and close the file.
+  pub fn finish(self) -> Result<()> {
+    self.segment.finish()
+  }
+
+  /// Return `true` if the current file is not empty and exceeds the rollover limits.
+  fn should_roll(&self, time: i64) -> Result<bool> {
+    let rollover = &self.options.rollover;
+    if !rollover.enabled() || self.last_video_time.is_none() {
+      return Ok(false);
+    }
+    if rollover
+      .max_duration
+      .is_some_and(|max| time - self.segment.start >= (max.as_nanos() / 100) as i64)
+    {
+      return Ok(true);
+    }
+    match rollover.max_bytes {
+      Some(max) => Ok(self.segment.bytes_written()? >= max),
+      None => Ok(false),
+    }
+  }
+
+  /// Finish the current file and continue in the next one, starting at `time`.
+  fn roll(&mut self, time: i64) -> Result<()> {
+    let index = self.segment.index + 1;
+    let next = Segment::create(segment_path(&self.path, index), index, &self.options)?;
+    let previous = mem::replace(&mut self.segment, next);
+    self.segment.start = time;
+    previous.finish()
+  }
+}
+
+/// One output file of a [`Recorder`].
+struct Segment {
+  writer: IMFSinkWriter,
+  path: PathBuf,
+  index: u32,
+  video_stream: u32,
+  audio_stream: Option<u32>,
+  /// The recording time where this file starts, in 100-nanosecond units.
+  start: i64,
+}
+
+impl Segment {
+  fn create(path: PathBuf, index: u32, options: &RecorderOptions) -> Result<Self> {
+    let mut attributes: Option<IMFAttributes> = None;
+    unsafe { MFCreateAttributes(&mut attributes, 1) }
+      .map_err(|e| Error::windows("MFCreateAttributes", e))?;
+    let attributes = attributes.unwrap();
+    encoder::configure(&attributes, options.encoder)?;
+    if options.encoder_settings.low_latency {
+      // also stop the sink writer from queuing samples
+      unsafe { attributes.SetUINT32(&MF_LOW_LATENCY, 1) }
+        .map_err(|e| Error::windows("IMFAttributes.SetUINT32", e))?;
+    }
+
+    let url = HSTRING::from(path.to_string_lossy().as_ref());
+    let writer = unsafe { MFCreateSinkWriterFromURL(&url, None, &attributes) }
+      .map_err(|e| Error::windows("MFCreateSinkWriterFromURL", e))?;
+
+    let video_stream = add_stream(
+      &writer,
+      &video_type(&options.codec.subtype(), options)?,
+      &video_type(&MFVideoFormat_RGB32, options)?,
+      Some(&options.encoder_settings.parameters(options.video_bitrate)?),
+    )?;
+    let audio_stream = match options.audio {
+      Some(format) => Some(add_stream(
+        &writer,
+        &audio_type(&MFAudioFormat_AAC, &format, options.audio_bitrate)?,
+        &audio_type(&MFAudioFormat_PCM, &format, 0)?,
+        None,
+      )?),
+      None => None,
+    };
+    unsafe { writer.BeginWriting() }.map_err(|e| Error::windows("BeginWriting", e))?;
+
+    Ok(Self {
+      writer,
+      path,
+      index,
+      video_stream,
+      audio_stream,
+      start: 0,
+    })
+  }
+
+  /// The encoded bytes processed by the media sink, without querying the file system.
+  fn bytes_written(&self) -> Result<u64> {
+    let mut statistics = MF_SINK_WRITER_STATISTICS {
+      cb: mem::size_of::<MF_SINK_WRITER_STATISTICS>() as u32,
+      ..Default::default()
+    };
+    unsafe {
+      self
+        .writer
+        .GetStatistics(MF_SINK_WRITER_ALL_STREAMS.0, &mut statistics)
+    }
+    .map_err(|e| Error::windows("IMFSinkWriter.GetStatistics", e))?;
+    Ok(statistics.qwByteCountProcessed)
+  }
+
+  fn finish(self) -> Result<()> {
+    unsafe { self.writer.Finalize() }.map_err(|e| Error::windows("IMFSinkWriter.Finalize", e))
+  }
+}
+
+/// Insert the segment index before the extension, e.g. `capture-001.mp4`.
+fn segment_path(path: &Path, index: u32) -> PathBuf {
+  let mut name = path.file_stem().unwrap_or_default().to_os_string();
+  name.push(format!("-{:03}", index));
+  if let Some(extension) = path.extension() {
+    name.push(".");
+    name.push(extension);
+  }
+  path.with_file_name(name)
+}
+
+/// Map QPC times in 100-nanosecond units to the recording time, with paused time cut out.
+#[derive(Debug, Clone, Copy, Default)]
+struct Timing {
+  /// The QPC time of the first sample.
+  origin: Option<i64>,
+  paused_at: Option<i64>,
+  /// Total paused time after the origin.
+  paused: i64,
+  /// Samples before this are skipped, e.g. audio captured during the pause but read late.
+  resumed_at: i64,
+}
+
+impl Timing {
+  fn pause(&mut self, now: i64) {
+    self.paused_at.get_or_insert(now);
+  }
+
+  /// Return `false` if not paused.
+  fn resume(&mut self, now: i64) -> bool {
+    let Some(paused_at) = self.paused_at.take() else {
+      return false;
+    };
+    if let Some(origin) = self.origin {
+      self.paused += now - paused_at.max(origin);
+    }
+    self.resumed_at = now;
+    true
+  }
+
+  /// Return `None` if the sample should be skipped.
+  fn relative(&mut self, time: i64) -> Option<i64> {
+    if self.paused_at.is_some() || time < self.resumed_at {
+      return None;
+    }
+    let origin = *self.origin.get_or_insert(time);
+    let time = time - origin - self.paused;
+    (time >= 0).then_some(time)
+  }
+}
+
+/// Timestamp audio packets by the device position, which is smoother than their QPC times,
+/// and re-anchor to the QPC time when the device clock drifts away or packets are lost.
+#[derive(Debug, Clone, Copy)]
+struct AudioClock {
+  sample_rate: u32,
+  /// The QPC time in 100-nanosecond units and the device position of a packet.
+  anchor: Option<(i64, u64)>,
+}
+
+impl AudioClock {
+  fn new(sample_rate: u32) -> Self {
+    Self {
+      sample_rate,
+      anchor: None,
+    }
+  }
+
+  fn reset(&mut self) {
+    self.anchor = None;
+  }
+
+  fn timestamp(&mut self, time: i64, position: u64, discontinuity: bool) -> i64 {
+    if let Some((anchor_time, anchor_position)) = self.anchor {
+      let frames = position as i64 - anchor_position as i64;
+      let expected = anchor_time + frames * HNS_PER_SECOND / self.sample_rate.max(1) as i64;
+      if !discontinuity && (time - expected).abs() <= MAX_AUDIO_DRIFT_HNS {
+        return expected;
+      }
+    }
+    self.anchor = Some((time, position));
+    time
+  }
+}
+
+/// Convert interleaved `f32` samples to 16-bit PCM.
+fn float_to_pcm16(src: &[u8], dest: &mut Vec<u8>) {
+  dest.clear();
+  dest.extend(src.chunks_exact(4).flat_map(|bytes| {
+    let sample = f32::from_le_bytes(bytes.try_into().unwrap());
+    ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes()
+  }));
+}
+
+fn add_stream(
+  writer: &IMFSinkWriter,
+  output: &IMFMediaType,
+  input: &IMFMediaType,
+  parameters: Option<&IMFAttributes>,
+) -> Result<u32> {
+  let stream = unsafe { writer.AddStream(output) }
+    .map_err(|e| Error::windows("IMFSinkWriter.AddStream", e))?;
+  unsafe { writer.SetInputMediaType(stream, input, parameters) }
+    .map_err(|e| Error::windows("IMFSinkWriter.SetInputMediaType", e))?;
+  Ok(stream)
+}
+
+fn media_type(
+  major: &GUID,
+  subtype: &GUID,
+  attributes: &[(&GUID, u32)],
+  packed: &[(&GUID, u32, u32)],
+) -> Result<IMFMediaType> {
+  let media_type =
+    unsafe { MFCreateMediaType() }.map_err(|e| Error::windows("MFCreateMediaType", e))?;
+  unsafe {
+    media_type
+      .SetGUID(&MF_MT_MAJOR_TYPE, major)
+      .and_then(|_| media_type.SetGUID(&MF_MT_SUBTYPE, subtype))
+      .and_then(|_| {
+        attributes
+          .iter()
+          .try_for_each(|(key, value)| media_type.SetUINT32(*key, *value))
+      })
+      .and_then(|_| {
+        // e.g. the frame size is packed as width and height
+        packed.iter().try_for_each(|(key, high, low)| {
+          media_type.SetUINT64(*key, (*high as u64) << 32 | *low as u64)
+        })
+      })
+  }
+  .map_err(|e| Error::windows("IMFMediaType.Set", e))?;
+  Ok(media_type)
+}
+
+fn video_type(subtype: &GUID, options: &RecorderOptions) -> Result<IMFMediaType> {
+  let mut attributes = vec![(&MF_MT_INTERLACE_MODE, MFVideoInterlace_Progressive.0 as u32)];
+  if *subtype == MFVideoFormat_RGB32 {
+    // a positive stride means top-down rows like the captured buffer
+    attributes.push((&MF_MT_DEFAULT_STRIDE, options.width * 4));
+  } else {
+    attributes.push((&MF_MT_AVG_BITRATE, options.video_bitrate));
+  }
+  media_type(
+    &MFMediaType_Video,
+    subtype,
+    &attributes,
+    &[
+      (&MF_MT_FRAME_SIZE, options.width, options.height),
+      (&MF_MT_FRAME_RATE, options.frame_rate, 1),
+      (&MF_MT_PIXEL_ASPECT_RATIO, 1, 1),
+    ],
+  )
+}
+
+fn audio_type(subtype: &GUID, format: &AudioFormat, bitrate: u32) -> Result<IMFMediaType> {
+  let block_align = format.channels as u32 * 2;
+  let bytes_per_second = if *subtype == MFAudioFormat_PCM {
+    format.sample_rate * block_align
+  } else {
+    bitrate
+  };
+  let mut attributes = vec![
+    (&MF_MT_AUDIO_BITS_PER_SAMPLE, 16),
+    (&MF_MT_AUDIO_SAMPLES_PER_SECOND, format.sample_rate),
+    (&MF_MT_AUDIO_NUM_CHANNELS, format.channels as u32),
+    (&MF_MT_AUDIO_AVG_BYTES_PER_SECOND, bytes_per_second),
+  ];
+  if *subtype == MFAudioFormat_PCM {
+    attributes.push((&MF_MT_AUDIO_BLOCK_ALIGNMENT, block_align));
+  }
+  media_type(&MFMediaType_Audio, subtype, &attributes, &[])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    float_to_pcm16, segment_path, AudioClock, Recorder, RecorderOptions, Rollover, Timing,
+  };
+  use crate::test_utils::gradient;
+  use std::path::Path;
+  use std::time::Duration;
+  use windows::Win32::Media::MediaFoundation::{MFShutdown, MFStartup, MFSTARTUP_FULL, MF_VERSION};
+
+  #[test]
+  fn audio_clock() {
+    // 1 frame is 1 hns
+    let mut clock = AudioClock::new(10_000_000);
+    assert_eq!(clock.timestamp(1000, 0, false), 1000);
+    // follow the device position within the drift limit
+    assert_eq!(clock.timestamp(1500, 480, false), 1480);
+    assert_eq!(clock.timestamp(100_000, 960, true), 100_000);
+    // drifted too far
+    assert_eq!(clock.timestamp(500_000, 1000, false), 500_000);
+  }
+
+  #[test]
+  fn timing() {
+    let mut timing = Timing::default();
+    assert_eq!(timing.relative(100), Some(0));
+    assert_eq!(timing.relative(150), Some(50));
+    timing.pause(200);
+    assert_eq!(timing.relative(210), None);
+    assert!(timing.resume(500));
+    assert!(!timing.resume(600));
+    // captured during the pause but written late
+    assert_eq!(timing.relative(400), None);
+    // the pause is cut out
+    assert_eq!(timing.relative(500), Some(100));
+    assert_eq!(timing.relative(550), Some(150));
+  }
+
+  #[test]
+  fn segment_paths() {
+    assert_eq!(
+      segment_path(Path::new("out/capture.mp4"), 1),
+      Path::new("out/capture-001.mp4")
+    );
+    assert_eq!(
+      segment_path(Path::new("capture"), 12),
+      Path::new("capture-012")
+    );
+  }
+
+  #[test]
+  fn pcm16() {
+    let src: Vec<u8> = [0.0f32, 1.0, -1.0, 2.0]
+      .iter()
+      .flat_map(|s| s.to_le_bytes())
+      .collect();
+    let mut dest = Vec::new();
+    float_to_pcm16(&src, &mut dest);
+    let samples: Vec<i16> = dest
+      .chunks_exact(2)
+      .map(|b| i16::from_le_bytes([b[0], b[1]]))
+      .collect();
+    assert_eq!(samples, [0, i16::MAX, -i16::MAX, i16::MAX]);
+  }
+
+  #[test]
+  fn recorder() {
+    unsafe { MFStartup(MF_VERSION, MFSTARTUP_FULL) }.unwrap();
+
+    let path = std::env::temp_dir().join("rusty-duplication-recorder.mp4");
+    let mut frame = gradient(64, 64);
+    let mut recorder = Recorder::create(&path, RecorderOptions::new(64, 64, 30)).unwrap();
+    assert!(!recorder.encoder().unwrap().name.is_empty());
+    for i in 1..=10 {
+      frame.info.LastPresentTime = i * 1000;
+      recorder.write_frame(&frame).unwrap();
+    }
+    recorder.finish().unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() > 0);
+    std::fs::remove_file(&path).ok();
+
+    // roll over every 3 frames at 30 fps, pausing doesn't count
+    let mut options = RecorderOptions::new(64, 64, 30);
+    options.rollover = Rollover {
+      max_duration: Some(Duration::from_millis(100)),
+      max_bytes: None,
+    };
+    let mut recorder = Recorder::create(&path, options).unwrap();
+    let mut paths = vec![recorder.path().to_path_buf()];
+    for i in 0..10 {
+      frame.info.LastPresentTime = 0;
+      recorder.write_frame(&frame).unwrap();
+      if i == 5 {
+        recorder.pause();
+        assert!(recorder.is_paused());
+        recorder.write_frame(&frame).unwrap();
+        recorder.resume();
+      }
+      if !paths.contains(&recorder.path().to_path_buf()) {
+        paths.push(recorder.path().to_path_buf());
+      }
+      std::thread::sleep(Duration::from_millis(34));
+    }
+    assert!(recorder.segment() >= 2);
+    recorder.finish().unwrap();
+    for path in paths {
+      assert!(std::fs::metadata(&path).unwrap().len() > 0);
+      std::fs::remove_file(&path).ok();
+    }
+
+    // roll over as soon as the sink writer has processed any encoded data
+    let mut options = RecorderOptions::new(64, 64, 30);
+    options.rollover = Rollover {
+      max_duration: None,
+      max_bytes: Some(1),
+    };
+    let mut recorder = Recorder::create(&path, options).unwrap();
+    let mut paths = vec![recorder.path().to_path_buf()];
+    for i in 1..=30 {
+      frame.info.LastPresentTime = i * 1000;
+      recorder.write_frame(&frame).unwrap();
+      if !paths.contains(&recorder.path().to_path_buf()) {
+        paths.push(recorder.path().to_path_buf());
+      }
+    }
+    assert!(recorder.segment() >= 1);
+    recorder.finish().unwrap();
+    for path in paths {
+      std::fs::remove_file(&path).ok();
+    }
+
+    unsafe { MFShutdown() }.unwrap();
+  }
+}
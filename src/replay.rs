@@ -0,0 +1,293 @@
+//! Keep the last seconds of a recording and save them on demand, like an instant replay.
+//! Enable the `recorder` feature to use this module.
+
+use crate::audio::AudioBuffer;
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::model::Result;
+use crate::recorder::{Recorder, RecorderOptions, Rollover};
+use crate::stream::RawStreamReader;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use windows::core::HSTRING;
+use windows::Win32::Media::MediaFoundation::{
+  IMFSample, IMFSinkWriter, IMFSourceReader, MFCreateSinkWriterFromURL,
+  MFCreateSourceReaderFromURL, MF_E_INVALIDSTREAMNUMBER, MF_SOURCE_READERF_ENDOFSTREAM,
+  MF_SOURCE_READER_ALL_STREAMS, MF_SOURCE_READER_ANY_STREAM,
+};
+
+/// How many files the buffered duration is split into.
+const SEGMENTS: u32 = 4;
+
+/// Make temporary directories unique within the process.
+static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+/// Encode continuously into short temporary files, keep the files covering the last `duration`
+/// and join them into one file by [`ReplayBuffer::save`].
+///
+/// Files are only cut at their boundaries, so a saved replay may be up to a quarter of `duration` longer.
+/// The temporary files are removed when the buffer is dropped.
+pub struct ReplayBuffer {
+  dir: PathBuf,
+  options: RecorderOptions,
+  duration: Duration,
+  /// `None` only while dropping.
+  recorder: Option<Recorder>,
+  /// How many recorders have been created, to name their files.
+  generation: u32,
+  /// Finished files from oldest to newest.
+  segments: VecDeque<PathBuf>,
+}
+
+impl ReplayBuffer {
+  /// `options.rollover` is replaced to split the buffer into files.
+  pub fn new(mut options: RecorderOptions, duration: Duration) -> Result<Self> {
+    options.rollover = Rollover {
+      max_duration: Some((duration / SEGMENTS).max(Duration::from_secs(1))),
+      max_bytes: None,
+    };
+    let dir = std::env::temp_dir().join(format!(
+      "rusty-duplication-replay-{}-{}",
+      std::process::id(),
+      NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir).map_err(|e| Error::new(format!("Create {:?}: {}", dir, e)))?;
+    let recorder = Recorder::create(dir.join("replay-0.mp4"), options)?;
+    Ok(Self {
+      dir,
+      options,
+      duration,
+      recorder: Some(recorder),
+      generation: 0,
+      segments: VecDeque::new(),
+    })
+  }
+
+  pub fn duration(&self) -> Duration {
+    self.duration
+  }
+
+  pub fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+    let recorder = self.recorder.as_mut().unwrap();
+    let path = recorder.path().to_path_buf();
+    recorder.write_frame(frame)?;
+    if recorder.path() != path {
+      self.push_segment(path);
+    }
+    Ok(())
+  }
+
+  /// Write the frames of a raw stream, e.g. a compressed recording from
+  /// [`RawStreamReader::zstd`](crate::stream::RawStreamReader::zstd), timed by their `LastPresentTime`.
+  /// Return how many frames are read.
+  pub fn write_stream<R: Read>(&mut self, stream: RawStreamReader<R>) -> Result<u64> {
+    let mut frames = 0;
+    for raw in stream {
+      self.write_frame(&raw?.frame)?;
+      frames += 1;
+    }
+    Ok(frames)
+  }
+
+  pub fn write_audio(&mut self, buffer: &AudioBuffer) -> Result<()> {
+    self.recorder.as_mut().unwrap().write_audio(buffer)
+  }
+
+  /// Write the buffered recording to `path`, e.g. `replay.mp4`, and keep buffering.
+  pub fn save(&mut self, path: impl AsRef<Path>) -> Result<()> {
+    // finish the current file so it can be read, and continue in a new one
+    self.generation += 1;
+    let next = Recorder::create(
+      self.dir.join(format!("replay-{}.mp4", self.generation)),
+      self.options,
+    )?;
+    let current = self.recorder.replace(next).unwrap();
+    let current_path = current.path().to_path_buf();
+    current.finish()?;
+    self.push_segment(current_path);
+
+    remux(self.segments.make_contiguous(), path.as_ref())
+  }
+
+  fn push_segment(&mut self, path: PathBuf) {
+    self.segments.push_back(path);
+    // the current file may have just started, keep one more file to cover the whole duration
+    while self.segments.len() > SEGMENTS as usize + 1 {
+      if let Some(evicted) = self.segments.pop_front() {
+        fs::remove_file(evicted).ok();
+      }
+    }
+  }
+}
+
+impl Drop for ReplayBuffer {
+  fn drop(&mut self) {
+    if let Some(recorder) = self.recorder.take() {
+      recorder.finish().ok();
+    }
+    fs::remove_dir_all(&self.dir).ok();
+  }
+}
+
+/// Join files with the same streams into `output` without re-encoding.
+fn remux(inputs: &[PathBuf], output: &Path) -> Result<()> {
+  let url = HSTRING::from(output.to_string_lossy().as_ref());
+  let writer = unsafe { MFCreateSinkWriterFromURL(&url, None, None) }
+    .map_err(|e| Error::windows("MFCreateSinkWriterFromURL", e))?;
+
+  let mut streams = None;
+  // each file starts at zero, shift it after the end of the previous file
+  let mut offset = 0;
+  for input in inputs {
+    let url = HSTRING::from(input.to_string_lossy().as_ref());
+    let reader = unsafe { MFCreateSourceReaderFromURL(&url, None) }
+      .map_err(|e| Error::windows("MFCreateSourceReaderFromURL", e))?;
+    let count = match streams {
+      Some(count) => count,
+      None => {
+        let count = add_streams(&writer, &reader)?;
+        unsafe { writer.BeginWriting() }.map_err(|e| Error::windows("BeginWriting", e))?;
+        streams = Some(count);
+        count
+      }
+    };
+    offset = copy_samples(&writer, &reader, count, offset)?;
+  }
+
+  unsafe { writer.Finalize() }.map_err(|e| Error::windows("IMFSinkWriter.Finalize", e))
+}
+
+/// Add every stream of the reader to the writer with the same media type.
+/// Return the number of streams.
+fn add_streams(writer: &IMFSinkWriter, reader: &IMFSourceReader) -> Result<u32> {
+  let mut count = 0;
+  loop {
+    let media_type = match unsafe { reader.GetCurrentMediaType(count) } {
+      Ok(media_type) => media_type,
+      Err(e) if e.code() == MF_E_INVALIDSTREAMNUMBER => return Ok(count),
+      Err(e) => return Err(Error::windows("GetCurrentMediaType", e)),
+    };
+    unsafe { writer.AddStream(&media_type) }
+      .map_err(|e| Error::windows("IMFSinkWriter.AddStream", e))?;
+    count += 1;
+  }
+}
+
+/// Copy all samples shifted by `offset`. Return the end time of the last sample.
+fn copy_samples(
+  writer: &IMFSinkWriter,
+  reader: &IMFSourceReader,
+  streams: u32,
+  offset: i64,
+) -> Result<i64> {
+  unsafe { reader.SetStreamSelection(MF_SOURCE_READER_ALL_STREAMS.0 as u32, true) }
+    .map_err(|e| Error::windows("SetStreamSelection", e))?;
+
+  let mut ended = vec![false; streams as usize];
+  let mut end = offset;
+  while ended.iter().any(|ended| !ended) {
+    let mut index = 0;
+    let mut flags = 0;
+    let mut time = 0;
+    let mut sample: Option<IMFSample> = None;
+    unsafe {
+      reader.ReadSample(
+        MF_SOURCE_READER_ANY_STREAM.0 as u32,
+        0,
+        Some(&mut index),
+        Some(&mut flags),
+        Some(&mut time),
+        Some(&mut sample),
+      )
+    }
+    .map_err(|e| Error::windows("IMFSourceReader.ReadSample", e))?;
+
+    if let Some(sample) = sample.filter(|_| index < streams) {
+      let duration = unsafe { sample.GetSampleDuration() }.unwrap_or(0);
+      unsafe {
+        sample
+          .SetSampleTime(time + offset)
+          .and_then(|_| writer.WriteSample(index, &sample))
+      }
+      .map_err(|e| Error::windows("IMFSinkWriter.WriteSample", e))?;
+      end = end.max(time + offset + duration);
+    }
+    if flags & MF_SOURCE_READERF_ENDOFSTREAM.0 as u32 != 0 {
+      if let Some(ended) = ended.get_mut(index as usize) {
+        *ended = true;
+      }
+    }
+  }
+  Ok(end)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::ReplayBuffer;
+  use crate::duplication_context::qpc_now;
+  use crate::recorder::RecorderOptions;
+  use crate::stream::{RawStreamReader, RawStreamWriter};
+  use crate::test_utils::gradient;
+  use std::time::Duration;
+  use windows::Win32::Media::MediaFoundation::{MFShutdown, MFStartup, MFSTARTUP_FULL, MF_VERSION};
+
+  #[test]
+  fn replay_buffer() {
+    unsafe { MFStartup(MF_VERSION, MFSTARTUP_FULL) }.unwrap();
+
+    let frame = gradient(64, 64);
+    let mut replay =
+      ReplayBuffer::new(RecorderOptions::new(64, 64, 30), Duration::from_secs(4)).unwrap();
+    for _ in 0..60 {
+      replay.write_frame(&frame).unwrap();
+      std::thread::sleep(Duration::from_millis(33));
+    }
+    // keep at most 5 files of 1 second
+    assert!(replay.segments.len() <= 5);
+
+    let path = std::env::temp_dir().join("rusty-duplication-replay.mp4");
+    replay.save(&path).unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() > 0);
+    // keep buffering after saving
+    replay.write_frame(&frame).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    let dir = replay.dir.clone();
+    drop(replay);
+    assert!(!dir.exists());
+
+    unsafe { MFShutdown() }.unwrap();
+  }
+
+  #[test]
+  fn replay_stream() {
+    unsafe { MFStartup(MF_VERSION, MFSTARTUP_FULL) }.unwrap();
+
+    // 2 seconds at 30 fps
+    let (start, freq) = qpc_now();
+    let mut writer = RawStreamWriter::new(Vec::new()).unwrap();
+    for i in 0..60 {
+      let mut frame = gradient(64, 64);
+      frame.info.LastPresentTime = start + i * freq / 30;
+      writer.write_frame(&frame, &[]).unwrap();
+    }
+    let bytes = writer.into_inner();
+
+    let mut replay =
+      ReplayBuffer::new(RecorderOptions::new(64, 64, 30), Duration::from_secs(4)).unwrap();
+    let stream = RawStreamReader::new(bytes.as_slice()).unwrap();
+    assert_eq!(replay.write_stream(stream).unwrap(), 60);
+
+    let path = std::env::temp_dir().join("rusty-duplication-replay-stream.mp4");
+    replay.save(&path).unwrap();
+    assert!(std::fs::metadata(&path).unwrap().len() > 0);
+    std::fs::remove_file(&path).ok();
+    drop(replay);
+
+    unsafe { MFShutdown() }.unwrap();
+  }
+}
@@ -0,0 +1,295 @@
+use crate::error::{Error, ErrorContext};
+use crate::manager::{Manager, DEFAULT_TIMEOUT_MS};
+use crate::model::{MonitorId, Result};
+use crate::utils::{AdapterDescExt, OutputDescExt};
+use std::fmt;
+use windows::Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIFactory1, DXGI_ADAPTER_DESC1};
+
+/// The result of trying every adapter and output, see [`Manager::report`].
+#[derive(Debug)]
+pub struct EnumerationReport {
+  pub adapters: Vec<AdapterReport>,
+}
+
+#[derive(Debug)]
+pub struct AdapterReport {
+  pub index: u32,
+  /// Whether `GetDesc1` succeeded, the fields below are empty if not.
+  pub desc: std::result::Result<(), Error>,
+  /// Adapter description, e.g. `NVIDIA GeForce RTX 3060`.
+  pub description: String,
+  pub luid: i64,
+  /// Whether this is a software adapter, which usually hosts indirect displays.
+  pub software: bool,
+  /// Whether the D3D11 device is created on this adapter.
+  pub device: std::result::Result<(), Error>,
+  pub outputs: Vec<OutputReport>,
+}
+
+#[derive(Debug)]
+pub struct OutputReport {
+  pub id: MonitorId,
+  /// Whether `GetDesc` succeeded, the fields below are empty if not.
+  pub desc: std::result::Result<(), Error>,
+  /// Output device name, e.g. `\\.\DISPLAY1`.
+  pub device_name: String,
+  pub attached_to_desktop: bool,
+  /// Whether `DuplicateOutput` succeeded on this output.
+  pub duplication: std::result::Result<(), Error>,
+}
+
+impl EnumerationReport {
+  /// Return `true` if every output can be duplicated.
+  pub fn is_ok(&self) -> bool {
+    self.adapters.iter().all(|adapter| {
+      adapter
+        .outputs
+        .iter()
+        .all(|output| output.desc.is_ok() && output.duplication.is_ok())
+    })
+  }
+}
+
+impl fmt::Display for EnumerationReport {
+  fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for adapter in &self.adapters {
+      write!(
+        fmt,
+        "Adapter {}: {} (LUID {:#x})",
+        adapter.index, adapter.description, adapter.luid
+      )?;
+      if adapter.software {
+        write!(fmt, " (software)")?;
+      }
+      writeln!(fmt)?;
+      if let Err(ref e) = adapter.desc {
+        writeln!(fmt, "  Description: {}", e)?;
+      }
+      match adapter.device {
+        Ok(_) => writeln!(fmt, "  Device: OK")?,
+        Err(ref e) => writeln!(fmt, "  Device: {}", e)?,
+      }
+      for output in &adapter.outputs {
+        write!(fmt, "  Output {}: {}", output.id.output, output.device_name)?;
+        if !output.attached_to_desktop {
+          write!(fmt, " (detached)")?;
+        }
+        if let Err(ref e) = output.desc {
+          write!(fmt, " (description: {})", e)?;
+        }
+        match output.duplication {
+          Ok(_) => writeln!(fmt, ": OK")?,
+          Err(ref e) => writeln!(fmt, ": {}", e)?,
+        }
+        if let Some(failure) = output
+          .duplication
+          .as_ref()
+          .err()
+          .and_then(|e| e.duplication)
+        {
+          writeln!(fmt, "    Reason: {}", failure)?;
+          writeln!(fmt, "    Remediation: {}", failure.remediation())?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Manager {
+  /// Try to create a device for every adapter and duplicate every output,
+  /// and report what succeeded and what failed instead of stopping at the first error.
+  /// The report can be printed to diagnose problems like the dual-GPU `DuplicateOutput` failure.
+  pub fn report() -> Result<EnumerationReport> {
+    let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>() }
+      .map_err(|e| Error::windows("CreateDXGIFactory1", e))?;
+    let mut adapters = Vec::new();
+
+    for adapter_index in 0.. {
+      let adapter = match unsafe { factory.EnumAdapters1(adapter_index) } {
+        Ok(adapter) => adapter,
+        Err(_) => break,
+      };
+      let adapter_id = MonitorId {
+        adapter: adapter_index,
+        output: 0,
+      };
+      let mut adapter_desc = DXGI_ADAPTER_DESC1::default();
+      let desc = unsafe { adapter.GetDesc1(&mut adapter_desc) }.map_err(|e| {
+        Error::windows_with_context(
+          "IDXGIAdapter1.GetDesc1",
+          e,
+          ErrorContext::collect(adapter_id, Some(&adapter), None),
+        )
+      });
+      let device = Self::create_device(adapter_id, &adapter);
+
+      let mut outputs = Vec::new();
+      for output_index in 0.. {
+        let output = match unsafe { adapter.EnumOutputs(output_index) } {
+          Ok(output) => output,
+          Err(_) => break,
+        };
+        let id = MonitorId {
+          adapter: adapter_index,
+          output: output_index,
+        };
+        let output_desc = Self::output_desc(id, Some(&adapter), &output);
+        let duplication = match device {
+          Ok((ref device, ref device_context)) => {
+            Self::duplicate(id, device, device_context, &output, DEFAULT_TIMEOUT_MS, &[])
+              .map(|_| ())
+          }
+          Err(_) => Err(Error::new("No device")),
+        };
+        outputs.push(match output_desc {
+          Ok(output_desc) => OutputReport {
+            id,
+            desc: Ok(()),
+            device_name: output_desc.device_name(),
+            attached_to_desktop: output_desc.is_attached(),
+            duplication,
+          },
+          Err(e) => OutputReport {
+            id,
+            desc: Err(e),
+            device_name: String::new(),
+            attached_to_desktop: false,
+            duplication,
+          },
+        });
+      }
+
+      adapters.push(AdapterReport {
+        index: adapter_index,
+        desc,
+        description: adapter_desc.description(),
+        luid: adapter_desc.luid(),
+        software: adapter_desc.is_software(),
+        device: device.map(|_| ()),
+        outputs,
+      });
+    }
+
+    Ok(EnumerationReport { adapters })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{AdapterReport, EnumerationReport, OutputReport};
+  use crate::{
+    error::{DuplicationFailure, Error},
+    manager::Manager,
+    model::MonitorId,
+  };
+
+  #[test]
+  fn display() {
+    let report = EnumerationReport {
+      adapters: vec![AdapterReport {
+        index: 0,
+        desc: Ok(()),
+        description: "Test Adapter".to_string(),
+        luid: 0x1234,
+        software: false,
+        device: Ok(()),
+        outputs: vec![
+          OutputReport {
+            id: MonitorId {
+              adapter: 0,
+              output: 0,
+            },
+            desc: Ok(()),
+            device_name: "\\\\.\\DISPLAY1".to_string(),
+            attached_to_desktop: true,
+            duplication: Ok(()),
+          },
+          OutputReport {
+            id: MonitorId {
+              adapter: 0,
+              output: 1,
+            },
+            desc: Ok(()),
+            device_name: "\\\\.\\DISPLAY2".to_string(),
+            attached_to_desktop: false,
+            duplication: Err(Error::new("DuplicateOutput")),
+          },
+          OutputReport {
+            id: MonitorId {
+              adapter: 0,
+              output: 2,
+            },
+            desc: Ok(()),
+            device_name: "\\\\.\\DISPLAY3".to_string(),
+            attached_to_desktop: true,
+            duplication: Err(Error {
+              duplication: Some(DuplicationFailure::LimitReached),
+              ..Error::new("DuplicateOutput")
+            }),
+          },
+        ],
+      }],
+    };
+    assert!(!report.is_ok());
+    assert_eq!(
+      report.to_string(),
+      "Adapter 0: Test Adapter (LUID 0x1234)\n  Device: OK\n  Output 0: \\\\.\\DISPLAY1: OK\n  Output 1: \\\\.\\DISPLAY2 (detached): DuplicateOutput\n  Output 2: \\\\.\\DISPLAY3: DuplicateOutput\n    Reason: too many applications are capturing the screen\n    Remediation: close other screen recording or sharing applications\n"
+    );
+  }
+
+  #[test]
+  fn failed_desc() {
+    let output = |output, desc, duplication| OutputReport {
+      id: MonitorId { adapter: 1, output },
+      desc,
+      device_name: if output == 0 {
+        String::new()
+      } else {
+        "\\\\.\\DISPLAY2".to_string()
+      },
+      attached_to_desktop: output != 0,
+      duplication,
+    };
+    let report = EnumerationReport {
+      adapters: vec![
+        AdapterReport {
+          index: 0,
+          desc: Err(Error::new("IDXGIAdapter1.GetDesc1")),
+          description: String::new(),
+          luid: 0,
+          software: false,
+          device: Ok(()),
+          outputs: vec![],
+        },
+        AdapterReport {
+          index: 1,
+          desc: Ok(()),
+          description: "Test Adapter".to_string(),
+          luid: 0x1234,
+          software: false,
+          device: Ok(()),
+          outputs: vec![
+            output(0, Err(Error::new("DXGI_OUTPUT_DESC.GetDesc")), Ok(())),
+            output(1, Ok(()), Ok(())),
+          ],
+        },
+      ],
+    };
+    assert!(!report.is_ok());
+    assert_eq!(
+      report.to_string(),
+      "Adapter 0:  (LUID 0x0)\n  Description: IDXGIAdapter1.GetDesc1\n  Device: OK\nAdapter 1: Test Adapter (LUID 0x1234)\n  Device: OK\n  Output 0:  (detached) (description: DXGI_OUTPUT_DESC.GetDesc): OK\n  Output 1: \\\\.\\DISPLAY2: OK\n"
+    );
+  }
+
+  #[test]
+  fn report() {
+    let report = Manager::report().unwrap();
+    assert_ne!(report.adapters.len(), 0);
+    assert!(report.adapters.iter().any(|adapter| adapter
+      .outputs
+      .iter()
+      .any(|output| output.duplication.is_ok())));
+  }
+}
@@ -0,0 +1,227 @@
+use crate::color::check_bgra8;
+use crate::duplication_context::DuplicationContext;
+use crate::error::Error;
+use crate::frame::{Frame, ProvenanceStamp};
+use crate::manager::{Manager, DEFAULT_TIMEOUT_MS};
+use crate::model::{CaptureOptions, MonitorId, MonitorSelector, Rect, Result};
+use crate::utils::{FormatExt, OutputDescExt};
+use std::thread;
+
+/// How many times to re-acquire before the first frame with a desktop image arrives.
+const SCREENSHOT_RETRIES: u32 = 10;
+
+/// Scan monitors, select one and capture a single frame from it.
+/// This will wait until a frame with the desktop image is available.
+pub fn screenshot(selector: MonitorSelector) -> Result<Frame> {
+  let manager = Manager::default()?;
+  capture_frame(manager.select(&selector)?)
+}
+
+/// Capture every monitor once in parallel, one thread per monitor.
+/// This will wait until a frame with the desktop image is available for each monitor.
+pub fn screenshot_all() -> Result<Vec<(MonitorId, Frame)>> {
+  // duplication contexts can't be sent to other threads,
+  // so only collect ids here and re-open each monitor in its own thread
+  let ids: Vec<MonitorId> = Manager::default()?
+    .contexts
+    .iter()
+    .map(|ctx| ctx.id())
+    .collect();
+
+  let handles: Vec<_> = ids
+    .into_iter()
+    .map(|id| {
+      thread::spawn(move || {
+        let ctx = Manager::open(id, DEFAULT_TIMEOUT_MS)?;
+        Ok((id, capture_frame(&ctx)?))
+      })
+    })
+    .collect();
+
+  handles
+    .into_iter()
+    .map(|handle| {
+      handle
+        .join()
+        .unwrap_or_else(|_| Err(Error::new("Screenshot thread panicked")))
+    })
+    .collect()
+}
+
+/// Scan monitors and capture a region in virtual desktop coordinates, which may span multiple monitors.
+/// See [`Manager::capture_region`].
+pub fn capture_region(rect: Rect) -> Result<Vec<u8>> {
+  Manager::default()?.capture_region(&rect)
+}
+
+impl Manager {
+  /// Capture every monitor the `rect` touches and assemble the region into one BGRA32 buffer.
+  /// Areas not covered by any monitor are left zeroed.
+  /// Return an error if a monitor the `rect` touches isn't duplicated in 8-bit BGRA.
+  ///
+  /// The coordinates are desktop coordinates reported by DXGI,
+  /// so the process should be per-monitor DPI aware to get physical pixels.
+  pub fn capture_region(&self, rect: &Rect) -> Result<Vec<u8>> {
+    if rect.is_empty() {
+      return Err(Error::new("Empty region"));
+    }
+    let mut buffer = vec![0u8; rect.width() as usize * rect.height() as usize * 4];
+
+    for ctx in &self.contexts {
+      let monitor_rect = ctx.dxgi_output_desc()?.rect();
+      let Some(area) = rect.intersect(&monitor_rect) else {
+        continue;
+      };
+
+      let frame = capture_frame(ctx)?;
+      check_bgra8(frame.format, "capture a region")?;
+      if frame.width != monitor_rect.width() || frame.height != monitor_rect.height() {
+        return Err(Error::new(
+          "Frame size doesn't match desktop coordinates, is the process DPI aware?",
+        ));
+      }
+      copy_rect(
+        &frame.buffer,
+        frame.width,
+        &monitor_rect,
+        &mut buffer,
+        rect,
+        &area,
+        4,
+      );
+    }
+
+    Ok(buffer)
+  }
+}
+
+/// Copy `area` from the `src` buffer which covers `src_rect`
+/// to the `dest` buffer which covers `dest_rect`.
+/// `area` must be inside both rectangles, and both buffers must have `bytes_per_pixel`.
+pub(crate) fn copy_rect(
+  src: &[u8],
+  src_width: u32,
+  src_rect: &Rect,
+  dest: &mut [u8],
+  dest_rect: &Rect,
+  area: &Rect,
+  bytes_per_pixel: usize,
+) {
+  let line_bytes = area.width() as usize * bytes_per_pixel;
+  for y in area.top..area.bottom {
+    let src_offset = ((y - src_rect.top) as usize * src_width as usize
+      + (area.left - src_rect.left) as usize)
+      * bytes_per_pixel;
+    let dest_offset = ((y - dest_rect.top) as usize * dest_rect.width() as usize
+      + (area.left - dest_rect.left) as usize)
+      * bytes_per_pixel;
+    dest[dest_offset..dest_offset + line_bytes]
+      .copy_from_slice(&src[src_offset..src_offset + line_bytes]);
+  }
+}
+
+fn capture_frame(ctx: &DuplicationContext) -> Result<Frame> {
+  let (width, height) = ctx.frame_size()?;
+  let format = ctx.texture_format()?;
+  let mut buffer = vec![0u8; width as usize * height as usize * format.bytes_per_pixel()];
+  let info = ctx.capture_into(
+    &mut buffer,
+    &CaptureOptions {
+      retries: SCREENSHOT_RETRIES,
+      ..Default::default()
+    },
+  )?;
+  Ok(Frame {
+    buffer,
+    width,
+    height,
+    info,
+    format,
+    provenance: Some(ProvenanceStamp::new(ctx)?.stamp()),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{capture_frame, capture_region, copy_rect, screenshot, screenshot_all};
+  use crate::{
+    manager::{Manager, DEFAULT_TIMEOUT_MS},
+    model::{MonitorSelector, Rect},
+    utils::{FrameInfoExt, OutputDescExt},
+  };
+  use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT_R16G16B16A16_FLOAT;
+
+  #[test]
+  fn primary_screenshot() {
+    let frame = screenshot(MonitorSelector::Primary).unwrap();
+    assert!(frame.info.desktop_updated());
+    assert_eq!(
+      frame.buffer.len(),
+      frame.width as usize * frame.height as usize * 4
+    );
+    assert!(frame.buffer.iter().any(|&b| b != 0));
+  }
+
+  #[test]
+  fn all_screenshots() {
+    let monitor_count = Manager::default().unwrap().contexts.len();
+    let frames = screenshot_all().unwrap();
+    assert_eq!(frames.len(), monitor_count);
+    for (_, frame) in frames {
+      assert!(frame.info.desktop_updated());
+      assert!(frame.buffer.iter().any(|&b| b != 0));
+    }
+  }
+
+  #[test]
+  fn copy_rect_across_monitors() {
+    // two 2x2 monitors side by side, filled with 1 and 2
+    let left_rect = Rect::new(0, 0, 2, 2);
+    let right_rect = Rect::new(2, 0, 4, 2);
+    let left = vec![1u8; 2 * 2 * 4];
+    let right = vec![2u8; 2 * 2 * 4];
+
+    // a 2x1 region on the border
+    let region = Rect::new(1, 1, 3, 2);
+    let mut buffer = vec![0u8; 2 * 4];
+    for (src, src_rect) in [(&left, &left_rect), (&right, &right_rect)] {
+      let area = region.intersect(src_rect).unwrap();
+      copy_rect(src, 2, src_rect, &mut buffer, &region, &area, 4);
+    }
+    assert_eq!(buffer, [1, 1, 1, 1, 2, 2, 2, 2]);
+  }
+
+  #[test]
+  fn primary_region() {
+    let manager = Manager::default().unwrap();
+    let position = manager
+      .select(&MonitorSelector::Primary)
+      .unwrap()
+      .dxgi_output_desc()
+      .unwrap()
+      .position();
+    let rect = Rect::new(position.x, position.y, position.x + 100, position.y + 100);
+    drop(manager);
+
+    let buffer = capture_region(rect).unwrap();
+    assert_eq!(buffer.len(), 100 * 100 * 4);
+    assert!(buffer.iter().any(|&b| b != 0));
+  }
+
+  #[test]
+  fn float_screenshot() {
+    let manager =
+      Manager::with_formats(DEFAULT_TIMEOUT_MS, &[DXGI_FORMAT_R16G16B16A16_FLOAT]).unwrap();
+    let ctx = manager.select(&MonitorSelector::Primary).unwrap();
+    let frame = capture_frame(ctx).unwrap();
+    assert_eq!(frame.format, DXGI_FORMAT_R16G16B16A16_FLOAT);
+    assert_eq!(
+      frame.buffer.len(),
+      frame.width as usize * frame.height as usize * 8
+    );
+
+    let position = ctx.dxgi_output_desc().unwrap().position();
+    let rect = Rect::new(position.x, position.y, position.x + 100, position.y + 100);
+    assert!(manager.capture_region(&rect).is_err());
+  }
+}
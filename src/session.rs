@@ -0,0 +1,312 @@
+//! Capture a monitor with one object which scans monitors, selects one, builds the capturer,
+//! recovers from desktop switches, mode changes and hotplug, and delivers frames.
+
+use crate::capturer::supervised::{RestartPolicy, SupervisedCapturer, SupervisorEvent};
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::model::{Backpressure, CaptureMode, MonitorId, MonitorSelector, Result};
+use std::time::Duration;
+use windows::Win32::Foundation::HANDLE;
+
+/// Parameters of a [`DuplicationSession`].
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+  /// The monitor to capture, selected again after each recovery,
+  /// e.g. the new primary monitor after the previous one is unplugged.
+  pub monitor: MonitorSelector,
+  pub restart: RestartPolicy,
+  pub backpressure: Backpressure,
+  pub mode: CaptureMode,
+}
+
+/// The state of a [`DuplicationSession`] when [`DuplicationSession::start`] returns.
+#[derive(Debug)]
+pub enum SessionStart {
+  /// The monitor is opened and frames are delivered.
+  Capturing(MonitorId),
+  /// Opening the monitor failed with a recoverable error, e.g. while a secure desktop is shown.
+  /// It is retried in the background and frames are delivered once it succeeds.
+  Reconnecting(Error),
+  /// The workstation is locked, capturing starts when it is unlocked.
+  Paused,
+  /// An exclusive fullscreen transition is bridged before the monitor is opened.
+  FullscreenTransition,
+}
+
+/// The typical capture loop in a small API: [`start`](DuplicationSession::start),
+/// iterate [`frames`](DuplicationSession::frames) and [`stop`](DuplicationSession::stop).
+///
+/// Capturing runs in a [`SupervisedCapturer`], which rebuilds the capturer when access is lost,
+/// the display mode changes or monitors are plugged or unplugged.
+pub struct DuplicationSession {
+  options: SessionOptions,
+  capturer: Option<SupervisedCapturer>,
+  monitor: Option<MonitorId>,
+  reconnects: u64,
+  transitions: u64,
+  paused: bool,
+  /// Why the capturer stopped, reported by [`DuplicationSession::stop`].
+  error: Option<Error>,
+}
+
+impl DuplicationSession {
+  pub fn new(options: SessionOptions) -> Self {
+    Self {
+      options,
+      capturer: None,
+      monitor: None,
+      reconnects: 0,
+      transitions: 0,
+      paused: false,
+      error: None,
+    }
+  }
+
+  pub fn options(&self) -> &SessionOptions {
+    &self.options
+  }
+
+  /// Start capturing and wait until the monitor is opened or the first attempt fails.
+  /// Fail if the session is already running or the monitor can't be opened,
+  /// unless the failure is recoverable, e.g. while a secure desktop is shown,
+  /// in which case the session keeps running and the returned state tells why it isn't capturing yet.
+  pub fn start(&mut self) -> Result<SessionStart> {
+    if self.capturer.is_some() {
+      return Err(Error::new("Session already started"));
+    }
+    let capturer = SupervisedCapturer::new(
+      self.options.monitor.clone(),
+      self.options.restart.clone(),
+      self.options.backpressure,
+    );
+    capturer.set_mode(self.options.mode);
+    self.capturer = Some(capturer);
+    self.error = None;
+    self.reconnects = 0;
+    self.transitions = 0;
+    self.paused = false;
+
+    // the worker reports the first attempt before any frame
+    match self.capturer.as_ref().and_then(|capturer| capturer.recv()) {
+      Some(SupervisorEvent::Started(id)) => {
+        self.monitor = Some(id);
+        Ok(SessionStart::Capturing(id))
+      }
+      Some(SupervisorEvent::Reconnecting { error, .. }) => {
+        self.reconnects += 1;
+        Ok(SessionStart::Reconnecting(error))
+      }
+      Some(SupervisorEvent::Paused) => {
+        self.paused = true;
+        Ok(SessionStart::Paused)
+      }
+      Some(SupervisorEvent::FullscreenTransition) => {
+        self.transitions += 1;
+        Ok(SessionStart::FullscreenTransition)
+      }
+      Some(SupervisorEvent::Stopped(e)) => {
+        self.capturer = None;
+        Err(e)
+      }
+      Some(
+        SupervisorEvent::Frame(_) | SupervisorEvent::StaleFrame(_) | SupervisorEvent::Resumed,
+      )
+      | None => {
+        self.stop().ok();
+        Err(Error::new("Session stopped"))
+      }
+    }
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.capturer.is_some()
+  }
+
+  /// The captured monitor, `None` while not running or reconnecting.
+  pub fn monitor(&self) -> Option<MonitorId> {
+    self.monitor
+  }
+
+  /// Whether capturing is paused while the workstation is locked,
+  /// see [`RestartPolicy`](crate::capturer::supervised::RestartPolicy).
+  pub fn is_paused(&self) -> bool {
+    self.paused
+  }
+
+  /// How many times the capturer was rebuilt since the session started.
+  pub fn reconnects(&self) -> u64 {
+    self.reconnects
+  }
+
+  /// How many exclusive fullscreen transitions were bridged since the session started,
+  /// see [`RestartPolicy::fullscreen`](crate::capturer::supervised::RestartPolicy::fullscreen).
+  pub fn fullscreen_transitions(&self) -> u64 {
+    self.transitions
+  }
+
+  /// How many frames are dropped or coalesced by the backpressure policy.
+  pub fn dropped_frames(&self) -> u64 {
+    self
+      .capturer
+      .as_ref()
+      .map_or(0, |capturer| capturer.dropped_frames())
+  }
+
+  /// Get an event which is signaled while [`DuplicationSession::next_frame`] returns immediately,
+  /// see [`SupervisedCapturer::frame_event`]. Each start creates a new event,
+  /// which is closed when the session stops.
+  pub fn frame_event(&self) -> Result<HANDLE> {
+    self
+      .capturer
+      .as_ref()
+      .ok_or_else(|| Error::new("Session not running"))?
+      .frame_event()
+  }
+
+  /// In [`CaptureMode::Pull`], ask for one frame.
+  pub fn request_frame(&self) {
+    if let Some(capturer) = &self.capturer {
+      capturer.request_frame();
+    }
+  }
+
+  /// Wait for frames. The iterator ends when the session stops,
+  /// see [`DuplicationSession::stop`] for the reason.
+  pub fn frames(&mut self) -> Frames<'_> {
+    Frames { session: self }
+  }
+
+  /// Wait for the next frame at most `timeout`. Return `Ok(None)` on timeout
+  /// and `Err` if the session is not running or has stopped.
+  pub fn next_frame(&mut self, timeout: Duration) -> Result<Option<Frame>> {
+    loop {
+      let capturer = self
+        .capturer
+        .as_ref()
+        .ok_or_else(|| Error::new("Session not running"))?;
+      let Some(event) = capturer.recv_timeout(timeout)? else {
+        return Ok(None);
+      };
+      if let Some(frame) = self.handle(event) {
+        return Ok(Some(frame));
+      }
+      if let Some(e) = self.error.take() {
+        return Err(e);
+      }
+    }
+  }
+
+  /// Stop capturing. Return the error which stopped the session earlier, if any.
+  /// The session can be started again.
+  pub fn stop(&mut self) -> Result<()> {
+    if let Some(capturer) = self.capturer.take() {
+      capturer.stop();
+    }
+    self.monitor = None;
+    self.paused = false;
+    self.error.take().map_or(Ok(()), Err)
+  }
+
+  /// Update the status by an event and return its frame.
+  fn handle(&mut self, event: SupervisorEvent) -> Option<Frame> {
+    match event {
+      SupervisorEvent::Started(id) => {
+        self.monitor = Some(id);
+        self.paused = false;
+      }
+      // stale frames are only marked if the policy doesn't drop them, see `Frame::age`
+      SupervisorEvent::Frame(frame) | SupervisorEvent::StaleFrame(frame) => return Some(frame),
+      SupervisorEvent::Reconnecting { .. } => {
+        self.monitor = None;
+        self.reconnects += 1;
+      }
+      SupervisorEvent::Paused => {
+        self.monitor = None;
+        self.paused = true;
+      }
+      SupervisorEvent::Resumed => {}
+      // the monitor is kept, frames resume after the transition
+      SupervisorEvent::FullscreenTransition => self.transitions += 1,
+      SupervisorEvent::Stopped(e) => {
+        self.capturer = None;
+        self.monitor = None;
+        self.error = Some(e);
+      }
+    }
+    None
+  }
+}
+
+/// Frames of a [`DuplicationSession`], see [`DuplicationSession::frames`].
+pub struct Frames<'a> {
+  session: &'a mut DuplicationSession,
+}
+
+impl Iterator for Frames<'_> {
+  type Item = Frame;
+
+  fn next(&mut self) -> Option<Frame> {
+    loop {
+      let event = self.session.capturer.as_ref()?.recv();
+      let Some(event) = event else {
+        self.session.capturer = None;
+        return None;
+      };
+      if let Some(frame) = self.session.handle(event) {
+        return Some(frame);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{DuplicationSession, SessionOptions, SessionStart};
+  use crate::model::{CaptureMode, MonitorSelector};
+  use std::time::Duration;
+
+  #[test]
+  fn session() {
+    let mut session = DuplicationSession::new(SessionOptions::default());
+    assert!(matches!(
+      session.start().unwrap(),
+      SessionStart::Capturing(id) if session.monitor() == Some(id)
+    ));
+    assert!(session.is_running());
+    assert!(session.start().is_err());
+
+    for frame in session.frames().take(1) {
+      assert_eq!(
+        frame.buffer.len(),
+        frame.width as usize * frame.height as usize * 4
+      );
+    }
+    assert!(session.monitor().is_some());
+    session.stop().unwrap();
+    assert!(!session.is_running());
+    assert!(session.next_frame(Duration::ZERO).is_err());
+
+    // frames are repeated on request in pull mode
+    let mut session = DuplicationSession::new(SessionOptions {
+      mode: CaptureMode::Pull,
+      ..Default::default()
+    });
+    session.start().unwrap();
+    for _ in 0..2 {
+      session.request_frame();
+      assert!(session
+        .next_frame(Duration::from_secs(5))
+        .unwrap()
+        .is_some());
+    }
+    session.stop().unwrap();
+
+    // the monitor doesn't exist
+    let mut session = DuplicationSession::new(SessionOptions {
+      monitor: MonitorSelector::Index(usize::MAX),
+      ..Default::default()
+    });
+    assert!(session.start().is_err());
+    assert!(!session.is_running());
+  }
+}
@@ -0,0 +1,305 @@
+//! Capture continuously but only keep a frame when enough of the screen changed or an interval elapsed,
+//! e.g. for time-lapses or audit logs which shouldn't store thousands of identical frames.
+
+use crate::duplication_context::DuplicationContext;
+#[cfg(feature = "image")]
+use crate::error::Error;
+use crate::frame::{Frame, ProvenanceStamp};
+use crate::model::{Rect, Result};
+use crate::utils::{FormatExt, FrameInfoExt};
+use std::time::{Duration, Instant};
+use windows::Win32::Graphics::Direct3D11::{ID3D11Texture2D, D3D11_TEXTURE2D_DESC};
+use windows::Win32::Graphics::Dxgi::{DXGI_ERROR_WAIT_TIMEOUT, DXGI_OUTDUPL_FRAME_INFO};
+
+/// Changes are tracked in square tiles of this size, in pixels.
+const TILE_SIZE: u32 = 16;
+
+/// When [`Snapshotter`] takes a snapshot. The first frame is always a snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotTrigger {
+  /// The fraction of the frame, from 0 to 1, which must change since the last snapshot.
+  /// Changes are measured from dirty rects, rounded up to 16x16 tiles.
+  pub min_changed: f64,
+  /// Take a snapshot after this time even without changes, `None` to only snapshot changes.
+  pub interval: Option<Duration>,
+}
+
+impl Default for SnapshotTrigger {
+  /// A snapshot when 1% of the frame changed, without interval.
+  fn default() -> Self {
+    Self {
+      min_changed: 0.01,
+      interval: None,
+    }
+  }
+}
+
+impl SnapshotTrigger {
+  /// `since_last` is `None` before the first snapshot.
+  fn due(&self, changed: f64, since_last: Option<Duration>) -> bool {
+    let Some(since_last) = since_last else {
+      return true;
+    };
+    (changed > 0.0 && changed >= self.min_changed)
+      || self.interval.is_some_and(|interval| since_last >= interval)
+  }
+}
+
+/// Capture a monitor and yield a [`Frame`] only when the [`SnapshotTrigger`] fires.
+///
+/// Each call to [`Snapshotter::capture`] waits for one frame up to the timeout of the context,
+/// so call it in a loop, or use [`Snapshotter::run`].
+pub struct Snapshotter<'a> {
+  ctx: &'a DuplicationContext,
+  trigger: SnapshotTrigger,
+  texture: ID3D11Texture2D,
+  texture_desc: D3D11_TEXTURE2D_DESC,
+  /// The latest desktop image, updated by dirty rects.
+  buffer: Vec<u8>,
+  /// The frame info of the latest desktop update, `None` before the first one.
+  info: Option<DXGI_OUTDUPL_FRAME_INFO>,
+  changes: ChangedTiles,
+  last_snapshot: Option<Instant>,
+  stamp: ProvenanceStamp,
+}
+
+impl<'a> Snapshotter<'a> {
+  pub fn new(ctx: &'a DuplicationContext, trigger: SnapshotTrigger) -> Result<Self> {
+    let (texture, _, texture_desc) = ctx.create_readable_texture()?;
+    let len = texture_desc.Width as usize
+      * texture_desc.Height as usize
+      * texture_desc.Format.bytes_per_pixel();
+    Ok(Self {
+      ctx,
+      trigger,
+      texture,
+      texture_desc,
+      buffer: vec![0u8; len],
+      info: None,
+      changes: ChangedTiles::new(texture_desc.Width, texture_desc.Height),
+      last_snapshot: None,
+      stamp: ProvenanceStamp::new(ctx)?,
+    })
+  }
+
+  pub fn trigger(&self) -> SnapshotTrigger {
+    self.trigger
+  }
+
+  pub fn set_trigger(&mut self, trigger: SnapshotTrigger) {
+    self.trigger = trigger;
+  }
+
+  /// The fraction of the frame changed since the last snapshot.
+  pub fn changed(&self) -> f64 {
+    self.changes.fraction()
+  }
+
+  /// Capture one frame and return a snapshot if the trigger fires.
+  /// A timeout without desktop updates is not an error, the interval is still checked.
+  pub fn capture(&mut self) -> Result<Option<Frame>> {
+    match self.ctx.acquire() {
+      Ok(frame) => {
+        if frame.info().desktop_updated() {
+          match frame.dirty_rects() {
+            Ok(rects) => rects.iter().for_each(|rect| self.changes.mark(rect)),
+            Err(_) => self.changes.mark_all(),
+          }
+          frame.update_slice(&mut self.buffer, &self.texture, &self.texture_desc)?;
+          self.info = Some(*frame.info());
+        }
+        frame.release()?;
+      }
+      Err(e) if e.windows.as_ref().map(|e| e.code()) == Some(DXGI_ERROR_WAIT_TIMEOUT) => {}
+      Err(e) => return Err(e),
+    }
+
+    let Some(info) = self.info else {
+      return Ok(None);
+    };
+    let now = Instant::now();
+    let since_last = self.last_snapshot.map(|last| now - last);
+    if !self.trigger.due(self.changes.fraction(), since_last) {
+      return Ok(None);
+    }
+    self.last_snapshot = Some(now);
+    self.changes.clear();
+    Ok(Some(Frame {
+      buffer: self.buffer.clone(),
+      width: self.texture_desc.Width,
+      height: self.texture_desc.Height,
+      info,
+      format: self.texture_desc.Format,
+      provenance: Some(self.stamp.stamp()),
+    }))
+  }
+
+  /// Pass snapshots to `on_snapshot` until it returns `false` or capturing fails.
+  pub fn run(&mut self, mut on_snapshot: impl FnMut(Frame) -> bool) -> Result<()> {
+    loop {
+      if let Some(frame) = self.capture()? {
+        if !on_snapshot(frame) {
+          return Ok(());
+        }
+      }
+    }
+  }
+
+  /// Like [`Snapshotter::capture`], but save the snapshot into `dir`
+  /// as `snapshot-<milliseconds since the Unix epoch>.<extension>` and return its path.
+  #[cfg(feature = "image")]
+  pub fn capture_to(
+    &mut self,
+    dir: impl AsRef<std::path::Path>,
+    format: crate::image::ImageFormat,
+  ) -> Result<Option<std::path::PathBuf>> {
+    let Some(frame) = self.capture()? else {
+      return Ok(None);
+    };
+    let millis = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map_err(|e| Error::new(format!("System time before the Unix epoch: {}", e)))?
+      .as_millis();
+    let path = dir
+      .as_ref()
+      .join(format!("snapshot-{}.{}", millis, format.extension()));
+    frame.save_as(&path, format)?;
+    Ok(Some(path))
+  }
+}
+
+/// Tiles touched by dirty rects since the last snapshot.
+#[derive(Debug, Clone)]
+struct ChangedTiles {
+  columns: u32,
+  rows: u32,
+  tiles: Vec<bool>,
+  count: usize,
+}
+
+impl ChangedTiles {
+  fn new(width: u32, height: u32) -> Self {
+    let columns = width.div_ceil(TILE_SIZE);
+    let rows = height.div_ceil(TILE_SIZE);
+    Self {
+      columns,
+      rows,
+      tiles: vec![false; columns as usize * rows as usize],
+      count: 0,
+    }
+  }
+
+  fn mark(&mut self, rect: &Rect) {
+    let bounds = Rect::new(
+      0,
+      0,
+      (self.columns * TILE_SIZE) as i32,
+      (self.rows * TILE_SIZE) as i32,
+    );
+    let Some(rect) = rect.intersect(&bounds) else {
+      return;
+    };
+    let tile = TILE_SIZE as i32;
+    for row in rect.top / tile..(rect.bottom + tile - 1) / tile {
+      for column in rect.left / tile..(rect.right + tile - 1) / tile {
+        let changed = &mut self.tiles[(row * self.columns as i32 + column) as usize];
+        if !*changed {
+          *changed = true;
+          self.count += 1;
+        }
+      }
+    }
+  }
+
+  fn mark_all(&mut self) {
+    self.tiles.fill(true);
+    self.count = self.tiles.len();
+  }
+
+  fn clear(&mut self) {
+    self.tiles.fill(false);
+    self.count = 0;
+  }
+
+  fn fraction(&self) -> f64 {
+    if self.tiles.is_empty() {
+      return 0.0;
+    }
+    self.count as f64 / self.tiles.len() as f64
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{ChangedTiles, SnapshotTrigger, Snapshotter};
+  use crate::manager::Manager;
+  use crate::model::Rect;
+  use std::time::Duration;
+
+  #[test]
+  fn changed_tiles() {
+    // 4x2 tiles, the last column is partial
+    let mut tiles = ChangedTiles::new(60, 32);
+    assert_eq!(tiles.fraction(), 0.0);
+    tiles.mark(&Rect::new(0, 0, 1, 1));
+    assert_eq!(tiles.fraction(), 1.0 / 8.0);
+    // overlapping rects count once
+    tiles.mark(&Rect::new(10, 10, 20, 20));
+    assert_eq!(tiles.fraction(), 4.0 / 8.0);
+    // clipped to the frame
+    tiles.mark(&Rect::new(50, -10, 100, 5));
+    assert_eq!(tiles.fraction(), 5.0 / 8.0);
+    tiles.clear();
+    assert_eq!(tiles.fraction(), 0.0);
+    tiles.mark_all();
+    assert_eq!(tiles.fraction(), 1.0);
+  }
+
+  #[test]
+  fn trigger() {
+    let trigger = SnapshotTrigger {
+      min_changed: 0.1,
+      interval: Some(Duration::from_secs(10)),
+    };
+    let second = Some(Duration::from_secs(1));
+    assert!(trigger.due(0.0, None));
+    assert!(!trigger.due(0.0, second));
+    assert!(!trigger.due(0.05, second));
+    assert!(trigger.due(0.1, second));
+    assert!(trigger.due(0.0, Some(Duration::from_secs(10))));
+
+    // any change with a zero threshold
+    let trigger = SnapshotTrigger {
+      min_changed: 0.0,
+      interval: None,
+    };
+    assert!(!trigger.due(0.0, Some(Duration::from_secs(100))));
+    assert!(trigger.due(0.001, second));
+  }
+
+  #[test]
+  fn snapshotter() {
+    let manager = Manager::default().unwrap();
+    let ctx = &manager.contexts[0];
+    let mut snapshotter = Snapshotter::new(
+      ctx,
+      SnapshotTrigger {
+        min_changed: 1.0,
+        interval: Some(Duration::from_millis(100)),
+      },
+    )
+    .unwrap();
+    let mut snapshots = 0;
+    snapshotter
+      .run(|frame| {
+        assert_eq!(
+          frame.buffer.len(),
+          frame.width as usize * frame.height as usize * 4
+        );
+        snapshots += 1;
+        snapshots < 3
+      })
+      .unwrap();
+    assert_eq!(snapshots, 3);
+  }
+}
@@ -0,0 +1,370 @@
+//! A simple container of raw frames, for lossless capture dumps and offline processing.
+//!
+//! A stream starts with [`STREAM_MAGIC`] and a `u32` version, followed by frames.
+//! Each frame is a header of little-endian integers: width, height, `DXGI_FORMAT`,
+//! dirty rect count (`u32`s), payload size (`u64`) and the [`FrameInfo`] fields in declaration order,
+//! then the dirty rects as `[left, top, right, bottom]` `i32`s and the payload, which is [`Frame::buffer`].
+//!
+//! With the `zstd` feature the whole stream can be compressed,
+//! see [`RawStreamWriter::zstd`] and [`RawStreamReader::zstd`].
+
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::model::{FrameInfo, Rect, Result};
+use std::io::{self, Read, Write};
+use windows::Win32::Graphics::Dxgi::Common::DXGI_FORMAT;
+
+/// The first bytes of a raw frame stream.
+pub const STREAM_MAGIC: [u8; 4] = *b"RDRF";
+/// Incremented on incompatible changes of the stream format.
+pub const STREAM_VERSION: u32 = 1;
+/// The size of a frame header in bytes.
+const FRAME_HEADER_SIZE: usize = 72;
+/// Reject larger counts and payloads of corrupted streams before allocating.
+const MAX_DIRTY_RECTS: u32 = 1 << 20;
+const MAX_BYTES_PER_PIXEL: u64 = 16;
+
+fn io_error(message: &str, e: io::Error) -> Error {
+  Error::new(format!("{}: {}", message, e))
+}
+
+/// A frame read from a stream, with the dirty rects it was written with.
+#[derive(Debug, Clone)]
+pub struct RawFrame {
+  pub frame: Frame,
+  pub dirty_rects: Vec<Rect>,
+}
+
+/// Append frames to any [`Write`], see the [module](self) for the format.
+pub struct RawStreamWriter<W: Write> {
+  writer: W,
+  frames: u64,
+}
+
+impl<W: Write> RawStreamWriter<W> {
+  /// Write the stream header to `writer`.
+  pub fn new(mut writer: W) -> Result<Self> {
+    let mut header = STREAM_MAGIC.to_vec();
+    header.extend_from_slice(&STREAM_VERSION.to_le_bytes());
+    writer
+      .write_all(&header)
+      .map_err(|e| io_error("Failed to write stream header", e))?;
+    Ok(Self { writer, frames: 0 })
+  }
+
+  /// How many frames are written.
+  pub fn frames(&self) -> u64 {
+    self.frames
+  }
+
+  /// Append `frame` with the dirty rects of its capture, which may be empty.
+  pub fn write_frame(&mut self, frame: &Frame, dirty_rects: &[Rect]) -> Result<()> {
+    let info = FrameInfo::from(&frame.info);
+    let mut header = Vec::with_capacity(FRAME_HEADER_SIZE + dirty_rects.len() * 16);
+    for value in [
+      frame.width,
+      frame.height,
+      frame.format.0,
+      dirty_rects.len() as u32,
+    ] {
+      header.extend_from_slice(&value.to_le_bytes());
+    }
+    header.extend_from_slice(&(frame.buffer.len() as u64).to_le_bytes());
+    header.extend_from_slice(&info.last_present_time.to_le_bytes());
+    header.extend_from_slice(&info.last_mouse_update_time.to_le_bytes());
+    for value in [
+      info.accumulated_frames,
+      info.rects_coalesced,
+      info.protected_content_masked_out,
+      info.pointer_visible,
+      info.pointer_x as u32,
+      info.pointer_y as u32,
+      info.total_metadata_buffer_size,
+      info.pointer_shape_buffer_size,
+    ] {
+      header.extend_from_slice(&value.to_le_bytes());
+    }
+    for rect in dirty_rects {
+      for value in [rect.left, rect.top, rect.right, rect.bottom] {
+        header.extend_from_slice(&value.to_le_bytes());
+      }
+    }
+
+    self
+      .writer
+      .write_all(&header)
+      .and_then(|_| self.writer.write_all(&frame.buffer))
+      .map_err(|e| io_error("Failed to write frame", e))?;
+    self.frames += 1;
+    Ok(())
+  }
+
+  pub fn flush(&mut self) -> Result<()> {
+    self
+      .writer
+      .flush()
+      .map_err(|e| io_error("Failed to flush stream", e))
+  }
+
+  /// Return the underlying writer, which is not flushed.
+  pub fn into_inner(self) -> W {
+    self.writer
+  }
+}
+
+#[cfg(feature = "zstd")]
+impl<W: Write> RawStreamWriter<zstd::Encoder<'static, W>> {
+  /// Compress the stream with zstd. Levels 1 to 3 keep up with continuous capture on a single core,
+  /// `0` is the default level 3. Call [`RawStreamWriter::finish`] to end the compressed stream,
+  /// frames written before a crash can still be read.
+  pub fn zstd(writer: W, level: i32) -> Result<Self> {
+    let encoder = zstd::Encoder::new(writer, level)
+      .map_err(|e| io_error("Failed to create zstd encoder", e))?;
+    Self::new(encoder)
+  }
+
+  /// End the compressed stream and return the underlying writer, which is not flushed.
+  pub fn finish(self) -> Result<W> {
+    self
+      .writer
+      .finish()
+      .map_err(|e| io_error("Failed to finish zstd stream", e))
+  }
+}
+
+/// Iterate the frames of a stream written by [`RawStreamWriter`].
+pub struct RawStreamReader<R: Read> {
+  reader: R,
+}
+
+impl<R: Read> RawStreamReader<R> {
+  /// Read and check the stream header.
+  pub fn new(mut reader: R) -> Result<Self> {
+    let mut header = [0u8; 8];
+    reader
+      .read_exact(&mut header)
+      .map_err(|e| io_error("Failed to read stream header", e))?;
+    if header[..4] != STREAM_MAGIC {
+      return Err(Error::new("Not a raw frame stream"));
+    }
+    let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+    if version != STREAM_VERSION {
+      return Err(Error::new(format!(
+        "Unsupported raw frame stream version {}, expected {}",
+        version, STREAM_VERSION
+      )));
+    }
+    Ok(Self { reader })
+  }
+
+  /// Read the next frame, or `None` at the end of the stream.
+  pub fn read_frame(&mut self) -> Result<Option<RawFrame>> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    if !self.read_header(&mut header)? {
+      return Ok(None);
+    }
+    let mut fields = Fields(&header);
+    let (width, height, format) = (fields.u32(), fields.u32(), fields.u32());
+    let dirty_rect_count = fields.u32();
+    let payload_size = fields.u64();
+    let info = FrameInfo {
+      last_present_time: fields.i64(),
+      last_mouse_update_time: fields.i64(),
+      accumulated_frames: fields.u32(),
+      rects_coalesced: fields.u32(),
+      protected_content_masked_out: fields.u32(),
+      pointer_visible: fields.u32(),
+      pointer_x: fields.i32(),
+      pointer_y: fields.i32(),
+      total_metadata_buffer_size: fields.u32(),
+      pointer_shape_buffer_size: fields.u32(),
+    };
+
+    let max_payload_size = (width as u64 * height as u64).checked_mul(MAX_BYTES_PER_PIXEL);
+    let payload_fits = match max_payload_size {
+      Some(max) => payload_size <= max,
+      None => false,
+    };
+    if dirty_rect_count > MAX_DIRTY_RECTS || !payload_fits {
+      return Err(Error::new("Corrupted raw frame header"));
+    }
+    let mut rects = vec![0u8; dirty_rect_count as usize * 16];
+    self
+      .reader
+      .read_exact(&mut rects)
+      .map_err(|e| io_error("Failed to read frame", e))?;
+    // grow the buffer while reading, so a corrupted size fails at the end of the stream
+    let mut buffer = Vec::new();
+    (&mut self.reader)
+      .take(payload_size)
+      .read_to_end(&mut buffer)
+      .map_err(|e| io_error("Failed to read frame", e))?;
+    if buffer.len() as u64 != payload_size {
+      return Err(Error::new("Failed to read frame: unexpected end of stream"));
+    }
+    let dirty_rects = rects
+      .chunks_exact(16)
+      .map(|rect| {
+        let mut fields = Fields(rect);
+        Rect::new(fields.i32(), fields.i32(), fields.i32(), fields.i32())
+      })
+      .collect();
+
+    Ok(Some(RawFrame {
+      frame: Frame {
+        buffer,
+        width,
+        height,
+        info: (&info).into(),
+        format: DXGI_FORMAT(format),
+        provenance: None,
+      },
+      dirty_rects,
+    }))
+  }
+
+  /// Fill `header`, return `false` if the stream ends before it.
+  fn read_header(&mut self, header: &mut [u8]) -> Result<bool> {
+    let mut filled = 0;
+    while filled < header.len() {
+      match self.reader.read(&mut header[filled..]) {
+        Ok(0) if filled == 0 => return Ok(false),
+        Ok(0) => return Err(Error::new("Truncated raw frame header")),
+        Ok(len) => filled += len,
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+        Err(e) => return Err(io_error("Failed to read frame header", e)),
+      }
+    }
+    Ok(true)
+  }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: Read> RawStreamReader<zstd::Decoder<'static, io::BufReader<R>>> {
+  /// Decompress a stream written by [`RawStreamWriter::zstd`] and check its header.
+  pub fn zstd(reader: R) -> Result<Self> {
+    let decoder =
+      zstd::Decoder::new(reader).map_err(|e| io_error("Failed to create zstd decoder", e))?;
+    Self::new(decoder)
+  }
+}
+
+/// Little-endian integers read from the front of a header.
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+  fn take<const N: usize>(&mut self) -> [u8; N] {
+    let (field, rest) = self.0.split_at(N);
+    self.0 = rest;
+    field.try_into().unwrap()
+  }
+
+  fn u32(&mut self) -> u32 {
+    u32::from_le_bytes(self.take())
+  }
+
+  fn i32(&mut self) -> i32 {
+    i32::from_le_bytes(self.take())
+  }
+
+  fn u64(&mut self) -> u64 {
+    u64::from_le_bytes(self.take())
+  }
+
+  fn i64(&mut self) -> i64 {
+    i64::from_le_bytes(self.take())
+  }
+}
+
+impl<R: Read> Iterator for RawStreamReader<R> {
+  type Item = Result<RawFrame>;
+
+  fn next(&mut self) -> Option<Result<RawFrame>> {
+    self.read_frame().transpose()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{RawStreamReader, RawStreamWriter};
+  use crate::model::Rect;
+  use crate::test_utils::{gradient, noise};
+
+  #[test]
+  fn round_trip() {
+    let mut first = gradient(4, 3);
+    first.info.PointerPosition.Position.x = -2;
+    let second = noise(2, 2, 7);
+    let mut writer = RawStreamWriter::new(Vec::new()).unwrap();
+    writer
+      .write_frame(&first, &[Rect::new(0, 0, 4, 1), Rect::new(1, 1, 2, 3)])
+      .unwrap();
+    writer.write_frame(&second, &[]).unwrap();
+    assert_eq!(writer.frames(), 2);
+    let bytes = writer.into_inner();
+
+    let frames: Vec<_> = RawStreamReader::new(bytes.as_slice())
+      .unwrap()
+      .collect::<Result<_, _>>()
+      .unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].frame.buffer, first.buffer);
+    assert_eq!((frames[0].frame.width, frames[0].frame.height), (4, 3));
+    assert_eq!(frames[0].frame.format, first.format);
+    assert_eq!(frames[0].frame.info, first.info);
+    assert_eq!(
+      frames[0].dirty_rects,
+      [Rect::new(0, 0, 4, 1), Rect::new(1, 1, 2, 3)]
+    );
+    assert_eq!(frames[1].frame.buffer, second.buffer);
+    assert!(frames[1].dirty_rects.is_empty());
+
+    // truncated frames and foreign streams are errors
+    let mut reader = RawStreamReader::new(&bytes[..bytes.len() - 1]).unwrap();
+    assert!(reader.next().unwrap().is_ok());
+    assert!(reader.next().unwrap().is_err());
+    assert!(RawStreamReader::new(&b"RIFF\x01\x00\x00\x00"[..]).is_err());
+  }
+
+  #[test]
+  fn corrupted_header() {
+    let mut writer = RawStreamWriter::new(Vec::new()).unwrap();
+    writer.write_frame(&gradient(4, 3), &[]).unwrap();
+    let mut bytes = writer.into_inner();
+    // the largest size overflows the payload limit
+    bytes[8..16].fill(0xff);
+    bytes[24..32].fill(0xff);
+    let mut reader = RawStreamReader::new(bytes.as_slice()).unwrap();
+    assert!(reader.next().unwrap().is_err());
+
+    // a size within the limit isn't allocated before the stream ends
+    bytes[24..32].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    let mut reader = RawStreamReader::new(bytes.as_slice()).unwrap();
+    assert!(reader.next().unwrap().is_err());
+  }
+
+  #[cfg(feature = "zstd")]
+  #[test]
+  fn zstd() {
+    let frames = [gradient(64, 32), noise(16, 16, 3)];
+    let mut writer = RawStreamWriter::zstd(Vec::new(), 1).unwrap();
+    for frame in &frames {
+      writer.write_frame(frame, &[Rect::new(0, 0, 8, 8)]).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    assert!(bytes.len() < frames.iter().map(|f| f.buffer.len()).sum());
+
+    let read: Vec<_> = RawStreamReader::zstd(bytes.as_slice())
+      .unwrap()
+      .collect::<Result<_, _>>()
+      .unwrap();
+    assert_eq!(read.len(), 2);
+    assert_eq!(read[0].frame.buffer, frames[0].buffer);
+    assert_eq!(read[1].frame.buffer, frames[1].buffer);
+    assert_eq!(read[1].dirty_rects, [Rect::new(0, 0, 8, 8)]);
+
+    // uncompressed streams are rejected
+    let plain = RawStreamWriter::new(Vec::new()).unwrap().into_inner();
+    assert!(RawStreamReader::zstd(plain.as_slice()).is_err());
+  }
+}
@@ -0,0 +1,163 @@
+//! Copy BGRA8 desktop images to `DXGI_FORMAT_R8G8B8A8_UNORM` readable textures,
+//! for adapters which reject BGRA8 staging textures.
+//! `CopyResource` can't convert between the formats, so a pixel shader draws the image swizzled,
+//! and the texture holds the bytes of BGRA pixels.
+
+use crate::duplication_context::DuplicationContext;
+use crate::model::Result;
+use std::slice;
+use windows::core::{s, PCSTR};
+use windows::Win32::Graphics::Direct3D::Fxc::D3DCompile;
+use windows::Win32::Graphics::Direct3D::{ID3DBlob, D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST};
+use windows::Win32::Graphics::Direct3D11::{
+  ID3D11PixelShader, ID3D11RenderTargetView, ID3D11Texture2D, ID3D11VertexShader,
+  D3D11_BIND_RENDER_TARGET, D3D11_CPU_ACCESS_FLAG, D3D11_RESOURCE_MISC_FLAG, D3D11_TEXTURE2D_DESC,
+  D3D11_USAGE_DEFAULT, D3D11_VIEWPORT,
+};
+use windows::Win32::Graphics::Dxgi::Common::{
+  DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_B8G8R8A8_UNORM_SRGB, DXGI_FORMAT_R8G8B8A8_UNORM,
+};
+
+/// Draw a triangle covering the target and load the source pixel of each target pixel with R and B swapped.
+const SHADER: &str = r"
+Texture2D desktop : register(t0);
+
+float4 vs(uint id : SV_VertexID) : SV_Position {
+  float2 uv = float2((id << 1) & 2, id & 2);
+  return float4(uv * float2(2, -2) + float2(-1, 1), 0, 1);
+}
+
+float4 ps(float4 position : SV_Position) : SV_Target {
+  return desktop.Load(int3(position.xy, 0)).bgra;
+}
+";
+
+/// Whether copying `src` to `dest` needs [`Swizzler::copy`].
+pub(crate) fn needs_swizzle(dest: &ID3D11Texture2D, src: &ID3D11Texture2D) -> bool {
+  let mut dest_desc = D3D11_TEXTURE2D_DESC::default();
+  let mut src_desc = D3D11_TEXTURE2D_DESC::default();
+  unsafe {
+    dest.GetDesc(&mut dest_desc);
+    src.GetDesc(&mut src_desc);
+  }
+  dest_desc.Format == DXGI_FORMAT_R8G8B8A8_UNORM
+    && (src_desc.Format == DXGI_FORMAT_B8G8R8A8_UNORM
+      || src_desc.Format == DXGI_FORMAT_B8G8R8A8_UNORM_SRGB)
+}
+
+/// The shaders and render target drawing swizzled frames of a duplication context.
+pub(crate) struct Swizzler {
+  vertex_shader: ID3D11VertexShader,
+  pixel_shader: ID3D11PixelShader,
+  /// The render target of the size of the last copy, copied to the readable texture.
+  target: Option<(ID3D11Texture2D, ID3D11RenderTargetView)>,
+}
+
+impl Swizzler {
+  pub(crate) fn new(ctx: &DuplicationContext) -> Result<Self> {
+    let device = ctx.device();
+    let vertex_code = compile(ctx, s!("vs"), s!("vs_4_0"))?;
+    let pixel_code = compile(ctx, s!("ps"), s!("ps_4_0"))?;
+    let mut vertex_shader = None;
+    unsafe { device.CreateVertexShader(blob_bytes(&vertex_code), None, Some(&mut vertex_shader)) }
+      .map_err(|e| ctx.windows_error("CreateVertexShader", e))?;
+    let mut pixel_shader = None;
+    unsafe { device.CreatePixelShader(blob_bytes(&pixel_code), None, Some(&mut pixel_shader)) }
+      .map_err(|e| ctx.windows_error("CreatePixelShader", e))?;
+    Ok(Self {
+      vertex_shader: vertex_shader.unwrap(),
+      pixel_shader: pixel_shader.unwrap(),
+      target: None,
+    })
+  }
+
+  /// Draw `src` to the `DXGI_FORMAT_R8G8B8A8_UNORM` texture `dest` of the same size, keeping the byte order.
+  /// This changes the pipeline state of the immediate context.
+  pub(crate) fn copy(
+    &mut self,
+    ctx: &DuplicationContext,
+    dest: &ID3D11Texture2D,
+    src: &ID3D11Texture2D,
+  ) -> Result<()> {
+    let device = ctx.device();
+    let context = ctx.device_context();
+    let mut desc = D3D11_TEXTURE2D_DESC::default();
+    unsafe { dest.GetDesc(&mut desc) };
+
+    let stale = match &self.target {
+      Some((texture, _)) => {
+        let mut target_desc = D3D11_TEXTURE2D_DESC::default();
+        unsafe { texture.GetDesc(&mut target_desc) };
+        (target_desc.Width, target_desc.Height) != (desc.Width, desc.Height)
+      }
+      None => true,
+    };
+    if stale {
+      let target_desc = D3D11_TEXTURE2D_DESC {
+        Usage: D3D11_USAGE_DEFAULT,
+        BindFlags: D3D11_BIND_RENDER_TARGET,
+        CPUAccessFlags: D3D11_CPU_ACCESS_FLAG::default(),
+        MiscFlags: D3D11_RESOURCE_MISC_FLAG::default(),
+        ..desc
+      };
+      let texture = ctx.create_texture(&target_desc)?;
+      let mut view = None;
+      unsafe { device.CreateRenderTargetView(&texture, None, Some(&mut view)) }
+        .map_err(|e| ctx.windows_error("CreateRenderTargetView", e))?;
+      self.target = Some((texture, view.unwrap()));
+    }
+    let (texture, view) = self.target.as_ref().unwrap();
+
+    let mut source = None;
+    unsafe { device.CreateShaderResourceView(src, None, Some(&mut source)) }
+      .map_err(|e| ctx.windows_error("CreateShaderResourceView", e))?;
+    let viewport = D3D11_VIEWPORT {
+      TopLeftX: 0.0,
+      TopLeftY: 0.0,
+      Width: desc.Width as f32,
+      Height: desc.Height as f32,
+      MinDepth: 0.0,
+      MaxDepth: 1.0,
+    };
+    unsafe {
+      context.OMSetRenderTargets(Some(&[Some(view.clone())]), None);
+      context.RSSetViewports(Some(&[viewport]));
+      context.IASetInputLayout(None);
+      context.IASetPrimitiveTopology(D3D_PRIMITIVE_TOPOLOGY_TRIANGLELIST);
+      context.VSSetShader(&self.vertex_shader, None);
+      context.PSSetShader(&self.pixel_shader, None);
+      context.PSSetShaderResources(0, Some(&[source]));
+      context.Draw(3, 0);
+      // unbind the frame so it can be released
+      context.PSSetShaderResources(0, Some(&[None]));
+      context.OMSetRenderTargets(None, None);
+      context.CopyResource(dest, texture);
+    }
+    Ok(())
+  }
+}
+
+fn compile(ctx: &DuplicationContext, entry: PCSTR, target: PCSTR) -> Result<ID3DBlob> {
+  let mut code = None;
+  unsafe {
+    D3DCompile(
+      SHADER.as_ptr() as *const _,
+      SHADER.len(),
+      PCSTR::null(),
+      None,
+      None,
+      entry,
+      target,
+      0,
+      0,
+      &mut code,
+      None,
+    )
+  }
+  .map_err(|e| ctx.windows_error("D3DCompile", e))?;
+  Ok(code.unwrap())
+}
+
+fn blob_bytes(blob: &ID3DBlob) -> &[u8] {
+  unsafe { slice::from_raw_parts(blob.GetBufferPointer() as *const u8, blob.GetBufferSize()) }
+}
@@ -0,0 +1,249 @@
+//! Generate synthetic frames with known content,
+//! e.g. to test converters or encoders without a real display.
+//!
+//! Frames use the same conventions as captured ones: BGRA32, row by row without padding,
+//! opaque alpha, and frame info reporting a desktop update.
+//! Compare frames against golden frames with [`compare`] and [`diff_heatmap`].
+//! Enable the `test-utils` feature to use this module outside this crate.
+
+use crate::error::Error;
+use crate::frame::Frame;
+use crate::model::Result;
+use windows::Win32::Graphics::Dxgi::{Common::DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_OUTDUPL_FRAME_INFO};
+
+/// Create a frame and fill each pixel with `f(x, y)` as `[b, g, r]`.
+pub fn generate(width: u32, height: u32, mut f: impl FnMut(u32, u32) -> [u8; 3]) -> Frame {
+  let mut buffer = Vec::with_capacity(width as usize * height as usize * 4);
+  for y in 0..height {
+    for x in 0..width {
+      let [b, g, r] = f(x, y);
+      buffer.extend_from_slice(&[b, g, r, 0xFF]);
+    }
+  }
+  Frame {
+    buffer,
+    width,
+    height,
+    info: DXGI_OUTDUPL_FRAME_INFO {
+      LastPresentTime: 1,
+      AccumulatedFrames: 1,
+      ..Default::default()
+    },
+    format: DXGI_FORMAT_B8G8R8A8_UNORM,
+    provenance: None,
+  }
+}
+
+/// Set every byte of a frame to `value`, including alpha, so frames are told apart by their bytes.
+pub fn filled(width: u32, height: u32, value: u8) -> Frame {
+  let mut frame = generate(width, height, |_, _| [value; 3]);
+  frame.buffer.fill(value);
+  frame
+}
+
+/// Red increases from left to right, green from top to bottom, blue is zero.
+pub fn gradient(width: u32, height: u32) -> Frame {
+  let scale = |value: u32, max: u32| (value * 255 / max.saturating_sub(1).max(1)) as u8;
+  generate(width, height, |x, y| [0, scale(y, height), scale(x, width)])
+}
+
+/// Black and white cells of `cell` pixels, the top-left cell is white.
+pub fn checkerboard(width: u32, height: u32, cell: u32) -> Frame {
+  let cell = cell.max(1);
+  generate(width, height, |x, y| {
+    if (x / cell + y / cell) % 2 == 1 {
+      [0; 3]
+    } else {
+      [0xFF; 3]
+    }
+  })
+}
+
+/// A white `size`x`size` box on black, moving one pixel right and down per `index`
+/// and wrapping at the edges, to simulate consecutive frames.
+/// The frame info's `LastPresentTime` increases with `index`.
+pub fn moving_box(width: u32, height: u32, size: u32, index: u32) -> Frame {
+  let left = index % width.max(1);
+  let top = index % height.max(1);
+  let inside = |value: u32, start: u32, max: u32| (value + max - start) % max < size;
+  let mut frame = generate(width, height, |x, y| {
+    if inside(x, left, width) && inside(y, top, height) {
+      [0xFF; 3]
+    } else {
+      [0; 3]
+    }
+  });
+  frame.info.LastPresentTime = index as i64 + 1;
+  frame
+}
+
+/// Deterministic pseudo-random pixels, the same `seed` generates the same frame.
+pub fn noise(width: u32, height: u32, seed: u64) -> Frame {
+  // xorshift64, the state must not be zero
+  let mut state = seed.max(1);
+  generate(width, height, |_, _| {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    let [b, g, r, ..] = state.to_le_bytes();
+    [b, g, r]
+  })
+}
+
+/// The difference between two frames of the same size, ignoring the alpha channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameDiff {
+  /// The maximum difference of a single color channel.
+  pub max_delta: u8,
+  /// How many pixels differ in any color channel.
+  pub different_pixels: usize,
+  /// Peak signal-to-noise ratio in dB, infinite if the frames are identical.
+  pub psnr: f64,
+}
+
+impl FrameDiff {
+  /// Return `true` if no color channel differs by more than `max_delta`.
+  pub fn within(&self, max_delta: u8) -> bool {
+    self.max_delta <= max_delta
+  }
+}
+
+/// Compare two frames pixel by pixel. Return an error if their sizes differ.
+pub fn compare(actual: &Frame, expected: &Frame) -> Result<FrameDiff> {
+  check_size(actual, expected)?;
+  let mut max_delta = 0;
+  let mut different_pixels = 0;
+  let mut squared_error = 0u64;
+  for (a, e) in actual
+    .buffer
+    .chunks_exact(4)
+    .zip(expected.buffer.chunks_exact(4))
+  {
+    let delta = pixel_delta(a, e);
+    max_delta = max_delta.max(delta);
+    if delta > 0 {
+      different_pixels += 1;
+    }
+    for channel in 0..3 {
+      let d = a[channel].abs_diff(e[channel]) as u64;
+      squared_error += d * d;
+    }
+  }
+
+  let samples = (actual.width as u64 * actual.height as u64 * 3).max(1);
+  let mse = squared_error as f64 / samples as f64;
+  let psnr = if mse == 0.0 {
+    f64::INFINITY
+  } else {
+    10.0 * (255.0 * 255.0 / mse).log10()
+  };
+  Ok(FrameDiff {
+    max_delta,
+    different_pixels,
+    psnr,
+  })
+}
+
+/// Draw where two frames differ: black is identical, brighter red to yellow is a larger difference.
+/// Return an error if their sizes differ.
+pub fn diff_heatmap(actual: &Frame, expected: &Frame) -> Result<Frame> {
+  check_size(actual, expected)?;
+  let mut pixels = actual
+    .buffer
+    .chunks_exact(4)
+    .zip(expected.buffer.chunks_exact(4))
+    .map(|(a, e)| pixel_delta(a, e));
+  Ok(generate(actual.width, actual.height, |_, _| {
+    let delta = pixels.next().unwrap_or(0) as u32;
+    let red = (delta * 2).min(255) as u8;
+    let green = (delta.saturating_sub(128) * 2).min(255) as u8;
+    [0, green, red]
+  }))
+}
+
+/// Panic with the frame difference if any color channel differs by more than `max_delta`.
+pub fn assert_similar(actual: &Frame, expected: &Frame, max_delta: u8) {
+  match compare(actual, expected) {
+    Ok(diff) if diff.within(max_delta) => {}
+    Ok(diff) => panic!("frames differ more than {}: {:?}", max_delta, diff),
+    Err(e) => panic!("{}", e),
+  }
+}
+
+fn check_size(actual: &Frame, expected: &Frame) -> Result<()> {
+  if actual.width != expected.width || actual.height != expected.height {
+    return Err(Error::new(format!(
+      "Frame size mismatch: {}x{} vs {}x{}",
+      actual.width, actual.height, expected.width, expected.height
+    )));
+  }
+  Ok(())
+}
+
+/// The maximum difference of the color channels of two BGRA pixels.
+fn pixel_delta(a: &[u8], b: &[u8]) -> u8 {
+  (0..3).map(|i| a[i].abs_diff(b[i])).max().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    assert_similar, checkerboard, compare, diff_heatmap, generate, gradient, moving_box, noise,
+  };
+  use crate::utils::FrameInfoExt;
+
+  fn pixel(frame: &crate::frame::Frame, x: u32, y: u32) -> &[u8] {
+    let offset = (y * frame.width + x) as usize * 4;
+    &frame.buffer[offset..offset + 4]
+  }
+
+  #[test]
+  fn generators() {
+    let frame = gradient(256, 2);
+    assert_eq!(frame.buffer.len(), 256 * 2 * 4);
+    assert!(frame.info.desktop_updated());
+    assert_eq!(pixel(&frame, 0, 0), [0, 0, 0, 0xFF]);
+    assert_eq!(pixel(&frame, 255, 1), [0, 0xFF, 0xFF, 0xFF]);
+
+    let frame = checkerboard(4, 4, 2);
+    assert_eq!(pixel(&frame, 1, 1), [0xFF; 4]);
+    assert_eq!(pixel(&frame, 2, 1), [0, 0, 0, 0xFF]);
+    assert_eq!(pixel(&frame, 2, 2), [0xFF; 4]);
+
+    let frame = moving_box(8, 8, 2, 7);
+    assert_eq!(frame.info.LastPresentTime, 8);
+    // the box wraps around the bottom-right corner
+    assert_eq!(pixel(&frame, 7, 7), [0xFF; 4]);
+    assert_eq!(pixel(&frame, 0, 0), [0xFF; 4]);
+    assert_eq!(pixel(&frame, 1, 1), [0, 0, 0, 0xFF]);
+
+    assert_eq!(noise(16, 16, 42).buffer, noise(16, 16, 42).buffer);
+    assert_ne!(noise(16, 16, 42).buffer, noise(16, 16, 43).buffer);
+  }
+
+  #[test]
+  fn comparison() {
+    let expected = gradient(16, 16);
+    let diff = compare(&expected, &expected).unwrap();
+    assert_eq!(diff.max_delta, 0);
+    assert_eq!(diff.different_pixels, 0);
+    assert!(diff.psnr.is_infinite());
+
+    // brighten the red channel of one pixel by 10
+    let mut actual = expected.clone();
+    actual.buffer[2] += 10;
+    let diff = compare(&actual, &expected).unwrap();
+    assert_eq!(diff.max_delta, 10);
+    assert_eq!(diff.different_pixels, 1);
+    assert!(diff.psnr > 40.0);
+    assert!(diff.within(10));
+    assert!(!diff.within(9));
+    assert_similar(&actual, &expected, 10);
+
+    let heatmap = diff_heatmap(&actual, &expected).unwrap();
+    assert_eq!(pixel(&heatmap, 0, 0), [0, 0, 20, 0xFF]);
+    assert_eq!(pixel(&heatmap, 1, 0), [0, 0, 0, 0xFF]);
+
+    assert!(compare(&expected, &generate(16, 8, |_, _| [0; 3])).is_err());
+  }
+}
@@ -0,0 +1,231 @@
+//! Split frames into fixed-size tiles and report which tiles changed,
+//! a compact change representation for custom encoders.
+
+use crate::frame::Frame;
+use crate::model::Rect;
+use crate::utils::FormatExt;
+
+/// Which tiles of a frame changed, as a bitmask in row-major order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TileMap {
+  tile_size: u32,
+  width: u32,
+  height: u32,
+  columns: u32,
+  rows: u32,
+  /// Bit `i % 64` of word `i / 64` is set if tile `i` changed.
+  bits: Vec<u64>,
+}
+
+impl TileMap {
+  fn new(tile_size: u32, width: u32, height: u32) -> Self {
+    let columns = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+    Self {
+      tile_size,
+      width,
+      height,
+      columns,
+      rows,
+      bits: vec![0; (columns as usize * rows as usize).div_ceil(64)],
+    }
+  }
+
+  pub fn tile_size(&self) -> u32 {
+    self.tile_size
+  }
+
+  pub fn columns(&self) -> u32 {
+    self.columns
+  }
+
+  pub fn rows(&self) -> u32 {
+    self.rows
+  }
+
+  /// The bitmask, tile `i` is at `column + row * columns`.
+  pub fn bits(&self) -> &[u64] {
+    &self.bits
+  }
+
+  /// Return `false` for tiles outside the map.
+  pub fn is_changed(&self, column: u32, row: u32) -> bool {
+    column < self.columns && row < self.rows && self.get(self.index(column, row))
+  }
+
+  /// How many tiles changed.
+  pub fn changed_count(&self) -> usize {
+    self
+      .bits
+      .iter()
+      .map(|word| word.count_ones() as usize)
+      .sum()
+  }
+
+  pub fn any_changed(&self) -> bool {
+    self.bits.iter().any(|&word| word != 0)
+  }
+
+  /// The rects of the changed tiles in frame coordinates,
+  /// tiles at the right and bottom edges are clipped to the frame.
+  pub fn changed_rects(&self) -> impl Iterator<Item = Rect> + '_ {
+    (0..self.rows).flat_map(move |row| {
+      (0..self.columns)
+        .filter(move |&column| self.get(self.index(column, row)))
+        .map(move |column| self.tile_rect(column, row))
+    })
+  }
+
+  fn index(&self, column: u32, row: u32) -> usize {
+    row as usize * self.columns as usize + column as usize
+  }
+
+  fn get(&self, index: usize) -> bool {
+    self.bits[index / 64] & (1 << (index % 64)) != 0
+  }
+
+  fn set(&mut self, index: usize, changed: bool) {
+    if changed {
+      self.bits[index / 64] |= 1 << (index % 64);
+    } else {
+      self.bits[index / 64] &= !(1 << (index % 64));
+    }
+  }
+
+  fn tile_rect(&self, column: u32, row: u32) -> Rect {
+    let left = column * self.tile_size;
+    let top = row * self.tile_size;
+    Rect::new(
+      left as i32,
+      top as i32,
+      (left + self.tile_size).min(self.width) as i32,
+      (top + self.tile_size).min(self.height) as i32,
+    )
+  }
+}
+
+/// Hash the tiles of each frame and compare them with the previous frame.
+///
+/// Hashing is more stable than dirty rects, which may cover unchanged pixels,
+/// e.g. a window redrawn with the same content.
+#[derive(Debug, Clone)]
+pub struct TileHasher {
+  map: TileMap,
+  /// The hash of each tile of the previous frame, empty before the first frame.
+  hashes: Vec<u64>,
+}
+
+impl TileHasher {
+  /// Use square tiles of `tile_size` pixels, e.g. 64.
+  pub fn new(tile_size: u32) -> Self {
+    Self {
+      map: TileMap::new(tile_size.max(1), 0, 0),
+      hashes: Vec::new(),
+    }
+  }
+
+  /// The change map of the last update.
+  pub fn map(&self) -> &TileMap {
+    &self.map
+  }
+
+  /// Hash the tiles of `frame` and mark the ones which differ from the previous frame.
+  /// Pass the dirty rects of the frame if available, tiles outside them are not hashed.
+  ///
+  /// All tiles are changed on the first frame and after a resolution change.
+  pub fn update(&mut self, frame: &Frame, dirty_rects: Option<&[Rect]>) -> &TileMap {
+    let first = self.map.width != frame.width || self.map.height != frame.height;
+    if first {
+      self.map = TileMap::new(self.map.tile_size, frame.width, frame.height);
+      self.hashes = vec![0; self.map.columns as usize * self.map.rows as usize];
+    }
+    let bytes_per_pixel = frame.format.bytes_per_pixel();
+
+    for row in 0..self.map.rows {
+      for column in 0..self.map.columns {
+        let index = self.map.index(column, row);
+        let rect = self.map.tile_rect(column, row);
+        if !first
+          && dirty_rects.is_some_and(|rects| rects.iter().all(|r| r.intersect(&rect).is_none()))
+        {
+          self.map.set(index, false);
+          continue;
+        }
+        let hash = hash_tile(frame, &rect, bytes_per_pixel);
+        self.map.set(index, first || hash != self.hashes[index]);
+        self.hashes[index] = hash;
+      }
+    }
+    &self.map
+  }
+}
+
+/// Hash the pixels of `rect`, 8 bytes at a time.
+fn hash_tile(frame: &Frame, rect: &Rect, bytes_per_pixel: usize) -> u64 {
+  const PRIME: u64 = 0x100000001b3;
+  let line_bytes = frame.width as usize * bytes_per_pixel;
+  let bytes = rect.width() as usize * bytes_per_pixel;
+  let mut hash = 0xcbf29ce484222325u64;
+  for y in rect.top as usize..rect.bottom as usize {
+    let start = y * line_bytes + rect.left as usize * bytes_per_pixel;
+    let row = &frame.buffer[start..start + bytes];
+    let words = row.chunks_exact(8);
+    let rest = words.remainder();
+    for word in words {
+      hash = (hash ^ u64::from_le_bytes(word.try_into().unwrap())).wrapping_mul(PRIME);
+    }
+    for &byte in rest {
+      hash = (hash ^ byte as u64).wrapping_mul(PRIME);
+    }
+  }
+  hash
+}
+
+#[cfg(test)]
+mod tests {
+  use super::TileHasher;
+  use crate::model::Rect;
+  use crate::test_utils::{generate, moving_box};
+
+  #[test]
+  fn tile_hasher() {
+    // 3x2 tiles, the right and bottom tiles are partial
+    let mut hasher = TileHasher::new(16);
+    let map = hasher.update(&moving_box(40, 20, 2, 0), None);
+    assert_eq!((map.columns(), map.rows()), (3, 2));
+    assert_eq!(map.changed_count(), 6);
+
+    // the box moves within the top-left tile
+    let map = hasher.update(&moving_box(40, 20, 2, 1), None);
+    assert_eq!(map.changed_count(), 1);
+    assert!(map.is_changed(0, 0));
+    assert!(!map.is_changed(3, 0));
+    assert_eq!(map.bits(), [0b000001]);
+
+    // the box moves across tiles: it leaves the top-left tile for the middle ones
+    let map = hasher.update(&moving_box(40, 20, 2, 16), None);
+    assert_eq!(
+      map.changed_rects().collect::<Vec<_>>(),
+      [Rect::new(0, 0, 16, 16), Rect::new(16, 16, 32, 20)]
+    );
+
+    // unchanged frames change nothing
+    let map = hasher.update(&moving_box(40, 20, 2, 16), None);
+    assert!(!map.any_changed());
+  }
+
+  #[test]
+  fn dirty_rects() {
+    let mut hasher = TileHasher::new(8);
+    hasher.update(&generate(16, 8, |_, _| [0; 3]), None);
+    // both tiles differ, but only the right one is dirty
+    let frame = generate(16, 8, |_, _| [0xFF; 3]);
+    let map = hasher.update(&frame, Some(&[Rect::new(10, 0, 12, 2)]));
+    assert!(!map.is_changed(0, 0));
+    assert!(map.is_changed(1, 0));
+
+    // a resolution change marks every tile
+    let map = hasher.update(&generate(8, 8, |_, _| [0; 3]), Some(&[]));
+    assert_eq!(map.changed_count(), 1);
+  }
+}
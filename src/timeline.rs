@@ -0,0 +1,182 @@
+use crate::duplication_context::{qpc_duration, qpc_now};
+use crate::frame::Frame;
+use crate::model::MonitorId;
+use std::time::Duration;
+
+/// Frames from multiple monitors on one timeline, ordered by the QPC time
+/// they were presented at (`LastPresentTime` of the frame info).
+///
+/// Use [`Timeline::matching_set`] to pick one frame per monitor for a point in time,
+/// e.g. to keep multi-monitor recordings in sync.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+  frames: Vec<(MonitorId, Frame)>,
+  frequency: i64,
+}
+
+impl Default for Timeline {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Timeline {
+  pub fn new() -> Self {
+    Self::with_frequency(qpc_now().1)
+  }
+
+  fn with_frequency(frequency: i64) -> Self {
+    Self {
+      frames: Vec::new(),
+      frequency,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.frames.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.frames.is_empty()
+  }
+
+  /// Frames ordered by present time, frames presented at the same time keep their insertion order.
+  pub fn frames(&self) -> &[(MonitorId, Frame)] {
+    &self.frames
+  }
+
+  /// Add a frame captured from the monitor.
+  /// Return `false` and drop the frame if it has no present time, e.g. only the mouse was updated.
+  pub fn push(&mut self, id: MonitorId, frame: Frame) -> bool {
+    let time = frame.info.LastPresentTime;
+    if time == 0 {
+      return false;
+    }
+    let index = self
+      .frames
+      .partition_point(|(_, f)| f.info.LastPresentTime <= time);
+    self.frames.insert(index, (id, frame));
+    true
+  }
+
+  /// The present time of the oldest frame, as the origin of [`Timeline::offset`].
+  pub fn start(&self) -> Option<i64> {
+    self.frames.first().map(|(_, f)| f.info.LastPresentTime)
+  }
+
+  /// The time from the oldest frame to the QPC `time`, zero if `time` is earlier.
+  pub fn offset(&self, time: i64) -> Option<Duration> {
+    self
+      .start()
+      .map(|start| qpc_duration(time - start, self.frequency))
+  }
+
+  /// For each monitor, pick the frame presented closest to the QPC `time`,
+  /// e.g. from `QueryPerformanceCounter` or the present time of another frame.
+  /// The set is ordered by the first appearance of each monitor on the timeline.
+  pub fn matching_set(&self, time: i64) -> Vec<(MonitorId, &Frame)> {
+    let mut set: Vec<(MonitorId, &Frame)> = Vec::new();
+    for (id, frame) in &self.frames {
+      let distance = (frame.info.LastPresentTime - time).abs();
+      match set.iter_mut().find(|(picked, _)| picked == id) {
+        Some(picked) => {
+          if distance < (picked.1.info.LastPresentTime - time).abs() {
+            picked.1 = frame;
+          }
+        }
+        None => set.push((*id, frame)),
+      }
+    }
+    set
+  }
+
+  /// The time between the earliest and the latest present time in a set of frames,
+  /// e.g. to check how far apart a [`Timeline::matching_set`] is.
+  pub fn spread(&self, set: &[(MonitorId, &Frame)]) -> Duration {
+    let times = set.iter().map(|(_, f)| f.info.LastPresentTime);
+    match (times.clone().min(), times.max()) {
+      (Some(min), Some(max)) => qpc_duration(max - min, self.frequency),
+      _ => Duration::ZERO,
+    }
+  }
+
+  /// Remove frames presented before the QPC `time`,
+  /// but keep the latest one of each monitor so a matching set is still complete.
+  pub fn drain_before(&mut self, time: i64) {
+    let mut kept: Vec<MonitorId> = Vec::new();
+    // walk from newest to oldest, keeping the latest frame of each monitor
+    let mut keep: Vec<bool> = self
+      .frames
+      .iter()
+      .rev()
+      .map(|(id, frame)| {
+        let latest = !kept.contains(id);
+        if latest {
+          kept.push(*id);
+        }
+        latest || frame.info.LastPresentTime >= time
+      })
+      .collect();
+    keep.reverse();
+    let mut keep = keep.into_iter();
+    self.frames.retain(|_| keep.next().unwrap());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Timeline;
+  use crate::model::MonitorId;
+  use crate::test_utils::{filled, moving_box};
+  use std::time::Duration;
+
+  fn monitor(output: u32) -> MonitorId {
+    MonitorId { adapter: 0, output }
+  }
+
+  #[test]
+  fn timeline() {
+    // 1 tick is 1ms
+    let mut timeline = Timeline::with_frequency(1000);
+    assert!(timeline.is_empty());
+    assert!(timeline.matching_set(0).is_empty());
+    // frames without a desktop image are not pushed
+    let mut unpresented = filled(1, 1, 0);
+    unpresented.info.LastPresentTime = 0;
+    assert!(!timeline.push(monitor(0), unpresented));
+
+    // frame `index` of `moving_box` is presented at `index + 1`
+    for (output, time) in [(0, 100), (1, 105), (0, 116), (1, 120), (0, 133)] {
+      timeline.push(monitor(output), moving_box(1, 1, 1, time - 1));
+    }
+    // out of order
+    timeline.push(monitor(1), moving_box(1, 1, 1, 89));
+    assert_eq!(timeline.len(), 6);
+    assert_eq!(timeline.start(), Some(90));
+    assert_eq!(timeline.offset(133), Some(Duration::from_millis(43)));
+    assert_eq!(timeline.offset(50), Some(Duration::ZERO));
+    let times: Vec<_> = timeline
+      .frames()
+      .iter()
+      .map(|(_, f)| f.info.LastPresentTime)
+      .collect();
+    assert_eq!(times, [90, 100, 105, 116, 120, 133]);
+
+    let set = timeline.matching_set(118);
+    assert_eq!(set.len(), 2);
+    assert_eq!(set[0].0, monitor(1));
+    assert_eq!(set[0].1.info.LastPresentTime, 120);
+    assert_eq!(set[1].1.info.LastPresentTime, 116);
+    assert_eq!(timeline.spread(&set), Duration::from_millis(4));
+    assert_eq!(timeline.spread(&[]), Duration::ZERO);
+
+    timeline.drain_before(125);
+    let times: Vec<_> = timeline
+      .frames()
+      .iter()
+      .map(|(_, f)| f.info.LastPresentTime)
+      .collect();
+    // the latest frame of monitor 1 is kept
+    assert_eq!(times, [120, 133]);
+  }
+}
@@ -1,22 +1,115 @@
+use windows::core::ComInterface;
 use windows::Win32::Graphics::{
-  Dxgi::{DXGI_OUTDUPL_DESC, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTPUT_DESC},
+  Dxgi::Common::{
+    DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020, DXGI_FORMAT, DXGI_FORMAT_R16G16B16A16_FLOAT,
+  },
+  Dxgi::{
+    IDXGIOutput6, DXGI_ADAPTER_DESC1, DXGI_ADAPTER_FLAG_SOFTWARE, DXGI_OUTDUPL_DESC,
+    DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTPUT_DESC, DXGI_OUTPUT_DESC1,
+  },
   Gdi::MONITORINFO,
 };
 
-use crate::model::MouseUpdateStatus;
+use crate::model::{MouseUpdateStatus, Point, Rect};
 
 pub trait OutputDescExt {
   fn width(&self) -> u32;
   fn height(&self) -> u32;
+  /// Return the top-left corner in virtual desktop coordinates.
+  fn position(&self) -> Point;
+  /// Return the desktop coordinates as a [`Rect`].
+  fn rect(&self) -> Rect;
+  /// Check if the point in virtual desktop coordinates is on this output.
+  fn contains_point(&self, point: &Point) -> bool;
+  /// Return `false` if the output is not attached to the desktop.
+  fn is_attached(&self) -> bool;
+  /// Return `false` if the output is detached or has a zero-sized desktop area, e.g. a headless dummy plug.
+  fn is_active(&self) -> bool;
+  /// Return the device name, e.g. `\\.\DISPLAY1`.
+  fn device_name(&self) -> String;
 }
 
-impl OutputDescExt for DXGI_OUTPUT_DESC {
-  fn width(&self) -> u32 {
-    (self.DesktopCoordinates.right - self.DesktopCoordinates.left) as u32
+/// `DXGI_OUTPUT_DESC1` repeats the fields of `DXGI_OUTPUT_DESC`.
+macro_rules! impl_output_desc_ext {
+  ($($desc:ty),*) => {$(
+    impl OutputDescExt for $desc {
+      fn width(&self) -> u32 {
+        (self.DesktopCoordinates.right - self.DesktopCoordinates.left) as u32
+      }
+      fn height(&self) -> u32 {
+        (self.DesktopCoordinates.bottom - self.DesktopCoordinates.top) as u32
+      }
+      fn position(&self) -> Point {
+        self.rect().position()
+      }
+      fn rect(&self) -> Rect {
+        self.DesktopCoordinates.into()
+      }
+      fn contains_point(&self, point: &Point) -> bool {
+        self.rect().contains(point)
+      }
+      fn is_attached(&self) -> bool {
+        self.AttachedToDesktop.as_bool()
+      }
+      fn is_active(&self) -> bool {
+        self.is_attached() && !self.rect().is_empty()
+      }
+      fn device_name(&self) -> String {
+        from_wide(&self.DeviceName)
+      }
+    }
+  )*};
+}
+
+impl_output_desc_ext!(DXGI_OUTPUT_DESC, DXGI_OUTPUT_DESC1);
+
+pub trait OutputDesc1Ext {
+  /// Whether the output is in HDR mode, i.e. its color space is BT.2100 PQ (`DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020`).
+  fn is_hdr(&self) -> bool;
+}
+
+impl OutputDesc1Ext for DXGI_OUTPUT_DESC1 {
+  fn is_hdr(&self) -> bool {
+    self.ColorSpace == DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020
   }
-  fn height(&self) -> u32 {
-    (self.DesktopCoordinates.bottom - self.DesktopCoordinates.top) as u32
+}
+
+/// Get `DXGI_OUTPUT_DESC1` of the output, or `None` if `IDXGIOutput6` isn't supported,
+/// i.e. before Windows 10 1803.
+pub(crate) fn output_desc1(
+  output: &impl ComInterface,
+) -> Option<windows::core::Result<DXGI_OUTPUT_DESC1>> {
+  let output: IDXGIOutput6 = output.cast().ok()?;
+  let mut desc = DXGI_OUTPUT_DESC1::default();
+  Some(unsafe { output.GetDesc1(&mut desc) }.map(|_| desc))
+}
+
+pub trait AdapterDescExt {
+  /// Return the adapter description, e.g. `NVIDIA GeForce RTX 3060`.
+  fn description(&self) -> String;
+  /// Return the locally unique identifier of the adapter as a single integer.
+  fn luid(&self) -> i64;
+  /// Whether this is a software adapter, e.g. the Microsoft Basic Render Driver
+  /// which hosts indirect displays of USB docks and virtual display drivers.
+  fn is_software(&self) -> bool;
+}
+
+impl AdapterDescExt for DXGI_ADAPTER_DESC1 {
+  fn description(&self) -> String {
+    from_wide(&self.Description)
   }
+  fn luid(&self) -> i64 {
+    ((self.AdapterLuid.HighPart as i64) << 32) | self.AdapterLuid.LowPart as i64
+  }
+  fn is_software(&self) -> bool {
+    self.Flags & DXGI_ADAPTER_FLAG_SOFTWARE.0 != 0
+  }
+}
+
+/// Convert a null terminated UTF-16 array to a string.
+pub(crate) fn from_wide(wide: &[u16]) -> String {
+  let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+  String::from_utf16_lossy(&wide[..len])
 }
 
 pub trait OutDuplDescExt {
@@ -26,7 +119,24 @@ pub trait OutDuplDescExt {
 impl OutDuplDescExt for DXGI_OUTDUPL_DESC {
   /// Return needed buffer size, in bytes.
   fn calc_buffer_size(&self) -> usize {
-    (self.ModeDesc.Width * self.ModeDesc.Height * 4) as usize // 4 for BGRA32
+    self.ModeDesc.Width as usize
+      * self.ModeDesc.Height as usize
+      * self.ModeDesc.Format.bytes_per_pixel()
+  }
+}
+
+pub trait FormatExt {
+  /// Return the size of a pixel of the duplication formats, in bytes.
+  fn bytes_per_pixel(&self) -> usize;
+}
+
+impl FormatExt for DXGI_FORMAT {
+  fn bytes_per_pixel(&self) -> usize {
+    match *self {
+      DXGI_FORMAT_R16G16B16A16_FLOAT => 8,
+      // BGRA32, RGBA32 and 10-bit formats
+      _ => 4,
+    }
   }
 }
 
@@ -34,6 +144,15 @@ pub trait FrameInfoExt {
   fn desktop_updated(&self) -> bool;
   /// Return `(position_updated, shape_updated)`.
   fn mouse_updated(&self) -> MouseUpdateStatus;
+  /// Return how many frames the OS accumulated into this frame since the last acquired one.
+  /// If this is greater than 1, some updates were missed.
+  fn accumulated_frames(&self) -> u32;
+  /// Whether dirty rects of multiple updates are merged and may contain unchanged pixels.
+  fn rects_coalesced(&self) -> bool;
+  /// Whether protected content (e.g. DRM video) is blacked out in the desktop image.
+  fn protected_content_masked_out(&self) -> bool;
+  /// Return the total size of the move rects and dirty rects metadata, in bytes.
+  fn metadata_size(&self) -> usize;
 }
 
 impl FrameInfoExt for DXGI_OUTDUPL_FRAME_INFO {
@@ -54,36 +173,115 @@ impl FrameInfoExt for DXGI_OUTDUPL_FRAME_INFO {
       }
     }
   }
+
+  fn accumulated_frames(&self) -> u32 {
+    self.AccumulatedFrames
+  }
+
+  fn rects_coalesced(&self) -> bool {
+    self.RectsCoalesced.as_bool()
+  }
+
+  fn protected_content_masked_out(&self) -> bool {
+    self.ProtectedContentMaskedOut.as_bool()
+  }
+
+  fn metadata_size(&self) -> usize {
+    self.TotalMetadataBufferSize as usize
+  }
 }
 
 pub trait MonitorInfoExt {
   fn is_primary(&self) -> bool;
+  /// Return the monitor rectangle in virtual desktop coordinates.
+  fn monitor_rect(&self) -> Rect;
+  /// Return the work area rectangle (excluding the taskbar and docked toolbars)
+  /// in virtual desktop coordinates.
+  fn work_area(&self) -> Rect;
 }
 
 impl MonitorInfoExt for MONITORINFO {
   fn is_primary(&self) -> bool {
-    self.dwFlags == 0x01 // MONITORINFOF_PRIMARY
+    self.dwFlags & 0x01 != 0 // MONITORINFOF_PRIMARY
+  }
+  fn monitor_rect(&self) -> Rect {
+    self.rcMonitor.into()
+  }
+  fn work_area(&self) -> Rect {
+    self.rcWork.into()
   }
 }
 
 #[cfg(test)]
 mod tests {
   use windows::Win32::Graphics::{
-    Dxgi::{DXGI_OUTDUPL_DESC, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTPUT_DESC},
+    Dxgi::Common::DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020,
+    Dxgi::Common::DXGI_FORMAT_R16G16B16A16_FLOAT,
+    Dxgi::{
+      DXGI_ADAPTER_DESC1, DXGI_OUTDUPL_DESC, DXGI_OUTDUPL_FRAME_INFO, DXGI_OUTPUT_DESC,
+      DXGI_OUTPUT_DESC1,
+    },
     Gdi::MONITORINFO,
   };
 
-  use crate::utils::{FrameInfoExt, MonitorInfoExt, OutDuplDescExt, OutputDescExt};
+  use crate::{
+    model::{Point, Rect},
+    utils::{
+      AdapterDescExt, FrameInfoExt, MonitorInfoExt, OutDuplDescExt, OutputDesc1Ext, OutputDescExt,
+    },
+  };
 
   #[test]
   fn output_desc_ext() {
     let mut desc = DXGI_OUTPUT_DESC::default();
+    assert!(!desc.is_active());
     desc.DesktopCoordinates.left = 0;
     desc.DesktopCoordinates.top = 0;
     desc.DesktopCoordinates.right = 1920;
     desc.DesktopCoordinates.bottom = 1080;
     assert_eq!(desc.width(), 1920);
     assert_eq!(desc.height(), 1080);
+    assert_eq!(desc.position(), Point::new(0, 0));
+    assert_eq!(desc.rect(), Rect::new(0, 0, 1920, 1080));
+    assert!(desc.contains_point(&Point::new(100, 100)));
+    assert!(!desc.contains_point(&Point::new(1920, 100)));
+    assert!(!desc.is_active());
+    desc.AttachedToDesktop = true.into();
+    assert!(desc.is_attached());
+    assert!(desc.is_active());
+
+    let name: Vec<u16> = "\\\\.\\DISPLAY1".encode_utf16().collect();
+    desc.DeviceName[..name.len()].copy_from_slice(&name);
+    assert_eq!(desc.device_name(), "\\\\.\\DISPLAY1");
+  }
+
+  #[test]
+  fn output_desc1_ext() {
+    let mut desc = DXGI_OUTPUT_DESC1::default();
+    desc.DesktopCoordinates.left = -1920;
+    desc.DesktopCoordinates.right = 0;
+    desc.DesktopCoordinates.bottom = 1080;
+    desc.AttachedToDesktop = true.into();
+    desc.BitsPerColor = 10;
+    assert_eq!(desc.rect(), Rect::new(-1920, 0, 0, 1080));
+    assert!(desc.is_active());
+    assert!(!desc.is_hdr());
+    desc.ColorSpace = DXGI_COLOR_SPACE_RGB_FULL_G2084_NONE_P2020;
+    assert!(desc.is_hdr());
+  }
+
+  #[test]
+  fn adapter_desc_ext() {
+    let mut desc = DXGI_ADAPTER_DESC1::default();
+    let name: Vec<u16> = "Test Adapter".encode_utf16().collect();
+    desc.Description[..name.len()].copy_from_slice(&name);
+    desc.AdapterLuid.LowPart = 0x5678;
+    desc.AdapterLuid.HighPart = 0x1234;
+    assert_eq!(desc.description(), "Test Adapter");
+    assert_eq!(desc.luid(), 0x1234_0000_5678);
+    assert!(!desc.is_software());
+    desc.Flags = 2; // DXGI_ADAPTER_FLAG_SOFTWARE
+    assert!(desc.is_software());
   }
 
   #[test]
@@ -92,6 +290,8 @@ mod tests {
     desc.ModeDesc.Width = 1920;
     desc.ModeDesc.Height = 1080;
     assert_eq!(desc.calc_buffer_size(), 1920 * 1080 * 4);
+    desc.ModeDesc.Format = DXGI_FORMAT_R16G16B16A16_FLOAT;
+    assert_eq!(desc.calc_buffer_size(), 1920 * 1080 * 8);
   }
 
   #[test]
@@ -106,6 +306,19 @@ mod tests {
     assert!(!desc.mouse_updated().shape_updated);
     desc.PointerShapeBufferSize = 1;
     assert!(desc.mouse_updated().shape_updated);
+
+    assert_eq!(desc.accumulated_frames(), 0);
+    assert!(!desc.rects_coalesced());
+    assert!(!desc.protected_content_masked_out());
+    assert_eq!(desc.metadata_size(), 0);
+    desc.AccumulatedFrames = 2;
+    desc.RectsCoalesced = true.into();
+    desc.ProtectedContentMaskedOut = true.into();
+    desc.TotalMetadataBufferSize = 64;
+    assert_eq!(desc.accumulated_frames(), 2);
+    assert!(desc.rects_coalesced());
+    assert!(desc.protected_content_masked_out());
+    assert_eq!(desc.metadata_size(), 64);
   }
 
   #[test]
@@ -114,5 +327,12 @@ mod tests {
     assert!(!info.is_primary());
     info.dwFlags = 0x01;
     assert!(info.is_primary());
+
+    info.rcMonitor.right = 1920;
+    info.rcMonitor.bottom = 1080;
+    info.rcWork.right = 1920;
+    info.rcWork.bottom = 1040;
+    assert_eq!(info.monitor_rect(), Rect::new(0, 0, 1920, 1080));
+    assert_eq!(info.work_area(), Rect::new(0, 0, 1920, 1040));
   }
 }
@@ -0,0 +1,224 @@
+//! Watch frames for pixel conditions, e.g. to wait for a button to turn green or a screen to go dark.
+
+use crate::color::{average_color, pixel_at};
+use crate::frame::Frame;
+use crate::model::Rect;
+
+/// Identifies a rule registered by [`FrameWatch::add`].
+pub type RuleId = u32;
+
+/// A condition evaluated on each frame. Coordinates are in full resolution frame pixels,
+/// colors are `[b, g, r]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+  /// The pixel differs from `color` by at most `tolerance` in each channel.
+  PixelEquals {
+    x: u32,
+    y: u32,
+    color: [u8; 3],
+    tolerance: u8,
+  },
+  /// The average color of the rect differs from `color` by at most `tolerance` in each channel.
+  AverageEquals {
+    rect: Rect,
+    color: [u8; 3],
+    tolerance: u8,
+  },
+  /// The average brightness of the rect, from 0 to 255, is below the threshold.
+  BrightnessBelow { rect: Rect, threshold: u8 },
+  /// The average brightness of the rect, from 0 to 255, is above the threshold.
+  BrightnessAbove { rect: Rect, threshold: u8 },
+}
+
+/// A rule started or stopped matching.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchEvent {
+  pub rule: RuleId,
+  /// Whether the condition holds now.
+  pub matched: bool,
+  /// The `LastPresentTime` of the frame, in QPC ticks.
+  pub time: i64,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+  id: RuleId,
+  condition: Condition,
+  /// The result of the previous frame, `None` before the first frame.
+  matched: Option<bool>,
+}
+
+/// Evaluate [`Condition`]s on frames and report when their results change.
+///
+/// A rule reports its first frame only if it matches. Conditions outside the frame never match.
+/// Frames must be 8-bit BGRA.
+#[derive(Debug, Clone, Default)]
+pub struct FrameWatch {
+  rules: Vec<Rule>,
+  next_id: RuleId,
+}
+
+impl FrameWatch {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn add(&mut self, condition: Condition) -> RuleId {
+    let id = self.next_id;
+    self.next_id += 1;
+    self.rules.push(Rule {
+      id,
+      condition,
+      matched: None,
+    });
+    id
+  }
+
+  /// Return `false` if the rule doesn't exist.
+  pub fn remove(&mut self, id: RuleId) -> bool {
+    let len = self.rules.len();
+    self.rules.retain(|rule| rule.id != id);
+    self.rules.len() != len
+  }
+
+  /// The result of the rule on the last frame, `None` before the first frame or if it doesn't exist.
+  pub fn matched(&self, id: RuleId) -> Option<bool> {
+    self
+      .rules
+      .iter()
+      .find(|rule| rule.id == id)
+      .and_then(|rule| rule.matched)
+  }
+
+  /// Evaluate the rules on a full resolution frame.
+  pub fn process(&mut self, frame: &Frame) -> Vec<WatchEvent> {
+    self.process_preview(frame, 1)
+  }
+
+  /// Evaluate the rules on a [`Frame::preview`] taken with `step`,
+  /// which is much cheaper for region conditions. Coordinates are scaled down by `step`.
+  pub fn process_preview(&mut self, preview: &Frame, step: u32) -> Vec<WatchEvent> {
+    let step = step.max(1);
+    let mut events = Vec::new();
+    for rule in &mut self.rules {
+      let matched = evaluate(&rule.condition, preview, step);
+      if rule.matched != Some(matched) && (rule.matched.is_some() || matched) {
+        events.push(WatchEvent {
+          rule: rule.id,
+          matched,
+          time: preview.info.LastPresentTime,
+        });
+      }
+      rule.matched = Some(matched);
+    }
+    events
+  }
+}
+
+fn evaluate(condition: &Condition, frame: &Frame, step: u32) -> bool {
+  let average = |rect: &Rect| average_color(&frame.buffer, frame.width, &scale(rect, step), 1);
+  match *condition {
+    Condition::PixelEquals {
+      x,
+      y,
+      color,
+      tolerance,
+    } => pixel_at(&frame.buffer, frame.width, x / step, y / step)
+      .is_some_and(|pixel| close(pixel, color, tolerance)),
+    Condition::AverageEquals {
+      rect,
+      color,
+      tolerance,
+    } => average(&rect).is_some_and(|pixel| close(pixel, color, tolerance)),
+    Condition::BrightnessBelow { rect, threshold } => {
+      average(&rect).is_some_and(|pixel| brightness(pixel) < threshold)
+    }
+    Condition::BrightnessAbove { rect, threshold } => {
+      average(&rect).is_some_and(|pixel| brightness(pixel) > threshold)
+    }
+  }
+}
+
+/// Map a rect to the preview pixels sampled from it, keeping at least one pixel.
+fn scale(rect: &Rect, step: u32) -> Rect {
+  if step == 1 {
+    return *rect;
+  }
+  let step = step as i32;
+  let (left, top) = (rect.left.div_euclid(step), rect.top.div_euclid(step));
+  Rect::new(
+    left,
+    top,
+    (rect.right + step - 1).div_euclid(step).max(left + 1),
+    (rect.bottom + step - 1).div_euclid(step).max(top + 1),
+  )
+}
+
+fn close(pixel: [u8; 4], color: [u8; 3], tolerance: u8) -> bool {
+  pixel
+    .iter()
+    .zip(color)
+    .all(|(&a, b)| a.abs_diff(b) <= tolerance)
+}
+
+/// The Rec. 601 luma of a BGRA pixel.
+fn brightness([b, g, r, _]: [u8; 4]) -> u8 {
+  ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114 + 500) / 1000) as u8
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Condition, FrameWatch};
+  use crate::model::Rect;
+  use crate::test_utils::generate;
+
+  #[test]
+  fn watch() {
+    let mut watch = FrameWatch::new();
+    let pixel = watch.add(Condition::PixelEquals {
+      x: 4,
+      y: 4,
+      color: [0xFF, 0, 0],
+      tolerance: 8,
+    });
+    let dark = watch.add(Condition::BrightnessBelow {
+      rect: Rect::new(0, 0, 8, 8),
+      threshold: 64,
+    });
+    let outside = watch.add(Condition::BrightnessAbove {
+      rect: Rect::new(100, 100, 200, 200),
+      threshold: 0,
+    });
+
+    // only matching rules report the first frame
+    let black = generate(16, 16, |_, _| [0, 0, 0]);
+    let events = watch.process(&black);
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].rule, events[0].matched), (dark, true));
+    assert_eq!(watch.matched(pixel), Some(false));
+    assert_eq!(watch.matched(outside), Some(false));
+    assert!(watch.process(&black).is_empty());
+
+    // a blue top-left quarter
+    let blue = generate(
+      16,
+      16,
+      |x, y| if x < 8 && y < 8 { [0xF8, 0, 0] } else { [0; 3] },
+    );
+    let events = watch.process(&blue);
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].rule, events[0].matched), (pixel, true));
+
+    // the same result on a preview
+    let events = watch.process_preview(&black.preview(4), 4);
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].rule, events[0].matched), (pixel, false));
+    assert!(watch.process_preview(&black.preview(4), 4).is_empty());
+    assert_eq!(watch.process_preview(&blue.preview(4), 4).len(), 1);
+
+    assert!(watch.remove(pixel));
+    assert!(!watch.remove(pixel));
+    assert_eq!(watch.matched(pixel), None);
+    assert!(watch.process(&black).is_empty());
+  }
+}
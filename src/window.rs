@@ -0,0 +1,280 @@
+//! Capture a window by following its rectangle on the desktop as it moves and resizes.
+//! Enable the `window` feature to use this module.
+
+use crate::capturer::model::Capturer;
+use crate::capturer::simple::SimpleCapturer;
+use crate::error::Error;
+use crate::manager::Manager;
+use crate::model::{Rect, Result};
+use crate::screenshot::copy_rect;
+use crate::utils::OutputDescExt;
+use std::mem;
+use windows::Win32::Foundation::{HWND, RECT};
+use windows::Win32::Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_EXTENDED_FRAME_BOUNDS};
+use windows::Win32::Graphics::Dxgi::DXGI_ERROR_WAIT_TIMEOUT;
+use windows::Win32::UI::WindowsAndMessaging::{GetWindowRect, IsIconic, IsWindow};
+
+/// Changes of the followed window, reported by [`WindowCapturer::capture`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEvent {
+  /// The window moved to the rect, its size is unchanged.
+  Moved(Rect),
+  /// The window size changed and the buffer is reallocated for the rect.
+  Resized(Rect),
+  Minimized,
+  /// The window is shown again at the rect after it was minimized.
+  Restored(Rect),
+  /// The window is destroyed, later captures fail.
+  Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WindowState {
+  Visible(Rect),
+  Minimized,
+  Closed,
+}
+
+/// Get the rectangle of a window in virtual desktop coordinates, without its drop shadow.
+/// Return `None` if it is minimized.
+pub fn window_rect(hwnd: HWND) -> Result<Option<Rect>> {
+  if !unsafe { IsWindow(hwnd) }.as_bool() {
+    return Err(Error::new("Window not found"));
+  }
+  if unsafe { IsIconic(hwnd) }.as_bool() {
+    return Ok(None);
+  }
+  let mut rect = RECT::default();
+  let bounds = unsafe {
+    DwmGetWindowAttribute(
+      hwnd,
+      DWMWA_EXTENDED_FRAME_BOUNDS,
+      &mut rect as *mut _ as *mut _,
+      mem::size_of::<RECT>() as u32,
+    )
+  };
+  // windows which aren't composed by DWM don't have the attribute
+  if bounds.is_err() && !unsafe { GetWindowRect(hwnd, &mut rect) }.as_bool() {
+    return Err(Error::windows(
+      "GetWindowRect",
+      windows::core::Error::from_win32(),
+    ));
+  }
+  Ok(Some(rect.into()))
+}
+
+fn window_state(hwnd: HWND) -> Result<WindowState> {
+  if !unsafe { IsWindow(hwnd) }.as_bool() {
+    return Ok(WindowState::Closed);
+  }
+  Ok(match window_rect(hwnd)? {
+    Some(rect) => WindowState::Visible(rect),
+    None => WindowState::Minimized,
+  })
+}
+
+/// The events of a change from `old` to `new`.
+fn transition(old: WindowState, new: WindowState) -> Option<WindowEvent> {
+  match (old, new) {
+    (old, new) if old == new => None,
+    (_, WindowState::Closed) => Some(WindowEvent::Closed),
+    (_, WindowState::Minimized) => Some(WindowEvent::Minimized),
+    (WindowState::Visible(old), WindowState::Visible(new))
+      if old.width() == new.width() && old.height() == new.height() =>
+    {
+      Some(WindowEvent::Moved(new))
+    }
+    (WindowState::Visible(_), WindowState::Visible(new)) => Some(WindowEvent::Resized(new)),
+    (_, WindowState::Visible(new)) => Some(WindowEvent::Restored(new)),
+  }
+}
+
+/// Capture the area of a window, polling its rectangle before each capture.
+///
+/// The window is captured from the monitor showing most of it, parts on other monitors
+/// or outside the desktop are black. Other windows covering it are captured too.
+/// The process should be per-monitor DPI aware so window and desktop coordinates match.
+pub struct WindowCapturer<'a> {
+  manager: &'a Manager,
+  hwnd: HWND,
+  /// Capturers of the manager's contexts, created when the window is shown on them.
+  capturers: Vec<Option<SimpleCapturer<'a>>>,
+  state: WindowState,
+  /// The rect the buffer is allocated for.
+  rect: Option<Rect>,
+  buffer: Vec<u8>,
+}
+
+impl<'a> WindowCapturer<'a> {
+  pub fn new(manager: &'a Manager, hwnd: HWND) -> Result<Self> {
+    let state = window_state(hwnd)?;
+    if state == WindowState::Closed {
+      return Err(Error::new("Window not found"));
+    }
+    let mut capturer = Self {
+      manager,
+      hwnd,
+      capturers: manager.contexts.iter().map(|_| None).collect(),
+      state: WindowState::Minimized,
+      rect: None,
+      buffer: Vec::new(),
+    };
+    capturer.update(state);
+    Ok(capturer)
+  }
+
+  pub fn hwnd(&self) -> HWND {
+    self.hwnd
+  }
+
+  /// The window rect of the last capture, `None` while minimized or closed.
+  pub fn rect(&self) -> Option<Rect> {
+    match self.state {
+      WindowState::Visible(rect) => Some(rect),
+      _ => None,
+    }
+  }
+
+  /// The size of [`WindowCapturer::buffer`], which is kept while the window is minimized.
+  pub fn size(&self) -> (u32, u32) {
+    self
+      .rect
+      .map_or((0, 0), |rect| (rect.width(), rect.height()))
+  }
+
+  /// The BGRA32 pixels of the window from the last capture, row by row without padding.
+  pub fn buffer(&self) -> &[u8] {
+    &self.buffer
+  }
+
+  /// Follow the window and capture it if it's visible.
+  /// Return how the window changed since the last capture.
+  pub fn capture(&mut self) -> Result<Vec<WindowEvent>> {
+    if self.state == WindowState::Closed {
+      return Err(Error::new("Window closed"));
+    }
+    let events: Vec<_> = self.update(window_state(self.hwnd)?).into_iter().collect();
+    if let WindowState::Visible(rect) = self.state {
+      self.capture_rect(&rect)?;
+    }
+    Ok(events)
+  }
+
+  /// Switch to the state, reallocating the buffer if the size changed.
+  fn update(&mut self, state: WindowState) -> Option<WindowEvent> {
+    let event = transition(self.state, state);
+    self.state = state;
+    if let WindowState::Visible(rect) = state {
+      if self.size() != (rect.width(), rect.height()) {
+        self.buffer = vec![0u8; rect.width() as usize * rect.height() as usize * 4];
+      }
+      self.rect = Some(rect);
+    }
+    event
+  }
+
+  fn capture_rect(&mut self, rect: &Rect) -> Result<()> {
+    self.buffer.fill(0);
+    // the monitor showing the largest part of the window
+    let mut best = None;
+    for (index, ctx) in self.manager.contexts.iter().enumerate() {
+      let monitor_rect = ctx.dxgi_output_desc()?.rect();
+      if let Some(area) = rect.intersect(&monitor_rect) {
+        let size = area.width() as u64 * area.height() as u64;
+        let larger = match best {
+          Some((_, _, _, best_size)) => size > best_size,
+          None => true,
+        };
+        if larger {
+          best = Some((index, monitor_rect, area, size));
+        }
+      }
+    }
+    let Some((index, monitor_rect, area, _)) = best else {
+      return Ok(());
+    };
+
+    let capturer = match &mut self.capturers[index] {
+      Some(capturer) => capturer,
+      slot => slot.insert(self.manager.contexts[index].simple_capturer()?),
+    };
+    match capturer.capture() {
+      Ok(_) => {}
+      // nothing changed on the monitor, crop the previous frame
+      Err(e) if e.windows.as_ref().map(|e| e.code()) == Some(DXGI_ERROR_WAIT_TIMEOUT) => {}
+      Err(e) => return Err(e),
+    }
+    let (width, height) = capturer.frame_size()?;
+    if width != monitor_rect.width() || height != monitor_rect.height() {
+      return Err(Error::new(
+        "Frame size doesn't match desktop coordinates, is the process DPI aware?",
+      ));
+    }
+    copy_rect(
+      capturer.buffer(),
+      width,
+      &monitor_rect,
+      &mut self.buffer,
+      rect,
+      &area,
+      4,
+    );
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{transition, window_rect, WindowCapturer, WindowEvent, WindowState};
+  use crate::manager::Manager;
+  use crate::model::Rect;
+  use windows::Win32::Foundation::HWND;
+  use windows::Win32::UI::WindowsAndMessaging::GetDesktopWindow;
+
+  #[test]
+  fn transitions() {
+    let rect = Rect::new(0, 0, 100, 50);
+    let moved = Rect::new(10, 10, 110, 60);
+    let resized = Rect::new(10, 10, 210, 60);
+    let visible = WindowState::Visible;
+    assert_eq!(transition(visible(rect), visible(rect)), None);
+    assert_eq!(
+      transition(visible(rect), visible(moved)),
+      Some(WindowEvent::Moved(moved))
+    );
+    assert_eq!(
+      transition(visible(moved), visible(resized)),
+      Some(WindowEvent::Resized(resized))
+    );
+    assert_eq!(
+      transition(visible(rect), WindowState::Minimized),
+      Some(WindowEvent::Minimized)
+    );
+    assert_eq!(
+      transition(WindowState::Minimized, visible(rect)),
+      Some(WindowEvent::Restored(rect))
+    );
+    assert_eq!(
+      transition(WindowState::Minimized, WindowState::Closed),
+      Some(WindowEvent::Closed)
+    );
+  }
+
+  #[test]
+  fn window_capturer() {
+    assert!(window_rect(HWND(0)).is_err());
+
+    // the desktop window covers the primary monitor
+    let manager = Manager::default().unwrap();
+    let hwnd = unsafe { GetDesktopWindow() };
+    let rect = window_rect(hwnd).unwrap().unwrap();
+    let mut capturer = WindowCapturer::new(&manager, hwnd).unwrap();
+    assert_eq!(capturer.rect(), Some(rect));
+    assert!(capturer.capture().unwrap().is_empty());
+    assert_eq!(
+      capturer.buffer().len(),
+      rect.width() as usize * rect.height() as usize * 4
+    );
+    assert!(WindowCapturer::new(&manager, HWND(0)).is_err());
+  }
+}